@@ -0,0 +1,239 @@
+use crate::orientation;
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::{AnimationDecoder, DynamicImage, Frames, ImageFormat};
+use std::io::Cursor;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// 遅延が極端に短いフレームを置き換える表示時間
+///
+/// 遅延0〜10msのGIFは、主要なブラウザと同様に100msとして扱います。
+const MIN_FRAME_DELAY: Duration = Duration::from_millis(11);
+const FALLBACK_FRAME_DELAY: Duration = Duration::from_millis(100);
+
+/// アニメーションの1フレーム（合成済みの全体画像）
+pub struct AnimationFrame {
+    pub image: Arc<DynamicImage>,
+    pub delay: Duration,
+}
+
+/// デコード済みのアニメーション（GIF・APNG・アニメーションWebP）
+pub struct Animation {
+    pub frames: Vec<AnimationFrame>,
+}
+
+impl Animation {
+    /// フレーム数
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// フレームが無いかどうか
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// 保持に必要なおおよそのメモリ量（バイト）
+    pub fn memory_size(&self) -> usize {
+        self.frames
+            .iter()
+            .map(|frame| frame.image.as_bytes().len())
+            .sum()
+    }
+}
+
+/// アニメーション画像かどうかを、フレームをデコードせずに判定する
+///
+/// GIFは画像ブロックの数を数え、APNG・WebPはヘッダーのアニメーション情報を確認します。
+/// GIFのブロックを解析できない場合は、デコードして確かめられるよう`true`を返します。
+pub fn is_animated(bytes: &[u8], path: &Path) -> bool {
+    match guess_format(bytes, path) {
+        Some(ImageFormat::Gif) => gif_has_multiple_frames(bytes),
+        Some(ImageFormat::Png) => {
+            PngDecoder::new(Cursor::new(bytes)).is_ok_and(|decoder| decoder.is_apng())
+        }
+        Some(ImageFormat::WebP) => {
+            WebPDecoder::new(Cursor::new(bytes)).is_ok_and(|decoder| decoder.has_animation())
+        }
+        _ => false,
+    }
+}
+
+fn guess_format(bytes: &[u8], path: &Path) -> Option<ImageFormat> {
+    image::guess_format(bytes)
+        .ok()
+        .or_else(|| ImageFormat::from_path(path).ok())
+}
+
+/// GIFに画像ブロックが2つ以上あるかどうか（画素は展開せず、ブロックをたどる）
+fn gif_has_multiple_frames(bytes: &[u8]) -> bool {
+    // データサブブロックの並び（長さ0で終わる）を読み飛ばす
+    let skip_sub_blocks = |mut pos: usize| -> Option<usize> {
+        loop {
+            let len = *bytes.get(pos)? as usize;
+            pos += 1 + len;
+            if len == 0 {
+                return Some(pos);
+            }
+        }
+    };
+    // カラーテーブルの大きさ（3バイト × 2^(n+1)）
+    let color_table = |flags: u8| {
+        if flags & 0x80 != 0 {
+            3 << ((flags & 0x07) + 1)
+        } else {
+            0
+        }
+    };
+    let scan = || -> Option<bool> {
+        // ヘッダー（6バイト）と論理画面記述子（7バイト）
+        let mut pos = 13 + color_table(*bytes.get(10)?);
+        let mut frames = 0;
+        loop {
+            match *bytes.get(pos)? {
+                // 画像記述子（10バイト）、ローカルカラーテーブル、LZWの最小符号長、画像データ
+                0x2C => {
+                    frames += 1;
+                    if frames >= 2 {
+                        return Some(true);
+                    }
+                    pos += 10 + color_table(*bytes.get(pos + 9)?);
+                    pos = skip_sub_blocks(pos + 1)?;
+                }
+                // 拡張ブロック（ラベルとサブブロック）
+                0x21 => pos = skip_sub_blocks(pos + 2)?,
+                0x3B => return Some(false),
+                _ => return None,
+            }
+        }
+    };
+    scan().unwrap_or(true)
+}
+
+/// ファイルがアニメーション画像であれば全フレームをデコードする
+///
+/// サイドカーXMP（なければEXIF）の向きは各フレームに適用します。
+/// 静止画は`is_animated`で判定し、フレームをデコードせずに`None`を返します。
+///
+/// # Arguments
+///
+/// * `bytes` - 画像ファイルの内容
+/// * `path` - 画像ファイルのパス（形式判定とサイドカーXMPの参照に使用）
+/// * `cancelled` - 中断要求の確認（フレームごとに呼ばれる）
+///
+/// # Returns
+///
+/// * `Ok(Some(Animation))` - 2フレーム以上のアニメーション
+/// * `Ok(None)` - 静止画、またはアニメーションに対応しない形式
+/// * `Err(String)` - エラーメッセージ（中断時は "Cancelled"）
+pub fn decode_animation(
+    bytes: &[u8],
+    path: &Path,
+    cancelled: impl Fn() -> bool,
+) -> Result<Option<Animation>, String> {
+    if !is_animated(bytes, path) {
+        return Ok(None);
+    }
+    let decode_error =
+        |e: image::ImageError| format!("アニメーションのデコードに失敗しました: {}", e);
+
+    let frames: Frames = match guess_format(bytes, path) {
+        Some(ImageFormat::Gif) => GifDecoder::new(Cursor::new(bytes))
+            .map_err(decode_error)?
+            .into_frames(),
+        Some(ImageFormat::Png) => PngDecoder::new(Cursor::new(bytes))
+            .map_err(decode_error)?
+            .apng()
+            .into_frames(),
+        Some(ImageFormat::WebP) => WebPDecoder::new(Cursor::new(bytes))
+            .map_err(decode_error)?
+            .into_frames(),
+        _ => return Ok(None),
+    };
+
+    let orientation = orientation::resolve_orientation(bytes, path)
+        .map(|applied| applied.orientation)
+        .unwrap_or_default();
+    let frames = frames
+        .map(|frame| {
+            if cancelled() {
+                return Err("Cancelled".to_string());
+            }
+            let frame = frame.map_err(decode_error)?;
+            let (numer, denom) = frame.delay().numer_denom_ms();
+            let delay = Duration::from_micros(numer as u64 * 1000 / denom.max(1) as u64);
+            Ok(AnimationFrame {
+                image: Arc::new(orientation.apply(DynamicImage::ImageRgba8(frame.into_buffer()))),
+                delay: if delay < MIN_FRAME_DELAY {
+                    FALLBACK_FRAME_DELAY
+                } else {
+                    delay
+                },
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    if frames.len() < 2 {
+        return Ok(None);
+    }
+    Ok(Some(Animation { frames }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::gif::GifEncoder;
+    use image::{Delay, Frame, GenericImageView, Rgba, RgbaImage};
+
+    fn encode_gif(delays_ms: &[u32]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = GifEncoder::new(&mut bytes);
+            let frames = delays_ms.iter().enumerate().map(|(i, &ms)| {
+                let color = Rgba([(i * 80) as u8, 0, 0, 255]);
+                Frame::from_parts(
+                    RgbaImage::from_pixel(4, 2, color),
+                    0,
+                    0,
+                    Delay::from_numer_denom_ms(ms, 1),
+                )
+            });
+            encoder.encode_frames(frames).unwrap();
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_gif_frames_and_delays() {
+        let bytes = encode_gif(&[50, 200, 0]);
+        let animation = decode_animation(&bytes, Path::new("anim.gif"), || false)
+            .unwrap()
+            .expect("3フレームのGIFはアニメーションとして扱う");
+
+        assert_eq!(animation.len(), 3);
+        assert_eq!(animation.frames[0].delay, Duration::from_millis(50));
+        assert_eq!(animation.frames[1].delay, Duration::from_millis(200));
+        assert_eq!(
+            animation.frames[2].delay, FALLBACK_FRAME_DELAY,
+            "遅延0のフレームは既定の表示時間にする"
+        );
+        assert_eq!(animation.frames[1].image.dimensions(), (4, 2));
+        assert_eq!(animation.frames[1].image.get_pixel(0, 0)[0], 80);
+        assert!(is_animated(&bytes, Path::new("anim.gif")));
+
+        // 中断要求があればフレームの途中で止める
+        assert!(decode_animation(&bytes, Path::new("anim.gif"), || true).is_err());
+    }
+
+    #[test]
+    fn test_single_frame_is_not_animation() {
+        let bytes = encode_gif(&[100]);
+        assert!(!is_animated(&bytes, Path::new("still.gif")));
+        assert!(decode_animation(&bytes, Path::new("still.gif"), || false)
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,675 @@
+use crate::export::{self, ExportOptions};
+use crate::history::{EditHistory, FileState};
+use crate::img::{self, RotationMode};
+use crate::jpeg_lossless::EdgeMode;
+use crate::letterbox::Letterbox;
+use crate::orientation::Orientation;
+use crate::safe_write;
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// 一括処理の内容
+#[derive(Debug, Clone, PartialEq)]
+pub enum BatchOperation {
+    /// 90度単位で回転して上書きする（編集履歴に記録する）
+    Rotate {
+        angle: i32,
+        mode: RotationMode,
+        jpeg_edges: EdgeMode,
+    },
+    /// 書き出しの設定で形式の変換・縮小を行い、新しいファイルに保存する
+    Export(ExportOptions),
+    /// 黒帯を付けて`<名前>_letterbox.<拡張子>`に保存する
+    Letterbox(Letterbox),
+}
+
+impl BatchOperation {
+    /// 表示・レポート用の名前
+    pub fn label(&self) -> &'static str {
+        match self {
+            BatchOperation::Rotate { .. } => "回転",
+            BatchOperation::Export(_) => "書き出し",
+            BatchOperation::Letterbox(_) => "黒帯",
+        }
+    }
+}
+
+/// 1ファイルの処理状態
+#[derive(Debug, Clone, PartialEq)]
+pub enum FileStatus {
+    /// 未処理
+    Pending,
+    /// 成功（新しいファイルに保存した場合はそのパス）
+    Done(Option<PathBuf>),
+    /// 失敗（エラーメッセージ）
+    Failed(String),
+    /// キャンセルにより処理しなかった
+    Cancelled,
+}
+
+/// 複数の画像に同じ処理を適用する一括処理
+#[derive(Debug, Clone)]
+pub struct BatchJob {
+    pub operation: BatchOperation,
+    pub files: Vec<PathBuf>,
+    /// 新しいファイルの保存先（`None`なら元の画像と同じフォルダ、回転では無視）
+    pub output_dir: Option<PathBuf>,
+    /// 保存先に同名のファイルがある場合に上書きするか
+    pub overwrite: bool,
+}
+
+/// 実行中の一括処理で共有する保存先の確認用の情報
+struct Reservations {
+    /// 処理対象の画像（他の画像の保存先にしない）
+    sources: HashSet<String>,
+    /// 保存先として確保済みのパス
+    outputs: Mutex<HashSet<String>>,
+}
+
+impl BatchJob {
+    /// 1つの画像を処理する
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - 画像ファイルのパス
+    /// * `history` - 上書きする処理で編集前の状態を記録する履歴
+    /// * `reservations` - 保存先の確認用の情報
+    ///
+    /// # Returns
+    ///
+    /// 新しいファイルに保存した場合はそのパス
+    ///
+    /// # Errors
+    ///
+    /// * 保存先フォルダの作成に失敗した場合
+    /// * `reserve`のエラー
+    /// * `img::apply_transform`・`export::export_to_folder`・`img::letterbox_image`のエラー
+    fn process(
+        &self,
+        path: &Path,
+        history: Option<&EditHistory>,
+        reservations: &Reservations,
+    ) -> Result<Option<PathBuf>, String> {
+        if let Some(dir) = &self.output_dir {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("保存先フォルダを作成できません: {}", e))?;
+        }
+        match &self.operation {
+            BatchOperation::Rotate {
+                angle,
+                mode,
+                jpeg_edges,
+            } => {
+                let before = FileState::capture(path);
+                img::apply_transform(
+                    path,
+                    Orientation::default().rotated_by(*angle),
+                    0,
+                    *mode,
+                    *jpeg_edges,
+                    None,
+                )?;
+                if let (Some(history), Ok(before)) = (history, before) {
+                    if let Err(e) = history.record(path, "回転", before) {
+                        eprintln!("[History] 履歴の保存に失敗しました: {}", e);
+                    }
+                }
+                Ok(None)
+            }
+            BatchOperation::Export(options) => {
+                export::export_to_folder(path, options, &self.output_folder(path), |output| {
+                    self.reserve(path, output, reservations)
+                })
+                .map(Some)
+            }
+            BatchOperation::Letterbox(letterbox) => {
+                let output = self.letterbox_output(path);
+                self.reserve(path, &output, reservations)?;
+                img::letterbox_image(path, letterbox, 0, Some(&output), None)?;
+                Ok(Some(output))
+            }
+        }
+    }
+
+    /// 新しいファイルの保存先フォルダ
+    fn output_folder(&self, path: &Path) -> PathBuf {
+        match &self.output_dir {
+            Some(dir) => dir.clone(),
+            None => path.parent().unwrap_or(Path::new(".")).to_path_buf(),
+        }
+    }
+
+    /// 黒帯を付けた画像の保存先
+    fn letterbox_output(&self, path: &Path) -> PathBuf {
+        let default_output = img::edited_output_path(path, "letterbox");
+        match (&self.output_dir, default_output.file_name()) {
+            (Some(dir), Some(name)) => dir.join(name),
+            _ => default_output,
+        }
+    }
+
+    /// 画像を読み込まずに決まる保存先（回転と、サイズに依存するファイル名では`None`）
+    fn planned_output(&self, path: &Path) -> Option<PathBuf> {
+        match &self.operation {
+            BatchOperation::Rotate { .. } => None,
+            BatchOperation::Export(options) => {
+                export::planned_output(path, options, &self.output_folder(path))
+            }
+            BatchOperation::Letterbox(_) => Some(self.letterbox_output(path)),
+        }
+    }
+
+    /// 保存先を確保する
+    ///
+    /// # Errors
+    ///
+    /// * 保存先が元の画像、または他の処理対象の画像と同じファイルになる場合
+    /// * 保存先に同名のファイルがあり、上書きしない設定の場合
+    /// * 同じ一括処理の他の画像が保存先を確保済みの場合
+    fn reserve(
+        &self,
+        source: &Path,
+        output: &Path,
+        reservations: &Reservations,
+    ) -> Result<(), String> {
+        let key = safe_write::path_key(output);
+        if key == safe_write::path_key(source) {
+            return Err("元のファイルと同じ名前になるため保存しません".to_string());
+        }
+        if reservations.sources.contains(&key) {
+            return Err(format!(
+                "処理対象の他の画像と同じ名前になるため保存しません: {}",
+                output.display()
+            ));
+        }
+        if !self.overwrite && output.exists() {
+            return Err(format!(
+                "同名のファイルが既に存在します: {}",
+                output.display()
+            ));
+        }
+        if !reservations.outputs.lock().unwrap().insert(key) {
+            return Err(format!(
+                "他の画像の保存先と重複します: {}",
+                output.display()
+            ));
+        }
+        Ok(())
+    }
+
+    /// すべての画像を並列に処理する
+    ///
+    /// 開始前に保存先が重複する画像を検出し、それらは処理せずに失敗にします。
+    /// `cancel_flag`が立つと、未着手の画像は処理せずに`FileStatus::Cancelled`にします。
+    /// 処理中の画像は最後まで処理します。
+    ///
+    /// # Arguments
+    ///
+    /// * `history` - 上書きする処理で編集前の状態を記録する履歴
+    /// * `cancel_flag` - キャンセル要求
+    /// * `on_file` - 各画像の処理が終わるたびに呼ばれるコールバック（番号, 状態）
+    pub fn run(
+        &self,
+        history: Option<&EditHistory>,
+        cancel_flag: &AtomicBool,
+        on_file: impl Fn(usize, &FileStatus) + Sync,
+    ) -> BatchReport {
+        let started = chrono::Local::now();
+        let timer = Instant::now();
+
+        let planned: Vec<Option<PathBuf>> = self
+            .files
+            .iter()
+            .map(|path| self.planned_output(path))
+            .collect();
+        let mut planned_count: HashMap<String, usize> = HashMap::new();
+        for output in planned.iter().flatten() {
+            *planned_count
+                .entry(safe_write::path_key(output))
+                .or_default() += 1;
+        }
+        let reservations = Reservations {
+            sources: self
+                .files
+                .iter()
+                .map(|path| safe_write::path_key(path))
+                .collect(),
+            outputs: Mutex::new(HashSet::new()),
+        };
+
+        let statuses: Vec<FileStatus> = self
+            .files
+            .par_iter()
+            .zip(&planned)
+            .enumerate()
+            .map(|(index, (path, planned))| {
+                let duplicated = planned
+                    .as_ref()
+                    .filter(|output| planned_count[&safe_write::path_key(output)] > 1);
+                let status = if let Some(output) = duplicated {
+                    FileStatus::Failed(format!(
+                        "保存先が他の画像と重複します: {}",
+                        output.display()
+                    ))
+                } else if cancel_flag.load(Ordering::Relaxed) {
+                    FileStatus::Cancelled
+                } else {
+                    match self.process(path, history, &reservations) {
+                        Ok(output) => FileStatus::Done(output),
+                        Err(e) => FileStatus::Failed(e),
+                    }
+                };
+                on_file(index, &status);
+                status
+            })
+            .collect();
+
+        BatchReport {
+            operation: self.operation.label(),
+            started,
+            elapsed: timer.elapsed(),
+            files: self.files.iter().cloned().zip(statuses).collect(),
+        }
+    }
+
+    /// レポートの保存先フォルダ（保存先フォルダ、未指定なら最初の画像のフォルダ）
+    pub fn report_dir(&self) -> Option<PathBuf> {
+        self.output_dir.clone().or_else(|| {
+            self.files
+                .first()
+                .and_then(|path| path.parent())
+                .map(Path::to_path_buf)
+        })
+    }
+}
+
+/// 一括処理の結果
+#[derive(Debug, Clone)]
+pub struct BatchReport {
+    pub operation: &'static str,
+    pub started: chrono::DateTime<chrono::Local>,
+    pub elapsed: Duration,
+    pub files: Vec<(PathBuf, FileStatus)>,
+}
+
+impl BatchReport {
+    /// 成功した画像の数
+    pub fn succeeded(&self) -> usize {
+        self.count(|status| matches!(status, FileStatus::Done(_)))
+    }
+
+    /// 失敗した画像の数
+    pub fn failed(&self) -> usize {
+        self.count(|status| matches!(status, FileStatus::Failed(_)))
+    }
+
+    /// キャンセルにより処理しなかった画像の数
+    pub fn cancelled(&self) -> usize {
+        self.count(|status| *status == FileStatus::Cancelled)
+    }
+
+    fn count(&self, predicate: impl Fn(&FileStatus) -> bool) -> usize {
+        self.files
+            .iter()
+            .filter(|(_, status)| predicate(status))
+            .count()
+    }
+
+    /// 件数の要約（`n件成功, n件失敗[, n件中断]`）
+    pub fn summary(&self) -> String {
+        let mut summary = format!("{}件成功, {}件失敗", self.succeeded(), self.failed());
+        if self.cancelled() > 0 {
+            summary.push_str(&format!(", {}件中断", self.cancelled()));
+        }
+        summary
+    }
+
+    /// テキスト形式のレポート
+    pub fn to_text(&self) -> String {
+        let mut text = format!(
+            "一括処理: {}\n開始: {}\n所要時間: {:.1}秒\n結果: {}\n\n",
+            self.operation,
+            self.started.format("%Y-%m-%d %H:%M:%S"),
+            self.elapsed.as_secs_f64(),
+            self.summary()
+        );
+        for (path, status) in &self.files {
+            let line = match status {
+                FileStatus::Pending => format!("未処理\t{}", path.display()),
+                FileStatus::Done(Some(output)) => {
+                    format!("成功\t{} -> {}", path.display(), output.display())
+                }
+                FileStatus::Done(None) => format!("成功\t{}", path.display()),
+                FileStatus::Failed(e) => format!("失敗\t{}: {}", path.display(), e),
+                FileStatus::Cancelled => format!("中断\t{}", path.display()),
+            };
+            text.push_str(&line);
+            text.push('\n');
+        }
+        text
+    }
+
+    /// レポートを`batch_report_<日時>.txt`として保存する
+    ///
+    /// # Returns
+    ///
+    /// 保存したファイルのパス
+    ///
+    /// # Errors
+    ///
+    /// * 書き込みに失敗した場合
+    pub fn save(&self, dir: &Path) -> Result<PathBuf, String> {
+        let path = dir.join(format!(
+            "batch_report_{}.txt",
+            self.started.format("%Y%m%d_%H%M%S")
+        ));
+        safe_write::write_atomic(&path, self.to_text().as_bytes())?;
+        Ok(path)
+    }
+}
+
+/// 一括処理の完了通知
+pub struct BatchOutcome {
+    pub report: BatchReport,
+    /// 保存したレポートのパス
+    pub report_path: Result<PathBuf, String>,
+}
+
+/// バックグラウンドで実行中の一括処理
+///
+/// 各画像の状態は処理の途中でも参照できます。ドロップしても処理は中断しません。
+pub struct BatchRun {
+    files: Vec<PathBuf>,
+    statuses: Arc<Mutex<Vec<FileStatus>>>,
+    cancel_flag: Arc<AtomicBool>,
+    receiver: mpsc::Receiver<BatchOutcome>,
+}
+
+impl BatchRun {
+    /// 一括処理をワーカースレッドで開始する
+    ///
+    /// 完了するとレポートを`BatchJob::report_dir`に保存します。
+    ///
+    /// # Arguments
+    ///
+    /// * `job` - 処理内容と対象の画像
+    /// * `history` - 上書きする処理で編集前の状態を記録する履歴
+    /// * `on_progress` - 各画像の処理が終わるたびと完了時に呼ばれるコールバック
+    pub fn start(
+        job: BatchJob,
+        history: Option<EditHistory>,
+        on_progress: impl Fn() + Send + Sync + 'static,
+    ) -> Self {
+        let files = job.files.clone();
+        let statuses = Arc::new(Mutex::new(vec![FileStatus::Pending; files.len()]));
+        let cancel_flag = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = mpsc::channel();
+
+        let worker_statuses = statuses.clone();
+        let worker_flag = cancel_flag.clone();
+        thread::spawn(move || {
+            println!(
+                "[Batch] 開始: {} ({} 件)",
+                job.operation.label(),
+                job.files.len()
+            );
+            let report = job.run(history.as_ref(), &worker_flag, |index, status| {
+                worker_statuses.lock().unwrap()[index] = status.clone();
+                on_progress();
+            });
+            println!("[Batch] 完了: {}", report.summary());
+
+            let report_path = job
+                .report_dir()
+                .ok_or_else(|| "レポートの保存先がありません".to_string())
+                .and_then(|dir| report.save(&dir));
+            if let Err(e) = &report_path {
+                eprintln!("[Batch] レポートの保存に失敗しました: {}", e);
+            }
+            let _ = tx.send(BatchOutcome {
+                report,
+                report_path,
+            });
+            on_progress();
+        });
+
+        Self {
+            files,
+            statuses,
+            cancel_flag,
+            receiver: rx,
+        }
+    }
+
+    /// 未着手の画像の処理を中断する
+    pub fn cancel(&self) {
+        self.cancel_flag.store(true, Ordering::Relaxed);
+    }
+
+    /// キャンセルを要求済みか
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel_flag.load(Ordering::Relaxed)
+    }
+
+    /// 処理が終わった画像の数と全体の数
+    pub fn progress(&self) -> (usize, usize) {
+        let statuses = self.statuses.lock().unwrap();
+        let finished = statuses
+            .iter()
+            .filter(|status| **status != FileStatus::Pending)
+            .count();
+        (finished, statuses.len())
+    }
+
+    /// これまでに失敗した画像とエラーメッセージ
+    pub fn failures(&self) -> Vec<(PathBuf, String)> {
+        self.statuses
+            .lock()
+            .unwrap()
+            .iter()
+            .zip(&self.files)
+            .filter_map(|(status, path)| match status {
+                FileStatus::Failed(e) => Some((path.clone(), e.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// 完了していれば結果を返す
+    pub fn poll(&self) -> Option<BatchOutcome> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GenericImageView, Rgb, RgbImage};
+
+    fn setup(name: &str) -> (PathBuf, Vec<PathBuf>) {
+        let dir = std::env::temp_dir().join(format!("vdi_batch_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut files = Vec::new();
+        for i in 0..3 {
+            let path = dir.join(format!("{}.png", i));
+            RgbImage::from_pixel(40, 10 + i, Rgb([10, 20, 30]))
+                .save(&path)
+                .unwrap();
+            files.push(path);
+        }
+        let broken = dir.join("broken.png");
+        std::fs::write(&broken, b"not an image").unwrap();
+        files.push(broken);
+        (dir, files)
+    }
+
+    #[test]
+    fn test_run_and_report() {
+        let (dir, files) = setup("run");
+        let job = BatchJob {
+            operation: BatchOperation::Letterbox(Letterbox {
+                aspect: 1.0,
+                ..Default::default()
+            }),
+            files: files.clone(),
+            output_dir: Some(dir.join("out")),
+            overwrite: false,
+        };
+        let report = job.run(None, &AtomicBool::new(false), |_, _| {});
+        assert_eq!(
+            (report.succeeded(), report.failed(), report.cancelled()),
+            (3, 1, 0)
+        );
+        assert_eq!(report.files[0].0, files[0]);
+        let output = dir.join("out").join("0_letterbox.png");
+        assert_eq!(report.files[0].1, FileStatus::Done(Some(output.clone())));
+        assert_eq!(image::open(&output).unwrap().dimensions(), (40, 40));
+        assert!(matches!(report.files[3].1, FileStatus::Failed(_)));
+
+        let saved = report.save(&job.report_dir().unwrap()).unwrap();
+        let text = std::fs::read_to_string(saved).unwrap();
+        assert!(text.contains("3件成功, 1件失敗"));
+        assert!(text.contains("失敗\t"));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_cancel() {
+        let (dir, files) = setup("cancel");
+        let job = BatchJob {
+            operation: BatchOperation::Export(ExportOptions::default()),
+            files,
+            output_dir: None,
+            overwrite: false,
+        };
+        let report = job.run(None, &AtomicBool::new(true), |_, _| {});
+        assert_eq!(report.cancelled(), 4);
+        assert!(!dir.join("0_export.jpg").exists());
+
+        // 開始後のキャンセル要求でも、完了の通知とレポートは届く
+        let run = BatchRun::start(job, None, || {});
+        run.cancel();
+        let outcome = loop {
+            if let Some(outcome) = run.poll() {
+                break outcome;
+            }
+            thread::sleep(Duration::from_millis(10));
+        };
+        assert_eq!(outcome.report.files.len(), 4);
+        assert_eq!(run.progress(), (4, 4));
+        assert!(outcome.report_path.unwrap().starts_with(&dir));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_output_conflicts() {
+        let (dir, mut files) = setup("conflict");
+        files.truncate(2);
+        // 拡張子だけが異なる画像は、書き出すと同じ名前になる
+        let bmp = dir.join("0.bmp");
+        RgbImage::from_pixel(4, 4, Rgb([0, 0, 0]))
+            .save(&bmp)
+            .unwrap();
+        files.push(bmp);
+        let mut job = BatchJob {
+            operation: BatchOperation::Export(ExportOptions::default()),
+            files,
+            output_dir: None,
+            overwrite: false,
+        };
+        let report = job.run(None, &AtomicBool::new(false), |_, _| {});
+        assert!(matches!(report.files[0].1, FileStatus::Failed(_)));
+        assert!(matches!(report.files[2].1, FileStatus::Failed(_)));
+        assert!(!dir.join("0_export.jpg").exists());
+
+        // 既存のファイルは上書きを指定した場合のみ上書きする
+        assert!(matches!(report.files[1].1, FileStatus::Done(_)));
+        job.files.truncate(2);
+        job.files.remove(0);
+        let report = job.run(None, &AtomicBool::new(false), |_, _| {});
+        assert!(matches!(report.files[0].1, FileStatus::Failed(_)));
+        job.overwrite = true;
+        let report = job.run(None, &AtomicBool::new(false), |_, _| {});
+        assert!(matches!(report.files[0].1, FileStatus::Done(_)));
+
+        // 元のファイルと同じ名前（大文字・小文字の違いのみを含む）には保存しない
+        let upper = dir.join("UPPER.PNG");
+        RgbImage::from_pixel(4, 4, Rgb([0, 0, 0]))
+            .save(&upper)
+            .unwrap();
+        let original = std::fs::read(&upper).unwrap();
+        job.operation = BatchOperation::Export(ExportOptions {
+            format: export::ExportFormat::Png,
+            template: "{name}".to_string(),
+            ..Default::default()
+        });
+        job.files = vec![upper.clone()];
+        let report = job.run(None, &AtomicBool::new(false), |_, _| {});
+        assert!(matches!(report.files[0].1, FileStatus::Failed(_)));
+        assert_eq!(std::fs::read(&upper).unwrap(), original);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_rotate_records_history_over_quota() {
+        let dir = std::env::temp_dir().join(format!("vdi_batch_quota_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        // 圧縮の効かない約200KBの画像を8枚（合計が上限の1MBを超える）
+        let files: Vec<PathBuf> = (0..8u32)
+            .map(|i| {
+                let path = dir.join(format!("{}.png", i));
+                RgbImage::from_fn(256, 256, |x, y| {
+                    let v = (x * 7919 + y * 104729 + i * 1299709).wrapping_mul(2654435761);
+                    Rgb([(v >> 24) as u8, (v >> 16) as u8, (v >> 8) as u8])
+                })
+                .save(&path)
+                .unwrap();
+                path
+            })
+            .collect();
+        let originals: Vec<Vec<u8>> = files.iter().map(|p| std::fs::read(p).unwrap()).collect();
+
+        let history = EditHistory::new(dir.join("history"), 1);
+        let job = BatchJob {
+            operation: BatchOperation::Rotate {
+                angle: 90,
+                mode: RotationMode::Pixels,
+                jpeg_edges: EdgeMode::Reject,
+            },
+            files: files.clone(),
+            output_dir: None,
+            overwrite: false,
+        };
+        let report = job.run(Some(&history), &AtomicBool::new(false), |_, _| {});
+        assert_eq!(report.succeeded(), 8);
+
+        // 索引とスナップショットのファイルが一致し、残った履歴はすべて元に戻せる
+        let depths: usize = files.iter().map(|p| history.depth(p).0).sum();
+        assert!((1..8).contains(&depths));
+        let snapshot_files = std::fs::read_dir(dir.join("history"))
+            .unwrap()
+            .flatten()
+            .flat_map(|entry| std::fs::read_dir(entry.path()).unwrap().flatten())
+            .filter(|entry| entry.path().extension().is_some_and(|e| e == "bin"))
+            .count();
+        assert_eq!(snapshot_files, depths);
+        for (path, original) in files.iter().zip(&originals) {
+            if history.depth(path).0 == 1 {
+                history.undo(path, None).unwrap();
+                assert_eq!(&std::fs::read(path).unwrap(), original);
+            }
+        }
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,3 +1,5 @@
+use crate::letterbox;
+use crate::navigation::SortOrder;
 use serde::{Deserialize, Serialize};
 
 /// アプリケーション起動時のコマンドライン引数から取得する設定
@@ -26,6 +28,29 @@ pub struct LaunchConfig {
     // グリッド設定
     pub grid_pattern: Option<String>,
     pub grid_opacity: Option<f32>,
+
+    // ナビゲーション設定
+    pub sort_order: Option<SortOrder>,
+    pub sort_reverse: Option<bool>,
+    /// ウィンドウ表示用の引数の不正な値（該当する設定は保存済みの値のまま起動する）
+    pub errors: Vec<String>,
+
+    // 黒帯の一括処理（`--letterbox`を指定するとウィンドウを開かずに処理して終了する）
+    pub letterbox_requested: bool,
+    pub letterbox_aspect: Option<f32>,
+    pub letterbox_color: Option<[u8; 3]>,
+    pub letterbox_offset: Option<f32>,
+    /// 一括処理の引数の不正な値（一括処理では既定値で続行せずにエラーにする）
+    pub letterbox_errors: Vec<String>,
+    /// 一括処理の保存先フォルダ（未指定なら元の画像と同じフォルダ）
+    pub output_dir: Option<String>,
+    /// 一括処理で保存先に同名のファイルがある場合に上書きするか
+    pub overwrite: Option<bool>,
+    /// 引数で指定された既存のファイル・フォルダ（一括処理の対象）
+    pub input_paths: Vec<String>,
+
+    /// 開いた画像に適用して自動保存する3D LUT（.cube）のパス
+    pub auto_lut: Option<String>,
 }
 
 impl LaunchConfig {
@@ -41,18 +66,32 @@ impl LaunchConfig {
     /// - `--peaking-blink <true|false>`
     /// - `--grid-pattern <3x3|gold|4x4|8x8>`
     /// - `--grid-opacity <0.0-1.0>`
+    /// - `--sort <name|created|modified|size|exif>`
+    /// - `--sort-reverse <true|false>`
+    /// - `--letterbox <2.39|16:9|...>` （指定した場合は黒帯の一括処理を行う）
+    /// - `--letterbox-color <#RRGGBB|black|white>`
+    /// - `--letterbox-offset <-1.0-1.0>`
+    /// - `--output-dir <フォルダ>`
+    /// - `--overwrite <true|false>` （一括処理で既存のファイルを上書きする）
+    /// - `--auto-lut <.cubeファイル>` （開いた画像にLUTを適用し、元の画像の隣に保存する）
     ///
     /// # Returns
     ///
     /// パースされた設定を含む`LaunchConfig`を返します。
     /// 無効な値が指定された場合、その項目は`None`になります。
     pub fn from_args() -> Self {
-        let mut config = LaunchConfig::default();
         let args: Vec<String> = std::env::args().collect();
 
         // デバッグ用：受け取った引数をログ出力
         println!("[CLI Args] Received arguments: {:?}", args);
 
+        Self::parse(&args)
+    }
+
+    /// 引数の一覧（先頭は実行ファイル名）から`LaunchConfig`を生成
+    pub fn parse(args: &[String]) -> Self {
+        let mut config = LaunchConfig::default();
+
         // 位置引数の処理
         // 引数1: 画像パス（存在するファイルの場合のみ）
         if let Some(path) = args.get(1) {
@@ -152,7 +191,103 @@ impl LaunchConfig {
                         i += 1;
                     }
                 }
-                _ => i += 1,
+                "--sort" => {
+                    let value = args.get(i + 1).map(String::as_str).unwrap_or_default();
+                    // 有効な並び順のみ受け入れる
+                    config.sort_order = SortOrder::from_cli_name(value);
+                    if config.sort_order.is_none() {
+                        let names: Vec<&str> = SortOrder::ALL
+                            .iter()
+                            .map(|order| order.cli_name())
+                            .collect();
+                        config.errors.push(format!(
+                            "--sort の並び順が不正です（{}）: {}",
+                            names.join("|"),
+                            value
+                        ));
+                    }
+                    i += 2;
+                }
+                "--sort-reverse" => {
+                    if i + 1 < args.len() {
+                        config.sort_reverse = args[i + 1].parse().ok();
+                        i += 2;
+                    } else {
+                        i += 1;
+                    }
+                }
+                "--letterbox" => {
+                    config.letterbox_requested = true;
+                    let value = args.get(i + 1).map(String::as_str).unwrap_or_default();
+                    config.letterbox_aspect = letterbox::parse_aspect(value);
+                    if config.letterbox_aspect.is_none() {
+                        config.letterbox_errors.push(format!(
+                            "--letterbox の縦横比が不正です（{}〜{}）: {}",
+                            letterbox::ASPECT_RANGE.start(),
+                            letterbox::ASPECT_RANGE.end(),
+                            value
+                        ));
+                    }
+                    i += 2;
+                }
+                "--letterbox-color" => {
+                    let value = args.get(i + 1).map(String::as_str).unwrap_or_default();
+                    config.letterbox_color = letterbox::parse_color(value);
+                    if config.letterbox_color.is_none() {
+                        config
+                            .letterbox_errors
+                            .push(format!("--letterbox-color の色が不正です: {}", value));
+                    }
+                    i += 2;
+                }
+                "--letterbox-offset" => {
+                    let value = args.get(i + 1).map(String::as_str).unwrap_or_default();
+                    // -1.0-1.0の範囲でバリデーション
+                    config.letterbox_offset = value
+                        .parse::<f32>()
+                        .ok()
+                        .filter(|offset| (-1.0..=1.0).contains(offset));
+                    if config.letterbox_offset.is_none() {
+                        config.letterbox_errors.push(format!(
+                            "--letterbox-offset の位置が不正です（-1.0〜1.0）: {}",
+                            value
+                        ));
+                    }
+                    i += 2;
+                }
+                "--output-dir" => {
+                    if i + 1 < args.len() {
+                        config.output_dir = Some(args[i + 1].to_string());
+                        i += 2;
+                    } else {
+                        i += 1;
+                    }
+                }
+                "--overwrite" => {
+                    let value = args.get(i + 1).map(String::as_str).unwrap_or_default();
+                    config.overwrite = value.parse().ok();
+                    if config.overwrite.is_none() {
+                        config.letterbox_errors.push(format!(
+                            "--overwrite の値が不正です（true または false）: {}",
+                            value
+                        ));
+                    }
+                    i += 2;
+                }
+                "--auto-lut" => {
+                    if i + 1 < args.len() {
+                        config.auto_lut = Some(args[i + 1].to_string());
+                        i += 2;
+                    } else {
+                        i += 1;
+                    }
+                }
+                arg => {
+                    if !arg.starts_with("--") && std::path::Path::new(arg).exists() {
+                        config.input_paths.push(arg.to_string());
+                    }
+                    i += 1;
+                }
             }
         }
 
@@ -205,5 +340,104 @@ mod tests {
         assert!(config.peaking_blink.is_none());
         assert!(config.grid_pattern.is_none());
         assert!(config.grid_opacity.is_none());
+        assert!(config.sort_order.is_none());
+        assert!(config.sort_reverse.is_none());
+        assert!(config.errors.is_empty());
+        assert!(!config.letterbox_requested);
+        assert!(config.letterbox_aspect.is_none());
+        assert!(config.letterbox_errors.is_empty());
+        assert!(config.input_paths.is_empty());
+        assert!(config.auto_lut.is_none());
+    }
+
+    #[test]
+    fn test_parse_letterbox_batch() {
+        let dir = std::env::temp_dir();
+        let dir = dir.to_string_lossy().to_string();
+        let args: Vec<String> = [
+            "vdi",
+            "--letterbox",
+            "2.39:1",
+            "--letterbox-color",
+            "#102030",
+            "--letterbox-offset",
+            "2.0",
+            &dir,
+            "--output-dir",
+            "out",
+            "--overwrite",
+            "true",
+            "missing-file.jpg",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+
+        let config = LaunchConfig::parse(&args);
+        assert!(config.letterbox_requested);
+        assert_eq!(config.letterbox_aspect, Some(2.39));
+        assert_eq!(config.letterbox_color, Some([0x10, 0x20, 0x30]));
+        // 範囲外のオフセットは既定値にせず、エラーとして記録する
+        assert_eq!(config.letterbox_offset, None);
+        assert_eq!(config.letterbox_errors.len(), 1);
+        assert_eq!(config.output_dir.as_deref(), Some("out"));
+        assert_eq!(config.overwrite, Some(true));
+        // 存在しないパスとオプションの値は対象にしない
+        assert_eq!(config.input_paths, vec![dir]);
+
+        // 解釈できない縦横比でも一括処理として扱い、エラーにする
+        let args: Vec<String> = ["vdi", "--letterbox", "wide"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let config = LaunchConfig::parse(&args);
+        assert!(config.letterbox_requested);
+        assert_eq!(config.letterbox_aspect, None);
+        assert_eq!(config.letterbox_errors.len(), 1);
+
+        // 上書きの指定はtrue/falseのみ受け付ける
+        let args: Vec<String> = ["vdi", "--letterbox", "2:1", "--overwrite", "yes"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let config = LaunchConfig::parse(&args);
+        assert_eq!(config.overwrite, None);
+        assert_eq!(config.letterbox_errors.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_sort() {
+        let args: Vec<String> = ["vdi", "--sort", "EXIF"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let config = LaunchConfig::parse(&args);
+        assert_eq!(config.sort_order, Some(SortOrder::CaptureTime));
+        assert!(config.errors.is_empty());
+
+        // 不明な並び順は無視せず、指定できる値とともにエラーとして記録する
+        let args: Vec<String> = ["vdi", "--sort", "bogus"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let config = LaunchConfig::parse(&args);
+        assert_eq!(config.sort_order, None);
+        assert_eq!(config.errors.len(), 1);
+        assert!(config.errors[0].contains("name|created|modified|size|exif"));
+        assert!(config.errors[0].contains("bogus"));
+    }
+
+    #[test]
+    fn test_parse_auto_lut() {
+        let lut = std::env::temp_dir().to_string_lossy().to_string();
+        let args: Vec<String> = ["vdi", "--auto-lut", &lut]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let config = LaunchConfig::parse(&args);
+        assert_eq!(config.auto_lut.as_deref(), Some(lut.as_str()));
+        // LUTのパスは画像として扱わない
+        assert!(config.input_paths.is_empty());
     }
 }
@@ -0,0 +1,209 @@
+use image::{DynamicImage, RgbaImage};
+use rayon::prelude::*;
+use std::sync::Arc;
+
+/// 並列に色変換する単位（ピクセル数）
+const CONVERT_CHUNK_PIXELS: usize = 16 * 1024;
+
+/// カラーマネジメントの設定
+#[derive(Clone, Default)]
+pub struct ColorSettings {
+    /// 埋め込みICCプロファイルを表示用に変換するかどうか
+    pub enabled: bool,
+    /// 表示プロファイル（`None`はsRGB）
+    pub display_icc: Option<Arc<Vec<u8>>>,
+}
+
+impl ColorSettings {
+    /// 画像のICCプロファイルから表示用の色変換を作成する
+    ///
+    /// 変換が不要な場合（無効・プロファイルが無いsRGB画像をsRGBで表示する）は`None`を返します。
+    pub fn converter_for(&self, source_icc: Option<&[u8]>) -> Option<ColorConverter> {
+        if !self.enabled {
+            return None;
+        }
+        ColorConverter::new(
+            source_icc,
+            self.display_icc.as_deref().map(|icc| icc.as_slice()),
+        )
+    }
+}
+
+/// 表示用の色変換（埋め込みプロファイル → sRGBまたは表示プロファイル）
+pub struct ColorConverter {
+    transform: qcms::Transform,
+}
+
+impl ColorConverter {
+    /// 色変換を作成する
+    ///
+    /// プロファイルが無い画像はsRGBとして扱います。RGB以外（グレー・CMYK）のプロファイルや
+    /// 解析できないプロファイルは変換しません。
+    ///
+    /// # Arguments
+    ///
+    /// * `source_icc` - 画像に埋め込まれたICCプロファイル
+    /// * `display_icc` - 表示プロファイル（`None`はsRGB）
+    ///
+    /// # Returns
+    ///
+    /// * `Some(ColorConverter)` - 色変換
+    /// * `None` - 変換が不要、または作成できない場合
+    pub fn new(source_icc: Option<&[u8]>, display_icc: Option<&[u8]>) -> Option<Self> {
+        let source_icc = source_icc.filter(|icc| !is_srgb_profile(icc));
+        let display_icc = display_icc.filter(|icc| !is_srgb_profile(icc));
+        if source_icc.is_none() && display_icc.is_none() {
+            return None;
+        }
+
+        let input = match source_icc {
+            Some(icc) if is_rgb_profile(icc) => qcms::Profile::new_from_slice(icc, false)?,
+            Some(_) => return None,
+            None => qcms::Profile::new_sRGB(),
+        };
+        let mut output = match display_icc {
+            Some(icc) if is_rgb_profile(icc) => qcms::Profile::new_from_slice(icc, false)?,
+            Some(_) => return None,
+            None => qcms::Profile::new_sRGB(),
+        };
+        output.precache_output_transform();
+
+        let transform = qcms::Transform::new(
+            &input,
+            &output,
+            qcms::DataType::RGBA8,
+            qcms::Intent::default(),
+        )?;
+        Some(Self { transform })
+    }
+
+    /// 8bit RGBA画像を変換する（アルファは変更しない）
+    pub fn apply(&self, rgba: &mut RgbaImage) {
+        rgba.par_chunks_mut(CONVERT_CHUNK_PIXELS * 4)
+            .for_each(|chunk| self.transform.apply(chunk));
+    }
+
+    /// 画像を表示色空間の8bit RGBA画像に変換する（ヒストグラムの表示空間モード用）
+    pub fn to_display_space(&self, img: &DynamicImage) -> DynamicImage {
+        let mut rgba = img.to_rgba8();
+        self.apply(&mut rgba);
+        DynamicImage::ImageRgba8(rgba)
+    }
+}
+
+/// ICCプロファイルの色空間がRGBかどうか（ヘッダーの色空間シグネチャ）
+fn is_rgb_profile(icc: &[u8]) -> bool {
+    icc.get(16..20) == Some(b"RGB ")
+}
+
+/// sRGBのプロファイルかどうか（変換を省略するため、説明文で判定する）
+fn is_srgb_profile(icc: &[u8]) -> bool {
+    profile_description(icc)
+        .map(|desc| desc.starts_with("sRGB"))
+        .unwrap_or(false)
+}
+
+/// ICCプロファイルの説明文（`desc`タグ）を読み込む
+///
+/// ICC v2の`desc`型とICC v4の`mluc`型（最初のレコード）に対応します。
+pub fn profile_description(icc: &[u8]) -> Option<String> {
+    let read_u32 = |pos: usize| -> Option<usize> {
+        Some(u32::from_be_bytes(icc.get(pos..pos + 4)?.try_into().ok()?) as usize)
+    };
+
+    // 不正なヘッダーで膨大な数を走査しないよう、ファイルに収まるタグ数までに制限する
+    let tag_count = read_u32(128)?.min(icc.len().saturating_sub(132) / 12);
+    let (offset, size) = (0..tag_count).find_map(|i| {
+        let entry = 132 + i * 12;
+        if icc.get(entry..entry + 4)? == b"desc" {
+            Some((read_u32(entry + 4)?, read_u32(entry + 8)?))
+        } else {
+            None
+        }
+    })?;
+    let tag = icc.get(offset..offset.checked_add(size)?)?;
+
+    let text = match tag.get(0..4)? {
+        b"desc" => {
+            let len = read_u32(offset + 8)?;
+            let ascii = tag.get(12..12 + len)?;
+            String::from_utf8_lossy(ascii)
+                .trim_end_matches('\0')
+                .to_string()
+        }
+        b"mluc" => {
+            let record_len = read_u32(offset + 20)?;
+            let record_offset = read_u32(offset + 24)?;
+            let utf16: Vec<u16> = tag
+                .get(record_offset..record_offset + record_len)?
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect();
+            String::from_utf16_lossy(&utf16)
+                .trim_end_matches('\0')
+                .to_string()
+        }
+        _ => return None,
+    };
+    let text = text.trim().to_string();
+    (!text.is_empty()).then_some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `desc`タグのみを持つICCプロファイル（v2形式）を作成する
+    fn profile_with_description(color_space: &[u8; 4], description: &str) -> Vec<u8> {
+        let mut icc = vec![0u8; 128];
+        icc[16..20].copy_from_slice(color_space);
+        icc.extend_from_slice(&1u32.to_be_bytes());
+        let tag_offset = 128 + 4 + 12;
+        let mut tag = b"desc\0\0\0\0".to_vec();
+        tag.extend_from_slice(&(description.len() as u32 + 1).to_be_bytes());
+        tag.extend_from_slice(description.as_bytes());
+        tag.push(0);
+
+        icc.extend_from_slice(b"desc");
+        icc.extend_from_slice(&(tag_offset as u32).to_be_bytes());
+        icc.extend_from_slice(&(tag.len() as u32).to_be_bytes());
+        icc.extend_from_slice(&tag);
+        icc
+    }
+
+    #[test]
+    fn test_profile_description() {
+        let icc = profile_with_description(b"RGB ", "Adobe RGB (1998)");
+        assert_eq!(
+            profile_description(&icc).as_deref(),
+            Some("Adobe RGB (1998)")
+        );
+        assert!(is_rgb_profile(&icc));
+        assert_eq!(profile_description(&[0u8; 64]), None);
+
+        // タグ数が実際の大きさを超えていても、ファイル内のタグだけを調べる
+        let mut broken = icc.clone();
+        broken[128..132].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert_eq!(
+            profile_description(&broken).as_deref(),
+            Some("Adobe RGB (1998)")
+        );
+        broken[132..136].copy_from_slice(b"cprt");
+        assert_eq!(profile_description(&broken), None);
+    }
+
+    #[test]
+    fn test_srgb_needs_no_conversion() {
+        let srgb = profile_with_description(b"RGB ", "sRGB IEC61966-2.1");
+        assert!(ColorConverter::new(None, None).is_none());
+        assert!(ColorConverter::new(Some(&srgb), None).is_none());
+
+        let disabled = ColorSettings {
+            enabled: false,
+            display_icc: None,
+        };
+        let gray = profile_with_description(b"GRAY", "Gray Gamma 2.2");
+        assert!(disabled.converter_for(Some(&gray)).is_none());
+        assert!(ColorConverter::new(Some(&gray), None).is_none());
+    }
+}
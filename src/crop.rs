@@ -0,0 +1,324 @@
+/// 切り抜き範囲の最小サイズ（画素）
+pub const MIN_SIZE: f32 = 8.0;
+
+/// 切り抜きの縦横比のプリセット
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AspectPreset {
+    Free,
+    Square,
+    Portrait4x5,
+    Photo3x2,
+    Wide16x9,
+    Cinema239,
+    /// 左右に並べたVR180（片目1:1、全体2:1）
+    VrSideBySide,
+    /// 上下に並べたVR360（片目2:1、全体1:1）
+    VrOverUnder,
+}
+
+impl AspectPreset {
+    pub const ALL: [AspectPreset; 8] = [
+        AspectPreset::Free,
+        AspectPreset::Square,
+        AspectPreset::Portrait4x5,
+        AspectPreset::Photo3x2,
+        AspectPreset::Wide16x9,
+        AspectPreset::Cinema239,
+        AspectPreset::VrSideBySide,
+        AspectPreset::VrOverUnder,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            AspectPreset::Free => "自由",
+            AspectPreset::Square => "1:1",
+            AspectPreset::Portrait4x5 => "4:5",
+            AspectPreset::Photo3x2 => "3:2",
+            AspectPreset::Wide16x9 => "16:9",
+            AspectPreset::Cinema239 => "2.39:1",
+            AspectPreset::VrSideBySide => "VR 左右 (片目 1:1)",
+            AspectPreset::VrOverUnder => "VR 上下 (片目 2:1)",
+        }
+    }
+
+    /// 画像全体の縦横比（幅÷高さ、自由なら`None`）
+    pub fn ratio(self) -> Option<f32> {
+        match self {
+            AspectPreset::Free => None,
+            AspectPreset::Square => Some(1.0),
+            AspectPreset::Portrait4x5 => Some(4.0 / 5.0),
+            AspectPreset::Photo3x2 => Some(3.0 / 2.0),
+            AspectPreset::Wide16x9 => Some(16.0 / 9.0),
+            AspectPreset::Cinema239 => Some(2.39),
+            AspectPreset::VrSideBySide => Some(2.0),
+            AspectPreset::VrOverUnder => Some(1.0),
+        }
+    }
+}
+
+/// 切り抜き範囲をドラッグするつまみ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Handle {
+    Move,
+    Left,
+    Right,
+    Top,
+    Bottom,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Handle {
+    /// 辺・角のつまみ（当たり判定は角を優先する）
+    pub const EDGES: [Handle; 8] = [
+        Handle::TopLeft,
+        Handle::TopRight,
+        Handle::BottomLeft,
+        Handle::BottomRight,
+        Handle::Left,
+        Handle::Right,
+        Handle::Top,
+        Handle::Bottom,
+    ];
+
+    /// 動かす辺の向き（左・上が-1、右・下が1、動かさない軸は0）
+    pub fn direction(self) -> (i8, i8) {
+        match self {
+            Handle::Move => (0, 0),
+            Handle::Left => (-1, 0),
+            Handle::Right => (1, 0),
+            Handle::Top => (0, -1),
+            Handle::Bottom => (0, 1),
+            Handle::TopLeft => (-1, -1),
+            Handle::TopRight => (1, -1),
+            Handle::BottomLeft => (-1, 1),
+            Handle::BottomRight => (1, 1),
+        }
+    }
+}
+
+/// 画像の画素座標での切り抜き範囲（表示中の向きを適用した画像が基準）
+///
+/// 画素座標で保持するため、ズームやパンを変えても範囲は変わりません。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CropRect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl CropRect {
+    /// 画像全体を覆う範囲
+    pub fn full(width: u32, height: u32) -> Self {
+        Self {
+            x: 0.0,
+            y: 0.0,
+            width: width as f32,
+            height: height as f32,
+        }
+    }
+
+    fn from_edges(left: f32, top: f32, right: f32, bottom: f32) -> Self {
+        Self {
+            x: left,
+            y: top,
+            width: right - left,
+            height: bottom - top,
+        }
+    }
+
+    /// 中心を保ったまま、範囲に収まる最大の指定縦横比の範囲に変える
+    pub fn fit_aspect(self, aspect: Option<f32>) -> Self {
+        let Some(aspect) = aspect else {
+            return self;
+        };
+        let width = self.width.min(self.height * aspect);
+        let height = width / aspect;
+        Self {
+            x: self.x + (self.width - width) / 2.0,
+            y: self.y + (self.height - height) / 2.0,
+            width,
+            height,
+        }
+    }
+
+    /// つまみのドラッグを反映した範囲を返す
+    ///
+    /// # Arguments
+    ///
+    /// * `handle` - ドラッグしているつまみ
+    /// * `delta` - ドラッグ量（画素）
+    /// * `aspect` - 固定する縦横比（幅÷高さ、自由なら`None`）
+    /// * `bounds` - 画像のサイズ（範囲はこの内側に収める）
+    pub fn drag(
+        self,
+        handle: Handle,
+        delta: (f32, f32),
+        aspect: Option<f32>,
+        bounds: (f32, f32),
+    ) -> Self {
+        let (bound_w, bound_h) = bounds;
+        if handle == Handle::Move {
+            return Self {
+                x: (self.x + delta.0).clamp(0.0, (bound_w - self.width).max(0.0)),
+                y: (self.y + delta.1).clamp(0.0, (bound_h - self.height).max(0.0)),
+                ..self
+            };
+        }
+
+        let (dir_x, dir_y) = handle.direction();
+        let (mut left, mut top) = (self.x, self.y);
+        let (mut right, mut bottom) = (self.x + self.width, self.y + self.height);
+        // 最小サイズより小さい画像・範囲でも、下限が上限を超えないようにする
+        match dir_x {
+            -1 => left = (left + delta.0).clamp(0.0, (right - MIN_SIZE).max(0.0)),
+            1 => right = (right + delta.0).clamp((left + MIN_SIZE).min(bound_w), bound_w),
+            _ => {}
+        }
+        match dir_y {
+            -1 => top = (top + delta.1).clamp(0.0, (bottom - MIN_SIZE).max(0.0)),
+            1 => bottom = (bottom + delta.1).clamp((top + MIN_SIZE).min(bound_h), bound_h),
+            _ => {}
+        }
+        let rect = Self::from_edges(left, top, right, bottom);
+        match aspect {
+            Some(aspect) => rect.constrain(dir_x, dir_y, aspect, bounds),
+            None => rect,
+        }
+    }
+
+    /// 動かした辺の反対側を固定して、縦横比を合わせる
+    ///
+    /// 動かさない軸は中心を固定し、画像からはみ出す場合は縦横比を保って縮めます。
+    fn constrain(self, dir_x: i8, dir_y: i8, aspect: f32, bounds: (f32, f32)) -> Self {
+        let (mut width, mut height) = (self.width, self.height);
+        if dir_x != 0 && dir_y != 0 {
+            // 角は大きい方の辺に合わせる
+            if width / aspect >= height {
+                height = width / aspect;
+            } else {
+                width = height * aspect;
+            }
+        } else if dir_x != 0 {
+            height = width / aspect;
+        } else {
+            width = height * aspect;
+        }
+
+        // 固定する位置と、その位置から広げられる最大のサイズ
+        let axis = |dir: i8, start: f32, size: f32, bound: f32| match dir {
+            -1 => (start + size, start + size),
+            1 => (start, bound - start),
+            _ => {
+                let center = start + size / 2.0;
+                (center, 2.0 * center.min(bound - center))
+            }
+        };
+        let (anchor_x, max_w) = axis(dir_x, self.x, self.width, bounds.0);
+        let (anchor_y, max_h) = axis(dir_y, self.y, self.height, bounds.1);
+        let scale = (max_w / width).min(max_h / height).min(1.0);
+        width *= scale;
+        height *= scale;
+
+        let place = |dir: i8, anchor: f32, size: f32| match dir {
+            -1 => anchor - size,
+            1 => anchor,
+            _ => anchor - size / 2.0,
+        };
+        Self {
+            x: place(dir_x, anchor_x, width),
+            y: place(dir_y, anchor_y, height),
+            width,
+            height,
+        }
+    }
+
+    /// 画像の範囲に収めた整数の画素範囲（x, y, 幅, 高さ）
+    pub fn to_pixels(self, bounds: (u32, u32)) -> (u32, u32, u32, u32) {
+        let x = (self.x.round().max(0.0) as u32).min(bounds.0.saturating_sub(1));
+        let y = (self.y.round().max(0.0) as u32).min(bounds.1.saturating_sub(1));
+        let width = (self.width.round() as u32).clamp(1, bounds.0 - x);
+        let height = (self.height.round() as u32).clamp(1, bounds.1 - y);
+        (x, y, width, height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn approx(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-3
+    }
+
+    #[test]
+    fn test_drag_stays_inside_image() {
+        let rect = CropRect::full(400, 300);
+        // 画像の外へは広がらない
+        let grown = rect.drag(Handle::BottomRight, (50.0, 50.0), None, (400.0, 300.0));
+        assert_eq!(grown, rect);
+        // 移動も画像の内側に収まる
+        let small = rect.drag(Handle::TopLeft, (100.0, 100.0), None, (400.0, 300.0));
+        assert_eq!(small.width, 300.0);
+        let moved = small.drag(Handle::Move, (500.0, -500.0), None, (400.0, 300.0));
+        assert_eq!((moved.x, moved.y), (100.0, 0.0));
+    }
+
+    #[test]
+    fn test_drag_on_tiny_image() {
+        // 最小サイズより小さい画像でも、どのつまみを動かしても範囲は画像の内側に収まる
+        let bounds = (4.0, 4.0);
+        for aspect in [None, Some(1.0), Some(16.0 / 9.0)] {
+            for handle in Handle::EDGES {
+                for delta in [(-3.0, -3.0), (3.0, 3.0), (-3.0, 3.0)] {
+                    let rect = CropRect::full(4, 4).drag(handle, delta, aspect, bounds);
+                    assert!(rect.x >= 0.0 && rect.y >= 0.0, "{:?}", handle);
+                    assert!(rect.x + rect.width <= 4.0 + 1e-3, "{:?}", handle);
+                    assert!(rect.y + rect.height <= 4.0 + 1e-3, "{:?}", handle);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_drag_keeps_aspect() {
+        let bounds = (400.0, 300.0);
+        let rect = CropRect::full(400, 300).fit_aspect(Some(1.0));
+        assert_eq!(
+            rect,
+            CropRect {
+                x: 50.0,
+                y: 0.0,
+                width: 300.0,
+                height: 300.0
+            }
+        );
+
+        // 角は反対側の角を固定して縮む
+        let corner = rect.drag(Handle::BottomRight, (-100.0, -20.0), Some(1.0), bounds);
+        assert!(approx(corner.width, corner.height));
+        assert_eq!((corner.x, corner.y), (50.0, 0.0));
+        assert!(approx(corner.width, 280.0));
+
+        // 辺は反対側の辺と中心を固定し、はみ出す分は縮める
+        let wide = corner.drag(Handle::Right, (200.0, 0.0), Some(16.0 / 9.0), bounds);
+        assert!(approx(wide.width / wide.height, 16.0 / 9.0));
+        assert_eq!(wide.x, 50.0);
+        assert!(wide.x + wide.width <= 400.0 + 1e-3);
+        assert!(wide.y >= 0.0 && wide.y + wide.height <= 300.0 + 1e-3);
+    }
+
+    #[test]
+    fn test_to_pixels_clamps() {
+        let rect = CropRect {
+            x: -0.4,
+            y: 10.6,
+            width: 500.0,
+            height: 20.2,
+        };
+        assert_eq!(rect.to_pixels((400, 300)), (0, 11, 400, 20));
+    }
+}
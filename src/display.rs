@@ -0,0 +1,340 @@
+use crate::lut;
+use image::{DynamicImage, ImageBuffer, RgbaImage};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// 整数形式の画像を線形値に戻す際のガンマ（表示ガンマの既定値と同じ）
+const SOURCE_GAMMA: f32 = 2.2;
+
+/// 表示用のトーンマッピング方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum ToneMap {
+    /// 1.0を超える値を切り捨てる
+    #[default]
+    Clip,
+    /// Reinhard（x / (1 + x)）
+    Reinhard,
+    /// ACES Filmic（Narkowiczによる近似）
+    Aces,
+}
+
+impl ToneMap {
+    /// 線形値にトーンマッピングを適用する
+    fn apply(self, x: f32) -> f32 {
+        match self {
+            ToneMap::Clip => x,
+            ToneMap::Reinhard => x / (1.0 + x),
+            ToneMap::Aces => (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14),
+        }
+    }
+}
+
+/// 表示時のみ適用する変換（露出・コントラスト・ホワイトバランス・彩度・ガンマ・トーンマッピング）
+///
+/// 元画像のビット深度（16bit・浮動小数点）は保持したまま、テクスチャ生成時にのみ適用します。
+/// 保存する場合は`bake`で表示と同じ見た目の画像を作成します。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DisplayTransform {
+    /// 露出補正（EV）
+    pub exposure: f32,
+    /// コントラスト（-1.0〜1.0、0.0で変更なし）
+    pub contrast: f32,
+    /// 色温度（-1.0で青寄り、1.0で黄寄り、単位はEV）
+    pub temperature: f32,
+    /// 色かぶり補正（-1.0で緑寄り、1.0でマゼンタ寄り、単位はEV）
+    pub tint: f32,
+    /// 彩度（0.0でモノクロ、1.0で変更なし）
+    pub saturation: f32,
+    /// 表示ガンマ
+    pub gamma: f32,
+    /// トーンマッピング方式
+    pub tone_map: ToneMap,
+}
+
+impl Default for DisplayTransform {
+    fn default() -> Self {
+        Self {
+            exposure: 0.0,
+            contrast: 0.0,
+            temperature: 0.0,
+            tint: 0.0,
+            saturation: 1.0,
+            gamma: SOURCE_GAMMA,
+            tone_map: ToneMap::Clip,
+        }
+    }
+}
+
+impl DisplayTransform {
+    /// 整数形式の画像をそのまま表示できる（変換が不要な）設定かどうか
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// 露出・コントラスト・ホワイトバランス・彩度を変更しているか（ガンマ・トーンマッピングを除く）
+    pub fn is_adjusted(&self) -> bool {
+        let default = Self::default();
+        Self {
+            gamma: default.gamma,
+            tone_map: default.tone_map,
+            ..*self
+        } != default
+    }
+
+    /// 各チャンネルの線形値に掛ける係数（露出とホワイトバランス）
+    fn channel_gains(&self) -> [f32; 3] {
+        let gain = 2f32.powf(self.exposure);
+        [
+            gain * 2f32.powf(self.temperature / 2.0),
+            gain * 2f32.powf(-self.tint / 2.0),
+            gain * 2f32.powf(-self.temperature / 2.0),
+        ]
+    }
+
+    /// 線形値を表示用の値（0.0〜1.0、表示ガンマで符号化済み）に変換する
+    fn encode(&self, linear: f32, gain: f32) -> f32 {
+        let mapped = self
+            .tone_map
+            .apply((linear * gain).max(0.0))
+            .clamp(0.0, 1.0);
+        // コントラストは中間調を中心に傾きを変える
+        ((mapped.powf(1.0 / self.gamma) - 0.5) * (1.0 + self.contrast) + 0.5).clamp(0.0, 1.0)
+    }
+
+    /// 表示用の値に彩度を適用する（輝度を保って色差を拡大・縮小する）
+    fn saturate(&self, [r, g, b]: [f32; 3]) -> [f32; 3] {
+        if self.saturation == 1.0 {
+            return [r, g, b];
+        }
+        let luma = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+        [r, g, b].map(|c| (luma + (c - luma) * self.saturation).clamp(0.0, 1.0))
+    }
+
+    /// 整数形式の各値に対する表示用の値の表（チャンネルごと）
+    fn tables(&self, max: u32) -> [Vec<f32>; 3] {
+        self.channel_gains().map(|gain| {
+            (0..=max)
+                .map(|v| self.encode((v as f32 / max as f32).powf(SOURCE_GAMMA), gain))
+                .collect()
+        })
+    }
+
+    /// RGBAの画素を並べたバッファの各画素に変換を適用する
+    ///
+    /// `channel`はチャンネル番号と元の値から表示用の値を、`alpha`はアルファを、
+    /// `output`は表示用の値から出力の値を求めます。
+    fn map_pixels<S: Copy + Sync, T: Copy + Send>(
+        &self,
+        src: &[S],
+        channel: impl Fn(usize, S) -> f32 + Sync,
+        alpha: impl Fn(S) -> T + Sync,
+        output: impl Fn(f32) -> T + Sync,
+    ) -> Vec<T> {
+        src.par_chunks(4)
+            .flat_map_iter(|px| {
+                let [r, g, b] =
+                    self.saturate([channel(0, px[0]), channel(1, px[1]), channel(2, px[2])]);
+                [output(r), output(g), output(b), alpha(px[3])]
+            })
+            .collect()
+    }
+
+    /// 表示用の8bit RGBA画像を作成する
+    ///
+    /// 整数形式（8bit/16bit）はガンマ2.2で符号化されているものとして線形値に戻し、
+    /// 浮動小数点形式（EXR・Radiance HDR）は線形値としてそのまま扱います。
+    pub fn render(&self, img: &DynamicImage) -> RgbaImage {
+        let to_u8 = |v: f32| (v * 255.0).round() as u8;
+        let (width, height) = (img.width(), img.height());
+        let pixels = match img {
+            DynamicImage::ImageLuma8(_)
+            | DynamicImage::ImageLumaA8(_)
+            | DynamicImage::ImageRgb8(_)
+            | DynamicImage::ImageRgba8(_) => {
+                let rgba = img.to_rgba8();
+                if self.is_identity() {
+                    return rgba;
+                }
+                let tables = self.tables(u8::MAX as u32);
+                self.map_pixels(rgba.as_raw(), |c, v| tables[c][v as usize], |a| a, to_u8)
+            }
+            DynamicImage::ImageLuma16(_)
+            | DynamicImage::ImageLumaA16(_)
+            | DynamicImage::ImageRgb16(_)
+            | DynamicImage::ImageRgba16(_) => {
+                if self.is_identity() {
+                    return img.to_rgba8();
+                }
+                let tables = self.tables(u16::MAX as u32);
+                self.map_pixels(
+                    img.to_rgba16().as_raw(),
+                    |c, v| tables[c][v as usize],
+                    |a| (a >> 8) as u8,
+                    to_u8,
+                )
+            }
+            _ => {
+                let gains = self.channel_gains();
+                self.map_pixels(
+                    img.to_rgba32f().as_raw(),
+                    |c, v| self.encode(v, gains[c]),
+                    |a| to_u8(a.clamp(0.0, 1.0)),
+                    to_u8,
+                )
+            }
+        };
+        RgbaImage::from_raw(width, height, pixels).unwrap_or_default()
+    }
+
+    /// 表示と同じ見た目の画像を作成する（保存用）
+    ///
+    /// 既定の表示変換で表示したときに、現在の表示と同じになる画像を返します。
+    /// 整数形式は元のビット深度で、浮動小数点形式は線形値のまま保存できる形にします。
+    /// アルファの有無は元の画像を引き継ぎ、グレースケールはRGBになります。
+    pub fn bake(&self, img: &DynamicImage) -> DynamicImage {
+        let (width, height) = (img.width(), img.height());
+        let baked = match img {
+            DynamicImage::ImageLuma8(_)
+            | DynamicImage::ImageLumaA8(_)
+            | DynamicImage::ImageRgb8(_)
+            | DynamicImage::ImageRgba8(_) => DynamicImage::ImageRgba8(self.render(img)),
+            DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => {
+                let gains = self.channel_gains();
+                let pixels = self.map_pixels(
+                    img.to_rgba32f().as_raw(),
+                    |c, v| self.encode(v, gains[c]),
+                    |a| a,
+                    |v| v.powf(SOURCE_GAMMA),
+                );
+                DynamicImage::ImageRgba32F(ImageBuffer::from_raw(width, height, pixels).unwrap())
+            }
+            _ => {
+                let tables = self.tables(u16::MAX as u32);
+                let pixels = self.map_pixels(
+                    img.to_rgba16().as_raw(),
+                    |c, v| tables[c][v as usize],
+                    |a| a,
+                    |v| (v * 65535.0).round() as u16,
+                );
+                DynamicImage::ImageRgba16(ImageBuffer::from_raw(width, height, pixels).unwrap())
+            }
+        };
+        lut::restore_alpha(baked, img)
+    }
+}
+
+/// 画像の1チャンネルあたりのビット深度を表す表示用の文字列
+pub fn bit_depth_label(img: &DynamicImage) -> &'static str {
+    match img {
+        DynamicImage::ImageLuma16(_)
+        | DynamicImage::ImageLumaA16(_)
+        | DynamicImage::ImageRgb16(_)
+        | DynamicImage::ImageRgba16(_) => "16bit",
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => "32bit float",
+        _ => "8bit",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, Rgb32FImage, RgbImage};
+
+    #[test]
+    fn test_default_transform_keeps_8bit_pixels() {
+        let img = DynamicImage::ImageRgb8(RgbImage::from_fn(16, 16, |x, y| {
+            Rgb([(x * 16) as u8, (y * 16) as u8, 200])
+        }));
+        assert_eq!(DisplayTransform::default().render(&img), img.to_rgba8());
+    }
+
+    #[test]
+    fn test_exposure_doubles_linear_value() {
+        // 線形0.25に+1EVで0.5（ガンマ2.2で約186）
+        let img = DynamicImage::ImageRgb32F(Rgb32FImage::from_pixel(1, 1, Rgb([0.25; 3])));
+        let transform = DisplayTransform {
+            exposure: 1.0,
+            ..Default::default()
+        };
+        let expected = (0.5f32.powf(1.0 / 2.2) * 255.0).round() as u8;
+        assert_eq!(transform.render(&img).get_pixel(0, 0)[0], expected);
+    }
+
+    #[test]
+    fn test_tone_mapping_keeps_highlight_detail() {
+        // 1.0を超える2つの値はClipでは区別できないが、トーンマッピングでは区別できる
+        let img = DynamicImage::ImageRgb32F(Rgb32FImage::from_fn(2, 1, |x, _| {
+            Rgb([2.0 + x as f32 * 4.0; 3])
+        }));
+
+        let clipped = DisplayTransform::default().render(&img);
+        assert_eq!(clipped.get_pixel(0, 0), clipped.get_pixel(1, 0));
+
+        for tone_map in [ToneMap::Reinhard, ToneMap::Aces] {
+            let transform = DisplayTransform {
+                tone_map,
+                ..Default::default()
+            };
+            let mapped = transform.render(&img);
+            assert!(
+                mapped.get_pixel(0, 0)[0] < mapped.get_pixel(1, 0)[0],
+                "{:?}でハイライトの階調が残るべき",
+                tone_map
+            );
+        }
+    }
+
+    #[test]
+    fn test_white_balance_and_saturation() {
+        let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(1, 1, Rgb([128, 128, 128])));
+
+        // 色温度を上げると赤が増えて青が減る
+        let warm = DisplayTransform {
+            temperature: 0.5,
+            ..Default::default()
+        };
+        let [r, g, b, _] = warm.render(&img).get_pixel(0, 0).0;
+        assert!(r > g && g > b, "{:?}", (r, g, b));
+
+        // 彩度0は輝度を保ったグレー
+        let color = DynamicImage::ImageRgb8(RgbImage::from_pixel(1, 1, Rgb([200, 40, 90])));
+        let mono = DisplayTransform {
+            saturation: 0.0,
+            ..Default::default()
+        };
+        let [r, g, b, _] = mono.render(&color).get_pixel(0, 0).0;
+        assert!(r == g && g == b);
+        assert!(mono.is_adjusted());
+        assert!(!DisplayTransform::default().is_adjusted());
+    }
+
+    #[test]
+    fn test_bake_matches_display() {
+        let transform = DisplayTransform {
+            exposure: 0.7,
+            contrast: 0.3,
+            tint: -0.2,
+            ..Default::default()
+        };
+        let img = DynamicImage::ImageRgb16(image::ImageBuffer::from_fn(8, 8, |x, y| {
+            Rgb([x as u16 * 8000, y as u16 * 8000, 30000])
+        }));
+
+        // 焼き込んだ画像を既定の表示変換で表示すると、調整中の表示と同じになる
+        let baked = transform.bake(&img);
+        assert_eq!(baked.color(), image::ColorType::Rgb16);
+        assert_eq!(
+            DisplayTransform::default().render(&baked),
+            transform.render(&img)
+        );
+
+        // 浮動小数点は線形値のまま保存する
+        let hdr = DynamicImage::ImageRgb32F(Rgb32FImage::from_pixel(1, 1, Rgb([0.25; 3])));
+        let baked = transform.bake(&hdr);
+        assert_eq!(baked.color(), image::ColorType::Rgb32F);
+        assert_eq!(
+            DisplayTransform::default().render(&baked),
+            transform.render(&hdr)
+        );
+    }
+}
@@ -0,0 +1,448 @@
+use crate::display::DisplayTransform;
+use crate::img;
+use crate::lut;
+use crate::metadata::Metadata;
+use crate::orientation::Orientation;
+use crate::safe_write;
+use crate::straighten;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// 書き出し形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExportFormat {
+    Png,
+    Jpeg,
+    WebP,
+    Tiff,
+    Bmp,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 5] = [
+        ExportFormat::Png,
+        ExportFormat::Jpeg,
+        ExportFormat::WebP,
+        ExportFormat::Tiff,
+        ExportFormat::Bmp,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ExportFormat::Png => "PNG",
+            ExportFormat::Jpeg => "JPEG",
+            ExportFormat::WebP => "WebP (可逆)",
+            ExportFormat::Tiff => "TIFF",
+            ExportFormat::Bmp => "BMP",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Png => "png",
+            ExportFormat::Jpeg => "jpg",
+            ExportFormat::WebP => "webp",
+            ExportFormat::Tiff => "tif",
+            ExportFormat::Bmp => "bmp",
+        }
+    }
+
+    fn image_format(self) -> ImageFormat {
+        match self {
+            ExportFormat::Png => ImageFormat::Png,
+            ExportFormat::Jpeg => ImageFormat::Jpeg,
+            ExportFormat::WebP => ImageFormat::WebP,
+            ExportFormat::Tiff => ImageFormat::Tiff,
+            ExportFormat::Bmp => ImageFormat::Bmp,
+        }
+    }
+}
+
+/// 縮小時の補間方法
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ResizeFilter {
+    Nearest,
+    Bilinear,
+    Bicubic,
+    Lanczos3,
+}
+
+impl ResizeFilter {
+    pub const ALL: [ResizeFilter; 4] = [
+        ResizeFilter::Nearest,
+        ResizeFilter::Bilinear,
+        ResizeFilter::Bicubic,
+        ResizeFilter::Lanczos3,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ResizeFilter::Nearest => "ニアレストネイバー",
+            ResizeFilter::Bilinear => "バイリニア",
+            ResizeFilter::Bicubic => "バイキュービック",
+            ResizeFilter::Lanczos3 => "Lanczos3",
+        }
+    }
+
+    fn filter_type(self) -> FilterType {
+        match self {
+            ResizeFilter::Nearest => FilterType::Nearest,
+            ResizeFilter::Bilinear => FilterType::Triangle,
+            ResizeFilter::Bicubic => FilterType::CatmullRom,
+            ResizeFilter::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+/// 書き出すメタデータ
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MetadataMode {
+    /// すべて引き継ぐ
+    Keep,
+    /// 位置情報（GPS）のみ取り除く
+    StripGps,
+    /// ICCプロファイル以外を取り除く（色が変わらないようICCは残す）
+    Strip,
+}
+
+/// 書き出しの設定
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExportOptions {
+    pub format: ExportFormat,
+    /// JPEGの品質（1〜100）
+    pub jpeg_quality: u8,
+    /// 長辺をこのサイズ以下に縮小する（`None`なら元のサイズ）
+    pub long_edge: Option<u32>,
+    pub filter: ResizeFilter,
+    pub metadata: MetadataMode,
+    /// ファイル名のテンプレート（拡張子を除く、`TEMPLATE_TOKENS`を置き換える）
+    pub template: String,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            format: ExportFormat::Jpeg,
+            jpeg_quality: 90,
+            long_edge: None,
+            filter: ResizeFilter::Lanczos3,
+            metadata: MetadataMode::Keep,
+            template: "{name}_export".to_string(),
+        }
+    }
+}
+
+/// ファイル名のテンプレートで使える置き換え（表示用の説明付き）
+pub const TEMPLATE_TOKENS: [(&str, &str); 5] = [
+    ("{name}", "元のファイル名"),
+    ("{width}", "書き出す幅"),
+    ("{height}", "書き出す高さ"),
+    ("{date}", "今日の日付 (YYYYMMDD)"),
+    ("{format}", "形式の拡張子"),
+];
+
+impl ExportOptions {
+    /// 書き出すサイズ（長辺の指定より小さい画像は拡大しない）
+    pub fn output_size(&self, width: u32, height: u32) -> (u32, u32) {
+        match self.long_edge {
+            Some(edge) if edge > 0 && width.max(height) > edge => {
+                let scale = edge as f64 / width.max(height) as f64;
+                (
+                    ((width as f64 * scale).round() as u32).max(1),
+                    ((height as f64 * scale).round() as u32).max(1),
+                )
+            }
+            _ => (width, height),
+        }
+    }
+
+    /// テンプレートから拡張子付きのファイル名を作る
+    ///
+    /// ファイル名に使えない文字は`_`に置き換え、空になる場合は元のファイル名を使います。
+    pub fn file_name(&self, source: &Path, (width, height): (u32, u32)) -> String {
+        let name = source
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let stem: String = self
+            .template
+            .replace("{name}", &name)
+            .replace("{width}", &width.to_string())
+            .replace("{height}", &height.to_string())
+            .replace("{date}", &chrono::Local::now().format("%Y%m%d").to_string())
+            .replace("{format}", self.format.extension())
+            .chars()
+            .map(|c| {
+                if matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|')
+                    || c.is_control()
+                {
+                    '_'
+                } else {
+                    c
+                }
+            })
+            .collect();
+        let stem = match stem.trim() {
+            "" => name.as_str(),
+            stem => stem,
+        };
+        format!("{}.{}", stem, self.format.extension())
+    }
+
+    /// 長辺の指定に合わせて縮小する
+    pub fn resize(&self, img: DynamicImage) -> DynamicImage {
+        let (width, height) = self.output_size(img.width(), img.height());
+        if (width, height) == (img.width(), img.height()) {
+            return img;
+        }
+        img.resize_exact(width, height, self.filter.filter_type())
+    }
+
+    /// 書き出す形式で保存できる色形式に変換する
+    ///
+    /// 浮動小数点の画像は既定の表示変換で8bitに、BMP・WebPは16bitを8bitにします。
+    /// JPEGの変換は`img::encode_with_quality`が行います。
+    fn convert(&self, img: DynamicImage) -> DynamicImage {
+        let img = match img {
+            DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => lut::restore_alpha(
+                DynamicImage::ImageRgba8(DisplayTransform::default().render(&img)),
+                &img,
+            ),
+            img => img,
+        };
+        if !matches!(self.format, ExportFormat::Bmp | ExportFormat::WebP) {
+            return img;
+        }
+        match img {
+            DynamicImage::ImageLuma16(_) => DynamicImage::ImageLuma8(img.to_luma8()),
+            DynamicImage::ImageLumaA16(_) => DynamicImage::ImageLumaA8(img.to_luma_alpha8()),
+            DynamicImage::ImageRgb16(_) => DynamicImage::ImageRgb8(img.to_rgb8()),
+            DynamicImage::ImageRgba16(_) => DynamicImage::ImageRgba8(img.to_rgba8()),
+            img => img,
+        }
+    }
+
+    /// 書き出すメタデータ（向きは画素に反映するためOrientationを1に戻す）
+    fn metadata(&self, mut metadata: Metadata) -> Metadata {
+        metadata.reset_orientation();
+        match self.metadata {
+            MetadataMode::Keep => metadata,
+            MetadataMode::StripGps => {
+                metadata.strip_gps();
+                metadata
+            }
+            MetadataMode::Strip => Metadata {
+                icc: metadata.icc,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// 表示中の画像を、表示中の回転を適用して指定形式で書き出す
+///
+/// 元のファイルは変更しません。
+///
+/// # Arguments
+///
+/// * `path` - 画像ファイルのパス
+/// * `page` - 書き出すページ番号（0始まり、複数ページのTIFF以外は無視）
+/// * `rotation` - 表示中の回転（90度単位）
+/// * `straighten` - 表示中の角度補正（度、最大の内接矩形で切り抜く）
+/// * `options` - 書き出しの設定
+/// * `output` - 保存先
+///
+/// # Errors
+///
+/// * 保存先が元のファイルと同じ場合
+/// * 画像の読み込み・エンコード・書き込みに失敗した場合
+pub fn export_image(
+    path: &Path,
+    page: usize,
+    rotation: i32,
+    straighten: f32,
+    options: &ExportOptions,
+    output: &Path,
+) -> Result<(), String> {
+    // 変更の確認・履歴を経ずに元のファイルを置き換えないようにする
+    if safe_write::path_key(output) == safe_write::path_key(path) {
+        return Err("元のファイルには書き出せません".to_string());
+    }
+    let (bytes, img) = render(path, page, rotation, straighten, options)?;
+    write(&bytes, page, &img, options, output)
+}
+
+/// 画像をそのままの向きで書き出し、ファイル名のテンプレートで指定フォルダに保存する
+///
+/// 一括処理用です。`{width}`・`{height}`には縮小後の実際のサイズが入ります。
+/// 書き込む前に`reserve`で保存先を確認し、エラーなら保存しません。
+///
+/// # Arguments
+///
+/// * `path` - 画像ファイルのパス
+/// * `options` - 書き出しの設定
+/// * `dir` - 保存先フォルダ
+/// * `reserve` - 保存先の確認（上書き・重複の防止）
+///
+/// # Returns
+///
+/// 保存したファイルのパス
+///
+/// # Errors
+///
+/// * `reserve`のエラー
+/// * `export_image`のエラー
+pub fn export_to_folder(
+    path: &Path,
+    options: &ExportOptions,
+    dir: &Path,
+    reserve: impl FnOnce(&Path) -> Result<(), String>,
+) -> Result<PathBuf, String> {
+    let (bytes, img) = render(path, 0, 0, 0.0, options)?;
+    let output = dir.join(options.file_name(path, (img.width(), img.height())));
+    reserve(&output)?;
+    write(&bytes, 0, &img, options, &output)?;
+    Ok(output)
+}
+
+/// ファイル名が画像のサイズに依存しない場合、保存先を読み込まずに決める
+///
+/// 一括処理で、開始前に保存先の重複を検出するために使用します。
+pub fn planned_output(path: &Path, options: &ExportOptions, dir: &Path) -> Option<PathBuf> {
+    if options.template.contains("{width}") || options.template.contains("{height}") {
+        return None;
+    }
+    Some(dir.join(options.file_name(path, (0, 0))))
+}
+
+/// 画像を読み込み、回転・角度補正・縮小・色形式の変換を適用する
+///
+/// # Returns
+///
+/// (元のファイルの内容, 書き出す画像)
+fn render(
+    path: &Path,
+    page: usize,
+    rotation: i32,
+    straighten: f32,
+    options: &ExportOptions,
+) -> Result<(Vec<u8>, DynamicImage), String> {
+    let bytes =
+        std::fs::read(path).map_err(|e| format!("ファイルの読み込みに失敗しました: {}", e))?;
+    let mut img = Orientation::default()
+        .rotated_by(rotation)
+        .apply(img::decode_page_or_image(&bytes, path, page)?);
+    if straighten != 0.0 {
+        img = straighten::straighten(&img, straighten, true);
+    }
+    let img = options.convert(options.resize(img));
+    Ok((bytes, img))
+}
+
+/// 元のファイルのメタデータを設定に従って引き継ぎ、エンコードして保存する
+fn write(
+    bytes: &[u8],
+    page: usize,
+    img: &DynamicImage,
+    options: &ExportOptions,
+    output: &Path,
+) -> Result<(), String> {
+    let metadata = options.metadata(Metadata::read_page(bytes, page));
+    let encoded = img::encode_with_quality(
+        img,
+        options.format.image_format(),
+        &metadata,
+        options.jpeg_quality,
+    )?;
+    safe_write::write_atomic(output, &encoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GenericImageView, Rgb, RgbImage};
+
+    #[test]
+    fn test_output_size_and_file_name() {
+        let options = ExportOptions {
+            long_edge: Some(1000),
+            template: "{name}_{width}x{height}/web".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(options.output_size(4000, 3000), (1000, 750));
+        assert_eq!(options.output_size(3000, 4000), (750, 1000));
+        // 小さい画像は拡大しない
+        assert_eq!(options.output_size(800, 600), (800, 600));
+
+        assert_eq!(
+            options.file_name(Path::new("/photos/IMG_1.CR2"), (1000, 750)),
+            "IMG_1_1000x750_web.jpg"
+        );
+        let empty = ExportOptions {
+            template: " ".to_string(),
+            format: ExportFormat::Png,
+            ..Default::default()
+        };
+        assert_eq!(empty.file_name(Path::new("a.tif"), (1, 1)), "a.png");
+    }
+
+    #[test]
+    fn test_export_image() {
+        let dir = std::env::temp_dir().join(format!("vdi_export_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("a.png");
+        let img = DynamicImage::ImageRgb16(image::ImageBuffer::from_pixel(
+            40,
+            20,
+            Rgb([1000u16, 30000, 60000]),
+        ));
+        std::fs::write(
+            &source,
+            img::encode_with_metadata(&img, ImageFormat::Png, &Metadata::default()).unwrap(),
+        )
+        .unwrap();
+
+        // 表示中の回転を適用し、長辺を縮小してBMP（8bit）で書き出す
+        let output = dir.join("a.bmp");
+        let options = ExportOptions {
+            format: ExportFormat::Bmp,
+            long_edge: Some(10),
+            ..Default::default()
+        };
+        export_image(&source, 0, 90, 0.0, &options, &output).unwrap();
+        let exported = image::open(&output).unwrap();
+        assert_eq!(exported.dimensions(), (5, 10));
+        assert_eq!(exported.color(), image::ColorType::Rgb8);
+
+        // 元のファイルには書き出さない
+        let original = std::fs::read(&source).unwrap();
+        assert!(export_image(&source, 0, 90, 0.0, &ExportOptions::default(), &source).is_err());
+        assert_eq!(std::fs::read(&source).unwrap(), original);
+
+        // JPEGの品質で大きさが変わる
+        let jpeg_size = |quality| {
+            let options = ExportOptions {
+                jpeg_quality: quality,
+                ..Default::default()
+            };
+            let output = dir.join(format!("q{}.jpg", quality));
+            let noisy = DynamicImage::ImageRgb8(RgbImage::from_fn(64, 64, |x, y| {
+                Rgb([
+                    (x * 37 % 256) as u8,
+                    (y * 91 % 256) as u8,
+                    ((x ^ y) * 5) as u8,
+                ])
+            }));
+            let noisy_path = dir.join("noisy.png");
+            noisy.save(&noisy_path).unwrap();
+            export_image(&noisy_path, 0, 0, 0.0, &options, &output).unwrap();
+            std::fs::metadata(&output).unwrap().len()
+        };
+        assert!(jpeg_size(40) < jpeg_size(95));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -42,6 +42,12 @@ pub struct HistogramResult {
     pub histogram_type: String,
     /// ヒストグラムデータ
     pub data: HistogramData,
+    /// チャンネルごとの白飛び画素数（元の形式の最大値以上、R/G/B）
+    pub clipped: [u32; 3],
+    /// チャンネルごとの範囲外画素数（浮動小数点で1.0を超える値、R/G/B）
+    ///
+    /// 露出補正で復元できる階調を持つ画素です。
+    pub over_range: [u32; 3],
 }
 
 /// キャンセルフラグを登録（古い処理を自動キャンセル）
@@ -74,13 +80,79 @@ fn unregister_histogram_cancel_flag(request_id: &str) {
     map.remove(request_id);
 }
 
+/// 元のビット深度を保ったRGB画素
+///
+/// 8bitに丸めると白飛びと飛ぶ直前の値が区別できなくなるため、元の精度のまま集計します。
+enum RgbSamples {
+    U8(image::RgbImage),
+    U16(image::ImageBuffer<image::Rgb<u16>, Vec<u16>>),
+    F32(image::Rgb32FImage),
+}
+
+impl RgbSamples {
+    fn from_image(img: &DynamicImage) -> Self {
+        match img {
+            DynamicImage::ImageLuma8(_)
+            | DynamicImage::ImageLumaA8(_)
+            | DynamicImage::ImageRgb8(_)
+            | DynamicImage::ImageRgba8(_) => RgbSamples::U8(img.to_rgb8()),
+            DynamicImage::ImageLuma16(_)
+            | DynamicImage::ImageLumaA16(_)
+            | DynamicImage::ImageRgb16(_)
+            | DynamicImage::ImageRgba16(_) => RgbSamples::U16(img.to_rgb16()),
+            _ => RgbSamples::F32(img.to_rgb32f()),
+        }
+    }
+
+    fn dimensions(&self) -> (u32, u32) {
+        match self {
+            RgbSamples::U8(img) => img.dimensions(),
+            RgbSamples::U16(img) => img.dimensions(),
+            RgbSamples::F32(img) => img.dimensions(),
+        }
+    }
+
+    /// 画素値（元の形式の値域のまま）
+    fn pixel(&self, x: u32, y: u32) -> [f32; 3] {
+        match self {
+            RgbSamples::U8(img) => img.get_pixel(x, y).0.map(|v| v as f32),
+            RgbSamples::U16(img) => img.get_pixel(x, y).0.map(|v| v as f32),
+            RgbSamples::F32(img) => img.get_pixel(x, y).0,
+        }
+    }
+
+    /// 元の値域から256段階のビンへの倍率
+    fn bin_scale(&self) -> f32 {
+        match self {
+            RgbSamples::U8(_) => 1.0,
+            RgbSamples::U16(_) => 1.0 / 256.0,
+            RgbSamples::F32(_) => 256.0,
+        }
+    }
+
+    /// 白（表示上の最大値）に相当する値
+    fn white(&self) -> f32 {
+        match self {
+            RgbSamples::U8(_) => u8::MAX as f32,
+            RgbSamples::U16(_) => u16::MAX as f32,
+            RgbSamples::F32(_) => 1.0,
+        }
+    }
+}
+
+/// 値をビン番号に変換する（範囲外は両端のビンに含める）
+fn to_bin(value: f32, scale: f32) -> usize {
+    ((value.max(0.0) * scale) as usize).min(255)
+}
+
 /// RGB別ヒストグラムを計算（並列化版）
 fn calculate_rgb_histogram(
     img: &DynamicImage,
     cancel_flag: Arc<AtomicBool>,
 ) -> Result<(Vec<u32>, Vec<u32>, Vec<u32>), String> {
-    let rgb_img = img.to_rgb8();
-    let (width, height) = rgb_img.dimensions();
+    let samples = RgbSamples::from_image(img);
+    let (width, height) = samples.dimensions();
+    let scale = samples.bin_scale();
 
     // 各行を並列処理してRGBヒストグラムを計算
     let row_histograms: Result<Vec<(Vec<u32>, Vec<u32>, Vec<u32>)>, String> = (0..height)
@@ -96,10 +168,10 @@ fn calculate_rgb_histogram(
             let mut hist_b = vec![0u32; 256];
 
             for x in 0..width {
-                let pixel = rgb_img.get_pixel(x, y);
-                hist_r[pixel[0] as usize] += 1;
-                hist_g[pixel[1] as usize] += 1;
-                hist_b[pixel[2] as usize] += 1;
+                let pixel = samples.pixel(x, y);
+                hist_r[to_bin(pixel[0], scale)] += 1;
+                hist_g[to_bin(pixel[1], scale)] += 1;
+                hist_b[to_bin(pixel[2], scale)] += 1;
             }
 
             Ok((hist_r, hist_g, hist_b))
@@ -130,8 +202,9 @@ fn calculate_luminance_histogram(
     img: &DynamicImage,
     cancel_flag: Arc<AtomicBool>,
 ) -> Result<Vec<u32>, String> {
-    let rgb_img = img.to_rgb8();
-    let (width, height) = rgb_img.dimensions();
+    let samples = RgbSamples::from_image(img);
+    let (width, height) = samples.dimensions();
+    let scale = samples.bin_scale();
 
     // 各行を並列処理して輝度ヒストグラムを計算
     let row_histograms: Result<Vec<Vec<u32>>, String> = (0..height)
@@ -145,12 +218,10 @@ fn calculate_luminance_histogram(
             let mut hist_y = vec![0u32; 256];
 
             for x in 0..width {
-                let pixel = rgb_img.get_pixel(x, y);
+                let pixel = samples.pixel(x, y);
                 // ITU-R BT.709
-                let y_value = (0.2126 * pixel[0] as f32
-                    + 0.7152 * pixel[1] as f32
-                    + 0.0722 * pixel[2] as f32) as u8;
-                hist_y[y_value as usize] += 1;
+                let y_value = 0.2126 * pixel[0] + 0.7152 * pixel[1] + 0.0722 * pixel[2];
+                hist_y[to_bin(y_value, scale)] += 1;
             }
 
             Ok(hist_y)
@@ -171,6 +242,52 @@ fn calculate_luminance_histogram(
     Ok(hist_y)
 }
 
+/// チャンネルごとの白飛び・範囲外の画素数を数える
+///
+/// # Returns
+/// * `(clipped, over_range)` - 白（最大値）以上の画素数と、1.0を超える浮動小数点値の画素数
+fn calculate_clipping(
+    img: &DynamicImage,
+    cancel_flag: Arc<AtomicBool>,
+) -> Result<([u32; 3], [u32; 3]), String> {
+    let samples = RgbSamples::from_image(img);
+    let (width, height) = samples.dimensions();
+    let white = samples.white();
+
+    (0..height)
+        .into_par_iter()
+        .map(|y| {
+            if y % 100 == 0 && cancel_flag.load(Ordering::Relaxed) {
+                return Err("Cancelled".to_string());
+            }
+
+            let mut clipped = [0u32; 3];
+            let mut over_range = [0u32; 3];
+            for x in 0..width {
+                let pixel = samples.pixel(x, y);
+                for c in 0..3 {
+                    if pixel[c] >= white {
+                        clipped[c] += 1;
+                    }
+                    if pixel[c] > white {
+                        over_range[c] += 1;
+                    }
+                }
+            }
+            Ok((clipped, over_range))
+        })
+        .try_reduce(
+            || ([0u32; 3], [0u32; 3]),
+            |(mut clipped, mut over_range), (row_clipped, row_over_range)| {
+                for c in 0..3 {
+                    clipped[c] += row_clipped[c];
+                    over_range[c] += row_over_range[c];
+                }
+                Ok((clipped, over_range))
+            },
+        )
+}
+
 /// ヒストグラム計算のTauri Command
 ///
 /// # Arguments
@@ -186,36 +303,49 @@ pub fn calculate_histogram(
     display_type: String,
     request_id: Option<String>,
 ) -> Result<HistogramResult, String> {
-    let total_start = Instant::now();
-
-    // ベースキーとユニークなリクエストIDを生成
-    let base_key = request_id.unwrap_or_else(|| format!("{}:{}", image_path, display_type));
-    let unique_request_id = generate_unique_request_id(&base_key);
-    println!("[Histogram] 新規リクエスト開始: {}", unique_request_id);
-
-    let cancel_flag = register_histogram_cancel_flag(&unique_request_id, &base_key);
-
     let path = Path::new(&image_path);
 
     // ファイル存在チェック
     if !path.exists() {
-        unregister_histogram_cancel_flag(&unique_request_id);
         return Err("File not found".to_string());
     }
 
     // 画像読み込み
     let load_start = Instant::now();
-    let img = image::open(path).map_err(|e| {
-        unregister_histogram_cancel_flag(&unique_request_id);
-        format!("Failed to load image: {}", e)
-    })?;
+    let img = crate::img::open_image(path).map_err(|e| format!("Failed to load image: {}", e))?;
+    println!("[Histogram] 画像読み込み: {:?}", load_start.elapsed());
+
+    let base_key = request_id.unwrap_or_else(|| format!("{}:{}", image_path, display_type));
+    calculate_histogram_image(&img, display_type, base_key)
+}
+
+/// デコード済みの画像のヒストグラムを計算する
+///
+/// RAWのフル現像結果など、ファイルとは別に用意した画像を解析する場合に使用します。
+///
+/// # Arguments
+/// * `img` - 解析する画像
+/// * `display_type` - ヒストグラムタイプ ("rgb" または "luminance")
+/// * `request_id` - リクエストID（同じIDの古い処理はキャンセルされる）
+///
+/// # Returns
+/// * `Ok(HistogramResult)` - ヒストグラムデータ
+/// * `Err(String)` - エラーメッセージ
+pub fn calculate_histogram_image(
+    img: &DynamicImage,
+    display_type: String,
+    request_id: String,
+) -> Result<HistogramResult, String> {
+    let total_start = Instant::now();
+
+    // ユニークなリクエストIDを生成
+    let base_key = request_id;
+    let unique_request_id = generate_unique_request_id(&base_key);
+    println!("[Histogram] 新規リクエスト開始: {}", unique_request_id);
+
+    let cancel_flag = register_histogram_cancel_flag(&unique_request_id, &base_key);
+
     let (width, height) = img.dimensions();
-    println!(
-        "[Histogram] 画像読み込み: {:?}, サイズ: {}x{}",
-        load_start.elapsed(),
-        width,
-        height
-    );
 
     // キャンセルチェック1
     if cancel_flag.load(Ordering::Relaxed) {
@@ -228,11 +358,11 @@ pub fn calculate_histogram(
     let calc_start = Instant::now();
     let (histogram_type, data) = match display_type.as_str() {
         "rgb" => {
-            let (r, g, b) = calculate_rgb_histogram(&img, cancel_flag.clone())?;
+            let (r, g, b) = calculate_rgb_histogram(img, cancel_flag.clone())?;
             ("rgb".to_string(), HistogramData::RGB { r, g, b })
         }
         "luminance" => {
-            let y = calculate_luminance_histogram(&img, cancel_flag.clone())?;
+            let y = calculate_luminance_histogram(img, cancel_flag.clone())?;
             ("luminance".to_string(), HistogramData::Luminance { y })
         }
         _ => {
@@ -240,6 +370,7 @@ pub fn calculate_histogram(
             return Err(format!("Invalid display_type: {}", display_type));
         }
     };
+    let (clipped, over_range) = calculate_clipping(img, cancel_flag.clone())?;
     println!("[Histogram] ヒストグラム計算: {:?}", calc_start.elapsed());
 
     // キャンセルチェック2
@@ -265,6 +396,8 @@ pub fn calculate_histogram(
         height,
         histogram_type,
         data,
+        clipped,
+        over_range,
     })
 }
 
@@ -333,6 +466,39 @@ mod tests {
         assert_eq!(hist_y.iter().sum::<u32>(), 1, "合計ピクセル数は1であるべき");
     }
 
+    #[test]
+    fn test_16bit_clipping_is_distinguished() {
+        // 最大値と最大値直前の値は同じビンに入るが、白飛びとして数えるのは最大値のみ
+        let img = DynamicImage::ImageRgb16(ImageBuffer::from_fn(2, 1, |x, _| {
+            Rgb([if x == 0 { u16::MAX } else { 65400 }, 0, 0])
+        }));
+        let cancel_flag = Arc::new(AtomicBool::new(false));
+
+        let (hist_r, _, _) = calculate_rgb_histogram(&img, cancel_flag.clone()).unwrap();
+        assert_eq!(hist_r[255], 2);
+
+        let (clipped, over_range) = calculate_clipping(&img, cancel_flag).unwrap();
+        assert_eq!(clipped, [1, 0, 0]);
+        assert_eq!(over_range, [0, 0, 0]);
+    }
+
+    #[test]
+    fn test_float_over_range_is_counted() {
+        let img = DynamicImage::ImageRgb32F(image::Rgb32FImage::from_fn(3, 1, |x, _| {
+            Rgb([x as f32, 0.5, 0.5])
+        }));
+        let cancel_flag = Arc::new(AtomicBool::new(false));
+
+        let (hist_r, _, hist_b) = calculate_rgb_histogram(&img, cancel_flag.clone()).unwrap();
+        assert_eq!(hist_r[0], 1);
+        assert_eq!(hist_r[255], 2, "1.0以上の値は最後のビンに入る");
+        assert_eq!(hist_b[128], 3);
+
+        let (clipped, over_range) = calculate_clipping(&img, cancel_flag).unwrap();
+        assert_eq!(clipped, [2, 0, 0]);
+        assert_eq!(over_range, [1, 0, 0]);
+    }
+
     #[test]
     fn test_histogram_cancellation() {
         let img = create_solid_color_image(100, 100, Rgb([0, 0, 0]));
@@ -0,0 +1,522 @@
+use crate::orientation;
+use crate::safe_write::{self, FileStamp};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// 履歴の索引ファイル名（画像ごとのディレクトリに置く）
+const INDEX_FILE: &str = "index.json";
+
+/// 索引の読み込みから書き戻しまでを直列化するロック
+///
+/// 容量の上限を超えた場合は他の画像の索引も書き換えるため、画像ごとではなく
+/// プロセス全体で1つにします（一括処理の並列な記録で索引が巻き戻らないように）。
+static INDEX_LOCK: Mutex<()> = Mutex::new(());
+
+fn lock_index() -> MutexGuard<'static, ()> {
+    INDEX_LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// 編集前後のファイルの状態（画像本体とサイドカーXMP）
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileState {
+    pub bytes: Vec<u8>,
+    /// サイドカーXMP（無い場合は`None`）
+    pub sidecar: Option<Vec<u8>>,
+}
+
+impl FileState {
+    /// 画像ファイルとサイドカーXMPの現在の内容を読み込む
+    ///
+    /// # Errors
+    ///
+    /// * 画像ファイルの読み込みに失敗した場合
+    pub fn capture(image_path: &Path) -> Result<Self, String> {
+        let bytes = std::fs::read(image_path)
+            .map_err(|e| format!("ファイルの読み込みに失敗しました: {}", e))?;
+        let sidecar = std::fs::read(orientation::sidecar_path(image_path)).ok();
+        Ok(Self { bytes, sidecar })
+    }
+
+    /// 画像ファイルとサイドカーXMPをこの状態に戻す
+    ///
+    /// 状態にサイドカーが無ければ、既存のサイドカーを削除します。
+    fn restore(&self, image_path: &Path) -> Result<(), String> {
+        safe_write::write_atomic(image_path, &self.bytes)?;
+        let sidecar_path = orientation::sidecar_path(image_path);
+        match &self.sidecar {
+            Some(sidecar) => safe_write::write_atomic(&sidecar_path, sidecar),
+            None if sidecar_path.exists() => std::fs::remove_file(&sidecar_path)
+                .map_err(|e| format!("サイドカーXMPの削除に失敗しました: {}", e)),
+            None => Ok(()),
+        }
+    }
+
+    fn size(&self) -> u64 {
+        (self.bytes.len() + self.sidecar.as_ref().map_or(0, Vec::len)) as u64
+    }
+}
+
+/// 保存済みのスナップショット
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Snapshot {
+    id: u64,
+    /// このスナップショットから先の状態へ進めた編集の名前（「回転」など）
+    label: String,
+    /// 作成日時（UNIXエポックからのミリ秒）
+    created: u64,
+    /// 画像本体とサイドカーの合計サイズ
+    size: u64,
+    sidecar: bool,
+}
+
+/// 画像1枚分の履歴の索引
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct HistoryIndex {
+    path: PathBuf,
+    next_id: u64,
+    undo: Vec<Snapshot>,
+    redo: Vec<Snapshot>,
+}
+
+/// 画像ごとの編集履歴（元に戻す・やり直し）
+///
+/// スナップショットは画像のフォルダではなくアプリのデータディレクトリに保存し、
+/// 合計サイズが上限を超えると古いものから削除します。索引もディスクに保存するため、
+/// アプリを再起動した後でも元に戻せます。
+#[derive(Debug, Clone)]
+pub struct EditHistory {
+    root: PathBuf,
+    quota_bytes: u64,
+}
+
+impl EditHistory {
+    /// 指定したディレクトリに履歴を保存する
+    pub fn new(root: PathBuf, quota_mb: u64) -> Self {
+        Self {
+            root,
+            quota_bytes: quota_mb * 1024 * 1024,
+        }
+    }
+
+    /// アプリのデータディレクトリ（`<データディレクトリ>/vdi-solid/history`）に履歴を保存する
+    pub fn open_default(quota_mb: u64) -> Option<Self> {
+        let root = dirs_next::data_dir()?.join("vdi-solid").join("history");
+        Some(Self::new(root, quota_mb))
+    }
+
+    /// 編集前の状態を履歴に追加する（やり直しの履歴は破棄する）
+    ///
+    /// # Arguments
+    ///
+    /// * `image_path` - 編集した画像ファイルのパス
+    /// * `label` - 編集の名前
+    /// * `before` - 編集前の状態（`FileState::capture`で取得したもの）
+    ///
+    /// # Errors
+    ///
+    /// * スナップショットまたは索引の書き込みに失敗した場合
+    pub fn record(&self, image_path: &Path, label: &str, before: FileState) -> Result<(), String> {
+        let _lock = lock_index();
+        let dir = self.image_dir(image_path);
+        let mut index = self.load_index(image_path);
+        for snapshot in std::mem::take(&mut index.redo) {
+            remove_snapshot_files(&dir, &snapshot);
+        }
+        let snapshot = self.store(&dir, &mut index, label, &before)?;
+        index.undo.push(snapshot);
+        self.save_index(&dir, &index)?;
+        self.enforce_quota(&dir);
+        Ok(())
+    }
+
+    /// 直前の編集を元に戻す
+    ///
+    /// 現在の状態をやり直しの履歴に保存してから、編集前の状態を書き戻します。
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(String))` - 元に戻した編集の名前
+    /// * `Ok(None)` - 元に戻せる編集が無い場合
+    ///
+    /// # Errors
+    ///
+    /// * 読み込み後に画像ファイルが変更された場合（`expected`が一致しない場合）
+    /// * スナップショットの読み込み、または画像ファイルの書き込みに失敗した場合
+    pub fn undo(
+        &self,
+        image_path: &Path,
+        expected: Option<FileStamp>,
+    ) -> Result<Option<String>, String> {
+        self.step(image_path, true, expected)
+    }
+
+    /// 元に戻した編集をやり直す
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(String))` - やり直した編集の名前
+    /// * `Ok(None)` - やり直せる編集が無い場合
+    ///
+    /// # Errors
+    ///
+    /// * 読み込み後に画像ファイルが変更された場合（`expected`が一致しない場合）
+    /// * スナップショットの読み込み、または画像ファイルの書き込みに失敗した場合
+    pub fn redo(
+        &self,
+        image_path: &Path,
+        expected: Option<FileStamp>,
+    ) -> Result<Option<String>, String> {
+        self.step(image_path, false, expected)
+    }
+
+    /// 元に戻せる数とやり直せる数
+    pub fn depth(&self, image_path: &Path) -> (usize, usize) {
+        let _lock = lock_index();
+        let index = self.load_index(image_path);
+        (index.undo.len(), index.redo.len())
+    }
+
+    /// 履歴を1つ戻す・進める
+    ///
+    /// `expected`（読み込み時点のスタンプ）が指定されていれば、他のアプリによる変更を
+    /// 上書きしないよう、一致する場合のみ書き戻します。
+    fn step(
+        &self,
+        image_path: &Path,
+        undo: bool,
+        expected: Option<FileStamp>,
+    ) -> Result<Option<String>, String> {
+        let _lock = lock_index();
+        let dir = self.image_dir(image_path);
+        let mut index = self.load_index(image_path);
+        let popped = if undo {
+            index.undo.pop()
+        } else {
+            index.redo.pop()
+        };
+        let Some(target) = popped else {
+            return Ok(None);
+        };
+        if let Some(expected) = expected {
+            safe_write::ensure_unchanged(image_path, expected)?;
+        }
+
+        let state = read_snapshot(&dir, &target)?;
+        let current = FileState::capture(image_path)?;
+        let saved = self.store(&dir, &mut index, &target.label, &current)?;
+        if undo {
+            index.redo.push(saved);
+        } else {
+            index.undo.push(saved);
+        }
+
+        state.restore(image_path)?;
+        remove_snapshot_files(&dir, &target);
+        self.save_index(&dir, &index)?;
+        self.enforce_quota(&dir);
+        println!(
+            "[History] {}: {} ({})",
+            if undo { "元に戻す" } else { "やり直し" },
+            target.label,
+            image_path.display()
+        );
+        Ok(Some(target.label))
+    }
+
+    /// スナップショットをディスクに書き込む
+    fn store(
+        &self,
+        dir: &Path,
+        index: &mut HistoryIndex,
+        label: &str,
+        state: &FileState,
+    ) -> Result<Snapshot, String> {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("履歴ディレクトリの作成に失敗しました: {}", e))?;
+        let snapshot = Snapshot {
+            id: index.next_id,
+            label: label.to_string(),
+            created: now_millis(),
+            size: state.size(),
+            sidecar: state.sidecar.is_some(),
+        };
+        index.next_id += 1;
+
+        safe_write::write_atomic(&dir.join(format!("{}.bin", snapshot.id)), &state.bytes)?;
+        if let Some(sidecar) = &state.sidecar {
+            safe_write::write_atomic(&dir.join(format!("{}.xmp", snapshot.id)), sidecar)?;
+        }
+        Ok(snapshot)
+    }
+
+    /// 画像ごとの履歴ディレクトリ（正規化したパスのハッシュ）
+    fn image_dir(&self, image_path: &Path) -> PathBuf {
+        let path = canonical(image_path);
+        self.root
+            .join(format!("{:016x}", fnv1a(path.to_string_lossy().as_bytes())))
+    }
+
+    fn load_index(&self, image_path: &Path) -> HistoryIndex {
+        let path = canonical(image_path);
+        std::fs::read_to_string(self.image_dir(image_path).join(INDEX_FILE))
+            .ok()
+            .and_then(|json| serde_json::from_str::<HistoryIndex>(&json).ok())
+            // ハッシュが衝突した別の画像の履歴は使わない
+            .filter(|index| index.path == path)
+            .unwrap_or(HistoryIndex {
+                path,
+                ..HistoryIndex::default()
+            })
+    }
+
+    fn save_index(&self, dir: &Path, index: &HistoryIndex) -> Result<(), String> {
+        if index.undo.is_empty() && index.redo.is_empty() {
+            let _ = std::fs::remove_dir_all(dir);
+            return Ok(());
+        }
+        let json = serde_json::to_string_pretty(index)
+            .map_err(|e| format!("履歴の保存に失敗しました: {}", e))?;
+        safe_write::write_atomic(&dir.join(INDEX_FILE), json.as_bytes())
+    }
+
+    /// 合計サイズが上限を超えていれば、全画像の履歴から古いスナップショットを削除する
+    ///
+    /// 直前に保存した`keep`ディレクトリの最新のスナップショットは残します。
+    fn enforce_quota(&self, keep: &Path) {
+        let Ok(entries) = std::fs::read_dir(&self.root) else {
+            return;
+        };
+        let mut indexes: Vec<(PathBuf, HistoryIndex)> = entries
+            .flatten()
+            .filter_map(|entry| {
+                let dir = entry.path();
+                let json = std::fs::read_to_string(dir.join(INDEX_FILE)).ok()?;
+                Some((dir, serde_json::from_str(&json).ok()?))
+            })
+            .collect();
+
+        let mut total: u64 = indexes
+            .iter()
+            .flat_map(|(_, index)| index.undo.iter().chain(&index.redo))
+            .map(|snapshot| snapshot.size)
+            .sum();
+        if total <= self.quota_bytes {
+            return;
+        }
+
+        // 古い順に（作成日時、ディレクトリ、ID）を並べる
+        let newest_kept = indexes
+            .iter()
+            .find(|(dir, _)| dir == keep)
+            .and_then(|(_, index)| {
+                index
+                    .undo
+                    .iter()
+                    .chain(&index.redo)
+                    .map(|s| s.created)
+                    .max()
+            });
+        let mut candidates: Vec<(u64, usize, u64)> = indexes
+            .iter()
+            .enumerate()
+            .flat_map(|(i, (dir, index))| {
+                index
+                    .undo
+                    .iter()
+                    .chain(&index.redo)
+                    .filter(move |s| !(dir == keep && Some(s.created) == newest_kept))
+                    .map(move |s| (s.created, i, s.id))
+            })
+            .collect();
+        candidates.sort_unstable();
+
+        let mut changed = vec![false; indexes.len()];
+        for (_, i, id) in candidates {
+            if total <= self.quota_bytes {
+                break;
+            }
+            let (dir, index) = &mut indexes[i];
+            for stack in [&mut index.undo, &mut index.redo] {
+                if let Some(pos) = stack.iter().position(|s| s.id == id) {
+                    let snapshot = stack.remove(pos);
+                    remove_snapshot_files(dir, &snapshot);
+                    total -= snapshot.size;
+                    changed[i] = true;
+                }
+            }
+        }
+
+        for ((dir, index), changed) in indexes.iter().zip(changed) {
+            if changed {
+                if let Err(e) = self.save_index(dir, index) {
+                    eprintln!("[History] 索引の更新に失敗しました: {}", e);
+                }
+            }
+        }
+        println!(
+            "[History] 容量の上限を超えたため古い履歴を削除しました（{}バイト）",
+            total
+        );
+    }
+}
+
+fn read_snapshot(dir: &Path, snapshot: &Snapshot) -> Result<FileState, String> {
+    let bytes = std::fs::read(dir.join(format!("{}.bin", snapshot.id)))
+        .map_err(|e| format!("履歴の読み込みに失敗しました: {}", e))?;
+    let sidecar = if snapshot.sidecar {
+        Some(
+            std::fs::read(dir.join(format!("{}.xmp", snapshot.id)))
+                .map_err(|e| format!("履歴の読み込みに失敗しました: {}", e))?,
+        )
+    } else {
+        None
+    };
+    Ok(FileState { bytes, sidecar })
+}
+
+fn remove_snapshot_files(dir: &Path, snapshot: &Snapshot) {
+    let _ = std::fs::remove_file(dir.join(format!("{}.bin", snapshot.id)));
+    let _ = std::fs::remove_file(dir.join(format!("{}.xmp", snapshot.id)));
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// 64bit FNV-1a（実行ごとに変わらないハッシュ）
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("vdi_history_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// ファイルを書き換える編集を模擬する
+    fn edit(history: &EditHistory, path: &Path, contents: &[u8]) {
+        let before = FileState::capture(path).unwrap();
+        std::fs::write(path, contents).unwrap();
+        history.record(path, "編集", before).unwrap();
+    }
+
+    #[test]
+    fn test_undo_redo_survives_restart() {
+        let dir = temp_dir("undo");
+        let path = dir.join("photo.jpg");
+        std::fs::write(&path, b"v1").unwrap();
+
+        let history = EditHistory::new(dir.join("history"), 16);
+        edit(&history, &path, b"v2");
+        edit(&history, &path, b"v3");
+        assert_eq!(history.depth(&path), (2, 0));
+
+        // 再起動後（新しいインスタンス）でも元に戻せる
+        let history = EditHistory::new(dir.join("history"), 16);
+        assert_eq!(history.undo(&path, None).unwrap().as_deref(), Some("編集"));
+        assert_eq!(std::fs::read(&path).unwrap(), b"v2");
+        history.undo(&path, None).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"v1");
+        assert_eq!(history.undo(&path, None).unwrap(), None);
+        assert_eq!(history.depth(&path), (0, 2));
+
+        history.redo(&path, None).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"v2");
+
+        // 新しい編集でやり直しの履歴は破棄される
+        edit(&history, &path, b"v4");
+        assert_eq!(history.depth(&path), (2, 0));
+        assert_eq!(history.redo(&path, None).unwrap(), None);
+
+        // 画像のフォルダには何も残らない
+        let names: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .flatten()
+            .map(|e| e.file_name())
+            .collect();
+        assert_eq!(names.len(), 2);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_undo_restores_sidecar() {
+        let dir = temp_dir("sidecar");
+        let path = dir.join("photo.raw");
+        let sidecar = orientation::sidecar_path(&path);
+        std::fs::write(&path, b"raw").unwrap();
+
+        let history = EditHistory::new(dir.join("history"), 16);
+        let before = FileState::capture(&path).unwrap();
+        std::fs::write(&sidecar, b"<xmp/>").unwrap();
+        history.record(&path, "回転", before).unwrap();
+
+        history.undo(&path, None).unwrap();
+        assert!(!sidecar.exists());
+        history.redo(&path, None).unwrap();
+        assert_eq!(std::fs::read(&sidecar).unwrap(), b"<xmp/>");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_quota_evicts_oldest_snapshots() {
+        let dir = temp_dir("quota");
+        let a = dir.join("a.png");
+        let b = dir.join("b.png");
+        std::fs::write(&a, vec![0u8; 400 * 1024]).unwrap();
+        std::fs::write(&b, vec![0u8; 400 * 1024]).unwrap();
+
+        // 上限1MBに対して400KBのスナップショットを3つ保存する
+        let history = EditHistory::new(dir.join("history"), 1);
+        edit(&history, &a, &vec![1u8; 400 * 1024]);
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        edit(&history, &b, &vec![1u8; 400 * 1024]);
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        edit(&history, &a, &vec![2u8; 400 * 1024]);
+
+        // 最も古いaのスナップショットが削除され、新しいものは残る
+        assert_eq!(history.depth(&a), (1, 0));
+        assert_eq!(history.depth(&b), (1, 0));
+        history.undo(&a, None).unwrap();
+        assert_eq!(std::fs::read(&a).unwrap(), vec![1u8; 400 * 1024]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_undo_refuses_changed_file() {
+        let dir = temp_dir("changed");
+        let path = dir.join("photo.jpg");
+        std::fs::write(&path, b"v1").unwrap();
+        let history = EditHistory::new(dir.join("history"), 16);
+        edit(&history, &path, b"v2");
+        let stamp = FileStamp::read(&path);
+
+        // 読み込み後に他のアプリが書き換えた
+        std::fs::write(&path, b"changed elsewhere").unwrap();
+        assert!(history.undo(&path, stamp).is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), b"changed elsewhere");
+        assert_eq!(history.depth(&path), (1, 0));
+
+        // 最新のスタンプであれば元に戻せる
+        let stamp = FileStamp::read(&path);
+        assert!(history.undo(&path, stamp).unwrap().is_some());
+        assert_eq!(std::fs::read(&path).unwrap(), b"v1");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
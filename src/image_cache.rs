@@ -0,0 +1,290 @@
+use crate::animation::Animation;
+use crate::color::{ColorConverter, ColorSettings};
+use crate::display::DisplayTransform;
+use crate::loader;
+use crate::orientation::AppliedOrientation;
+use crate::safe_write::FileStamp;
+use crate::tiles::MipPyramid;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// キャッシュに保持するデコード済み画像
+#[derive(Clone)]
+pub struct CachedImage {
+    pub image: Arc<image::DynamicImage>,
+    pub pyramid: Arc<MipPyramid>,
+    /// `pyramid`の作成に使用した表示変換
+    pub transform: DisplayTransform,
+    pub animation: Option<Arc<Animation>>,
+    pub page_count: usize,
+    pub icc_profile: Option<Arc<Vec<u8>>>,
+    pub color: Option<Arc<ColorConverter>>,
+    pub orientation: Option<AppliedOrientation>,
+    pub file_size: u64,
+    pub stamp: Option<FileStamp>,
+}
+
+impl CachedImage {
+    /// 保持に必要なおおよそのメモリ量（バイト）
+    fn memory_size(&self) -> usize {
+        self.image.as_bytes().len()
+            + self.pyramid.memory_size()
+            + self
+                .animation
+                .as_ref()
+                .map(|animation| animation.memory_size())
+                .unwrap_or(0)
+    }
+}
+
+struct CacheEntry {
+    image: CachedImage,
+    stamp: FileStamp,
+    last_used: u64,
+    bytes: usize,
+}
+
+/// メモリ上限付きのLRUデコードキャッシュ
+///
+/// エントリはファイルの更新日時とサイズで検証し、ファイルが変更されていれば破棄します。
+pub struct ImageCache {
+    entries: HashMap<PathBuf, CacheEntry>,
+    budget_bytes: usize,
+    used_bytes: usize,
+    tick: u64,
+}
+
+/// スレッド間で共有するキャッシュ
+pub type SharedImageCache = Arc<Mutex<ImageCache>>;
+
+impl ImageCache {
+    /// 指定したメモリ上限（MB）でキャッシュを作成する
+    pub fn new(budget_mb: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            budget_bytes: budget_mb * 1024 * 1024,
+            used_bytes: 0,
+            tick: 0,
+        }
+    }
+
+    /// メモリ上限（MB）を変更し、超過分を破棄する
+    pub fn set_budget_mb(&mut self, budget_mb: usize) {
+        self.budget_bytes = budget_mb * 1024 * 1024;
+        self.evict_to_budget();
+    }
+
+    /// ファイルが変更されていなければキャッシュ済みの画像を返す
+    pub fn get(&mut self, path: &Path) -> Option<CachedImage> {
+        let stamp = FileStamp::read(path)?;
+        self.get_if_fresh(path, stamp)
+    }
+
+    /// スタンプが一致する場合のみキャッシュ済みの画像を返す（不一致なら破棄する）
+    pub fn get_if_fresh(&mut self, path: &Path, stamp: FileStamp) -> Option<CachedImage> {
+        let fresh = self.entries.get(path)?.stamp == stamp;
+        if !fresh {
+            println!("[ImageCache] 更新を検出したため破棄: {}", path.display());
+            self.remove(path);
+            return None;
+        }
+
+        self.tick += 1;
+        let entry = self.entries.get_mut(path)?;
+        entry.last_used = self.tick;
+        Some(entry.image.clone())
+    }
+
+    /// キャッシュ済みかつ最新かどうか（使用順は更新しない）
+    pub fn contains_fresh(&self, path: &Path, stamp: FileStamp) -> bool {
+        self.entries
+            .get(path)
+            .map(|entry| entry.stamp == stamp)
+            .unwrap_or(false)
+    }
+
+    /// 画像をキャッシュに追加し、上限を超えた分を古い順に破棄する
+    pub fn insert(&mut self, path: PathBuf, stamp: FileStamp, image: CachedImage) {
+        let bytes = image.memory_size();
+        if bytes > self.budget_bytes {
+            return;
+        }
+
+        self.remove(&path);
+        self.tick += 1;
+        self.used_bytes += bytes;
+        self.entries.insert(
+            path,
+            CacheEntry {
+                image,
+                stamp,
+                last_used: self.tick,
+                bytes,
+            },
+        );
+        self.evict_to_budget();
+    }
+
+    /// すべてのエントリを破棄する（表示用データの作り方が変わった場合など）
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.used_bytes = 0;
+    }
+
+    fn remove(&mut self, path: &Path) {
+        if let Some(entry) = self.entries.remove(path) {
+            self.used_bytes -= entry.bytes;
+        }
+    }
+
+    fn evict_to_budget(&mut self) {
+        while self.used_bytes > self.budget_bytes {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(path, _)| path.clone());
+            match oldest {
+                Some(path) => self.remove(&path),
+                None => break,
+            }
+        }
+    }
+}
+
+/// 隣接画像の先読み処理
+///
+/// ドロップすると未処理の先読みを中断します。
+pub struct PrefetchJob {
+    cancel_flag: Arc<AtomicBool>,
+}
+
+impl PrefetchJob {
+    /// 指定した画像を順番にデコードしてキャッシュに格納する
+    pub fn start(
+        paths: Vec<PathBuf>,
+        cache: SharedImageCache,
+        transform: DisplayTransform,
+        color: ColorSettings,
+    ) -> Self {
+        let cancel_flag = Arc::new(AtomicBool::new(false));
+        let worker_flag = cancel_flag.clone();
+
+        thread::spawn(move || {
+            for path in paths {
+                if worker_flag.load(Ordering::Relaxed) {
+                    return;
+                }
+                let Some(stamp) = FileStamp::read(&path) else {
+                    continue;
+                };
+                if cache.lock().unwrap().contains_fresh(&path, stamp) {
+                    continue;
+                }
+
+                match loader::decode_for_display(&path, &worker_flag, transform, &color, |_| {}) {
+                    Ok(loaded) => {
+                        println!("[Prefetch] 先読み完了: {}", path.display());
+                        cache
+                            .lock()
+                            .unwrap()
+                            .insert(path, stamp, loaded.into_cached());
+                    }
+                    Err(e) => {
+                        if !worker_flag.load(Ordering::Relaxed) {
+                            eprintln!("[Prefetch] 先読み失敗: {} ({})", path.display(), e);
+                        }
+                    }
+                }
+            }
+        });
+
+        Self { cancel_flag }
+    }
+}
+
+impl Drop for PrefetchJob {
+    fn drop(&mut self) {
+        self.cancel_flag.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eframe::egui;
+    use image::{DynamicImage, RgbaImage};
+    use std::time::Duration;
+
+    /// 1x1ピクセルの画像（8バイト相当）を生成
+    fn tiny_image() -> CachedImage {
+        CachedImage {
+            image: Arc::new(DynamicImage::ImageRgba8(RgbaImage::new(1, 1))),
+            pyramid: Arc::new(MipPyramid::new(egui::ColorImage::new(
+                [1, 1],
+                egui::Color32::BLACK,
+            ))),
+            transform: DisplayTransform::default(),
+            animation: None,
+            page_count: 1,
+            icc_profile: None,
+            color: None,
+            orientation: None,
+            file_size: 0,
+            stamp: None,
+        }
+    }
+
+    fn stamp(secs: u64) -> FileStamp {
+        FileStamp {
+            modified: std::time::UNIX_EPOCH + Duration::from_secs(secs),
+            len: 100,
+            sidecar_modified: None,
+        }
+    }
+
+    #[test]
+    fn test_least_recently_used_entry_is_evicted() {
+        let mut cache = ImageCache::new(0);
+        // 2枚分（16バイト）だけ保持できる上限にする
+        cache.budget_bytes = 16;
+
+        cache.insert(PathBuf::from("a.png"), stamp(1), tiny_image());
+        cache.insert(PathBuf::from("b.png"), stamp(1), tiny_image());
+        // aを参照して最近使用にする
+        assert!(cache.get_if_fresh(Path::new("a.png"), stamp(1)).is_some());
+        cache.insert(PathBuf::from("c.png"), stamp(1), tiny_image());
+
+        assert!(cache.contains_fresh(Path::new("a.png"), stamp(1)));
+        assert!(
+            !cache.contains_fresh(Path::new("b.png"), stamp(1)),
+            "最も古く使われたbが破棄されるべき"
+        );
+        assert!(cache.contains_fresh(Path::new("c.png"), stamp(1)));
+        assert_eq!(cache.used_bytes, 16);
+    }
+
+    #[test]
+    fn test_modified_file_invalidates_entry() {
+        let mut cache = ImageCache::new(1);
+        cache.insert(PathBuf::from("a.png"), stamp(1), tiny_image());
+
+        assert!(
+            cache.get_if_fresh(Path::new("a.png"), stamp(2)).is_none(),
+            "更新日時が変わったエントリは返さない"
+        );
+        assert!(cache.entries.is_empty(), "古いエントリは破棄されるべき");
+        assert_eq!(cache.used_bytes, 0);
+    }
+
+    #[test]
+    fn test_shrinking_budget_evicts_entries() {
+        let mut cache = ImageCache::new(1);
+        cache.insert(PathBuf::from("a.png"), stamp(1), tiny_image());
+        cache.set_budget_mb(0);
+        assert!(cache.entries.is_empty());
+    }
+}
@@ -1,103 +1,467 @@
-use image::ImageFormat;
-use std::path::Path;
+use crate::animation;
+use crate::display::DisplayTransform;
+use crate::jpeg_lossless::{self, EdgeMode};
+use crate::letterbox::Letterbox;
+use crate::lut::LutGrade;
+use crate::metadata::{self, Metadata};
+use crate::multipage;
+use crate::orientation::{self, AppliedOrientation, Orientation, OrientationSource};
+use crate::raw;
+use crate::safe_write::{self, FileStamp};
+use crate::straighten;
+use image::codecs::jpeg::{JpegDecoder, JpegEncoder};
+use image::codecs::png::PngDecoder;
+use image::codecs::tiff::TiffDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::{DynamicImage, ImageDecoder, ImageFormat};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
 
-/// 画像ファイルのバックアップを作成する
+/// JPEG XLのシグネチャ（コードストリーム / コンテナ）
+const JXL_CODESTREAM_SIGNATURE: &[u8] = &[0xFF, 0x0A];
+const JXL_CONTAINER_SIGNATURE: &[u8] = &[
+    0x00, 0x00, 0x00, 0x0C, 0x4A, 0x58, 0x4C, 0x20, 0x0D, 0x0A, 0x87, 0x0A,
+];
+
+/// 拡張子がJPEG XLかどうか
+fn is_jxl_path(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase() == "jxl")
+        .unwrap_or(false)
+}
+
+/// ISOBMFF（HEIF/AVIF）コンテナの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum IsoBmffKind {
+    Avif,
+    Heif,
+}
+
+/// `ftyp`ボックスのブランドからHEIF/AVIFを判定する
+///
+/// メジャーブランドが汎用の`mif1`/`msf1`でも互換ブランドに`avif`/`avis`があればAVIF
+/// とするため、互換ブランドまで調べます。AVIFのブランドを優先し、HEVCまたは汎用の
+/// HEIFのブランドのみの場合にHEIFとします。
+fn detect_isobmff(bytes: &[u8]) -> Option<IsoBmffKind> {
+    if bytes.len() < 12 || &bytes[4..8] != b"ftyp" {
+        return None;
+    }
+    // メジャーブランド（8..12）、マイナーバージョン（12..16）、互換ブランド（16..ボックス末尾）
+    let box_size = u32::from_be_bytes(bytes[0..4].try_into().ok()?) as usize;
+    let end = box_size.clamp(12, bytes.len());
+    let compatible = bytes.get(16..end).unwrap_or_default();
+    let brands: Vec<&[u8]> = std::iter::once(&bytes[8..12])
+        .chain(compatible.chunks_exact(4))
+        .collect();
+    let has = |candidates: &[&[u8; 4]]| {
+        brands
+            .iter()
+            .any(|brand| candidates.iter().any(|c| *brand == &c[..]))
+    };
+
+    if has(&[b"avif", b"avis"]) {
+        Some(IsoBmffKind::Avif)
+    } else if has(&[
+        b"heic", b"heix", b"heim", b"heis", b"hevc", b"hevx", b"mif1", b"msf1",
+    ]) {
+        Some(IsoBmffKind::Heif)
+    } else {
+        None
+    }
+}
+
+/// 拡張子からHEIF/AVIFを判定する
+fn isobmff_kind_from_path(path: &Path) -> Option<IsoBmffKind> {
+    let ext = path.extension()?.to_string_lossy().to_lowercase();
+    match ext.as_str() {
+        "avif" => Some(IsoBmffKind::Avif),
+        "heic" | "heif" => Some(IsoBmffKind::Heif),
+        _ => None,
+    }
+}
+
+/// 画像ファイルを読み込んでデコードする
+///
+/// 表示・ピーキング・ヒストグラムなど、画像を読み込むすべての処理の共通の入口です。
 ///
 /// # Arguments
 ///
-/// * `image_path` - バックアップを作成する画像ファイルのパス
+/// * `path` - 画像ファイルのパス
 ///
 /// # Returns
 ///
-/// * `Ok(String)` - 作成されたバックアップファイルのパス
+/// * `Ok(DynamicImage)` - デコード済み画像（サイドカーXMPの向きを適用済み）
 /// * `Err(String)` - エラーメッセージ
+pub fn open_image(path: &Path) -> Result<DynamicImage, String> {
+    let bytes =
+        std::fs::read(path).map_err(|e| format!("ファイルの読み込みに失敗しました: {}", e))?;
+    decode_image(&bytes, path)
+}
+
+/// 解析用に画像ファイルを読み込んでデコードする
 ///
-/// # Errors
+/// `full_raw_decode`が有効な場合、RAWは埋め込みプレビューではなくフル現像した画像を返します。
+/// RAW以外は`open_image`と同じです。
 ///
-/// * ファイルが存在しない場合
-/// * ファイルのコピーに失敗した場合
-pub fn create_image_backup(image_path: String) -> Result<String, String> {
-    let path = std::path::Path::new(&image_path);
-
-    if !path.exists() {
-        return Err("指定されたファイルが存在しません".to_string());
+/// # Arguments
+///
+/// * `path` - 画像ファイルのパス
+/// * `full_raw_decode` - RAWをフル現像するかどうか
+/// * `cancelled` - 中断が要求されているかどうか（フル現像の各段階の間で確認する）
+///
+/// # Returns
+///
+/// * `Ok(DynamicImage)` - デコード済み画像（向きを適用済み）
+/// * `Err(String)` - エラーメッセージ
+pub fn open_image_for_analysis(
+    path: &Path,
+    full_raw_decode: bool,
+    cancelled: impl Fn() -> bool,
+) -> Result<DynamicImage, String> {
+    if !(full_raw_decode && raw::is_raw_path(path)) {
+        return open_image(path);
     }
 
-    // バックアップファイル名を生成
-    let backup_path = format!("{}.backup", image_path);
+    let bytes =
+        std::fs::read(path).map_err(|e| format!("ファイルの読み込みに失敗しました: {}", e))?;
+    let developed = raw::decode_full(path, cancelled)?;
+    Ok(current_orientation(&bytes, path).apply(developed))
+}
 
-    // ファイルをコピーしてバックアップ作成
-    std::fs::copy(&image_path, &backup_path)
-        .map_err(|e| format!("バックアップ作成に失敗しました: {}", e))?;
+/// 現在の表示向き（サイドカーXMPがあれば優先し、なければ埋め込みEXIF）
+fn current_orientation(bytes: &[u8], path: &Path) -> Orientation {
+    orientation::resolve_orientation(bytes, path)
+        .map(|applied| applied.orientation)
+        .unwrap_or_default()
+}
 
-    Ok(backup_path)
+/// デコード結果（画像と、表示に関わる付随情報）
+pub struct DecodedImage {
+    /// デコード済み画像（向きを適用済み）
+    pub image: DynamicImage,
+    /// 埋め込みICCプロファイル
+    pub icc_profile: Option<Vec<u8>>,
+    /// 適用した向き（向きの情報が無い場合は`None`）
+    pub orientation: Option<AppliedOrientation>,
 }
 
-/// バックアップから画像を復元する
+/// 読み込み済みのファイル内容をデコードする
 ///
-/// バックアップファイルから元の画像を復元し、バックアップファイルを削除します。
+/// 形式はファイル内容から判定し、判定できない場合は拡張子を使用します。
 ///
 /// # Arguments
 ///
-/// * `image_path` - 復元先の画像ファイルパス
+/// * `bytes` - 画像ファイルの内容
+/// * `path` - 画像ファイルのパス（形式判定とサイドカーXMPの参照に使用）
 ///
 /// # Returns
 ///
-/// * `Ok(String)` - 復元された画像ファイルのパス
+/// * `Ok(DynamicImage)` - デコード済み画像（サイドカーXMP・EXIFの向きを適用済み）
 /// * `Err(String)` - エラーメッセージ
+pub fn decode_image(bytes: &[u8], path: &Path) -> Result<DynamicImage, String> {
+    decode_image_detailed(bytes, path).map(|decoded| decoded.image)
+}
+
+/// 読み込み済みのファイル内容をデコードし、ICCプロファイルと適用した向きも返す
 ///
-/// # Errors
+/// ICCプロファイルはJPEG・PNG・WebP・TIFF・JPEG XLから取り出します。
+/// 向きはサイドカーXMPを優先し、なければEXIFのOrientationタグを適用します。
+/// JPEG XL・HEIF/AVIFはデコーダーがコンテナ内の向きを適用するため、サイドカーのみ参照します。
+///
+/// # Arguments
+///
+/// * `bytes` - 画像ファイルの内容
+/// * `path` - 画像ファイルのパス（形式判定とサイドカーXMPの参照に使用）
+///
+/// # Returns
 ///
-/// * バックアップファイルが存在しない場合
-/// * ファイルのコピーに失敗した場合
-/// * バックアップファイルの削除に失敗した場合
-pub fn restore_image_from_backup(image_path: String) -> Result<String, String> {
-    let backup_path = format!("{}.backup", image_path);
-    let backup = std::path::Path::new(&backup_path);
+/// * `Ok(DecodedImage)` - デコード結果
+/// * `Err(String)` - エラーメッセージ
+pub fn decode_image_detailed(bytes: &[u8], path: &Path) -> Result<DecodedImage, String> {
+    let sidecar_only = || {
+        orientation::read_sidecar_orientation(path).map(|orientation| AppliedOrientation {
+            orientation,
+            source: OrientationSource::Sidecar,
+        })
+    };
+
+    // RAWはTIFFとして判定されないよう、拡張子で先に振り分ける
+    let (img, icc_profile, applied) = if raw::is_raw_path(path) {
+        let preview = raw::decode_preview(bytes)?;
+        (preview, None, orientation::resolve_orientation(bytes, path))
+    } else if bytes.starts_with(JXL_CODESTREAM_SIGNATURE)
+        || bytes.starts_with(JXL_CONTAINER_SIGNATURE)
+        || is_jxl_path(path)
+    {
+        let (img, icc_profile) = decode_jxl(bytes)?;
+        (img, icc_profile, sidecar_only())
+    } else if let Some(kind) = detect_isobmff(bytes).or_else(|| isobmff_kind_from_path(path)) {
+        (decode_isobmff(bytes, kind)?, None, sidecar_only())
+    } else {
+        let format = image::guess_format(bytes)
+            .ok()
+            .or_else(|| ImageFormat::from_path(path).ok())
+            .ok_or_else(|| "画像形式の判定に失敗しました".to_string())?;
+        let (img, icc_profile) = decode_with_format(bytes, format).map_err(|e| e.to_string())?;
+        (
+            img,
+            icc_profile,
+            orientation::resolve_orientation(bytes, path),
+        )
+    };
 
-    if !backup.exists() {
-        return Err("バックアップファイルが存在しません".to_string());
+    let image = match applied {
+        Some(applied) => applied.orientation.apply(img),
+        None => img,
+    };
+    Ok(DecodedImage {
+        image,
+        icc_profile,
+        orientation: applied,
+    })
+}
+
+/// imageクレートでデコードする（ICCプロファイルに対応する形式はデコーダーから取り出す）
+fn decode_with_format(
+    bytes: &[u8],
+    format: ImageFormat,
+) -> image::ImageResult<(DynamicImage, Option<Vec<u8>>)> {
+    fn with_profile<'a>(
+        mut decoder: impl ImageDecoder<'a>,
+    ) -> image::ImageResult<(DynamicImage, Option<Vec<u8>>)> {
+        let icc_profile = decoder.icc_profile();
+        Ok((DynamicImage::from_decoder(decoder)?, icc_profile))
     }
 
-    // バックアップから元ファイルを復元
-    std::fs::copy(&backup_path, &image_path)
-        .map_err(|e| format!("画像復元に失敗しました: {}", e))?;
+    let cursor = std::io::Cursor::new(bytes);
+    match format {
+        ImageFormat::Jpeg => with_profile(JpegDecoder::new(cursor)?),
+        ImageFormat::Png => with_profile(PngDecoder::new(cursor)?),
+        ImageFormat::WebP => with_profile(WebPDecoder::new(cursor)?),
+        ImageFormat::Tiff => with_profile(TiffDecoder::new(cursor)?),
+        _ => Ok((image::load_from_memory_with_format(bytes, format)?, None)),
+    }
+}
+
+/// JPEG XLをデコードする（jxl-oxide、16bitで取り出す）
+///
+/// 出力の色空間がsRGB以外の場合は、出力を表すICCプロファイルも返します。
+fn decode_jxl(bytes: &[u8]) -> Result<(DynamicImage, Option<Vec<u8>>), String> {
+    let image = jxl_oxide::JxlImage::builder()
+        .read(std::io::Cursor::new(bytes))
+        .map_err(|e| format!("JPEG XLの読み込みに失敗しました: {}", e))?;
+    let render = image
+        .render_frame(0)
+        .map_err(|e| format!("JPEG XLのデコードに失敗しました: {}", e))?;
 
-    // バックアップファイルを削除
-    std::fs::remove_file(&backup_path)
-        .map_err(|e| format!("バックアップファイル削除に失敗しました: {}", e))?;
+    // ストリームはコードストリーム内の向き情報を適用済み
+    let mut stream = render.stream();
+    let (width, height, channels) = (stream.width(), stream.height(), stream.channels());
+    let mut samples = vec![0u16; width as usize * height as usize * channels as usize];
+    stream.write_to_buffer(&mut samples);
 
-    Ok(image_path)
+    let img = match channels {
+        1 => image::ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageLuma16),
+        2 => image::ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageLumaA16),
+        3 => image::ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageRgb16),
+        4 if !image.pixel_format().has_black() => {
+            image::ImageBuffer::from_raw(width, height, samples).map(DynamicImage::ImageRgba16)
+        }
+        _ => None,
+    };
+    let img = img.ok_or_else(|| "このJPEG XLのチャンネル構成には対応していません".to_string())?;
+
+    // CICP（色域1=BT.709、伝達関数13=sRGB）であればプロファイルは不要
+    let icc_profile = match image.rendered_cicp() {
+        Some([1, 13, _, _]) => None,
+        _ => Some(image.rendered_icc()),
+    };
+    Ok((img, icc_profile))
 }
 
-/// バックアップファイルを削除するコマンド
+/// HEIF/AVIFをデコードする
 ///
-/// 指定された画像パスに対応するバックアップファイル（`<path>.backup`）を削除します。
-/// 存在しない場合は何も行いません。
+/// AVIFは`avif`フィーチャー（imageクレートのデコーダー）、HEIFは`heif`フィーチャー
+/// （libheif）が有効な場合のみ対応します。
+fn decode_isobmff(bytes: &[u8], kind: IsoBmffKind) -> Result<DynamicImage, String> {
+    match kind {
+        IsoBmffKind::Avif => decode_avif(bytes),
+        IsoBmffKind::Heif => decode_heif(bytes),
+    }
+}
+
+#[cfg(feature = "avif")]
+fn decode_avif(bytes: &[u8]) -> Result<DynamicImage, String> {
+    image::load_from_memory_with_format(bytes, ImageFormat::Avif)
+        .map_err(|e| format!("AVIFのデコードに失敗しました: {}", e))
+}
+
+#[cfg(not(feature = "avif"))]
+fn decode_avif(_bytes: &[u8]) -> Result<DynamicImage, String> {
+    Err(
+        "このビルドはAVIFに対応していません（`avif`フィーチャーを有効にしてビルドしてください）"
+            .to_string(),
+    )
+}
+
+/// HEIFをデコードする（libheif、8bit RGBAで取り出す）
+#[cfg(feature = "heif")]
+fn decode_heif(bytes: &[u8]) -> Result<DynamicImage, String> {
+    use libheif_rs::{ColorSpace, HeifContext, LibHeif, RgbChroma};
+
+    let lib_heif = LibHeif::new();
+    let context = HeifContext::read_from_bytes(bytes)
+        .map_err(|e| format!("HEIFの読み込みに失敗しました: {}", e))?;
+    let handle = context
+        .primary_image_handle()
+        .map_err(|e| format!("HEIFの読み込みに失敗しました: {}", e))?;
+    // libheifはコンテナ内の回転・反転（irot/imir）を適用済みの画像を返す
+    let decoded = lib_heif
+        .decode(&handle, ColorSpace::Rgb(RgbChroma::Rgba), None)
+        .map_err(|e| format!("HEIFのデコードに失敗しました: {}", e))?;
+
+    let plane = decoded
+        .planes()
+        .interleaved
+        .ok_or_else(|| "HEIFの画素データを取得できませんでした".to_string())?;
+    let row_bytes = plane.width as usize * 4;
+    let mut pixels = Vec::with_capacity(row_bytes * plane.height as usize);
+    for row in plane.data.chunks(plane.stride).take(plane.height as usize) {
+        pixels.extend_from_slice(&row[..row_bytes]);
+    }
+
+    image::RgbaImage::from_raw(plane.width, plane.height, pixels)
+        .map(DynamicImage::ImageRgba8)
+        .ok_or_else(|| "HEIFの画素データが不正です".to_string())
+}
+
+#[cfg(not(feature = "heif"))]
+fn decode_heif(_bytes: &[u8]) -> Result<DynamicImage, String> {
+    Err(
+        "このビルドはHEIFに対応していません（`heif`フィーチャーを有効にしてビルドしてください）"
+            .to_string(),
+    )
+}
+
+/// 回転の保存方法
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum RotationMode {
+    /// 画素を書き換える（JPEGは無劣化で回転）
+    #[default]
+    Pixels,
+    /// 向き情報（EXIF Orientation）のみを書き換える
+    Orientation,
+}
+
+/// 90度単位の回転・反転を、設定された保存方法で画像に適用する
+///
+/// `RotationMode::Orientation`では向き情報のみを書き換え、対応しない画像は
+/// 画素を書き換えて保存します。
 ///
 /// # Arguments
 ///
-/// * `image_path` - バックアップ対象だった画像ファイルのパス
+/// * `image_path` - 画像ファイルのパス
+/// * `transform` - 表示中の向きに加える変換
+/// * `page` - 複数ページのTIFFで変換するページ番号（0始まり）
+/// * `mode` - 保存方法
+/// * `jpeg_edges` - JPEGのサイズがMCUの倍数でない場合の扱い
+/// * `expected` - 読み込み時点のファイルのスタンプ（一致しなければ上書きしない）
+///
+/// # Errors
+///
+/// * `transform_orientation_only`・`transform_image_page`のエラー
+pub fn apply_transform(
+    image_path: &Path,
+    transform: Orientation,
+    page: usize,
+    mode: RotationMode,
+    jpeg_edges: EdgeMode,
+    expected: Option<FileStamp>,
+) -> Result<(), String> {
+    if mode == RotationMode::Orientation {
+        if transform_orientation_only(image_path, transform, expected)? {
+            return Ok(());
+        }
+        println!("[Img] 向き情報を書き換えられないため、画素を書き換えます");
+    }
+    transform_image_page(image_path, transform, page, jpeg_edges, expected)
+}
+
+/// 画像データを書き換えず、向き情報のみを更新して回転・反転させる
+///
+/// JPEG・TIFF・PNG・WebPは埋め込みEXIFのOrientationタグをその場で書き換えます。
+/// サイドカーXMPで向きを管理している画像と、デコーダーがコンテナ内の向きを適用する
+/// HEIF/AVIF・JPEG XL・カメラRAWはサイドカーXMPを更新します。
+///
+/// # Arguments
+///
+/// * `image_path` - 画像ファイルのパス
+/// * `transform` - 表示中の向きに加える変換
+/// * `expected` - 読み込み時点のファイルのスタンプ（一致しなければ上書きしない）
 ///
 /// # Returns
 ///
-/// * `Ok(())` - 成功
+/// * `Ok(true)` - 向き情報を更新した場合
+/// * `Ok(false)` - 書き換え可能な向き情報が無い場合（複数ページのTIFF、Orientationタグの無い画像など）
 /// * `Err(String)` - エラーメッセージ
-pub fn cleanup_image_backup(image_path: String) -> Result<(), String> {
-    let backup_path = format!("{}.backup", image_path);
-    let backup = std::path::Path::new(&backup_path);
+///
+/// # Errors
+///
+/// * ファイルの読み込み・書き込みに失敗した場合
+/// * 読み込み後にファイルが変更された場合
+/// * サイドカーXMPの更新に失敗した場合
+pub fn transform_orientation_only(
+    image_path: &Path,
+    transform: Orientation,
+    expected: Option<FileStamp>,
+) -> Result<bool, String> {
+    if transform.is_identity() {
+        return Ok(true);
+    }
+    let expected = current_stamp(image_path, expected)?;
 
-    if backup.exists() {
-        std::fs::remove_file(&backup_path)
-            .map_err(|e| format!("バックアップファイル削除に失敗しました: {}", e))?;
+    let mut bytes = std::fs::read(image_path)
+        .map_err(|e| format!("ファイルの読み込みに失敗しました: {}", e))?;
+    let applied = orientation::resolve_orientation(&bytes, image_path);
+    let target = applied
+        .map(|applied| applied.orientation)
+        .unwrap_or_default()
+        .then(transform);
+
+    let uses_sidecar = applied.is_some_and(|applied| applied.source == OrientationSource::Sidecar)
+        || is_jxl_path(image_path)
+        || raw::is_raw_path(image_path)
+        || detect_isobmff(&bytes)
+            .or_else(|| isobmff_kind_from_path(image_path))
+            .is_some();
+    if uses_sidecar {
+        orientation::write_sidecar_orientation(image_path, target)?;
+        return Ok(true);
     }
 
-    Ok(())
+    // 1つのタグでは表示中のページのみを変換できないため、複数ページのTIFFは対象外
+    if multipage::page_count(&bytes) > 1 || !metadata::set_orientation_in_place(&mut bytes, target)
+    {
+        return Ok(false);
+    }
+    safe_write::replace_file(image_path, &bytes, Some(expected))?;
+    Ok(true)
+}
+
+/// 上書き前に照合するスタンプを求める
+///
+/// `expected`が指定されていれば現在のファイルと照合し、無ければ現在のスタンプを返します。
+fn current_stamp(path: &Path, expected: Option<FileStamp>) -> Result<FileStamp, String> {
+    match expected {
+        Some(stamp) => safe_write::ensure_unchanged(path, stamp).map(|_| stamp),
+        None => FileStamp::read(path)
+            .ok_or_else(|| format!("ファイルの情報を取得できませんでした: {}", path.display())),
+    }
 }
 
 /// 画像を指定角度で回転させる
 ///
-/// 画像を90度単位で回転させ、元のファイルを上書きします。
+/// 先頭ページを画素の書き換えで回転させる`apply_transform`の簡易版です。
 ///
 /// # Arguments
 ///
@@ -111,55 +475,503 @@ pub fn cleanup_image_backup(image_path: String) -> Result<(), String> {
 ///
 /// # Errors
 ///
-/// * ファイルが存在しない場合
 /// * 回転角が90度単位でない場合
-/// * 画像の読み込みまたは保存に失敗した場合
+/// * `apply_transform`のエラー
 pub fn rotate_image(image_path: String, rotation_angle: f32) -> Result<String, String> {
-    let path = Path::new(&image_path);
+    let angle = (rotation_angle.round() as i32).rem_euclid(360);
+    if angle % 90 != 0 {
+        return Err("回転角は90度単位で指定してください".to_string());
+    }
+    apply_transform(
+        Path::new(&image_path),
+        Orientation::default().rotated_by(angle),
+        0,
+        RotationMode::Pixels,
+        EdgeMode::Reject,
+        None,
+    )?;
+    Ok(image_path)
+}
 
+/// 複数ページの画像の指定ページに、90度単位の回転・反転を加えて保存する
+///
+/// 画像を90度単位で回転させ、一時ファイルを経由して元のファイルを置き換えます。
+/// 処理中に他のアプリがファイルを変更した場合は上書きしません。
+/// JPEGは無劣化、JPEG XL・カメラRAW・アニメーション画像はサイドカーXMP、
+/// それ以外は再エンコードで保存します。
+/// 複数ページのTIFFは指定したページのみを変換し、全ページを書き戻します。
+///
+/// # Arguments
+///
+/// * `path` - 画像ファイルのパス
+/// * `transform` - 表示中の向きに加える変換
+/// * `page` - 変換するページ番号（0始まり、複数ページのTIFF以外は無視）
+/// * `jpeg_edges` - JPEGのサイズがMCUの倍数でない場合の扱い
+/// * `expected` - 読み込み時点のファイルのスタンプ（一致しなければ上書きしない）
+///
+/// # Errors
+///
+/// * ファイルが存在しない場合
+/// * ページ番号が範囲外の場合
+/// * 画像の読み込みまたは保存に失敗した場合
+/// * 処理中にファイルが変更された場合
+/// * サイドカーXMPの更新に失敗した場合
+pub fn transform_image_page(
+    path: &Path,
+    transform: Orientation,
+    page: usize,
+    jpeg_edges: EdgeMode,
+    expected: Option<FileStamp>,
+) -> Result<(), String> {
     // パスの検証
     if !path.exists() {
         return Err("指定されたファイルが存在しません".to_string());
     }
+    if transform.is_identity() {
+        return Ok(());
+    }
+
+    // 読み込み時（指定が無ければ処理開始時）から変更されたファイルは上書きしない
+    let expected = current_stamp(path, expected)?;
+
+    // JPEG XLは再エンコードせず、サイドカーXMPの向き情報を更新して無劣化で回転する
+    if is_jxl_path(path) {
+        let current = orientation::read_sidecar_orientation(path).unwrap_or_default();
+        return orientation::write_sidecar_orientation(path, current.then(transform));
+    }
+
+    // カメラRAWは書き換えず、現在の向き（サイドカーまたはRAW本体）を基準にサイドカーへ記録する
+    let bytes =
+        std::fs::read(path).map_err(|e| format!("ファイルの読み込みに失敗しました: {}", e))?;
+    if raw::is_raw_path(path) {
+        let current = current_orientation(&bytes, path);
+        return orientation::write_sidecar_orientation(path, current.then(transform));
+    }
 
-    // JPEG XL形式のチェック
-    if let Some(ext) = path.extension() {
-        if ext.to_string_lossy().to_lowercase() == "jxl" {
-            return Err("JPEG XL形式の画像編集は現在サポートされていません".to_string());
+    // アニメーション画像は再エンコードすると先頭フレームのみになるため、サイドカーに記録する
+    if animation::is_animated(&bytes, path) {
+        let current = current_orientation(&bytes, path);
+        return orientation::write_sidecar_orientation(path, current.then(transform));
+    }
+
+    // 複数ページのTIFFは表示中のページのみ変換し、他のページはそのまま書き戻す
+    if multipage::page_count(&bytes) > 1 {
+        return write_edited_page(path, &bytes, page, |img| Ok(transform.apply(img)), expected);
+    }
+
+    // JPEGは再エンコードせず、DCT係数の並べ替えで無劣化に回転・反転する
+    if jpeg_lossless::is_jpeg(&bytes) {
+        // 表示中の向き（サイドカーまたはEXIF）に変換を加えた向きを係数に反映する
+        let current = orientation::resolve_orientation(&bytes, path);
+        let target = current
+            .map(|applied| applied.orientation)
+            .unwrap_or_default()
+            .then(transform);
+        let transformed = jpeg_lossless::transform(&bytes, target, jpeg_edges)?;
+        safe_write::replace_file(path, &transformed, Some(expected))?;
+        if current.is_some_and(|applied| applied.source == OrientationSource::Sidecar) {
+            orientation::write_sidecar_orientation(path, Orientation::default())?;
         }
+        return Ok(());
     }
 
-    let normalized_angle = ((rotation_angle.round() as i32 % 360) + 360) % 360;
+    // 画像を読み込み（表示中の向きを適用済み）
+    let img =
+        decode_image(&bytes, path).map_err(|e| format!("画像の読み込みに失敗しました: {}", e))?;
+    write_edited_image(path, &bytes, &transform.apply(img), expected)
+}
 
-    if normalized_angle == 0 {
-        return Ok(image_path);
+/// 画像を任意の角度で回転（角度補正）して保存する
+///
+/// 画素の補間が必要なため再エンコードして保存します（JPEGも無劣化にはなりません）。
+/// 複数ページのTIFFは指定したページのみを回転させます。
+///
+/// # Arguments
+///
+/// * `path` - 画像ファイルのパス
+/// * `angle` - 表示中の向きに対する回転角度（度、時計回りが正、±45度まで）
+/// * `crop` - 回転で生じる透明な角を除くよう、最大の内接矩形で切り抜くか
+/// * `page` - 回転させるページ番号（0始まり、複数ページのTIFF以外は無視）
+/// * `expected` - 読み込み時点のファイルのスタンプ（一致しなければ上書きしない）
+///
+/// # Errors
+///
+/// * 角度が範囲外の場合
+/// * 画素を書き換えられない形式（JPEG XL・カメラRAW・HEIF/AVIF・アニメーション画像）の場合
+/// * 画像の読み込みまたは保存に失敗した場合
+/// * 読み込み後にファイルが変更された場合
+pub fn straighten_image(
+    path: &Path,
+    angle: f32,
+    crop: bool,
+    page: usize,
+    expected: Option<FileStamp>,
+) -> Result<(), String> {
+    if !angle.is_finite() || angle.abs() > straighten::MAX_ANGLE {
+        return Err(format!(
+            "角度は±{}度の範囲で指定してください",
+            straighten::MAX_ANGLE
+        ));
     }
+    if angle == 0.0 {
+        return Ok(());
+    }
+    edit_image(path, page, None, expected, |img| {
+        Ok(straighten::straighten(&img, angle, crop))
+    })
+}
 
-    if normalized_angle % 90 != 0 {
-        return Err("回転角は90度単位で指定してください".to_string());
+/// 画像を切り抜いて保存する
+///
+/// `output`が`None`なら元のファイルを上書きし、指定されていれば拡張子の形式で
+/// 新しいファイルに保存します（元のメタデータは引き継ぎます）。
+/// 複数ページのTIFFは指定したページのみを切り抜きます（別名で保存する場合はそのページのみ）。
+///
+/// # Arguments
+///
+/// * `path` - 画像ファイルのパス
+/// * `rect` - 表示中の向きを適用した画像での範囲（x, y, 幅, 高さ）
+/// * `page` - 切り抜くページ番号（0始まり、複数ページのTIFF以外は無視）
+/// * `output` - 保存先（`None`なら上書き）
+/// * `expected` - 読み込み時点のファイルのスタンプ（上書きする場合、一致しなければ保存しない）
+///
+/// # Errors
+///
+/// * 範囲が画像の外にある場合
+/// * 上書きで、画素を書き換えられない形式（JPEG XL・カメラRAW・HEIF/AVIF・アニメーション画像）の場合
+/// * 画像の読み込みまたは保存に失敗した場合
+/// * 上書きで、読み込み後にファイルが変更された場合
+pub fn crop_image(
+    path: &Path,
+    rect: (u32, u32, u32, u32),
+    page: usize,
+    output: Option<&Path>,
+    expected: Option<FileStamp>,
+) -> Result<(), String> {
+    let (x, y, width, height) = rect;
+    edit_image(path, page, output, expected, |img| {
+        if width == 0 || height == 0 || x + width > img.width() || y + height > img.height() {
+            return Err("切り抜き範囲が画像の外にあります".to_string());
+        }
+        Ok(img.crop_imm(x, y, width, height))
+    })
+}
+
+/// 画像に黒帯（レターボックス・ピラーボックス）を付けて保存する
+///
+/// 保存先と複数ページのTIFFの扱いは`crop_image`と同じです。
+///
+/// # Arguments
+///
+/// * `path` - 画像ファイルのパス
+/// * `letterbox` - 帯の縦横比・色・位置
+/// * `page` - 帯を付けるページ番号（0始まり、複数ページのTIFF以外は無視）
+/// * `output` - 保存先（`None`なら上書き）
+/// * `expected` - 読み込み時点のファイルのスタンプ（上書きする場合、一致しなければ保存しない）
+///
+/// # Errors
+///
+/// * 縦横比が正の値でない場合
+/// * `crop_image`のエラー（範囲に関するものを除く）
+pub fn letterbox_image(
+    path: &Path,
+    letterbox: &Letterbox,
+    page: usize,
+    output: Option<&Path>,
+    expected: Option<FileStamp>,
+) -> Result<(), String> {
+    if !(letterbox.aspect.is_finite() && letterbox.aspect > 0.0) {
+        return Err("縦横比は正の値で指定してください".to_string());
     }
+    edit_image(
+        path,
+        page,
+        output,
+        expected,
+        |img| Ok(letterbox.apply(&img)),
+    )
+}
+
+/// 画像に3D LUTを適用して保存する
+///
+/// 保存先と複数ページのTIFFの扱いは`crop_image`と同じです。
+/// ビット深度とアルファは元の画像を引き継ぎます。
+///
+/// # Arguments
+///
+/// * `path` - 画像ファイルのパス
+/// * `grade` - 適用するLUTと補間方法
+/// * `page` - 適用するページ番号（0始まり、複数ページのTIFF以外は無視）
+/// * `output` - 保存先（`None`なら上書き）
+/// * `expected` - 読み込み時点のファイルのスタンプ（上書きする場合、一致しなければ保存しない）
+///
+/// # Errors
+///
+/// * `crop_image`のエラー（範囲に関するものを除く）
+pub fn lut_image(
+    path: &Path,
+    grade: &LutGrade,
+    page: usize,
+    output: Option<&Path>,
+    expected: Option<FileStamp>,
+) -> Result<(), String> {
+    edit_image(path, page, output, expected, |img| Ok(grade.apply(&img)))
+}
 
-    // 画像を読み込み
-    let img = image::open(path).map_err(|e| format!("画像の読み込みに失敗しました: {}", e))?;
+/// 表示調整（露出・コントラスト・ホワイトバランス・彩度など）を画素に焼き込んで保存する
+///
+/// 保存先と複数ページのTIFFの扱いは`crop_image`と同じで、上書きは回転と同じ
+/// `write_edited_image`・`write_edited_page`で保存します。
+///
+/// # Arguments
+///
+/// * `path` - 画像ファイルのパス
+/// * `transform` - 焼き込む表示変換
+/// * `page` - 焼き込むページ番号（0始まり、複数ページのTIFF以外は無視）
+/// * `output` - 保存先（`None`なら上書き）
+/// * `expected` - 読み込み時点のファイルのスタンプ（上書きする場合、一致しなければ保存しない）
+///
+/// # Errors
+///
+/// * `crop_image`のエラー（範囲に関するものを除く）
+pub fn adjust_image(
+    path: &Path,
+    transform: &DisplayTransform,
+    page: usize,
+    output: Option<&Path>,
+    expected: Option<FileStamp>,
+) -> Result<(), String> {
+    edit_image(path, page, output, expected, |img| Ok(transform.bake(&img)))
+}
 
-    // 回転処理（90度単位での回転を想定）
-    let rotated_img = match normalized_angle {
-        90 => img.rotate90(),
-        180 => img.rotate180(),
-        270 => img.rotate270(),
-        _ => img, // 0度または無効な角度の場合はそのまま
+/// 画像を編集して、上書きまたは新しいファイルに保存する
+///
+/// `edit`には表示中の向きを適用した画像（複数ページのTIFFは指定したページ）を渡します。
+/// 上書きは`write_edited_image`・`write_edited_page`、新しいファイルは拡張子の形式で
+/// 元のメタデータを引き継いで保存します。
+///
+/// # Errors
+///
+/// * 画像の読み込み・エンコード・書き込みに失敗した場合
+/// * `edit`がエラーを返した場合
+/// * 上書きで、画素を書き換えられない形式の場合
+/// * 上書きで、読み込み後にファイルが変更された場合
+/// * 別名の保存先が元のファイルと同じ場合（上書きは`output`を`None`にする）
+pub fn edit_image(
+    path: &Path,
+    page: usize,
+    output: Option<&Path>,
+    expected: Option<FileStamp>,
+    edit: impl FnOnce(DynamicImage) -> Result<DynamicImage, String>,
+) -> Result<(), String> {
+    // 上書きする場合は、読み込み前に変更を確認する
+    let expected = match output {
+        // 変更の確認・履歴・形式の確認を経ずに元のファイルを置き換えないようにする
+        Some(output) if safe_write::path_key(output) == safe_write::path_key(path) => {
+            return Err("元のファイルと同じ保存先は選べません".to_string());
+        }
+        Some(_) => None,
+        None => Some(current_stamp(path, expected)?),
     };
+    let bytes =
+        std::fs::read(path).map_err(|e| format!("ファイルの読み込みに失敗しました: {}", e))?;
+    let multipage = multipage::page_count(&bytes) > 1;
+
+    // 別名で保存する場合は、元のファイルに手を加えない
+    if let Some(output) = output {
+        let img = decode_page_or_image(&bytes, path, page)?;
+        let format = ImageFormat::from_path(output).unwrap_or(ImageFormat::Png);
+        let mut metadata = Metadata::read_page(&bytes, page);
+        metadata.reset_orientation();
+        let encoded = encode_with_metadata(&edit(img)?, format, &metadata)?;
+        return safe_write::write_atomic(output, &encoded);
+    }
+
+    let expected = expected.expect("上書きする場合はスタンプを取得済み");
+    ensure_pixels_writable(path, &bytes)?;
+    if multipage {
+        return write_edited_page(path, &bytes, page, edit, expected);
+    }
+    let img =
+        decode_image(&bytes, path).map_err(|e| format!("画像の読み込みに失敗しました: {}", e))?;
+    write_edited_image(path, &bytes, &edit(img)?, expected)
+}
 
+/// 複数ページのTIFFは指定したページを、それ以外は画像をデコードする（表示中の向きを適用済み）
+pub fn decode_page_or_image(
+    bytes: &[u8],
+    path: &Path,
+    page: usize,
+) -> Result<DynamicImage, String> {
+    if multipage::page_count(bytes) > 1 {
+        multipage::decode_page(bytes, path, page)
+    } else {
+        decode_image(bytes, path)
+    }
+}
+
+/// 編集した画像を別名で保存する際の既定の保存先（`<名前>_<suffix>.<拡張子>`）
+///
+/// 書き出しに対応しない形式（RAW・JPEG XL・HEIFなど）はPNGで保存します。
+pub fn edited_output_path(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .filter(|e| {
+            matches!(
+                e.as_str(),
+                "jpg" | "jpeg" | "png" | "webp" | "tif" | "tiff" | "bmp"
+            )
+        })
+        .unwrap_or_else(|| "png".to_string());
+    path.with_file_name(format!("{}_{}.{}", stem, suffix, extension))
+}
+
+/// 画素を書き換えて上書き保存できる形式か確認する
+///
+/// JPEG XL・HEIF/AVIFは書き出しに対応せず、カメラRAWは現像前のデータを失い、
+/// アニメーション画像は先頭フレームのみになるため、上書きしません。
+fn ensure_pixels_writable(path: &Path, bytes: &[u8]) -> Result<(), String> {
+    if is_jxl_path(path)
+        || raw::is_raw_path(path)
+        || detect_isobmff(bytes)
+            .or_else(|| isobmff_kind_from_path(path))
+            .is_some()
+        || animation::is_animated(bytes, path)
+    {
+        return Err("この形式の画像は画素を書き換えて保存できません".to_string());
+    }
+    Ok(())
+}
+
+/// 編集した画像を元の形式・元のメタデータで保存する
+///
+/// 表示中の向きは画素に反映済みとして、メタデータのOrientationとサイドカーXMPの
+/// 向きは1（変換なし）に戻します。
+///
+/// # Arguments
+///
+/// * `path` - 画像ファイルのパス
+/// * `bytes` - 編集前のファイル内容（メタデータの引き継ぎに使用）
+/// * `edited` - 編集後の画像
+/// * `expected` - 編集前のファイルのスタンプ（一致しなければ上書きしない）
+///
+/// # Errors
+///
+/// * エンコード・メタデータの埋め込み・書き込みに失敗した場合
+/// * 読み込み後にファイルが変更された場合
+pub fn write_edited_image(
+    path: &Path,
+    bytes: &[u8],
+    edited: &DynamicImage,
+    expected: FileStamp,
+) -> Result<(), String> {
     // 元の画像形式を推測
-    let format = image::ImageFormat::from_path(path).unwrap_or(ImageFormat::Png);
+    let format = image::guess_format(bytes)
+        .ok()
+        .or_else(|| ImageFormat::from_path(path).ok())
+        .unwrap_or(ImageFormat::Png);
 
-    // 元のファイルを直接上書き
-    rotated_img
-        .save_with_format(&image_path, format)
-        .map_err(|e| format!("回転した画像の保存に失敗しました: {}", e))?;
+    // 向きは画素に反映したため、引き継ぐメタデータのOrientationは1に戻す
+    let mut metadata = Metadata::read(bytes);
+    metadata.reset_orientation();
+    let encoded = encode_with_metadata(edited, format, &metadata)?;
 
-    // 元のパスをそのまま返す
-    Ok(image_path)
+    // 一時ファイルを経由して元のファイルを置き換える
+    safe_write::replace_file(path, &encoded, Some(expected))?;
+    if orientation::read_sidecar_orientation(path).is_some() {
+        orientation::write_sidecar_orientation(path, Orientation::default())?;
+    }
+    Ok(())
+}
+
+/// 複数ページのTIFFの1ページを編集し、全ページを書き戻す
+///
+/// EXIFの向きは全ページの画素に反映し、各ページのOrientationタグを1に戻します。
+///
+/// # Errors
+///
+/// * ページのデコード・エンコード・書き込みに失敗した場合
+/// * ページ番号が範囲外の場合
+/// * `edit`がエラーを返した場合
+/// * 読み込み後にファイルが変更された場合
+pub fn write_edited_page(
+    path: &Path,
+    bytes: &[u8],
+    page: usize,
+    edit: impl FnOnce(DynamicImage) -> Result<DynamicImage, String>,
+    expected: FileStamp,
+) -> Result<(), String> {
+    let mut pages = multipage::decode_all_pages(bytes)?;
+    let mut metadata: Vec<Metadata> = (0..pages.len())
+        .map(|i| Metadata::read_page(bytes, i))
+        .collect();
+    // EXIFの向きは画素に反映し、各ページのOrientationタグを1に戻す
+    if let Some(applied) = orientation::resolve_orientation(bytes, path)
+        .filter(|applied| applied.source == OrientationSource::Exif)
+    {
+        pages = pages
+            .into_iter()
+            .map(|page| applied.orientation.apply(page))
+            .collect();
+        metadata.iter_mut().for_each(Metadata::reset_orientation);
+    }
+    if page >= pages.len() {
+        return Err(format!("ページ{}が存在しません", page + 1));
+    }
+    let target = pages.remove(page);
+    pages.insert(page, edit(target)?);
+    let encoded = metadata::embed_tiff_pages(multipage::encode_pages(&pages)?, &metadata)?;
+    safe_write::replace_file(path, &encoded, Some(expected))
+}
+
+/// JPEGで再エンコードする際の品質
+pub const JPEG_QUALITY: u8 = 95;
+
+/// 画像をエンコードし、メタデータ（EXIF・XMP・IPTC・ICC）を埋め込んだファイル内容を返す
+///
+/// JPEGはアルファチャンネルと16bit以上の精度を持てないため、8bitのRGB
+/// （グレースケールはそのまま）に変換して品質95で保存します。
+///
+/// # Errors
+///
+/// * 指定形式でエンコードできない場合
+/// * メタデータの埋め込みに失敗した場合
+pub fn encode_with_metadata(
+    img: &DynamicImage,
+    format: ImageFormat,
+    metadata: &Metadata,
+) -> Result<Vec<u8>, String> {
+    encode_with_quality(img, format, metadata, JPEG_QUALITY)
+}
+
+/// `encode_with_metadata`と同じく、JPEGの品質（1〜100）を指定してエンコードする
+///
+/// # Errors
+///
+/// * `encode_with_metadata`のエラー
+pub fn encode_with_quality(
+    img: &DynamicImage,
+    format: ImageFormat,
+    metadata: &Metadata,
+    jpeg_quality: u8,
+) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    let result = if format == ImageFormat::Jpeg {
+        let encoder = JpegEncoder::new_with_quality(&mut bytes, jpeg_quality.clamp(1, 100));
+        match img {
+            DynamicImage::ImageLuma8(_) => img.write_with_encoder(encoder),
+            _ => DynamicImage::ImageRgb8(img.to_rgb8()).write_with_encoder(encoder),
+        }
+    } else {
+        img.write_to(&mut std::io::Cursor::new(&mut bytes), format)
+    };
+    result.map_err(|e| format!("画像のエンコードに失敗しました: {}", e))?;
+    metadata.embed(bytes, format)
 }
 
 /// 起動時の引数から画像ファイルパスを取得する
@@ -185,3 +997,175 @@ pub fn get_launch_image_path() -> Option<String> {
 pub fn get_launch_window_mode() -> Option<String> {
     crate::cli_args::LaunchConfig::from_args().window_mode
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GenericImageView, Rgb, RgbImage};
+
+    #[test]
+    fn test_rotation_keeps_metadata() {
+        let dir = std::env::temp_dir().join(format!("vdi_rotate_metadata_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = metadata::sample_metadata();
+        let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(32, 16, Rgb([200, 40, 90])));
+
+        for (name, format) in [
+            ("a.jpg", ImageFormat::Jpeg),
+            ("a.png", ImageFormat::Png),
+            ("a.webp", ImageFormat::WebP),
+            ("a.tif", ImageFormat::Tiff),
+        ] {
+            let path = dir.join(name);
+            let bytes = encode_with_metadata(&img, format, &source).unwrap();
+            std::fs::write(&path, bytes).unwrap();
+
+            // EXIFの向き（90度）に、さらに90度の回転を加える
+            rotate_image(path.to_string_lossy().to_string(), 90.0).unwrap();
+
+            let bytes = std::fs::read(&path).unwrap();
+            let rotated = Metadata::read(&bytes);
+            assert_eq!(
+                orientation::read_exif_orientation(&bytes),
+                Some(Orientation::default()),
+                "{}: Orientationは1に戻す",
+                name
+            );
+            assert!(
+                String::from_utf8_lossy(rotated.xmp.as_deref().unwrap())
+                    .contains("tiff:Orientation=\"1\""),
+                "{}: XMPのOrientationも1に戻す",
+                name
+            );
+            assert_eq!(rotated.icc, source.icc, "{}", name);
+            if format != ImageFormat::WebP {
+                assert_eq!(rotated.iptc, source.iptc, "{}", name);
+            }
+            assert!(rotated.exif.is_some(), "{}", name);
+
+            // 180度回転した画素が、向きの無い状態で保存される
+            let decoded = image::load_from_memory(&bytes).unwrap();
+            assert_eq!(decoded.dimensions(), (32, 16), "{}", name);
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_transform_orientation_only() {
+        let dir =
+            std::env::temp_dir().join(format!("vdi_rotate_orientation_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(30, 20, Rgb([20, 120, 220])));
+
+        // EXIFの向き（90度）に90度を加えると180度（Orientation 3）になる
+        let path = dir.join("a.jpg");
+        let original =
+            encode_with_metadata(&img, ImageFormat::Jpeg, &metadata::sample_metadata()).unwrap();
+        std::fs::write(&path, &original).unwrap();
+        assert_eq!(
+            transform_orientation_only(&path, Orientation::default().rotated_by(90), None),
+            Ok(true)
+        );
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(bytes.len(), original.len());
+        assert_eq!(
+            orientation::read_exif_orientation(&bytes),
+            Orientation::from_exif(3)
+        );
+        assert_eq!(decode_image(&bytes, &path).unwrap().dimensions(), (30, 20));
+
+        // Orientationタグの無い画像は書き換えない
+        let path = dir.join("b.png");
+        let original = encode_with_metadata(&img, ImageFormat::Png, &Metadata::default()).unwrap();
+        std::fs::write(&path, &original).unwrap();
+        assert_eq!(
+            transform_orientation_only(&path, Orientation::default().rotated_by(90), None),
+            Ok(false)
+        );
+        assert_eq!(std::fs::read(&path).unwrap(), original);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_crop_image() {
+        let dir = std::env::temp_dir().join(format!("vdi_crop_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(30, 20, Rgb([20, 120, 220])));
+        let path = dir.join("a.png");
+        let original =
+            encode_with_metadata(&img, ImageFormat::Png, &metadata::sample_metadata()).unwrap();
+        std::fs::write(&path, &original).unwrap();
+
+        // 範囲は表示中の向き（EXIFの90度回転で20x30）が基準
+        assert!(crop_image(&path, (10, 0, 20, 5), 0, None, None).is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), original);
+
+        // 別名で保存しても元のファイルは変わらない
+        let output = dir.join("a_crop.jpg");
+        crop_image(&path, (2, 3, 10, 12), 0, Some(&output), None).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), original);
+        let saved = std::fs::read(&output).unwrap();
+
+        // 別名の保存先に元のファイルを選んでも上書きしない
+        assert!(crop_image(&path, (2, 3, 10, 12), 0, Some(&path), None).is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), original);
+        assert_eq!(
+            decode_image(&saved, &output).unwrap().dimensions(),
+            (10, 12)
+        );
+
+        // 上書きでは向きを画素に反映し、Orientationを1に戻す
+        crop_image(&path, (2, 3, 10, 12), 0, None, None).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(
+            orientation::read_exif_orientation(&bytes),
+            Orientation::from_exif(1)
+        );
+        assert_eq!(decode_image(&bytes, &path).unwrap().dimensions(), (10, 12));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_detect_isobmff() {
+        let ftyp = |major: &[u8; 4], compatible: &[&[u8; 4]]| {
+            let size = 16 + compatible.len() * 4;
+            let mut bytes = (size as u32).to_be_bytes().to_vec();
+            bytes.extend_from_slice(b"ftyp");
+            bytes.extend_from_slice(major);
+            bytes.extend_from_slice(&[0; 4]);
+            for brand in compatible {
+                bytes.extend_from_slice(&brand[..]);
+            }
+            // 続くボックスのブランド風のデータは対象にしない
+            bytes.extend_from_slice(b"\0\0\0\x08avif");
+            bytes
+        };
+        assert_eq!(
+            detect_isobmff(&ftyp(b"mif1", &[b"mif1", b"avif", b"miaf"])),
+            Some(IsoBmffKind::Avif)
+        );
+        assert_eq!(
+            detect_isobmff(&ftyp(b"heic", &[b"mif1", b"heic"])),
+            Some(IsoBmffKind::Heif)
+        );
+        assert_eq!(
+            detect_isobmff(&ftyp(b"mif1", &[b"mif1", b"miaf"])),
+            Some(IsoBmffKind::Heif)
+        );
+        assert_eq!(detect_isobmff(&ftyp(b"isom", &[b"mp41"])), None);
+    }
+
+    #[test]
+    fn test_edited_output_path() {
+        assert_eq!(
+            edited_output_path(Path::new("/photos/a.JPG"), "crop"),
+            PathBuf::from("/photos/a_crop.jpg")
+        );
+        assert_eq!(
+            edited_output_path(Path::new("/photos/b.cr2"), "letterbox"),
+            PathBuf::from("/photos/b_letterbox.png")
+        );
+    }
+}
@@ -0,0 +1,1329 @@
+use crate::metadata;
+use crate::orientation::Orientation;
+
+/// 自然順（行優先）の位置 → ジグザグ順の番号
+const NATURAL_TO_ZIGZAG: [usize; 64] = [
+    0, 1, 5, 6, 14, 15, 27, 28, 2, 4, 7, 13, 16, 26, 29, 42, 3, 8, 12, 17, 25, 30, 41, 43, 9, 11,
+    18, 24, 31, 40, 44, 53, 10, 19, 23, 32, 39, 45, 52, 54, 20, 22, 33, 38, 46, 51, 55, 60, 21, 34,
+    37, 47, 50, 56, 59, 61, 35, 36, 48, 49, 57, 58, 62, 63,
+];
+
+/// ジグザグ順の番号 → 自然順（行優先）の位置
+const ZIGZAG_TO_NATURAL: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20,
+    13, 6, 7, 14, 21, 28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59,
+    52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+/// DCT係数のブロック（ジグザグ順）
+type Block = [i16; 64];
+
+/// MCU境界に揃わない端の扱い
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeMode {
+    /// 端の不完全なブロックが左端・上端に移動する変換はエラーにする
+    Reject,
+    /// 揃わない端を切り捨てて変換する（jpegtranの`-trim`相当）
+    Trim,
+}
+
+/// ファイル内容がJPEGかどうか
+pub fn is_jpeg(bytes: &[u8]) -> bool {
+    bytes.starts_with(&[0xFF, 0xD8, 0xFF])
+}
+
+/// JPEGを再圧縮せずに回転・反転する
+///
+/// DCT係数のブロックを並べ替え・転置するため、画質は劣化しません。EXIF・ICCなどの
+/// メタデータはそのまま引き継ぎ、EXIF・XMPのOrientationは画素に反映したうえで1に戻します。
+/// 出力はハフマン符号を最適化したベースラインJPEGです。
+///
+/// # Arguments
+///
+/// * `bytes` - JPEGファイルの内容
+/// * `orientation` - 画素に適用する向き（例: 90度回転はEXIF値6）
+/// * `edges` - 画像サイズがMCUの倍数でない場合の扱い
+///
+/// # Errors
+///
+/// * JPEGとして解析できない場合、未対応の形式（算術符号・ロスレス・階層型）の場合
+/// * `EdgeMode::Reject`で、端のブロックが移動する変換を指定した場合
+pub fn transform(
+    bytes: &[u8],
+    orientation: Orientation,
+    edges: EdgeMode,
+) -> Result<Vec<u8>, String> {
+    let mut jpeg = parse(bytes)?;
+    let op = BlockTransform::from_orientation(orientation);
+    let frame = op.apply(&jpeg.frame, edges)?;
+    if op.transpose {
+        for table in jpeg.quant_tables.iter_mut().flatten() {
+            *table = transpose_table(table);
+        }
+    }
+    for segment in &mut jpeg.metadata {
+        metadata::reset_jpeg_segment_orientation(segment);
+    }
+    Ok(encode(&jpeg.metadata, &jpeg.quant_tables, &frame))
+}
+
+/// 画像成分（Y・Cb・Crなど）
+struct Component {
+    id: u8,
+    h: usize,
+    v: usize,
+    quant_table: usize,
+    /// MCU単位に切り上げた横方向のブロック数
+    blocks_w: usize,
+    /// MCU単位に切り上げた縦方向のブロック数
+    blocks_h: usize,
+    blocks: Vec<Block>,
+}
+
+/// フレーム（画像サイズと成分の係数）
+struct Frame {
+    precision: u8,
+    width: usize,
+    height: usize,
+    components: Vec<Component>,
+}
+
+impl Frame {
+    fn new(precision: u8, width: usize, height: usize, components: Vec<Component>) -> Self {
+        let mut frame = Self {
+            precision,
+            width,
+            height,
+            components,
+        };
+        let (mcux, mcuy) = frame.mcu_count();
+        for component in &mut frame.components {
+            component.blocks_w = mcux * component.h;
+            component.blocks_h = mcuy * component.v;
+            component.blocks = vec![[0; 64]; component.blocks_w * component.blocks_h];
+        }
+        frame
+    }
+
+    fn max_sampling(&self) -> (usize, usize) {
+        let h = self.components.iter().map(|c| c.h).max().unwrap_or(1);
+        let v = self.components.iter().map(|c| c.v).max().unwrap_or(1);
+        (h, v)
+    }
+
+    /// MCUのサイズ（ピクセル）
+    fn mcu_size(&self) -> (usize, usize) {
+        let (h, v) = self.max_sampling();
+        (8 * h, 8 * v)
+    }
+
+    /// MCUの数（横, 縦）
+    fn mcu_count(&self) -> (usize, usize) {
+        let (w, h) = self.mcu_size();
+        (self.width.div_ceil(w), self.height.div_ceil(h))
+    }
+
+    /// 成分が画像を覆うのに必要なブロック数（MCUの切り上げを含まない）
+    fn component_blocks(&self, index: usize) -> (usize, usize) {
+        let (max_h, max_v) = self.max_sampling();
+        let component = &self.components[index];
+        (
+            (self.width * component.h).div_ceil(max_h).div_ceil(8),
+            (self.height * component.v).div_ceil(max_v).div_ceil(8),
+        )
+    }
+
+    /// スキャン内のMCUを順に走査し、各MCUに含まれるブロック（成分の番号, ブロックの位置）を渡す
+    ///
+    /// 1成分のみのスキャンは非インターリーブで、ブロック1つが1つのMCUになります。
+    fn for_each_mcu(
+        &self,
+        scan_components: &[usize],
+        mut f: impl FnMut(&[(usize, usize)]) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let mut mcu = Vec::new();
+        if let [index] = scan_components {
+            let (w, h) = self.component_blocks(*index);
+            let blocks_w = self.components[*index].blocks_w;
+            for y in 0..h {
+                for x in 0..w {
+                    mcu.clear();
+                    mcu.push((0, y * blocks_w + x));
+                    f(&mcu)?;
+                }
+            }
+            return Ok(());
+        }
+
+        let (mcux, mcuy) = self.mcu_count();
+        for my in 0..mcuy {
+            for mx in 0..mcux {
+                mcu.clear();
+                for (i, &index) in scan_components.iter().enumerate() {
+                    let c = &self.components[index];
+                    for by in 0..c.v {
+                        for bx in 0..c.h {
+                            mcu.push((i, (my * c.v + by) * c.blocks_w + mx * c.h + bx));
+                        }
+                    }
+                }
+                f(&mcu)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 解析済みのJPEG
+struct Jpeg {
+    /// APPn・COMセグメント（マーカーを含む）
+    metadata: Vec<Vec<u8>>,
+    /// 量子化テーブル（ジグザグ順）
+    quant_tables: [Option<[u16; 64]>; 4],
+    frame: Frame,
+}
+
+/// JPEGを解析し、DCT係数まで復号する
+fn parse(bytes: &[u8]) -> Result<Jpeg, String> {
+    if !is_jpeg(bytes) {
+        return Err("JPEGファイルではありません".to_string());
+    }
+
+    let mut metadata = Vec::new();
+    let mut quant_tables = [None; 4];
+    let mut dc_tables: [Option<HuffmanDecoder>; 4] = Default::default();
+    let mut ac_tables: [Option<HuffmanDecoder>; 4] = Default::default();
+    let mut frame: Option<Frame> = None;
+    let mut progressive = false;
+    let mut restart_interval = 0;
+    let mut pos = 2;
+
+    while let Some(marker) = next_marker(bytes, &mut pos) {
+        match marker {
+            0xD9 => break,
+            0xD0..=0xD7 | 0x01 => continue,
+            _ => {}
+        }
+        let segment = read_segment(bytes, pos)?;
+        let body = &segment[4..];
+        pos += segment.len() - 2;
+
+        match marker {
+            0xE0..=0xEF | 0xFE => metadata.push(segment.to_vec()),
+            0xDB => parse_quant_tables(body, &mut quant_tables)?,
+            0xC4 => parse_huffman_tables(body, &mut dc_tables, &mut ac_tables)?,
+            0xDD => {
+                restart_interval = read_u16(body, 0)? as usize;
+            }
+            0xC0..=0xC2 => {
+                progressive = marker == 0xC2;
+                frame = Some(parse_frame(body)?);
+            }
+            0xC3 | 0xC5..=0xC7 | 0xC8..=0xCB | 0xCD..=0xCF => {
+                return Err("算術符号・ロスレス・階層型のJPEGには対応していません".to_string());
+            }
+            0xDA => {
+                let frame = frame
+                    .as_mut()
+                    .ok_or_else(|| "SOFより前にスキャンがあります".to_string())?;
+                let scan = parse_scan(body, frame)?;
+                let mut decoder = ScanDecoder {
+                    reader: BitReader::new(&bytes[pos..]),
+                    dc_tables: &dc_tables,
+                    ac_tables: &ac_tables,
+                    eobrun: 0,
+                };
+                decoder.decode(frame, &scan, progressive, restart_interval)?;
+                pos += decoder.reader.pos;
+            }
+            _ => {}
+        }
+    }
+
+    let frame = frame.ok_or_else(|| "JPEGに画像データがありません".to_string())?;
+    for component in &frame.components {
+        if quant_tables[component.quant_table].is_none() {
+            return Err("JPEGの量子化テーブルが見つかりません".to_string());
+        }
+    }
+    Ok(Jpeg {
+        metadata,
+        quant_tables,
+        frame,
+    })
+}
+
+/// 次のマーカーまで進み、マーカーの種類を返す（ファイル末尾に達した場合は`None`）
+fn next_marker(bytes: &[u8], pos: &mut usize) -> Option<u8> {
+    while *pos + 1 < bytes.len() {
+        let (byte, next) = (bytes[*pos], bytes[*pos + 1]);
+        if byte == 0xFF && next != 0x00 && next != 0xFF {
+            *pos += 2;
+            return Some(next);
+        }
+        *pos += 1;
+    }
+    None
+}
+
+/// マーカーから始まるセグメント全体（`pos`はマーカー直後の長さフィールド）
+fn read_segment(bytes: &[u8], pos: usize) -> Result<&[u8], String> {
+    let length = read_u16(bytes, pos)? as usize;
+    if length < 2 || pos + length > bytes.len() {
+        return Err("JPEGのセグメント長が不正です".to_string());
+    }
+    Ok(&bytes[pos - 2..pos + length])
+}
+
+fn read_u16(bytes: &[u8], pos: usize) -> Result<u16, String> {
+    bytes
+        .get(pos..pos + 2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+        .ok_or_else(|| "JPEGのデータが途中で終わっています".to_string())
+}
+
+fn parse_quant_tables(mut body: &[u8], tables: &mut [Option<[u16; 64]>; 4]) -> Result<(), String> {
+    while let Some(&info) = body.first() {
+        let (sixteen_bit, id) = (info >> 4 != 0, (info & 0x0F) as usize);
+        let size = if sixteen_bit { 128 } else { 64 };
+        let values = body
+            .get(1..1 + size)
+            .filter(|_| id < 4)
+            .ok_or_else(|| "JPEGの量子化テーブルが不正です".to_string())?;
+        let mut table = [0u16; 64];
+        for (k, value) in table.iter_mut().enumerate() {
+            *value = if sixteen_bit {
+                u16::from_be_bytes([values[2 * k], values[2 * k + 1]])
+            } else {
+                values[k] as u16
+            };
+        }
+        tables[id] = Some(table);
+        body = &body[1 + size..];
+    }
+    Ok(())
+}
+
+fn parse_huffman_tables(
+    mut body: &[u8],
+    dc_tables: &mut [Option<HuffmanDecoder>; 4],
+    ac_tables: &mut [Option<HuffmanDecoder>; 4],
+) -> Result<(), String> {
+    let invalid = || "JPEGのハフマンテーブルが不正です".to_string();
+    while let Some(&info) = body.first() {
+        let (class, id) = (info >> 4, (info & 0x0F) as usize);
+        let counts: [u8; 16] = body
+            .get(1..17)
+            .ok_or_else(invalid)?
+            .try_into()
+            .map_err(|_| invalid())?;
+        let total: usize = counts.iter().map(|&c| c as usize).sum();
+        let values = body.get(17..17 + total).ok_or_else(invalid)?.to_vec();
+        let table = HuffmanDecoder::new(&counts, values)?;
+        match (class, id) {
+            (0, 0..=3) => dc_tables[id] = Some(table),
+            (1, 0..=3) => ac_tables[id] = Some(table),
+            _ => return Err(invalid()),
+        }
+        body = &body[17 + total..];
+    }
+    Ok(())
+}
+
+fn parse_frame(body: &[u8]) -> Result<Frame, String> {
+    let invalid = || "JPEGのフレームヘッダーが不正です".to_string();
+    let precision = *body.first().ok_or_else(invalid)?;
+    let height = read_u16(body, 1)? as usize;
+    let width = read_u16(body, 3)? as usize;
+    let count = *body.get(5).ok_or_else(invalid)? as usize;
+    if width == 0 || height == 0 {
+        return Err("高さが後から定義されるJPEG（DNL）には対応していません".to_string());
+    }
+
+    let mut components = Vec::with_capacity(count);
+    for i in 0..count {
+        let spec = body.get(6 + i * 3..9 + i * 3).ok_or_else(invalid)?;
+        let (h, v) = ((spec[1] >> 4) as usize, (spec[1] & 0x0F) as usize);
+        if !(1..=4).contains(&h) || !(1..=4).contains(&v) || spec[2] > 3 {
+            return Err(invalid());
+        }
+        components.push(Component {
+            id: spec[0],
+            h,
+            v,
+            quant_table: spec[2] as usize,
+            blocks_w: 0,
+            blocks_h: 0,
+            blocks: Vec::new(),
+        });
+    }
+    if components.is_empty() || components.len() > 4 {
+        return Err(invalid());
+    }
+    Ok(Frame::new(precision, width, height, components))
+}
+
+/// スキャンヘッダー
+struct Scan {
+    /// スキャンに含まれる成分（フレーム内の番号）
+    components: Vec<usize>,
+    /// 各成分のハフマンテーブル番号（DC, AC）
+    tables: Vec<(usize, usize)>,
+    spectral_start: usize,
+    spectral_end: usize,
+    approx_high: u8,
+    approx_low: u8,
+}
+
+fn parse_scan(body: &[u8], frame: &Frame) -> Result<Scan, String> {
+    let invalid = || "JPEGのスキャンヘッダーが不正です".to_string();
+    let count = *body.first().ok_or_else(invalid)? as usize;
+    let mut components = Vec::with_capacity(count);
+    let mut tables = Vec::with_capacity(count);
+    for i in 0..count {
+        let spec = body.get(1 + i * 2..3 + i * 2).ok_or_else(invalid)?;
+        let index = frame
+            .components
+            .iter()
+            .position(|c| c.id == spec[0])
+            .ok_or_else(invalid)?;
+        components.push(index);
+        tables.push(((spec[1] >> 4) as usize & 3, (spec[1] & 0x0F) as usize & 3));
+    }
+    let params = body.get(1 + count * 2..4 + count * 2).ok_or_else(invalid)?;
+    let scan = Scan {
+        components,
+        tables,
+        spectral_start: params[0] as usize,
+        spectral_end: params[1] as usize,
+        approx_high: params[2] >> 4,
+        approx_low: params[2] & 0x0F,
+    };
+    if scan.components.is_empty()
+        || scan.spectral_end > 63
+        || scan.spectral_start > scan.spectral_end
+    {
+        return Err(invalid());
+    }
+    Ok(scan)
+}
+
+/// 復号用のハフマンテーブル（符号長ごとの最小・最大符号による標準的な方式）
+struct HuffmanDecoder {
+    max_code: [i32; 18],
+    min_code: [i32; 17],
+    value_offset: [usize; 17],
+    values: Vec<u8>,
+}
+
+impl HuffmanDecoder {
+    fn new(counts: &[u8; 16], values: Vec<u8>) -> Result<Self, String> {
+        let mut max_code = [-1; 18];
+        let mut min_code = [0; 17];
+        let mut value_offset = [0; 17];
+        let (mut code, mut k) = (0i32, 0usize);
+        for len in 1..=16 {
+            let count = counts[len - 1] as usize;
+            value_offset[len] = k;
+            min_code[len] = code;
+            code += count as i32;
+            k += count;
+            if count > 0 {
+                max_code[len] = code - 1;
+            }
+            if code > 1 << len {
+                return Err("JPEGのハフマンテーブルが不正です".to_string());
+            }
+            code <<= 1;
+        }
+        // 16ビットを超える符号は存在しないため、番兵として最大値を置く
+        max_code[17] = i32::MAX;
+        Ok(Self {
+            max_code,
+            min_code,
+            value_offset,
+            values,
+        })
+    }
+}
+
+/// スキャンの符号化データを読むビットリーダー（0xFF00のスタッフィングとRSTマーカーを処理する）
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buffer: u32,
+    bits: u32,
+    marker_reached: bool,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            pos: 0,
+            buffer: 0,
+            bits: 0,
+            marker_reached: false,
+        }
+    }
+
+    fn fill(&mut self) {
+        while self.bits <= 24 {
+            let byte = if self.marker_reached || self.pos >= self.data.len() {
+                // マーカー以降（データ不足）は0で埋める
+                self.marker_reached = true;
+                0
+            } else if self.data[self.pos] == 0xFF {
+                if self.data.get(self.pos + 1) == Some(&0x00) {
+                    self.pos += 2;
+                    0xFF
+                } else {
+                    self.marker_reached = true;
+                    0
+                }
+            } else {
+                self.pos += 1;
+                self.data[self.pos - 1]
+            };
+            self.buffer |= (byte as u32) << (24 - self.bits);
+            self.bits += 8;
+        }
+    }
+
+    fn read_bits(&mut self, count: u32) -> u32 {
+        if count == 0 {
+            return 0;
+        }
+        if self.bits < count {
+            self.fill();
+        }
+        let value = self.buffer >> (32 - count);
+        self.buffer <<= count;
+        self.bits -= count;
+        value
+    }
+
+    /// 符号付きの値を読む（JPEGのEXTEND手順）
+    fn read_signed(&mut self, size: u32) -> i32 {
+        let value = self.read_bits(size) as i32;
+        if size > 0 && value < 1 << (size - 1) {
+            value - (1 << size) + 1
+        } else {
+            value
+        }
+    }
+
+    fn decode(&mut self, table: &HuffmanDecoder) -> Result<u8, String> {
+        let mut code = self.read_bits(1) as i32;
+        let mut len = 1;
+        while code > table.max_code[len] {
+            code = (code << 1) | self.read_bits(1) as i32;
+            len += 1;
+        }
+        if len > 16 {
+            return Err("JPEGのハフマン符号が不正です".to_string());
+        }
+        let index = table.value_offset[len] + (code - table.min_code[len]) as usize;
+        table
+            .values
+            .get(index)
+            .copied()
+            .ok_or_else(|| "JPEGのハフマン符号が不正です".to_string())
+    }
+
+    /// リスタート区間の境界で、ビットを捨ててRSTマーカーを読み飛ばす
+    fn restart(&mut self) {
+        self.buffer = 0;
+        self.bits = 0;
+        self.marker_reached = false;
+        if self.data.get(self.pos) == Some(&0xFF)
+            && matches!(self.data.get(self.pos + 1), Some(0xD0..=0xD7))
+        {
+            self.pos += 2;
+        }
+    }
+}
+
+/// 1スキャン分の係数を復号する
+struct ScanDecoder<'a> {
+    reader: BitReader<'a>,
+    dc_tables: &'a [Option<HuffmanDecoder>; 4],
+    ac_tables: &'a [Option<HuffmanDecoder>; 4],
+    eobrun: u32,
+}
+
+impl ScanDecoder<'_> {
+    fn decode(
+        &mut self,
+        frame: &mut Frame,
+        scan: &Scan,
+        progressive: bool,
+        restart_interval: usize,
+    ) -> Result<(), String> {
+        let missing = || "JPEGのハフマンテーブルが見つかりません".to_string();
+        let uses_dc = scan.spectral_start == 0 && scan.approx_high == 0;
+        let uses_ac = scan.spectral_end > 0;
+        let (dc_tables, ac_tables) = (self.dc_tables, self.ac_tables);
+        let mut tables = Vec::with_capacity(scan.tables.len());
+        for &(dc, ac) in &scan.tables {
+            let dc = dc_tables[dc].as_ref();
+            let ac = ac_tables[ac].as_ref();
+            if (uses_dc && dc.is_none()) || (uses_ac && ac.is_none()) {
+                return Err(missing());
+            }
+            tables.push((dc, ac));
+        }
+
+        // 走査中は係数を書き換えるため、各成分のブロックを一時的に取り出す
+        let mut blocks: Vec<Vec<Block>> = scan
+            .components
+            .iter()
+            .map(|&i| std::mem::take(&mut frame.components[i].blocks))
+            .collect();
+        let mut predictions = vec![0i32; scan.components.len()];
+        let mut mcu_index = 0;
+
+        let result = frame.for_each_mcu(&scan.components, |mcu| {
+            if restart_interval > 0 && mcu_index > 0 && mcu_index % restart_interval == 0 {
+                self.reader.restart();
+                predictions.iter_mut().for_each(|p| *p = 0);
+                self.eobrun = 0;
+            }
+            mcu_index += 1;
+
+            for &(i, index) in mcu {
+                let block = &mut blocks[i][index];
+                let (dc, ac) = tables[i];
+                if !progressive {
+                    self.decode_baseline(block, &mut predictions[i], dc.unwrap(), ac.unwrap())?;
+                } else if scan.spectral_start == 0 {
+                    self.decode_dc(
+                        block,
+                        &mut predictions[i],
+                        dc,
+                        scan.approx_high,
+                        scan.approx_low,
+                    )?;
+                } else if scan.approx_high == 0 {
+                    self.decode_ac_first(block, ac.unwrap(), scan)?;
+                } else {
+                    self.decode_ac_refine(block, ac.unwrap(), scan)?;
+                }
+            }
+            Ok(())
+        });
+
+        for (blocks, &i) in blocks.into_iter().zip(&scan.components) {
+            frame.components[i].blocks = blocks;
+        }
+        result
+    }
+
+    fn decode_baseline(
+        &mut self,
+        block: &mut Block,
+        prediction: &mut i32,
+        dc: &HuffmanDecoder,
+        ac: &HuffmanDecoder,
+    ) -> Result<(), String> {
+        let size = self.reader.decode(dc)? as u32;
+        *prediction += self.reader.read_signed(size);
+        block[0] = *prediction as i16;
+
+        let mut k = 1;
+        while k < 64 {
+            let symbol = self.reader.decode(ac)?;
+            let (run, size) = ((symbol >> 4) as usize, (symbol & 0x0F) as u32);
+            if size == 0 {
+                if run != 15 {
+                    break;
+                }
+                k += 16;
+                continue;
+            }
+            k += run;
+            if k > 63 {
+                return Err("JPEGの係数データが不正です".to_string());
+            }
+            block[k] = self.reader.read_signed(size) as i16;
+            k += 1;
+        }
+        Ok(())
+    }
+
+    /// プログレッシブのDC係数（初回・精度の追加）
+    fn decode_dc(
+        &mut self,
+        block: &mut Block,
+        prediction: &mut i32,
+        dc: Option<&HuffmanDecoder>,
+        approx_high: u8,
+        approx_low: u8,
+    ) -> Result<(), String> {
+        match dc {
+            Some(dc) if approx_high == 0 => {
+                let size = self.reader.decode(dc)? as u32;
+                *prediction += self.reader.read_signed(size);
+                block[0] = (*prediction << approx_low) as i16;
+            }
+            _ => {
+                if self.reader.read_bits(1) != 0 {
+                    block[0] |= 1 << approx_low;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// プログレッシブのAC係数（初回）
+    fn decode_ac_first(
+        &mut self,
+        block: &mut Block,
+        ac: &HuffmanDecoder,
+        scan: &Scan,
+    ) -> Result<(), String> {
+        if self.eobrun > 0 {
+            self.eobrun -= 1;
+            return Ok(());
+        }
+        let mut k = scan.spectral_start;
+        while k <= scan.spectral_end {
+            let symbol = self.reader.decode(ac)?;
+            let (run, size) = ((symbol >> 4) as u32, (symbol & 0x0F) as u32);
+            if size == 0 {
+                if run < 15 {
+                    self.eobrun = (1 << run) - 1 + self.reader.read_bits(run);
+                    break;
+                }
+                k += 16;
+                continue;
+            }
+            k += run as usize;
+            if k > 63 {
+                return Err("JPEGの係数データが不正です".to_string());
+            }
+            block[k] = (self.reader.read_signed(size) << scan.approx_low) as i16;
+            k += 1;
+        }
+        Ok(())
+    }
+
+    /// プログレッシブのAC係数（精度の追加）
+    fn decode_ac_refine(
+        &mut self,
+        block: &mut Block,
+        ac: &HuffmanDecoder,
+        scan: &Scan,
+    ) -> Result<(), String> {
+        let positive = 1i16 << scan.approx_low;
+        let negative = -1i16 << scan.approx_low;
+        let mut k = scan.spectral_start;
+
+        if self.eobrun == 0 {
+            while k <= scan.spectral_end {
+                let symbol = self.reader.decode(ac)?;
+                let (mut run, size) = ((symbol >> 4) as i32, symbol & 0x0F);
+                let mut value = 0;
+                if size != 0 {
+                    value = if self.reader.read_bits(1) != 0 {
+                        positive
+                    } else {
+                        negative
+                    };
+                } else if run != 15 {
+                    self.eobrun = (1u32 << run) + self.reader.read_bits(run as u32);
+                    break;
+                }
+
+                // 既に非ゼロの係数は精度を追加し、ゼロの係数をrun個飛ばした位置に新しい係数を置く
+                while k <= scan.spectral_end {
+                    let coef = &mut block[k];
+                    if *coef != 0 {
+                        self.refine(coef, positive, negative);
+                    } else {
+                        run -= 1;
+                        if run < 0 {
+                            break;
+                        }
+                    }
+                    k += 1;
+                }
+                if value != 0 && k <= scan.spectral_end {
+                    block[k] = value;
+                }
+                k += 1;
+            }
+        }
+
+        if self.eobrun > 0 {
+            while k <= scan.spectral_end {
+                let coef = &mut block[k];
+                if *coef != 0 {
+                    self.refine(coef, positive, negative);
+                }
+                k += 1;
+            }
+            self.eobrun -= 1;
+        }
+        Ok(())
+    }
+
+    fn refine(&mut self, coef: &mut i16, positive: i16, negative: i16) {
+        if self.reader.read_bits(1) != 0 && *coef & positive == 0 {
+            *coef += if *coef >= 0 { positive } else { negative };
+        }
+    }
+}
+
+/// ブロック単位の変換（転置の後に、出力座標で左右・上下反転する）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct BlockTransform {
+    transpose: bool,
+    flip_x: bool,
+    flip_y: bool,
+}
+
+impl BlockTransform {
+    fn from_orientation(orientation: Orientation) -> Self {
+        let (transpose, flip_x, flip_y) = match orientation.to_exif() {
+            2 => (false, true, false),
+            3 => (false, true, true),
+            4 => (false, false, true),
+            5 => (true, false, false),
+            6 => (true, true, false),
+            7 => (true, true, true),
+            8 => (true, false, true),
+            _ => (false, false, false),
+        };
+        Self {
+            transpose,
+            flip_x,
+            flip_y,
+        }
+    }
+
+    /// フレームの係数を変換する
+    fn apply(self, frame: &Frame, edges: EdgeMode) -> Result<Frame, String> {
+        // 反転する軸は、端の不完全なMCUが先頭側に来るためMCUの倍数に揃える必要がある
+        let (flip_width, flip_height) = if self.transpose {
+            (self.flip_y, self.flip_x)
+        } else {
+            (self.flip_x, self.flip_y)
+        };
+        let (mcu_w, mcu_h) = frame.mcu_size();
+        let mut width = frame.width;
+        let mut height = frame.height;
+        for (flipped, size, mcu, name) in [
+            (flip_width, &mut width, mcu_w, "幅"),
+            (flip_height, &mut height, mcu_h, "高さ"),
+        ] {
+            if !flipped || *size % mcu == 0 {
+                continue;
+            }
+            match edges {
+                EdgeMode::Reject => {
+                    return Err(format!(
+                        "画像の{}（{}px）がMCU（{}px）の倍数でないため、無劣化では回転できません。端を切り捨てる設定を有効にしてください",
+                        name, size, mcu
+                    ));
+                }
+                EdgeMode::Trim if *size < mcu => {
+                    return Err(format!(
+                        "画像の{}（{}px）がMCU（{}px）より小さいため、無劣化では回転できません",
+                        name, size, mcu
+                    ));
+                }
+                EdgeMode::Trim => *size -= *size % mcu,
+            }
+        }
+
+        let (out_width, out_height) = if self.transpose {
+            (height, width)
+        } else {
+            (width, height)
+        };
+        let components = frame
+            .components
+            .iter()
+            .map(|c| {
+                let (h, v) = if self.transpose {
+                    (c.v, c.h)
+                } else {
+                    (c.h, c.v)
+                };
+                Component {
+                    id: c.id,
+                    h,
+                    v,
+                    quant_table: c.quant_table,
+                    blocks_w: 0,
+                    blocks_h: 0,
+                    blocks: Vec::new(),
+                }
+            })
+            .collect();
+        let mut out = Frame::new(frame.precision, out_width, out_height, components);
+
+        for (source, target) in frame.components.iter().zip(&mut out.components) {
+            let (blocks_w, blocks_h) = (target.blocks_w, target.blocks_h);
+            for y in 0..blocks_h {
+                for x in 0..blocks_w {
+                    let px = if self.flip_x { blocks_w - 1 - x } else { x };
+                    let py = if self.flip_y { blocks_h - 1 - y } else { y };
+                    let (sx, sy) = if self.transpose { (py, px) } else { (px, py) };
+                    if sx < source.blocks_w && sy < source.blocks_h {
+                        target.blocks[y * blocks_w + x] =
+                            self.apply_block(&source.blocks[sy * source.blocks_w + sx]);
+                    }
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    /// ブロック内の係数を変換する（転置と、奇数次の周波数成分の符号反転）
+    fn apply_block(self, block: &Block) -> Block {
+        let mut out = [0; 64];
+        for (k, coef) in out.iter_mut().enumerate() {
+            let natural = ZIGZAG_TO_NATURAL[k];
+            let (row, col) = (natural / 8, natural % 8);
+            let source = if self.transpose {
+                col * 8 + row
+            } else {
+                natural
+            };
+            let negate = (self.flip_x && col % 2 == 1) != (self.flip_y && row % 2 == 1);
+            let value = block[NATURAL_TO_ZIGZAG[source]];
+            *coef = if negate { -value } else { value };
+        }
+        out
+    }
+}
+
+/// 量子化テーブル（ジグザグ順）を転置する
+fn transpose_table(table: &[u16; 64]) -> [u16; 64] {
+    let mut out = [0; 64];
+    for (k, value) in out.iter_mut().enumerate() {
+        let natural = ZIGZAG_TO_NATURAL[k];
+        let transposed = (natural % 8) * 8 + natural / 8;
+        *value = table[NATURAL_TO_ZIGZAG[transposed]];
+    }
+    out
+}
+
+/// ハフマン符号化する記号（テーブルの種類と、記号に続く付加ビット）
+struct Symbol {
+    ac: bool,
+    table: usize,
+    value: u8,
+    extra: u32,
+    extra_len: u32,
+}
+
+/// 成分が使うハフマンテーブル（輝度と色差で分ける）
+fn table_for(component: usize) -> usize {
+    usize::from(component > 0)
+}
+
+/// 値を符号化する際のビット数と付加ビット
+fn magnitude(value: i32) -> (u32, u32) {
+    let size = 32 - value.unsigned_abs().leading_zeros();
+    let bits = (if value < 0 { value - 1 } else { value }) as u32 & ((1u32 << size) - 1);
+    (size, bits)
+}
+
+/// 出力するスキャン（成分の組）
+///
+/// 1MCUのブロック数がベースラインの上限（10）以内であれば全成分をインターリーブします。
+fn output_scans(frame: &Frame) -> Vec<Vec<usize>> {
+    let blocks_per_mcu: usize = frame.components.iter().map(|c| c.h * c.v).sum();
+    if frame.components.len() == 1 || blocks_per_mcu <= 10 {
+        vec![(0..frame.components.len()).collect()]
+    } else {
+        (0..frame.components.len()).map(|i| vec![i]).collect()
+    }
+}
+
+/// スキャン内の全ブロックを符号化する記号を順に渡す
+fn for_each_symbol(frame: &Frame, scan: &[usize], mut f: impl FnMut(Symbol)) {
+    let mut predictions = vec![0i32; scan.len()];
+    let _ = frame.for_each_mcu(scan, |mcu| {
+        for &(i, index) in mcu {
+            let component = scan[i];
+            let block = &frame.components[component].blocks[index];
+            let table = table_for(component);
+
+            let dc = block[0] as i32;
+            let (size, extra) = magnitude(dc - predictions[i]);
+            predictions[i] = dc;
+            f(Symbol {
+                ac: false,
+                table,
+                value: size as u8,
+                extra,
+                extra_len: size,
+            });
+
+            let mut run = 0;
+            for &coef in &block[1..] {
+                if coef == 0 {
+                    run += 1;
+                    continue;
+                }
+                while run > 15 {
+                    f(Symbol {
+                        ac: true,
+                        table,
+                        value: 0xF0,
+                        extra: 0,
+                        extra_len: 0,
+                    });
+                    run -= 16;
+                }
+                let (size, extra) = magnitude(coef as i32);
+                f(Symbol {
+                    ac: true,
+                    table,
+                    value: ((run << 4) | size) as u8,
+                    extra,
+                    extra_len: size,
+                });
+                run = 0;
+            }
+            if run > 0 {
+                f(Symbol {
+                    ac: true,
+                    table,
+                    value: 0x00,
+                    extra: 0,
+                    extra_len: 0,
+                });
+            }
+        }
+        Ok(())
+    });
+}
+
+/// 出現頻度から符号長16ビット以内の最適なハフマンテーブルを作る（JPEG規格 K.2の手順）
+///
+/// 戻り値は符号長ごとの数と、符号長順の記号です。
+fn optimal_table(frequencies: &[u32; 256]) -> ([u8; 16], Vec<u8>) {
+    let mut freq = [0u64; 257];
+    for (f, &count) in freq.iter_mut().zip(frequencies) {
+        *f = count as u64;
+    }
+    if freq.iter().all(|&f| f == 0) {
+        freq[0] = 1;
+    }
+    // 全ビットが1の符号を使わないよう、予約用の記号を加える
+    freq[256] = 1;
+
+    let mut code_size = [0usize; 257];
+    let mut others = [usize::MAX; 257];
+    loop {
+        let smallest = |exclude: Option<usize>| {
+            (0..257)
+                .filter(|&i| freq[i] != 0 && Some(i) != exclude)
+                .min_by_key(|&i| (freq[i], std::cmp::Reverse(i)))
+        };
+        let Some(c1) = smallest(None) else { break };
+        let Some(c2) = smallest(Some(c1)) else { break };
+
+        freq[c1] += freq[c2];
+        freq[c2] = 0;
+        for start in [c1, c2] {
+            let mut c = start;
+            code_size[c] += 1;
+            while others[c] != usize::MAX {
+                c = others[c];
+                code_size[c] += 1;
+            }
+            if start == c1 {
+                others[c] = c2;
+            }
+        }
+    }
+
+    let mut bits = [0usize; 33];
+    for &size in code_size.iter().filter(|&&s| s > 0) {
+        bits[size.min(32)] += 1;
+    }
+    // 16ビットを超える符号を短くする
+    for i in (17..=32).rev() {
+        while bits[i] > 0 {
+            let mut j = i - 2;
+            while bits[j] == 0 {
+                j -= 1;
+            }
+            bits[i] -= 2;
+            bits[i - 1] += 1;
+            bits[j + 1] += 2;
+            bits[j] -= 1;
+        }
+    }
+    // 予約用の記号を取り除く
+    if let Some(longest) = (1..=16).rev().find(|&i| bits[i] > 0) {
+        bits[longest] -= 1;
+    }
+
+    let mut counts = [0u8; 16];
+    for (count, &b) in counts.iter_mut().zip(&bits[1..=16]) {
+        *count = b as u8;
+    }
+    let mut values = Vec::new();
+    for size in 1..=32 {
+        values.extend((0..256).filter(|&v| code_size[v] == size).map(|v| v as u8));
+    }
+    (counts, values)
+}
+
+/// 符号化用のハフマンテーブル（記号 → 符号, 符号長）
+fn encoding_table(counts: &[u8; 16], values: &[u8]) -> [(u32, u32); 256] {
+    let mut table = [(0, 0); 256];
+    let mut code = 0u32;
+    let mut k = 0;
+    for len in 1..=16 {
+        for _ in 0..counts[len - 1] {
+            table[values[k] as usize] = (code, len as u32);
+            code += 1;
+            k += 1;
+        }
+        code <<= 1;
+    }
+    table
+}
+
+/// 符号化データを書き込むビットライター（0xFFの後に0x00を挿入する）
+struct BitWriter {
+    out: Vec<u8>,
+    buffer: u32,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, len: u32) {
+        if len == 0 {
+            return;
+        }
+        self.buffer = (self.buffer << len) | (value & ((1 << len) - 1));
+        self.bits += len;
+        while self.bits >= 8 {
+            let byte = (self.buffer >> (self.bits - 8)) as u8;
+            self.out.push(byte);
+            if byte == 0xFF {
+                self.out.push(0x00);
+            }
+            self.bits -= 8;
+        }
+        self.buffer &= (1 << self.bits) - 1;
+    }
+
+    /// 残りのビットを1で埋めて書き出す
+    fn flush(&mut self) {
+        if self.bits > 0 {
+            self.write((1 << (8 - self.bits)) - 1, 8 - self.bits);
+        }
+    }
+}
+
+fn push_segment(out: &mut Vec<u8>, marker: u8, body: &[u8]) {
+    out.extend_from_slice(&[0xFF, marker]);
+    out.extend_from_slice(&((body.len() + 2) as u16).to_be_bytes());
+    out.extend_from_slice(body);
+}
+
+/// 係数からJPEGファイルを組み立てる
+fn encode(metadata: &[Vec<u8>], quant_tables: &[Option<[u16; 64]>; 4], frame: &Frame) -> Vec<u8> {
+    let mut out = vec![0xFF, 0xD8];
+    for segment in metadata {
+        out.extend_from_slice(segment);
+    }
+
+    let mut dqt = Vec::new();
+    let mut sixteen_bit = false;
+    for (id, table) in quant_tables.iter().enumerate() {
+        let Some(table) = table else { continue };
+        if table.iter().any(|&q| q > 255) {
+            sixteen_bit = true;
+            dqt.push(0x10 | id as u8);
+            table
+                .iter()
+                .for_each(|q| dqt.extend_from_slice(&q.to_be_bytes()));
+        } else {
+            dqt.push(id as u8);
+            dqt.extend(table.iter().map(|&q| q as u8));
+        }
+    }
+    push_segment(&mut out, 0xDB, &dqt);
+
+    // 8bit精度・8bit量子化テーブルであればベースライン、それ以外は拡張シーケンシャル
+    let mut sof = vec![frame.precision];
+    sof.extend_from_slice(&(frame.height as u16).to_be_bytes());
+    sof.extend_from_slice(&(frame.width as u16).to_be_bytes());
+    sof.push(frame.components.len() as u8);
+    for c in &frame.components {
+        sof.extend_from_slice(&[c.id, ((c.h << 4) | c.v) as u8, c.quant_table as u8]);
+    }
+    let baseline = frame.precision == 8 && !sixteen_bit;
+    push_segment(&mut out, if baseline { 0xC0 } else { 0xC1 }, &sof);
+
+    let scans = output_scans(frame);
+    let mut frequencies = [[[0u32; 256]; 2]; 2];
+    for scan in &scans {
+        for_each_symbol(frame, scan, |s| {
+            frequencies[s.ac as usize][s.table][s.value as usize] += 1
+        });
+    }
+
+    let table_count = if frame.components.len() > 1 { 2 } else { 1 };
+    let mut dht = Vec::new();
+    let mut codes = [[[(0, 0); 256]; 2]; 2];
+    for (class, class_frequencies) in frequencies.iter().enumerate() {
+        for (id, freq) in class_frequencies.iter().enumerate().take(table_count) {
+            let (counts, values) = optimal_table(freq);
+            dht.push(((class << 4) | id) as u8);
+            dht.extend_from_slice(&counts);
+            dht.extend_from_slice(&values);
+            codes[class][id] = encoding_table(&counts, &values);
+        }
+    }
+    push_segment(&mut out, 0xC4, &dht);
+
+    for scan in &scans {
+        let mut sos = vec![scan.len() as u8];
+        for &i in scan {
+            let table = table_for(i) as u8;
+            sos.extend_from_slice(&[frame.components[i].id, (table << 4) | table]);
+        }
+        sos.extend_from_slice(&[0, 63, 0]);
+        push_segment(&mut out, 0xDA, &sos);
+
+        let mut writer = BitWriter {
+            out,
+            buffer: 0,
+            bits: 0,
+        };
+        for_each_symbol(frame, scan, |s| {
+            let (code, len) = codes[s.ac as usize][s.table][s.value as usize];
+            writer.write(code, len);
+            writer.write(s.extra, s.extra_len);
+        });
+        writer.flush();
+        out = writer.out;
+    }
+
+    out.extend_from_slice(&[0xFF, 0xD9]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orientation;
+    use image::codecs::jpeg::JpegEncoder;
+    use image::{DynamicImage, GenericImageView, Rgb, RgbImage};
+
+    fn encode_jpeg(width: u32, height: u32) -> Vec<u8> {
+        let img = RgbImage::from_fn(width, height, |x, y| {
+            Rgb([(x * 7) as u8, (y * 11) as u8, ((x + y) * 5) as u8])
+        });
+        let mut bytes = Vec::new();
+        JpegEncoder::new_with_quality(&mut bytes, 90)
+            .encode_image(&DynamicImage::ImageRgb8(img))
+            .unwrap();
+        bytes
+    }
+
+    fn max_difference(a: &DynamicImage, b: &DynamicImage) -> i32 {
+        assert_eq!(a.dimensions(), b.dimensions());
+        a.to_rgb8()
+            .pixels()
+            .zip(b.to_rgb8().pixels())
+            .flat_map(|(p, q)| (0..3).map(move |c| (p[c] as i32 - q[c] as i32).abs()))
+            .max()
+            .unwrap_or(0)
+    }
+
+    #[test]
+    fn test_rotation_matches_decoded_rotation() {
+        let bytes = encode_jpeg(48, 32);
+        let original = image::load_from_memory(&bytes).unwrap();
+
+        for (exif, expected) in [
+            (6, original.rotate90()),
+            (3, original.rotate180()),
+            (8, original.rotate270()),
+            (2, original.fliph()),
+            (4, original.flipv()),
+        ] {
+            let orientation = Orientation::from_exif(exif).unwrap();
+            let rotated = transform(&bytes, orientation, EdgeMode::Reject).unwrap();
+            let decoded = image::load_from_memory(&rotated).unwrap();
+            // IDCT・色差の補間の丸め誤差のみ許容する
+            assert!(max_difference(&decoded, &expected) <= 4, "EXIF値{}", exif);
+        }
+    }
+
+    #[test]
+    fn test_four_rotations_restore_coefficients() {
+        let bytes = encode_jpeg(48, 32);
+        let quarter = Orientation::from_exif(6).unwrap();
+        let mut rotated = bytes.clone();
+        for _ in 0..4 {
+            rotated = transform(&rotated, quarter, EdgeMode::Reject).unwrap();
+        }
+        let original = parse(&bytes).unwrap().frame;
+        let restored = parse(&rotated).unwrap().frame;
+        for (a, b) in original.components.iter().zip(&restored.components) {
+            assert!(a.blocks == b.blocks, "係数が元に戻ること");
+        }
+    }
+
+    #[test]
+    fn test_unaligned_edges() {
+        let bytes = encode_jpeg(44, 30);
+        let (mcu_w, mcu_h) = parse(&bytes).unwrap().frame.mcu_size();
+        let half_turn = Orientation::from_exif(3).unwrap();
+        assert!(transform(&bytes, half_turn, EdgeMode::Reject).is_err());
+
+        let trimmed = transform(&bytes, half_turn, EdgeMode::Trim).unwrap();
+        let decoded = image::load_from_memory(&trimmed).unwrap();
+        assert_eq!(
+            decoded.dimensions(),
+            ((44 / mcu_w * mcu_w) as u32, (30 / mcu_h * mcu_h) as u32)
+        );
+
+        // 右端・下端が移動しない変換は揃っていなくても回転できる
+        let transpose = Orientation::from_exif(5).unwrap();
+        let transposed = transform(&bytes, transpose, EdgeMode::Reject).unwrap();
+        let decoded = image::load_from_memory(&transposed).unwrap();
+        assert_eq!(decoded.dimensions(), (30, 44));
+    }
+
+    #[test]
+    fn test_exif_orientation_is_reset() {
+        let bytes = encode_jpeg(32, 32);
+        // Orientation=6のみを持つEXIF（リトルエンディアン）
+        let mut app1 = vec![0xFF, 0xE1, 0, 0];
+        app1.extend_from_slice(b"Exif\0\0II*\0\x08\0\0\0\x01\0");
+        app1.extend_from_slice(&[0x12, 0x01, 3, 0, 1, 0, 0, 0, 6, 0, 0, 0, 0, 0, 0, 0]);
+        let length = (app1.len() - 2) as u16;
+        app1[2..4].copy_from_slice(&length.to_be_bytes());
+        let with_exif = [&bytes[..2], &app1[..], &bytes[2..]].concat();
+        assert_eq!(
+            orientation::read_exif_orientation(&with_exif),
+            Orientation::from_exif(6)
+        );
+
+        let rotated = transform(
+            &with_exif,
+            Orientation::from_exif(6).unwrap(),
+            EdgeMode::Reject,
+        )
+        .unwrap();
+        assert_eq!(
+            orientation::read_exif_orientation(&rotated),
+            Some(Orientation::default())
+        );
+    }
+}
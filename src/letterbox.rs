@@ -0,0 +1,271 @@
+use image::{DynamicImage, ImageBuffer, Luma, LumaA, Pixel, Rgb, Rgba};
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+
+/// 黒帯（レターボックス・ピラーボックス）の設定
+///
+/// 画像の縦横比が目標より横長なら上下に、縦長なら左右に帯を付けます。
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Letterbox {
+    /// 目標の縦横比（幅÷高さ）
+    pub aspect: f32,
+    /// 帯の色（sRGB）
+    pub color: [u8; 3],
+    /// 画像の位置（-1.0で上・左端、0.0で中央、1.0で下・右端）
+    pub offset: f32,
+}
+
+impl Default for Letterbox {
+    fn default() -> Self {
+        Self {
+            aspect: 2.39,
+            color: [0, 0, 0],
+            offset: 0.0,
+        }
+    }
+}
+
+/// 指定できる縦横比の範囲（極端な値で巨大な画像を作らないように制限する）
+pub const ASPECT_RANGE: RangeInclusive<f32> = 0.1..=10.0;
+
+/// よく使う縦横比（表示名, 幅÷高さ）
+pub const ASPECT_PRESETS: [(&str, f32); 6] = [
+    ("2.39:1", 2.39),
+    ("1.85:1", 1.85),
+    ("16:9", 16.0 / 9.0),
+    ("3:2", 1.5),
+    ("4:3", 4.0 / 3.0),
+    ("1:1", 1.0),
+];
+
+impl Letterbox {
+    /// `ASPECT_RANGE`に収めた縦横比（数値でなければ既定値）
+    ///
+    /// 設定ファイルの不正な値でも帯のサイズが際限なく大きくならないようにします。
+    pub fn clamped_aspect(&self) -> f32 {
+        if self.aspect.is_finite() {
+            self.aspect
+                .clamp(*ASPECT_RANGE.start(), *ASPECT_RANGE.end())
+        } else {
+            Self::default().aspect
+        }
+    }
+
+    /// 帯を付けた後のサイズと、その中での画像の位置
+    ///
+    /// # Returns
+    ///
+    /// (幅, 高さ, 画像の左端, 画像の上端)
+    pub fn layout(&self, width: u32, height: u32) -> (u32, u32, u32, u32) {
+        let (w, h) = (width as f32, height as f32);
+        let aspect = self.clamped_aspect();
+        let (out_w, out_h) = if w / h > aspect {
+            (width, ((w / aspect).round() as u32).max(height))
+        } else {
+            (((h * aspect).round() as u32).max(width), height)
+        };
+        let position = (self.offset.clamp(-1.0, 1.0) + 1.0) / 2.0;
+        let x = ((out_w - width) as f32 * position).round() as u32;
+        let y = ((out_h - height) as f32 * position).round() as u32;
+        (out_w, out_h, x, y)
+    }
+
+    /// 帯を付けた後のサイズと画像の位置（丸めない値、表示のプレビュー用）
+    ///
+    /// # Returns
+    ///
+    /// (幅, 高さ, 画像の左端, 画像の上端)
+    pub fn layout_exact(&self, width: f32, height: f32) -> (f32, f32, f32, f32) {
+        let aspect = self.clamped_aspect();
+        let (out_w, out_h) = if width / height > aspect {
+            (width, (width / aspect).max(height))
+        } else {
+            ((height * aspect).max(width), height)
+        };
+        let position = (self.offset.clamp(-1.0, 1.0) + 1.0) / 2.0;
+        (
+            out_w,
+            out_h,
+            (out_w - width) * position,
+            (out_h - height) * position,
+        )
+    }
+
+    /// 画像に帯を付ける
+    ///
+    /// 色形式（ビット深度・アルファ）は元の画像を引き継ぎます。
+    /// グレースケールの画像に色付きの帯を付ける場合はRGBに変換します。
+    pub fn apply(&self, img: &DynamicImage) -> DynamicImage {
+        let layout = self.layout(img.width(), img.height());
+        let [r, g, b] = self.color;
+        let gray = r == g && g == b;
+        let wide = |v: u8| v as u16 * 257;
+        let float = |v: u8| v as f32 / 255.0;
+
+        match img {
+            DynamicImage::ImageLuma8(buf) if gray => {
+                DynamicImage::ImageLuma8(pad(buf, layout, Luma([r])))
+            }
+            DynamicImage::ImageLumaA8(buf) if gray => {
+                DynamicImage::ImageLumaA8(pad(buf, layout, LumaA([r, 255])))
+            }
+            DynamicImage::ImageLuma16(buf) if gray => {
+                DynamicImage::ImageLuma16(pad(buf, layout, Luma([wide(r)])))
+            }
+            DynamicImage::ImageLumaA16(buf) if gray => {
+                DynamicImage::ImageLumaA16(pad(buf, layout, LumaA([wide(r), u16::MAX])))
+            }
+            DynamicImage::ImageRgb8(buf) => {
+                DynamicImage::ImageRgb8(pad(buf, layout, Rgb([r, g, b])))
+            }
+            DynamicImage::ImageRgba8(buf) => {
+                DynamicImage::ImageRgba8(pad(buf, layout, Rgba([r, g, b, 255])))
+            }
+            DynamicImage::ImageRgb16(buf) => {
+                DynamicImage::ImageRgb16(pad(buf, layout, Rgb([wide(r), wide(g), wide(b)])))
+            }
+            DynamicImage::ImageRgba16(buf) => DynamicImage::ImageRgba16(pad(
+                buf,
+                layout,
+                Rgba([wide(r), wide(g), wide(b), u16::MAX]),
+            )),
+            DynamicImage::ImageRgb32F(buf) => {
+                DynamicImage::ImageRgb32F(pad(buf, layout, Rgb([float(r), float(g), float(b)])))
+            }
+            DynamicImage::ImageRgba32F(buf) => DynamicImage::ImageRgba32F(pad(
+                buf,
+                layout,
+                Rgba([float(r), float(g), float(b), 1.0]),
+            )),
+            // 色付きの帯を付けるグレースケールなどは、同じビット深度のRGBに変換する
+            _ => {
+                let color = img.color();
+                let high_depth = color.bytes_per_pixel() > color.channel_count();
+                let converted = match (high_depth, color.has_alpha()) {
+                    (true, true) => DynamicImage::ImageRgba16(img.to_rgba16()),
+                    (true, false) => DynamicImage::ImageRgb16(img.to_rgb16()),
+                    (false, true) => DynamicImage::ImageRgba8(img.to_rgba8()),
+                    (false, false) => DynamicImage::ImageRgb8(img.to_rgb8()),
+                };
+                self.apply(&converted)
+            }
+        }
+    }
+}
+
+/// 指定色で塗りつぶしたキャンバスの指定位置に画像を置く
+fn pad<P: Pixel>(
+    img: &ImageBuffer<P, Vec<P::Subpixel>>,
+    (width, height, x, y): (u32, u32, u32, u32),
+    fill: P,
+) -> ImageBuffer<P, Vec<P::Subpixel>> {
+    let mut canvas = ImageBuffer::from_pixel(width, height, fill);
+    image::imageops::replace(&mut canvas, img, x as i64, y as i64);
+    canvas
+}
+
+/// 縦横比の文字列をパースする（`2.39`・`2.39:1`・`16:9`・`16x9`、`ASPECT_RANGE`の範囲外は`None`）
+pub fn parse_aspect(s: &str) -> Option<f32> {
+    let s = s.trim();
+    let aspect = match s.split_once([':', 'x', '/']) {
+        Some((w, h)) => w.trim().parse::<f32>().ok()? / h.trim().parse::<f32>().ok()?,
+        None => s.parse().ok()?,
+    };
+    ASPECT_RANGE.contains(&aspect).then_some(aspect)
+}
+
+/// 色の文字列をパースする（`#RRGGBB`・`RRGGBB`・`black`・`white`）
+pub fn parse_color(s: &str) -> Option<[u8; 3]> {
+    let s = s.trim();
+    match s.to_ascii_lowercase().as_str() {
+        "black" => return Some([0, 0, 0]),
+        "white" => return Some([255, 255, 255]),
+        _ => {}
+    }
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GenericImageView, GrayImage, RgbImage};
+
+    #[test]
+    fn test_layout() {
+        let letterbox = Letterbox::default();
+        // 16:9を2.39:1にすると左右に帯
+        assert_eq!(letterbox.layout(1920, 1080), (2581, 1080, 331, 0));
+        let cinema = Letterbox {
+            aspect: 1920.0 / 1080.0,
+            ..letterbox
+        };
+        // 2.39:1より横長な画像を16:9にすると上下に帯
+        assert_eq!(cinema.layout(2390, 1000), (2390, 1344, 0, 172));
+        // 縦長な画像は左右に帯、オフセットで片側に寄せる
+        let left = Letterbox {
+            aspect: 1.0,
+            offset: -1.0,
+            ..letterbox
+        };
+        assert_eq!(left.layout(300, 400), (400, 400, 0, 0));
+        let right = Letterbox {
+            offset: 1.0,
+            ..left
+        };
+        assert_eq!(right.layout(300, 400), (400, 400, 100, 0));
+
+        // 不正な縦横比でも帯のサイズは範囲内に収める
+        let zero = Letterbox {
+            aspect: 0.0,
+            ..letterbox
+        };
+        assert_eq!(zero.layout(100, 100), (100, 1000, 0, 450));
+        let nan = Letterbox {
+            aspect: f32::NAN,
+            ..letterbox
+        };
+        assert_eq!(nan.layout(1920, 1080), letterbox.layout(1920, 1080));
+    }
+
+    #[test]
+    fn test_apply_keeps_color_type() {
+        let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(40, 10, Rgb([10, 20, 30])));
+        let letterbox = Letterbox {
+            aspect: 2.0,
+            color: [255, 0, 0],
+            offset: 0.0,
+        };
+        let padded = letterbox.apply(&img);
+        assert_eq!(padded.color(), image::ColorType::Rgb8);
+        assert_eq!(padded.dimensions(), (40, 20));
+        assert_eq!(padded.get_pixel(0, 0).0, [255, 0, 0, 255]);
+        assert_eq!(padded.get_pixel(20, 10).0, [10, 20, 30, 255]);
+
+        // グレースケールに色付きの帯はRGBになる
+        let gray = DynamicImage::ImageLuma8(GrayImage::from_pixel(10, 10, Luma([128])));
+        assert_eq!(letterbox.apply(&gray).color(), image::ColorType::Rgb8);
+        let black = Letterbox {
+            color: [0, 0, 0],
+            ..letterbox
+        };
+        assert_eq!(black.apply(&gray).color(), image::ColorType::L8);
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse_aspect("16:9"), Some(16.0 / 9.0));
+        assert_eq!(parse_aspect("2.39:1"), Some(2.39));
+        assert_eq!(parse_aspect("2.39"), Some(2.39));
+        assert_eq!(parse_aspect("0:1"), None);
+        assert_eq!(parse_aspect("1000:1"), None);
+        assert_eq!(parse_color("#FF8000"), Some([255, 128, 0]));
+        assert_eq!(parse_color("white"), Some([255, 255, 255]));
+        assert_eq!(parse_color("#FFF"), None);
+    }
+}
@@ -1,7 +1,23 @@
+pub mod animation;
+pub mod batch;
 pub mod cli_args;
+pub mod color;
+pub mod crop;
+pub mod display;
+pub mod export;
 pub mod file_operations;
 pub mod histogram;
+pub mod history;
 pub mod img;
+pub mod jpeg_lossless;
+pub mod letterbox;
+pub mod lut;
+pub mod metadata;
+pub mod multipage;
 pub mod navigation;
+pub mod orientation;
 pub mod peaking;
 pub mod process_manager;
+pub mod raw;
+pub mod safe_write;
+pub mod straighten;
@@ -0,0 +1,413 @@
+use crate::animation::{self, Animation};
+use crate::color::{ColorConverter, ColorSettings};
+use crate::display::DisplayTransform;
+use crate::image_cache::{CachedImage, SharedImageCache};
+use crate::img;
+use crate::lut::LutGrade;
+use crate::multipage;
+use crate::orientation::AppliedOrientation;
+use crate::safe_write::FileStamp;
+use crate::tiles::MipPyramid;
+use eframe::egui;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex, Once};
+use std::thread;
+use std::time::Instant;
+
+/// ファイル読み込み時のチャンクサイズ（進捗通知の粒度）
+const READ_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// 読み込み処理の進行段階
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoadStage {
+    /// ファイルを読み込み中
+    Reading { read: u64, total: u64 },
+    /// 画像をデコード中
+    Decoding,
+    /// 表示用データを準備中
+    Preparing,
+}
+
+/// デコード済みの画像
+pub struct LoadedImage {
+    pub path: PathBuf,
+    pub image: Arc<image::DynamicImage>,
+    /// 表示用のミップピラミッド
+    pub pyramid: Arc<MipPyramid>,
+    /// `pyramid`の作成に使用した表示変換
+    pub transform: DisplayTransform,
+    /// アニメーション画像の全フレーム（静止画は`None`）
+    pub animation: Option<Arc<Animation>>,
+    /// 複数ページのTIFFのページ数（それ以外は1）
+    pub page_count: usize,
+    /// 埋め込みICCプロファイル
+    pub icc_profile: Option<Arc<Vec<u8>>>,
+    /// `pyramid`の作成に使用した色変換（変換不要の場合は`None`）
+    pub color: Option<Arc<ColorConverter>>,
+    /// デコード時に適用した向き（サイドカーXMPまたはEXIF）
+    pub orientation: Option<AppliedOrientation>,
+    pub file_size: u64,
+    /// 読み込み時点のファイルのスタンプ（上書き前の変更検出に使用）
+    pub stamp: Option<FileStamp>,
+}
+
+impl LoadedImage {
+    /// キャッシュ済みの画像から作成する
+    pub fn from_cached(path: PathBuf, cached: CachedImage) -> Self {
+        Self {
+            path,
+            image: cached.image,
+            pyramid: cached.pyramid,
+            transform: cached.transform,
+            animation: cached.animation,
+            page_count: cached.page_count,
+            icc_profile: cached.icc_profile,
+            color: cached.color,
+            orientation: cached.orientation,
+            file_size: cached.file_size,
+            stamp: cached.stamp,
+        }
+    }
+
+    /// キャッシュ格納用に変換する
+    pub fn into_cached(self) -> CachedImage {
+        CachedImage {
+            image: self.image,
+            pyramid: self.pyramid,
+            transform: self.transform,
+            animation: self.animation,
+            page_count: self.page_count,
+            icc_profile: self.icc_profile,
+            color: self.color,
+            orientation: self.orientation,
+            file_size: self.file_size,
+            stamp: self.stamp,
+        }
+    }
+}
+
+enum LoadEvent {
+    Progress(LoadStage),
+    Finished(Result<LoadedImage, String>),
+}
+
+/// 読み込みワーカーへの要求
+struct LoadRequest {
+    path: PathBuf,
+    ctx: egui::Context,
+    cache: SharedImageCache,
+    transform: DisplayTransform,
+    color: ColorSettings,
+    cancel_flag: Arc<AtomicBool>,
+    sender: mpsc::Sender<LoadEvent>,
+}
+
+/// 読み込みワーカーが次に処理する要求
+///
+/// 要求は1件のみ保持し、未着手の古い要求は新しい要求で置き換えます。
+static NEXT_REQUEST: Mutex<Option<LoadRequest>> = Mutex::new(None);
+static REQUEST_READY: Condvar = Condvar::new();
+static START_WORKER: Once = Once::new();
+
+/// バックグラウンドで実行中の画像読み込み
+///
+/// 読み込みは1つのワーカースレッドで順に処理します。キーを押し続けて画像を
+/// 次々に切り替えても、同時に実行するデコードは1つだけです。
+///
+/// ドロップまたは`cancel`で処理を中断します。未着手の要求は破棄され、読み込み中の
+/// ファイルは次のチャンクで、アニメーションは次のフレームで中断します。
+/// 静止画のデコード自体は途中で中断できないため、完了を待ってから破棄します。
+pub struct LoadJob {
+    path: PathBuf,
+    cancel_flag: Arc<AtomicBool>,
+    receiver: mpsc::Receiver<LoadEvent>,
+    stage: LoadStage,
+}
+
+impl LoadJob {
+    /// 画像の読み込みをワーカースレッドに依頼する
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - 読み込む画像ファイルのパス
+    /// * `ctx` - 進捗・完了時に再描画を要求するためのコンテキスト
+    /// * `cache` - デコード結果を格納するキャッシュ
+    /// * `transform` - 表示用データの作成に使用する表示変換
+    /// * `color` - 表示用データの作成に使用するカラーマネジメント設定
+    pub fn start(
+        path: PathBuf,
+        ctx: egui::Context,
+        cache: SharedImageCache,
+        transform: DisplayTransform,
+        color: ColorSettings,
+    ) -> Self {
+        START_WORKER.call_once(|| {
+            thread::spawn(run_worker);
+        });
+
+        let cancel_flag = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = mpsc::channel();
+        let request = LoadRequest {
+            path: path.clone(),
+            ctx,
+            cache,
+            transform,
+            color,
+            cancel_flag: cancel_flag.clone(),
+            sender: tx,
+        };
+        if let Some(stale) = NEXT_REQUEST.lock().unwrap().replace(request) {
+            println!("[Loader] 未着手の読み込みを破棄: {}", stale.path.display());
+        }
+        REQUEST_READY.notify_one();
+
+        Self {
+            path,
+            cancel_flag,
+            receiver: rx,
+            stage: LoadStage::Reading { read: 0, total: 0 },
+        }
+    }
+
+    /// 読み込み中の画像パス
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// 最後に通知された進行段階
+    pub fn stage(&self) -> LoadStage {
+        self.stage
+    }
+
+    /// 処理を中断する
+    pub fn cancel(&self) {
+        self.cancel_flag.store(true, Ordering::Relaxed);
+    }
+
+    /// 進捗を取り込み、完了していれば結果を返す
+    pub fn poll(&mut self) -> Option<Result<LoadedImage, String>> {
+        while let Ok(event) = self.receiver.try_recv() {
+            match event {
+                LoadEvent::Progress(stage) => self.stage = stage,
+                LoadEvent::Finished(result) => return Some(result),
+            }
+        }
+        None
+    }
+}
+
+impl Drop for LoadJob {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+/// 読み込みワーカーの本体（要求を1件ずつ処理する）
+fn run_worker() {
+    loop {
+        let request = {
+            let mut next = NEXT_REQUEST.lock().unwrap();
+            loop {
+                if let Some(request) = next.take() {
+                    break request;
+                }
+                next = REQUEST_READY.wait(next).unwrap();
+            }
+        };
+        if request.cancel_flag.load(Ordering::Relaxed) {
+            continue;
+        }
+        process_request(request);
+    }
+}
+
+/// 要求された画像を読み込み、キャッシュに格納して結果を通知する
+fn process_request(request: LoadRequest) {
+    let LoadRequest {
+        path,
+        ctx,
+        cache,
+        transform,
+        color,
+        cancel_flag,
+        sender,
+    } = request;
+    let result = decode_for_display(&path, &cancel_flag, transform, &color, |stage| {
+        let _ = sender.send(LoadEvent::Progress(stage));
+        ctx.request_repaint();
+    });
+    if cancel_flag.load(Ordering::Relaxed) {
+        println!("[Loader] キャンセル: {}", path.display());
+        return;
+    }
+
+    if let Ok(
+        loaded @ LoadedImage {
+            stamp: Some(stamp), ..
+        },
+    ) = &result
+    {
+        let cached = CachedImage {
+            image: loaded.image.clone(),
+            pyramid: loaded.pyramid.clone(),
+            transform: loaded.transform,
+            animation: loaded.animation.clone(),
+            page_count: loaded.page_count,
+            icc_profile: loaded.icc_profile.clone(),
+            color: loaded.color.clone(),
+            orientation: loaded.orientation,
+            file_size: loaded.file_size,
+            stamp: Some(*stamp),
+        };
+        cache.lock().unwrap().insert(path.clone(), *stamp, cached);
+    }
+
+    let _ = sender.send(LoadEvent::Finished(result));
+    ctx.request_repaint();
+}
+
+/// 画像ファイルを読み込み、表示用データまで作成する
+///
+/// # Arguments
+///
+/// * `path` - 画像ファイルのパス
+/// * `cancel_flag` - 中断要求フラグ（読み込みのチャンク・アニメーションのフレーム・各段階の間で確認する）
+/// * `transform` - 表示用データの作成に使用する表示変換
+/// * `report` - 進行段階の通知先
+///
+/// # Returns
+///
+/// * `Ok(LoadedImage)` - デコード済み画像
+/// * `Err(String)` - エラーメッセージ（中断時は "Cancelled"）
+pub fn decode_for_display(
+    path: &Path,
+    cancel_flag: &AtomicBool,
+    transform: DisplayTransform,
+    color: &ColorSettings,
+    report: impl Fn(LoadStage),
+) -> Result<LoadedImage, String> {
+    let total_start = Instant::now();
+    let cancelled = || cancel_flag.load(Ordering::Relaxed);
+
+    // ファイルをチャンク単位で読み込み、進捗を通知する
+    let stamp = FileStamp::read(path);
+    let mut file =
+        std::fs::File::open(path).map_err(|e| format!("ファイルを開けませんでした: {}", e))?;
+    let total = file.metadata().map(|m| m.len()).unwrap_or(0);
+    let mut bytes = Vec::with_capacity(total as usize);
+    let mut chunk = vec![0u8; READ_CHUNK_SIZE];
+    loop {
+        if cancelled() {
+            return Err("Cancelled".to_string());
+        }
+        let read = file
+            .read(&mut chunk)
+            .map_err(|e| format!("ファイルの読み込みに失敗しました: {}", e))?;
+        if read == 0 {
+            break;
+        }
+        bytes.extend_from_slice(&chunk[..read]);
+        report(LoadStage::Reading {
+            read: bytes.len() as u64,
+            total,
+        });
+    }
+
+    if cancelled() {
+        return Err("Cancelled".to_string());
+    }
+
+    // デコード
+    report(LoadStage::Decoding);
+    let decode_start = Instant::now();
+    let img::DecodedImage {
+        image: img,
+        icc_profile,
+        orientation,
+    } = img::decode_image_detailed(&bytes, path)?;
+    println!(
+        "[Loader] デコード: {:?}, サイズ: {}x{}",
+        decode_start.elapsed(),
+        img.width(),
+        img.height()
+    );
+
+    // アニメーション画像は全フレームをデコードする（先頭フレームは静止画と同じ）
+    let animation = animation::decode_animation(&bytes, path, cancelled)?.map(Arc::new);
+    if let Some(animation) = &animation {
+        println!("[Loader] アニメーション: {}フレーム", animation.len());
+    }
+
+    // 複数ページのTIFFは先頭ページを表示し、ページ数のみ数えておく
+    let page_count = multipage::page_count(&bytes);
+    if page_count > 1 {
+        println!("[Loader] 複数ページ: {}ページ", page_count);
+    }
+
+    if cancelled() {
+        return Err("Cancelled".to_string());
+    }
+
+    // 表示用のColorImageを作成（UIスレッドではテクスチャ転送のみ行う）
+    // 元画像は16bit・浮動小数点のまま保持し、表示用データにのみ表示変換を適用する
+    report(LoadStage::Preparing);
+    // 埋め込みICCプロファイルは表示プロファイル（既定はsRGB）へ変換する
+    let converter = color.converter_for(icc_profile.as_deref()).map(Arc::new);
+    // LUTのプレビューは読み込み後に表示側で再作成する
+    let pyramid = render_pyramid(&img, transform, None, converter.as_deref());
+
+    println!(
+        "[Loader] 読み込み完了: {} - 合計時間: {:?}",
+        path.display(),
+        total_start.elapsed()
+    );
+
+    Ok(LoadedImage {
+        path: path.to_path_buf(),
+        image: Arc::new(img),
+        pyramid: Arc::new(pyramid),
+        transform,
+        animation,
+        page_count,
+        icc_profile: icc_profile.map(Arc::new),
+        color: converter,
+        orientation,
+        file_size: total,
+        stamp,
+    })
+}
+
+/// 表示変換・LUT・色変換を適用した表示用のミップピラミッドを作成する
+///
+/// GPUのテクスチャ上限を超える画像も表示できるよう、タイル単位で転送できる形にします。
+pub fn render_pyramid(
+    img: &image::DynamicImage,
+    transform: DisplayTransform,
+    grade: Option<&LutGrade>,
+    color: Option<&ColorConverter>,
+) -> MipPyramid {
+    MipPyramid::new(render_color_image(img, transform, grade, color))
+}
+
+/// 表示変換・LUT・色変換を適用した表示用のColorImageを作成する
+///
+/// LUTは保存時と同じく画像の色空間で適用し、その後で表示プロファイルへ変換します。
+fn render_color_image(
+    img: &image::DynamicImage,
+    transform: DisplayTransform,
+    grade: Option<&LutGrade>,
+    color: Option<&ColorConverter>,
+) -> egui::ColorImage {
+    let size = [img.width() as _, img.height() as _];
+    let mut image_buffer = transform.render(img);
+    if let Some(grade) = grade {
+        grade.apply_rgba8(&mut image_buffer);
+    }
+    if let Some(color) = color {
+        color.apply(&mut image_buffer);
+    }
+    let pixels = image_buffer.as_flat_samples();
+    egui::ColorImage::from_rgba_unmultiplied(size, pixels.as_slice())
+}
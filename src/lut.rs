@@ -0,0 +1,387 @@
+use image::{DynamicImage, ImageBuffer, RgbaImage};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Arc;
+
+/// 3D LUTの格子の最大サイズ（.cubeの仕様上の上限）
+const MAX_SIZE: usize = 256;
+
+/// 3D LUTの補間方法
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum Interpolation {
+    /// 周囲8点による補間
+    Trilinear,
+    /// 周囲4点（四面体）による補間（中間調の色ずれが少ない）
+    #[default]
+    Tetrahedral,
+}
+
+/// .cube形式の3D LUT
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lut3d {
+    /// `TITLE`で指定された名前
+    pub title: Option<String>,
+    size: usize,
+    domain_min: [f32; 3],
+    domain_max: [f32; 3],
+    /// 格子点の値（Rが最も速く変化する順）
+    table: Vec<[f32; 3]>,
+}
+
+impl Lut3d {
+    /// .cubeファイルを読み込む
+    ///
+    /// # Errors
+    ///
+    /// * ファイルの読み込みに失敗した場合
+    /// * `parse`のエラー
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("LUTファイルの読み込みに失敗しました: {}", e))?;
+        Self::parse(&text)
+    }
+
+    /// .cube形式のテキストをパースする
+    ///
+    /// # Errors
+    ///
+    /// * `LUT_3D_SIZE`が無い・範囲外の場合（1D LUTは非対応）
+    /// * 格子点の数がサイズと一致しない場合
+    /// * 数値として解釈できない行がある場合
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut title = None;
+        let mut size = None;
+        let mut domain_min = [0.0; 3];
+        let mut domain_max = [1.0; 3];
+        let mut table = Vec::new();
+
+        for (line_no, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |what: &str| format!("LUTの{}行目が不正です: {}", line_no + 1, what);
+            let (keyword, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let rest = rest.trim();
+            match keyword {
+                "TITLE" => title = Some(rest.trim_matches('"').to_string()),
+                "LUT_3D_SIZE" => {
+                    let n: usize = rest.parse().map_err(|_| error(line))?;
+                    if !(2..=MAX_SIZE).contains(&n) {
+                        return Err(format!("LUT_3D_SIZEが範囲外です: {}", n));
+                    }
+                    size = Some(n);
+                }
+                "LUT_1D_SIZE" => return Err("1D LUTには対応していません".to_string()),
+                "DOMAIN_MIN" => domain_min = parse_triplet(rest).ok_or_else(|| error(line))?,
+                "DOMAIN_MAX" => domain_max = parse_triplet(rest).ok_or_else(|| error(line))?,
+                // DaVinci Resolveが書き出す定義域（3チャンネル共通の最小値と最大値）
+                "LUT_3D_INPUT_RANGE" => {
+                    let [min, max] = parse_pair(rest).ok_or_else(|| error(line))?;
+                    domain_min = [min; 3];
+                    domain_max = [max; 3];
+                }
+                // 他の製品固有のキーワードは無視する
+                _ if keyword.starts_with(|c: char| c.is_ascii_alphabetic()) => {}
+                _ => table.push(parse_triplet(line).ok_or_else(|| error(line))?),
+            }
+        }
+
+        let size = size.ok_or("LUT_3D_SIZEが指定されていません")?;
+        if table.len() != size * size * size {
+            return Err(format!(
+                "LUTの格子点の数が一致しません（{}点、期待値{}点）",
+                table.len(),
+                size * size * size
+            ));
+        }
+        if (0..3).any(|c| domain_max[c] <= domain_min[c]) {
+            return Err("DOMAIN_MINがDOMAIN_MAX以上です".to_string());
+        }
+        Ok(Self {
+            title,
+            size,
+            domain_min,
+            domain_max,
+            table,
+        })
+    }
+
+    /// 格子の1辺の点数
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    fn at(&self, r: usize, g: usize, b: usize) -> [f32; 3] {
+        self.table[r + g * self.size + b * self.size * self.size]
+    }
+
+    /// 1色を変換する（入力は定義域の範囲に丸める）
+    pub fn lookup(&self, rgb: [f32; 3], interpolation: Interpolation) -> [f32; 3] {
+        let max = (self.size - 1) as f32;
+        let mut base = [0usize; 3];
+        let mut frac = [0f32; 3];
+        for c in 0..3 {
+            let t = (rgb[c] - self.domain_min[c]) / (self.domain_max[c] - self.domain_min[c]);
+            let scaled = (t * max).clamp(0.0, max);
+            // 上端は1つ手前の格子と割合1.0として扱う
+            let i = (scaled.floor() as usize).min(self.size - 2);
+            base[c] = i;
+            frac[c] = scaled - i as f32;
+        }
+        let [r, g, b] = base;
+        let [fr, fg, fb] = frac;
+        let c000 = self.at(r, g, b);
+        let c111 = self.at(r + 1, g + 1, b + 1);
+
+        let mix = |weights: [(f32, [f32; 3]); 4]| -> [f32; 3] {
+            let mut out = [0.0; 3];
+            for (w, c) in weights {
+                for i in 0..3 {
+                    out[i] += w * c[i];
+                }
+            }
+            out
+        };
+
+        match interpolation {
+            Interpolation::Trilinear => {
+                let lerp = |a: [f32; 3], b: [f32; 3], t: f32| {
+                    [
+                        a[0] + (b[0] - a[0]) * t,
+                        a[1] + (b[1] - a[1]) * t,
+                        a[2] + (b[2] - a[2]) * t,
+                    ]
+                };
+                let c00 = lerp(c000, self.at(r + 1, g, b), fr);
+                let c10 = lerp(self.at(r, g + 1, b), self.at(r + 1, g + 1, b), fr);
+                let c01 = lerp(self.at(r, g, b + 1), self.at(r + 1, g, b + 1), fr);
+                let c11 = lerp(self.at(r, g + 1, b + 1), c111, fr);
+                lerp(lerp(c00, c10, fg), lerp(c01, c11, fg), fb)
+            }
+            Interpolation::Tetrahedral => {
+                // 立方体を6つの四面体に分け、割合の大小関係で含まれる四面体を選ぶ
+                if fr > fg {
+                    if fg > fb {
+                        mix([
+                            (1.0 - fr, c000),
+                            (fr - fg, self.at(r + 1, g, b)),
+                            (fg - fb, self.at(r + 1, g + 1, b)),
+                            (fb, c111),
+                        ])
+                    } else if fr > fb {
+                        mix([
+                            (1.0 - fr, c000),
+                            (fr - fb, self.at(r + 1, g, b)),
+                            (fb - fg, self.at(r + 1, g, b + 1)),
+                            (fg, c111),
+                        ])
+                    } else {
+                        mix([
+                            (1.0 - fb, c000),
+                            (fb - fr, self.at(r, g, b + 1)),
+                            (fr - fg, self.at(r + 1, g, b + 1)),
+                            (fg, c111),
+                        ])
+                    }
+                } else if fb > fg {
+                    mix([
+                        (1.0 - fb, c000),
+                        (fb - fg, self.at(r, g, b + 1)),
+                        (fg - fr, self.at(r, g + 1, b + 1)),
+                        (fr, c111),
+                    ])
+                } else if fb > fr {
+                    mix([
+                        (1.0 - fg, c000),
+                        (fg - fb, self.at(r, g + 1, b)),
+                        (fb - fr, self.at(r, g + 1, b + 1)),
+                        (fr, c111),
+                    ])
+                } else {
+                    mix([
+                        (1.0 - fg, c000),
+                        (fg - fr, self.at(r, g + 1, b)),
+                        (fr - fb, self.at(r + 1, g + 1, b)),
+                        (fb, c111),
+                    ])
+                }
+            }
+        }
+    }
+}
+
+/// 3D LUTのカラーグレーディング（LUTと補間方法）
+#[derive(Debug, Clone)]
+pub struct LutGrade {
+    pub lut: Arc<Lut3d>,
+    pub interpolation: Interpolation,
+}
+
+impl LutGrade {
+    /// 表示用の8bit RGBA画像に適用する（プレビュー用）
+    pub fn apply_rgba8(&self, img: &mut RgbaImage) {
+        // 8bitの入力は256段階しか無いため、チャンネルごとの値を先に正規化しておく
+        img.par_chunks_mut(4).for_each(|px| {
+            let out = self.lookup_unit([px[0], px[1], px[2]].map(|v| v as f32 / 255.0));
+            for (c, v) in px[..3].iter_mut().zip(out) {
+                *c = (v * 255.0).round() as u8;
+            }
+        });
+    }
+
+    /// 画像に適用する（保存用、元のビット深度とアルファを保持する）
+    pub fn apply(&self, img: &DynamicImage) -> DynamicImage {
+        match img {
+            DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => {
+                let mut rgba = img.to_rgba32f();
+                rgba.par_chunks_mut(4).for_each(|px| {
+                    let out = self.lookup_unit([px[0], px[1], px[2]]);
+                    px[..3].copy_from_slice(&out);
+                });
+                restore_alpha(DynamicImage::ImageRgba32F(rgba), img)
+            }
+            DynamicImage::ImageLuma8(_)
+            | DynamicImage::ImageLumaA8(_)
+            | DynamicImage::ImageRgb8(_)
+            | DynamicImage::ImageRgba8(_) => {
+                let mut rgba = img.to_rgba8();
+                self.apply_rgba8(&mut rgba);
+                restore_alpha(DynamicImage::ImageRgba8(rgba), img)
+            }
+            _ => {
+                let rgba = img.to_rgba16();
+                let (width, height) = rgba.dimensions();
+                let mut data = rgba.into_raw();
+                data.par_chunks_mut(4).for_each(|px| {
+                    let out = self.lookup_unit([px[0], px[1], px[2]].map(|v| v as f32 / 65535.0));
+                    for (c, v) in px[..3].iter_mut().zip(out) {
+                        *c = (v * 65535.0).round() as u16;
+                    }
+                });
+                let rgba = ImageBuffer::from_raw(width, height, data).unwrap();
+                restore_alpha(DynamicImage::ImageRgba16(rgba), img)
+            }
+        }
+    }
+
+    /// 0〜1に丸めた結果を返す
+    fn lookup_unit(&self, rgb: [f32; 3]) -> [f32; 3] {
+        self.lut
+            .lookup(rgb, self.interpolation)
+            .map(|v| v.clamp(0.0, 1.0))
+    }
+}
+
+/// 元の画像にアルファが無ければ取り除く（グレースケールは色が付くためRGBにする）
+pub(crate) fn restore_alpha(graded: DynamicImage, original: &DynamicImage) -> DynamicImage {
+    if original.color().has_alpha() {
+        return graded;
+    }
+    match graded {
+        DynamicImage::ImageRgba8(_) => DynamicImage::ImageRgb8(graded.to_rgb8()),
+        DynamicImage::ImageRgba16(_) => DynamicImage::ImageRgb16(graded.to_rgb16()),
+        DynamicImage::ImageRgba32F(_) => DynamicImage::ImageRgb32F(graded.to_rgb32f()),
+        other => other,
+    }
+}
+
+fn parse_pair(s: &str) -> Option<[f32; 2]> {
+    let mut values = s.split_whitespace().map(|v| v.parse::<f32>());
+    let pair = [values.next()?.ok()?, values.next()?.ok()?];
+    values.next().is_none().then_some(pair)
+}
+
+fn parse_triplet(s: &str) -> Option<[f32; 3]> {
+    let mut values = s.split_whitespace().map(|v| v.parse::<f32>());
+    let triplet = [
+        values.next()?.ok()?,
+        values.next()?.ok()?,
+        values.next()?.ok()?,
+    ];
+    values.next().is_none().then_some(triplet)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    /// 各格子点の値を関数で求めた.cubeのテキスト
+    pub(crate) fn cube_text(size: usize, f: impl Fn([f32; 3]) -> [f32; 3]) -> String {
+        let mut text = format!("TITLE \"test\"\nLUT_3D_SIZE {}\n", size);
+        let max = (size - 1) as f32;
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    let [r, g, b] = f([r as f32 / max, g as f32 / max, b as f32 / max]);
+                    text.push_str(&format!("{} {} {}\n", r, g, b));
+                }
+            }
+        }
+        text
+    }
+
+    #[test]
+    fn test_parse_and_identity() {
+        let lut = Lut3d::parse(&cube_text(5, |c| c)).unwrap();
+        assert_eq!(lut.title.as_deref(), Some("test"));
+        assert_eq!(lut.size(), 5);
+        for interpolation in [Interpolation::Trilinear, Interpolation::Tetrahedral] {
+            let out = lut.lookup([0.3, 0.62, 1.0], interpolation);
+            for (a, b) in out.iter().zip([0.3, 0.62, 1.0]) {
+                assert!((a - b).abs() < 1e-5, "{:?}", interpolation);
+            }
+        }
+
+        assert!(Lut3d::parse("LUT_3D_SIZE 2\n0 0 0\n").is_err());
+        assert!(Lut3d::parse("LUT_1D_SIZE 2\n0 0 0\n1 1 1\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_input_range() {
+        // 定義域が0〜2の恒等LUTは、入力1.0を格子の中央で変換する
+        let text = cube_text(3, |c| c).replace(
+            "LUT_3D_SIZE 3\n",
+            "LUT_3D_SIZE 3\nLUT_3D_INPUT_RANGE 0.0 2.0\n",
+        );
+        let lut = Lut3d::parse(&text).unwrap();
+        assert_eq!(lut.domain_min, [0.0; 3]);
+        assert_eq!(lut.domain_max, [2.0; 3]);
+        assert_eq!(
+            lut.lookup([1.0, 1.0, 1.0], Interpolation::Trilinear),
+            [0.5, 0.5, 0.5]
+        );
+
+        assert!(Lut3d::parse(&text.replace("0.0 2.0", "0.0")).is_err());
+        assert!(Lut3d::parse(&text.replace("0.0 2.0", "1.0 0.0")).is_err());
+    }
+
+    #[test]
+    fn test_interpolation_of_nonlinear_lut() {
+        // 格子点の間は補間方法により値が異なるが、格子点上は一致する
+        let lut = Lut3d::parse(&cube_text(3, |[r, g, b]| [r * g, g, b * b])).unwrap();
+        for interpolation in [Interpolation::Trilinear, Interpolation::Tetrahedral] {
+            assert_eq!(lut.lookup([0.5, 1.0, 0.5], interpolation), [0.5, 1.0, 0.25]);
+        }
+        // R=G=Bの軸上ではテトラヘドラル補間は両端の2点のみを使う
+        let tetra = lut.lookup([0.25, 0.25, 0.25], Interpolation::Tetrahedral);
+        assert!((tetra[0] - 0.125).abs() < 1e-6);
+        let tri = lut.lookup([0.25, 0.25, 0.25], Interpolation::Trilinear);
+        assert!((tri[0] - 0.0625).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_grade_keeps_color_type() {
+        let lut = Lut3d::parse(&cube_text(2, |[r, g, b]| [1.0 - r, 1.0 - g, 1.0 - b])).unwrap();
+        let grade = LutGrade {
+            lut: Arc::new(lut),
+            interpolation: Interpolation::Tetrahedral,
+        };
+        let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(2, 2, Rgb([0, 100, 255])));
+        let graded = grade.apply(&img);
+        assert_eq!(graded.color(), image::ColorType::Rgb8);
+        assert_eq!(graded.to_rgb8().get_pixel(0, 0).0, [255, 155, 0]);
+    }
+}
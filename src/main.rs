@@ -1,21 +1,85 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod animation;
+mod batch;
 mod cli_args;
+mod color;
+mod crop;
+mod display;
+mod export;
 mod histogram;
+mod history;
+mod image_cache;
 mod img;
+mod jpeg_lossless;
+mod letterbox;
+mod loader;
+mod lut;
+mod metadata;
+mod multipage;
 mod navigation;
+mod orientation;
 mod peaking;
+mod raw;
+mod safe_write;
 mod settings;
+mod straighten;
+mod tiles;
 mod update;
 
 use eframe::egui;
 use settings::*;
+use std::collections::HashSet;
 use std::io::Write;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc;
 use std::sync::Arc;
 use std::thread;
 
+/// LUTの適用を履歴・状態表示で示す名前
+const LUT_LABEL: &str = "LUT";
+
+/// 表示調整の焼き込みを履歴・状態表示で示す名前
+const ADJUST_LABEL: &str = "表示調整";
+
+/// 保存した編集により、再読み込みする画像に焼き込まれる表示プレビュー
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BakedPreview {
+    /// LUTのプレビュー
+    Lut,
+    /// 露出・コントラストなどの表示調整
+    Adjustments,
+}
+
+impl BakedPreview {
+    /// 編集の名前から、焼き込まれるプレビューを求める（該当しない編集は`None`）
+    fn from_label(label: &str) -> Option<Self> {
+        match label {
+            LUT_LABEL => Some(Self::Lut),
+            ADJUST_LABEL => Some(Self::Adjustments),
+            _ => None,
+        }
+    }
+}
+
+/// 切り抜きのつまみの表示サイズ（画面上のピクセル）
+const CROP_HANDLE_SIZE: f32 = 8.0;
+
+/// 画面上の切り抜き範囲でのつまみの位置
+fn crop_handle_pos(rect: egui::Rect, handle: crop::Handle) -> egui::Pos2 {
+    let (dx, dy) = handle.direction();
+    rect.center() + egui::vec2(dx as f32 * rect.width(), dy as f32 * rect.height()) / 2.0
+}
+
+/// 画面上の位置にある切り抜きのつまみ（範囲の内側なら移動、外側なら`None`）
+fn crop_handle_at(rect: egui::Rect, pos: egui::Pos2) -> Option<crop::Handle> {
+    crop::Handle::EDGES
+        .into_iter()
+        .find(|&handle| crop_handle_pos(rect, handle).distance(pos) <= CROP_HANDLE_SIZE * 1.5)
+        .or_else(|| rect.contains(pos).then_some(crop::Handle::Move))
+}
+
 /// CLI引数から起動設定を取得
 static LAUNCH_CONFIG: once_cell::sync::Lazy<cli_args::LaunchConfig> =
     once_cell::sync::Lazy::new(cli_args::LaunchConfig::from_args);
@@ -24,6 +88,14 @@ fn main() -> eframe::Result {
     // CLI引数をパース
     let launch_config = &*LAUNCH_CONFIG;
     println!("[MAIN] Launch config: {:?}", launch_config);
+    for error in &launch_config.errors {
+        eprintln!("[MAIN] {}", error);
+    }
+
+    // 黒帯の一括処理が指定されていれば、ウィンドウを開かずに処理して終了する
+    if launch_config.letterbox_requested {
+        std::process::exit(run_letterbox_batch(launch_config));
+    }
 
     // ウィンドウ設定を構築
     let viewport_builder = build_viewport_from_config(launch_config);
@@ -48,6 +120,79 @@ fn main() -> eframe::Result {
     )
 }
 
+/// CLI引数で指定された画像・フォルダに黒帯を付け、新しいファイルに保存する
+///
+/// 保存先は`--output-dir`（未指定なら元の画像と同じフォルダ）の
+/// `<名前>_letterbox.<拡張子>`です。元の画像は変更しません。画像は並列に処理します。
+/// 保存先に同名のファイルがある場合は、`--overwrite true`の場合のみ上書きします。
+///
+/// # Returns
+///
+/// プロセスの終了コード（すべて成功で0、失敗があれば1、引数が不正か対象が無ければ2）
+fn run_letterbox_batch(config: &cli_args::LaunchConfig) -> i32 {
+    // 不正な値を既定値に置き換えて処理すると意図しない画像ができるため、処理しない
+    if !config.letterbox_errors.is_empty() {
+        for error in &config.letterbox_errors {
+            eprintln!("[Letterbox] {}", error);
+        }
+        return 2;
+    }
+    let default = letterbox::Letterbox::default();
+    let letterbox = letterbox::Letterbox {
+        aspect: config.letterbox_aspect.unwrap_or(default.aspect),
+        color: config.letterbox_color.unwrap_or(default.color),
+        offset: config.letterbox_offset.unwrap_or(default.offset),
+    };
+
+    let files: Vec<PathBuf> = config
+        .input_paths
+        .iter()
+        .flat_map(|input| {
+            navigation::get_folder_images(input.clone()).unwrap_or_else(|| {
+                if PathBuf::from(input).is_dir() {
+                    Vec::new()
+                } else {
+                    vec![input.clone()]
+                }
+            })
+        })
+        .map(PathBuf::from)
+        .collect();
+    if files.is_empty() {
+        eprintln!("[Letterbox] 処理する画像が指定されていません");
+        return 2;
+    }
+
+    let job = batch::BatchJob {
+        operation: batch::BatchOperation::Letterbox(letterbox),
+        files,
+        output_dir: config.output_dir.as_ref().map(PathBuf::from),
+        overwrite: config.overwrite.unwrap_or(false),
+    };
+    let report = job.run(
+        None,
+        &std::sync::atomic::AtomicBool::new(false),
+        |index, status| match status {
+            batch::FileStatus::Done(Some(output)) => println!(
+                "[Letterbox] {} -> {}",
+                job.files[index].display(),
+                output.display()
+            ),
+            batch::FileStatus::Failed(e) => {
+                eprintln!("[Letterbox] {}: {}", job.files[index].display(), e)
+            }
+            _ => {}
+        },
+    );
+    println!("[Letterbox] 完了: {}", report.summary());
+    let failed = report.failed();
+    if failed > 0 {
+        1
+    } else {
+        0
+    }
+}
+
 /// CLI引数からViewportBuilderを構築
 fn build_viewport_from_config(config: &cli_args::LaunchConfig) -> egui::ViewportBuilder {
     let mut builder = egui::ViewportBuilder::default()
@@ -173,19 +318,117 @@ fn create_dark_theme() -> egui::Visuals {
     visuals
 }
 
+/// バックグラウンドで読み込んだTIFFのページ（画像・表示用データ・使用した表示変換）
+struct PageResult {
+    path: PathBuf,
+    page: usize,
+    res: Result<
+        (
+            Arc<image::DynamicImage>,
+            Arc<tiles::MipPyramid>,
+            display::DisplayTransform,
+        ),
+        String,
+    >,
+}
+
+/// バックグラウンドで実行した元に戻す・やり直しの結果（操作できる履歴が無ければ`None`）
+struct HistoryResult {
+    path: PathBuf,
+    res: Result<Option<String>, String>,
+}
+
+/// バックグラウンドで実行した編集（反転・角度補正）の結果
+struct EditResult {
+    path: PathBuf,
+    label: &'static str,
+    res: Result<(), String>,
+}
+
+/// LUTを適用した画像の保存方法
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LutSave {
+    Overwrite,
+    SaveAs,
+    /// 元の画像の隣に`<名前>_lut.<拡張子>`で保存する（`--auto-lut`）
+    Auto,
+}
+
+/// バックグラウンドで実行したLUTの自動保存の結果
+struct LutSaveResult {
+    source: PathBuf,
+    output: PathBuf,
+    res: Result<(), String>,
+}
+
+/// 一括処理の種類（各処理の設定は`AppSettings`のものを使用する）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BatchKind {
+    Rotate,
+    Export,
+    Letterbox,
+}
+
 struct VdiApp {
     // 設定
     settings: AppSettings,
 
     // 画像の状態
     current_path: Option<PathBuf>,
-    texture: Option<egui::TextureHandle>,
+    texture: Option<tiles::TiledTexture>,
     original_image: Option<Arc<image::DynamicImage>>,
     image_dimensions: Option<(u32, u32)>,
     file_size_bytes: Option<u64>,
+    /// 表示中の画像を読み込んだ時点のファイルのスタンプ（上書き前の変更検出に使用）
+    file_stamp: Option<safe_write::FileStamp>,
     rotation: f32,
     rotation_in_progress: bool,
     pending_rotations: usize,
+    load_job: Option<loader::LoadJob>,
+
+    // アニメーション再生
+    animation: Option<Arc<animation::Animation>>,
+    frame_index: usize,
+    animation_playing: bool,
+    frame_elapsed: f32,
+
+    // カラーマネジメント（埋め込みICCプロファイル → 表示プロファイル）
+    icc_profile: Option<Arc<Vec<u8>>>,
+    color_converter: Option<Arc<color::ColorConverter>>,
+    display_icc: Option<Arc<Vec<u8>>>,
+
+    /// デコード時に適用した向き（サイドカーXMPまたはEXIF）
+    applied_orientation: Option<orientation::AppliedOrientation>,
+
+    // 複数ページのTIFF
+    page_count: usize,
+    current_page: usize,
+    page_receiver: Option<mpsc::Receiver<PageResult>>,
+    /// 回転後の再読み込みで表示に戻すページ
+    restore_page: Option<(PathBuf, usize)>,
+
+    // 表示変換（露出・コントラスト・ホワイトバランス・彩度・ガンマは表示時のみ、
+    // トーンマッピングは設定に保存）
+    exposure: f32,
+    contrast: f32,
+    temperature: f32,
+    tint: f32,
+    saturation: f32,
+    display_gamma: f32,
+    show_display_panel: bool,
+    display_render_receiver: Option<mpsc::Receiver<(PathBuf, tiles::MipPyramid)>>,
+    display_render_pending: bool,
+
+    // RAWのフル現像（ピーキング・ヒストグラム用）
+    analysis_image: Option<Arc<image::DynamicImage>>,
+    raw_develop_receiver: Option<mpsc::Receiver<Result<Arc<image::DynamicImage>, String>>>,
+    /// 実行中のRAWフル現像の中断フラグ
+    raw_develop_cancel: Option<Arc<AtomicBool>>,
+
+    // 先読みキャッシュ
+    image_cache: image_cache::SharedImageCache,
+    prefetch_job: Option<image_cache::PrefetchJob>,
+    prefetched_for: Option<PathBuf>,
 
     // 表示状態
     zoom: f32,
@@ -200,10 +443,66 @@ struct VdiApp {
     histogram_result: Option<Arc<histogram::HistogramResult>>,
     histogram_receiver: Option<mpsc::Receiver<histogram::HistogramResult>>,
 
-    rotation_receiver: Option<mpsc::Receiver<PathBuf>>,
+    rotation_receiver: Option<mpsc::Receiver<Result<PathBuf, String>>>,
+
+    // 編集履歴（元に戻す・やり直し）
+    history: Option<history::EditHistory>,
+    /// 表示中の画像の元に戻せる数・やり直せる数
+    history_depth: (usize, usize),
+    history_receiver: Option<mpsc::Receiver<HistoryResult>>,
+
+    // 反転・角度補正
+    edit_receiver: Option<mpsc::Receiver<EditResult>>,
+    show_straighten: bool,
+    /// 角度補正のプレビュー角度（度、時計回りが正）
+    straighten_angle: f32,
+
+    // 切り抜き（範囲があれば切り抜きモード）
+    crop_rect: Option<crop::CropRect>,
+    crop_aspect: crop::AspectPreset,
+    crop_drag: Option<crop::Handle>,
+
+    /// 黒帯のプレビュー中か（帯の設定は`AppSettings`に保存）
+    show_letterbox: bool,
+
+    // 3D LUT（補間方法は`AppSettings`に保存）
+    lut: Option<Arc<lut::Lut3d>>,
+    /// 読み込んだLUTの表示名
+    lut_name: String,
+    lut_preview: bool,
+    show_lut: bool,
+    /// 開いた画像にLUTを適用して自動保存するか（`--auto-lut`）
+    lut_auto_save: bool,
+    /// LUTを適用して保存した元の画像と保存先（二重適用の警告に使用）
+    lut_saved: HashSet<PathBuf>,
+    /// 確認待ちの保存方法と警告文
+    lut_confirm: Option<(LutSave, String)>,
+    /// 自動保存は画像を切り替えても並行して進むため、受信側を保持し続ける
+    lut_save_sender: mpsc::Sender<LutSaveResult>,
+    lut_save_receiver: mpsc::Receiver<LutSaveResult>,
+
+    // 書き出し（設定は`AppSettings`に保存）
+    show_export: bool,
+    export_receiver: Option<mpsc::Receiver<Result<PathBuf, String>>>,
+
+    // 一括処理
+    show_batch: bool,
+    /// 表示中のフォルダの画像と選択状態
+    batch_files: Vec<(PathBuf, bool)>,
+    batch_kind: BatchKind,
+    batch_angle: i32,
+    batch_output_dir: Option<PathBuf>,
+    batch_overwrite: bool,
+    /// 実行中の一括処理（回転中は表示中の画像の編集を受け付けない）
+    batch_run: Option<(BatchKind, batch::BatchRun)>,
+    /// 直前に完了した一括処理の結果
+    batch_outcome: Option<batch::BatchOutcome>,
 
     grid_enabled: bool,
 
+    // フォルダナビゲーション
+    folder_index: Option<navigation::FolderIndex>,
+
     // UI状態
     status_message: String,
     show_settings: bool,
@@ -229,7 +528,30 @@ struct VdiApp {
 
 impl VdiApp {
     fn new(_cc: &eframe::CreationContext<'_>) -> Self {
-        let settings = AppSettings::load();
+        let mut settings = AppSettings::load();
+        let history = history::EditHistory::open_default(settings.history_quota_mb);
+
+        // CLI引数の並び順指定は保存済みの設定より優先する
+        if let Some(order) = LAUNCH_CONFIG.sort_order {
+            settings.sort_order = order;
+        }
+        if let Some(reverse) = LAUNCH_CONFIG.sort_reverse {
+            settings.sort_reverse = reverse;
+        }
+
+        let image_cache = Arc::new(std::sync::Mutex::new(image_cache::ImageCache::new(
+            settings.cache_size_mb,
+        )));
+
+        // 表示プロファイル（未設定または読み込めない場合はsRGB）
+        let display_icc = settings.display_icc_path.as_ref().and_then(|path| {
+            std::fs::read(path)
+                .map_err(|e| eprintln!("[COLOR] 表示プロファイルを読み込めません: {}", e))
+                .ok()
+                .map(Arc::new)
+        });
+
+        let (lut_save_sender, lut_save_receiver) = mpsc::channel();
 
         // フォントの非同期ダウンロード開始
         let (font_tx, font_rx) = mpsc::channel();
@@ -266,7 +588,7 @@ impl VdiApp {
             }
         });
 
-        Self {
+        let mut app = Self {
             peaking_dirty: false,
             last_peaking_trigger: 0.0,
             font_download_receiver: Some(font_rx),
@@ -275,11 +597,40 @@ impl VdiApp {
             current_path: None,
             texture: None,
             original_image: None,
+            analysis_image: None,
+            animation: None,
+            frame_index: 0,
+            animation_playing: false,
+            frame_elapsed: 0.0,
+            icc_profile: None,
+            color_converter: None,
+            display_icc,
+            applied_orientation: None,
+            page_count: 1,
+            current_page: 0,
+            page_receiver: None,
+            restore_page: None,
+            exposure: 0.0,
+            contrast: 0.0,
+            temperature: 0.0,
+            tint: 0.0,
+            saturation: 1.0,
+            display_gamma: display::DisplayTransform::default().gamma,
+            show_display_panel: false,
+            display_render_receiver: None,
+            display_render_pending: false,
+            raw_develop_receiver: None,
+            raw_develop_cancel: None,
             image_dimensions: None,
             file_size_bytes: None,
+            file_stamp: None,
             rotation: 0.0,
             rotation_in_progress: false,
             pending_rotations: 0,
+            load_job: None,
+            image_cache,
+            prefetch_job: None,
+            prefetched_for: None,
             zoom: 1.0,
             pan: egui::Vec2::ZERO,
             peaking_enabled: false,
@@ -289,8 +640,43 @@ impl VdiApp {
             histogram_result: None,
             histogram_receiver: None,
             rotation_receiver: None,
+            history,
+            history_depth: (0, 0),
+            history_receiver: None,
+            edit_receiver: None,
+            show_straighten: false,
+            straighten_angle: 0.0,
+            crop_rect: None,
+            crop_aspect: crop::AspectPreset::Free,
+            crop_drag: None,
+            show_letterbox: false,
+            lut: None,
+            lut_name: String::new(),
+            lut_preview: false,
+            show_lut: false,
+            lut_auto_save: false,
+            lut_saved: HashSet::new(),
+            lut_confirm: None,
+            lut_save_sender,
+            lut_save_receiver,
+            show_export: false,
+            export_receiver: None,
+            show_batch: false,
+            batch_files: Vec::new(),
+            batch_kind: BatchKind::Export,
+            batch_angle: 90,
+            batch_output_dir: None,
+            batch_overwrite: false,
+            batch_run: None,
+            batch_outcome: None,
             grid_enabled: false,
-            status_message: "準備完了".to_string(),
+            folder_index: None,
+            // 不正な引数は、既定の設定で起動したことが分かるように表示する
+            status_message: LAUNCH_CONFIG
+                .errors
+                .first()
+                .cloned()
+                .unwrap_or_else(|| "準備完了".to_string()),
             show_settings: false,
             blink_time: 0.0,
             fit_requested: false,
@@ -300,82 +686,589 @@ impl VdiApp {
             update_receiver: None,
             update_status: None,
             show_update_dialog: false,
+        };
+
+        // 起動時に指定されたLUTは、開いた画像に適用して自動保存する
+        if let Some(path) = &LAUNCH_CONFIG.auto_lut {
+            app.open_lut(std::path::Path::new(path));
+            app.lut_auto_save = app.lut.is_some();
         }
+        app
     }
 
+    /// 画像の読み込みをバックグラウンドで開始する
+    ///
+    /// 読み込みが完了するまで現在の画像は表示したままにする。
+    /// 実行中の読み込みがあれば、置き換えによってキャンセルされる。
     fn load_image(&mut self, path: PathBuf, ctx: &egui::Context) {
         println!("[LOAD_IMAGE] Starting load for: {}", path.display());
+
+        // 先読み済みであれば即座に表示する
+        let cached = self.image_cache.lock().unwrap().get(&path);
+        if let Some(cached) = cached {
+            println!("[LOAD_IMAGE] Cache hit: {}", path.display());
+            self.load_job = None;
+            self.apply_loaded_image(loader::LoadedImage::from_cached(path, cached), ctx);
+            return;
+        }
+
+        self.status_message = format!("{} を読み込み中...", path.display());
+        self.load_job = Some(loader::LoadJob::start(
+            path,
+            ctx.clone(),
+            self.image_cache.clone(),
+            self.display_transform(),
+            self.color_settings(),
+        ));
+    }
+
+    /// 表示中の画像の前後を先読みする（フォルダインデックスの準備完了後に一度だけ）
+    fn maybe_start_prefetch(&mut self) {
+        if self.load_job.is_some() || self.settings.prefetch_count == 0 {
+            return;
+        }
+        let (Some(path), Some(index)) = (&self.current_path, &self.folder_index) else {
+            return;
+        };
+        if !index.is_ready() || self.prefetched_for.as_ref() == Some(path) {
+            return;
+        }
+
+        let neighbours = index.neighbours(path, self.settings.prefetch_count);
+        self.prefetched_for = Some(path.clone());
+        self.prefetch_job = Some(image_cache::PrefetchJob::start(
+            neighbours,
+            self.image_cache.clone(),
+            self.display_transform(),
+            self.color_settings(),
+        ));
+    }
+
+    /// バックグラウンド読み込みの完了を処理する
+    fn poll_load_job(&mut self, ctx: &egui::Context) {
+        let Some(job) = &mut self.load_job else {
+            return;
+        };
+        let Some(result) = job.poll() else {
+            return;
+        };
+        self.load_job = None;
+
+        match result {
+            Ok(loaded) => self.apply_loaded_image(loaded, ctx),
+            Err(err) => {
+                self.status_message = format!("画像の読み込みに失敗しました: {}", err);
+            }
+        }
+    }
+
+    fn apply_loaded_image(&mut self, loaded: loader::LoadedImage, ctx: &egui::Context) {
+        let loader::LoadedImage {
+            path,
+            image,
+            pyramid,
+            transform,
+            animation,
+            page_count,
+            icc_profile,
+            color,
+            orientation,
+            file_size,
+            stamp,
+        } = loaded;
+        // 再読み込みではなく、別の画像を開いたか
+        let opened = self.current_path.as_ref() != Some(&path);
+
+        println!(
+            "[LOAD_IMAGE] Successfully opened image: {}x{}",
+            image.width(),
+            image.height()
+        );
         println!(
             "[LOAD_IMAGE] Current rotation before load: {}°",
             self.rotation
         );
 
-        self.status_message = format!("{} を読み込み中...", path.display());
+        self.file_size_bytes = Some(file_size);
+        self.file_stamp = stamp;
+        self.history_depth = self
+            .history
+            .as_ref()
+            .map(|history| history.depth(&path))
+            .unwrap_or_default();
+        self.image_dimensions = Some((image.width(), image.height()));
+        self.set_texture(pyramid);
+        self.original_image = Some(image);
+        self.animation_playing = animation.is_some();
+        self.animation = animation;
+        self.frame_index = 0;
+        self.frame_elapsed = 0.0;
+        self.icc_profile = icc_profile;
+        self.color_converter = color;
+        self.applied_orientation = orientation;
+        self.page_count = page_count;
+        self.current_page = 0;
+        self.page_receiver = None;
+        // 先読み後に表示変換が変更されていれば作り直す（LUTのプレビューは常に作り直す）
+        self.display_render_pending =
+            transform != self.display_transform() || self.lut_grade().is_some();
+        self.display_render_receiver = None;
+        self.analysis_image = None;
+        self.cancel_raw_develop();
+        if self.settings.raw_full_decode && raw::is_raw_path(&path) {
+            if raw::supports_full_decode(&path) {
+                self.start_raw_develop(path.clone());
+            } else {
+                println!("[RAW] フル現像に対応しない形式のため、埋め込みプレビューを使用します");
+            }
+        }
+        self.ensure_folder_index(&path, ctx);
+        self.current_path = Some(path);
+        self.zoom = 1.0;
+        self.pan = egui::Vec2::ZERO;
+
+        // 読み込み後、常に回転を0.0にリセットする
+        // ファイル自体は回転後の再読み込みであれば既に回転している
+        // そのため、視覚的な回転を適用する必要はもうない
+        println!("[LOAD_IMAGE] Resetting rotation to 0° (New image loaded)");
+        self.rotation = 0.0;
+        self.straighten_angle = 0.0;
+        self.crop_rect = None;
+
+        println!("[LOAD_IMAGE] Final rotation: {}°", self.rotation);
+
+        // 新しい画像の画面合わせをリクエスト
+        self.fit_requested = true;
+
+        // 機能をリセット
+        self.peaking_result = None;
+        self.histogram_result = None;
+
+        // 有効な場合、機能をトリガーする
+        if self.peaking_enabled {
+            self.trigger_peaking();
+        }
+        if self.histogram_enabled {
+            self.trigger_histogram();
+        }
 
-        // ファイルサイズを取得
-        self.file_size_bytes = std::fs::metadata(&path).ok().map(|m| m.len());
+        self.status_message = "読み込み完了".to_string();
 
-        match image::open(&path) {
-            Ok(img) => {
-                println!(
-                    "[LOAD_IMAGE] Successfully opened image: {}x{}",
-                    img.width(),
-                    img.height()
-                );
+        if opened {
+            self.lut_confirm = None;
+            if self.lut_auto_save {
+                self.save_lut(LutSave::Auto, false);
+            }
+        }
 
-                self.image_dimensions = Some((img.width(), img.height()));
-                let size = [img.width() as _, img.height() as _];
-                let image_buffer = img.to_rgba8();
-                let pixels = image_buffer.as_flat_samples();
-                let color_image = egui::ColorImage::from_rgba_unmultiplied(size, pixels.as_slice());
+        // 回転後の再読み込みであれば、回転したページを再表示する
+        if let Some((restore_path, page)) = self.restore_page.take() {
+            if self.current_path.as_ref() == Some(&restore_path) && page < self.page_count {
+                self.show_page(page, ctx);
+            }
+        }
+    }
 
-                self.texture =
-                    Some(ctx.load_texture("main_image", color_image, egui::TextureOptions::LINEAR));
-                self.original_image = Some(Arc::new(img));
-                self.current_path = Some(path);
-                self.zoom = 1.0;
-                self.pan = egui::Vec2::ZERO;
+    /// 読み込み進捗の表示用テキスト
+    fn load_progress_text(job: &loader::LoadJob) -> String {
+        match job.stage() {
+            loader::LoadStage::Reading { read, total } if total > 0 => {
+                format!("読み込み中 {:.0}%", read as f64 / total as f64 * 100.0)
+            }
+            loader::LoadStage::Reading { .. } => "読み込み中...".to_string(),
+            loader::LoadStage::Decoding => "デコード中...".to_string(),
+            loader::LoadStage::Preparing => "表示準備中...".to_string(),
+        }
+    }
 
-                // 読み込み後、常に回転を0.0にリセットする
-                // ファイル自体は回転後の再読み込みであれば既に回転している
-                // そのため、視覚的な回転を適用する必要はもうない
-                println!("[LOAD_IMAGE] Resetting rotation to 0° (New image loaded)");
-                self.rotation = 0.0;
+    /// 現在の表示変換
+    fn display_transform(&self) -> display::DisplayTransform {
+        display::DisplayTransform {
+            exposure: self.exposure,
+            contrast: self.contrast,
+            temperature: self.temperature,
+            tint: self.tint,
+            saturation: self.saturation,
+            gamma: self.display_gamma,
+            tone_map: self.settings.tone_map,
+        }
+    }
 
-                println!("[LOAD_IMAGE] Final rotation: {}°", self.rotation);
+    /// 表示調整をすべて既定値に戻す（トーンマッピングは設定のまま）
+    fn reset_display_adjustments(&mut self) {
+        let default = display::DisplayTransform::default();
+        self.exposure = default.exposure;
+        self.contrast = default.contrast;
+        self.temperature = default.temperature;
+        self.tint = default.tint;
+        self.saturation = default.saturation;
+        self.display_gamma = default.gamma;
+        self.request_display_render();
+    }
 
-                // 新しい画像の画面合わせをリクエスト
-                self.fit_requested = true;
+    /// 表示調整を焼き込んだ画像を保存する（`save_as`なら保存先を選んで新しいファイルに保存する）
+    fn save_adjustments(&mut self, save_as: bool) {
+        let transform = self.display_transform();
+        let page = self.current_page;
+        if save_as {
+            self.start_save_as(ADJUST_LABEL, "adjusted", move |path, output| {
+                img::adjust_image(path, &transform, page, Some(output), None)
+            });
+        } else {
+            self.start_edit(ADJUST_LABEL, move |path, expected| {
+                img::adjust_image(path, &transform, page, None, expected)
+            });
+        }
+    }
+
+    /// 表示に適用するLUT（プレビューが無効なら`None`）
+    fn lut_grade(&self) -> Option<lut::LutGrade> {
+        if self.lut_preview {
+            self.loaded_lut_grade()
+        } else {
+            None
+        }
+    }
+
+    /// 読み込んだLUTと補間方法（保存用、プレビューの有無に関係しない）
+    fn loaded_lut_grade(&self) -> Option<lut::LutGrade> {
+        self.lut.clone().map(|lut| lut::LutGrade {
+            lut,
+            interpolation: self.settings.lut_interpolation,
+        })
+    }
+
+    /// .cubeファイルを読み込み、プレビューを有効にする
+    fn open_lut(&mut self, path: &std::path::Path) {
+        match lut::Lut3d::load(path) {
+            Ok(lut) => {
+                let name = lut
+                    .title
+                    .clone()
+                    .filter(|title| !title.is_empty())
+                    .or_else(|| path.file_name().map(|n| n.to_string_lossy().to_string()))
+                    .unwrap_or_default();
+                println!("[LUT] 読み込み: {} ({}点)", path.display(), lut.size());
+                self.status_message = format!("LUTを読み込みました: {}", name);
+                self.lut = Some(Arc::new(lut));
+                self.lut_name = name;
+                self.lut_preview = true;
+                self.settings.lut_dir = path.parent().map(|dir| dir.to_string_lossy().to_string());
+                self.settings.save();
+                self.request_display_render();
+            }
+            Err(err) => {
+                self.status_message = format!("LUTの読み込みに失敗しました: {}", err);
+            }
+        }
+    }
+
+    /// 現在のカラーマネジメント設定
+    fn color_settings(&self) -> color::ColorSettings {
+        color::ColorSettings {
+            enabled: self.settings.color_management,
+            display_icc: self.display_icc.clone(),
+        }
+    }
+
+    /// カラーマネジメント設定の変更を表示中の画像と先読みキャッシュに反映する
+    fn apply_color_settings(&mut self) {
+        self.color_converter = self
+            .color_settings()
+            .converter_for(self.icc_profile.as_deref().map(|icc| icc.as_slice()))
+            .map(Arc::new);
+        // キャッシュ済みの表示用データは古い設定で作成されているため破棄する
+        self.image_cache.lock().unwrap().clear();
+        self.prefetched_for = None;
+        self.request_display_render();
+        if self.histogram_enabled {
+            self.trigger_histogram();
+        }
+    }
+
+    /// 表示中の画像（アニメーションは現在のフレーム）
+    fn displayed_image(&self) -> Option<Arc<image::DynamicImage>> {
+        match &self.animation {
+            Some(animation) => animation
+                .frames
+                .get(self.frame_index)
+                .map(|frame| frame.image.clone()),
+            None => self.original_image.clone(),
+        }
+    }
+
+    /// 表示用テクスチャを差し替える（タイルは描画時に必要な分だけ転送される）
+    fn set_texture(&mut self, pyramid: Arc<tiles::MipPyramid>) {
+        match &mut self.texture {
+            Some(texture) => texture.set(pyramid),
+            None => self.texture = Some(tiles::TiledTexture::new(pyramid)),
+        }
+    }
+
+    /// 現在のフレームをテクスチャに反映する
+    fn show_frame(&mut self) {
+        let Some(image) = self.displayed_image() else {
+            return;
+        };
+        let pyramid = loader::render_pyramid(
+            &image,
+            self.display_transform(),
+            self.lut_grade().as_ref(),
+            self.color_converter.as_deref(),
+        );
+        self.set_texture(Arc::new(pyramid));
+    }
+
+    /// 再生中のアニメーションを経過時間に応じて進める（各フレームの遅延時間に従う）
+    fn advance_animation(&mut self, ctx: &egui::Context) {
+        if !self.animation_playing {
+            return;
+        }
+        let Some(animation) = self.animation.clone() else {
+            return;
+        };
+
+        self.frame_elapsed += ctx.input(|i| i.stable_dt);
+        let mut changed = false;
+        loop {
+            let delay = animation.frames[self.frame_index].delay.as_secs_f32();
+            if self.frame_elapsed < delay {
+                ctx.request_repaint_after(std::time::Duration::from_secs_f32(
+                    delay - self.frame_elapsed,
+                ));
+                break;
+            }
+            self.frame_elapsed -= delay;
+            self.frame_index = (self.frame_index + 1) % animation.len();
+            changed = true;
+        }
 
-                // 機能をリセット
+        if changed {
+            self.show_frame();
+        }
+    }
+
+    /// 再生・一時停止を切り替える（一時停止時は表示中のフレームを解析する）
+    fn toggle_animation(&mut self) {
+        if self.animation.is_none() {
+            return;
+        }
+        self.animation_playing = !self.animation_playing;
+        self.frame_elapsed = 0.0;
+        if !self.animation_playing {
+            self.refresh_analysis();
+        }
+    }
+
+    /// 一時停止してフレームを1つ進める・戻す
+    fn step_frame(&mut self, forward: bool) {
+        let Some(len) = self.animation.as_ref().map(|animation| animation.len()) else {
+            return;
+        };
+        self.animation_playing = false;
+        self.frame_elapsed = 0.0;
+        self.frame_index = if forward {
+            (self.frame_index + 1) % len
+        } else {
+            (self.frame_index + len - 1) % len
+        };
+        self.show_frame();
+        self.refresh_analysis();
+    }
+
+    /// 複数ページのTIFFのページをバックグラウンドで読み込んで表示する
+    fn show_page(&mut self, page: usize, ctx: &egui::Context) {
+        let Some(path) = self.current_path.clone() else {
+            return;
+        };
+        if page >= self.page_count {
+            return;
+        }
+        self.current_page = page;
+        self.status_message = format!("ページ {} を読み込み中...", page + 1);
+
+        let transform = self.display_transform();
+        let grade = self.lut_grade();
+        let converter = self.color_converter.clone();
+        let (tx, rx) = mpsc::channel();
+        self.page_receiver = Some(rx);
+        let repaint_ctx = ctx.clone();
+        thread::spawn(move || {
+            let res = std::fs::read(&path)
+                .map_err(|e| format!("ファイルの読み込みに失敗しました: {}", e))
+                .and_then(|bytes| multipage::decode_page(&bytes, &path, page))
+                .map(|image| {
+                    let pyramid = loader::render_pyramid(
+                        &image,
+                        transform,
+                        grade.as_ref(),
+                        converter.as_deref(),
+                    );
+                    (Arc::new(image), Arc::new(pyramid), transform)
+                });
+            let _ = tx.send(PageResult { path, page, res });
+            repaint_ctx.request_repaint();
+        });
+    }
+
+    /// 前後のページへ移動する（複数ページのTIFFのみ）
+    fn step_page(&mut self, forward: bool, ctx: &egui::Context) {
+        if self.page_count < 2 {
+            return;
+        }
+        let page = if forward {
+            (self.current_page + 1).min(self.page_count - 1)
+        } else {
+            self.current_page.saturating_sub(1)
+        };
+        if page != self.current_page {
+            self.show_page(page, ctx);
+        }
+    }
+
+    /// 読み込んだページを表示し、解析対象を切り替える
+    fn poll_page(&mut self) {
+        let Some(rx) = &self.page_receiver else {
+            return;
+        };
+        let Ok(PageResult { path, page, res }) = rx.try_recv() else {
+            return;
+        };
+        self.page_receiver = None;
+        if self.current_path.as_ref() != Some(&path) || self.current_page != page {
+            return;
+        }
+
+        match res {
+            Ok((image, pyramid, transform)) => {
+                self.image_dimensions = Some((image.width(), image.height()));
+                self.set_texture(pyramid);
+                self.original_image = Some(image);
+                self.display_render_receiver = None;
+                self.display_render_pending = transform != self.display_transform();
+                self.fit_requested = true;
                 self.peaking_result = None;
                 self.histogram_result = None;
+                self.refresh_analysis();
+                self.status_message = format!("ページ {}/{}", page + 1, self.page_count);
+            }
+            Err(err) => {
+                self.status_message = format!("ページの読み込みに失敗しました: {}", err);
+            }
+        }
+    }
 
-                // 有効な場合、機能をトリガーする
-                if self.peaking_enabled {
-                    self.trigger_peaking();
-                }
-                if self.histogram_enabled {
-                    self.trigger_histogram();
-                }
+    /// 有効なピーキング・ヒストグラムを再計算する
+    fn refresh_analysis(&mut self) {
+        if self.peaking_enabled {
+            self.trigger_peaking();
+        }
+        if self.histogram_enabled {
+            self.trigger_histogram();
+        }
+    }
+
+    /// 表示変換を変更した後、表示用テクスチャの作り直しを要求する
+    fn request_display_render(&mut self) {
+        self.display_render_pending = true;
+    }
 
-                self.status_message = "読み込み完了".to_string();
+    /// 表示用テクスチャの作り直しを開始する（同時に実行するのは1つまで）
+    fn poll_display_render(&mut self, ctx: &egui::Context) {
+        if let Some(rx) = &self.display_render_receiver {
+            match rx.try_recv() {
+                Ok((path, pyramid)) => {
+                    self.display_render_receiver = None;
+                    if self.current_path.as_ref() == Some(&path) {
+                        self.set_texture(Arc::new(pyramid));
+                    }
+                }
+                Err(mpsc::TryRecvError::Empty) => return,
+                Err(mpsc::TryRecvError::Disconnected) => self.display_render_receiver = None,
             }
-            Err(err) => {
-                self.status_message = format!("画像の読み込みに失敗しました: {}", err);
+        }
+
+        if !self.display_render_pending {
+            return;
+        }
+        // アニメーションはフレームごとに描画するため、現在のフレームのみ作り直す
+        if self.animation.is_some() {
+            self.display_render_pending = false;
+            self.show_frame();
+            return;
+        }
+        let (Some(path), Some(image)) = (self.current_path.clone(), self.original_image.clone())
+        else {
+            return;
+        };
+        self.display_render_pending = false;
+
+        // 表示調整を反映したヒストグラムも作り直す
+        if self.histogram_enabled && self.settings.histogram_adjusted {
+            self.trigger_histogram();
+        }
+
+        let transform = self.display_transform();
+        let grade = self.lut_grade();
+        let converter = self.color_converter.clone();
+        let (tx, rx) = mpsc::channel();
+        self.display_render_receiver = Some(rx);
+        let repaint_ctx = ctx.clone();
+        thread::spawn(move || {
+            let pyramid =
+                loader::render_pyramid(&image, transform, grade.as_ref(), converter.as_deref());
+            let _ = tx.send((path, pyramid));
+            repaint_ctx.request_repaint();
+        });
+    }
+
+    /// RAWのフル現像をバックグラウンドで開始する（表示は埋め込みプレビューのまま）
+    fn start_raw_develop(&mut self, path: PathBuf) {
+        let (tx, rx) = mpsc::channel();
+        let cancel_flag = Arc::new(AtomicBool::new(false));
+        self.raw_develop_receiver = Some(rx);
+        self.raw_develop_cancel = Some(cancel_flag.clone());
+
+        thread::spawn(move || {
+            let start = std::time::Instant::now();
+            let cancelled = || cancel_flag.load(Ordering::Relaxed);
+            let res = img::open_image_for_analysis(&path, true, cancelled).map(Arc::new);
+            if cancelled() {
+                println!("[RAW] フル現像を中断: {}", path.display());
+                return;
             }
+            println!("[RAW] フル現像: {} - {:?}", path.display(), start.elapsed());
+            let _ = tx.send(res);
+        });
+    }
+
+    /// 実行中のRAWフル現像を中断する（別の画像に移動した場合など）
+    fn cancel_raw_develop(&mut self) {
+        if let Some(cancel_flag) = self.raw_develop_cancel.take() {
+            cancel_flag.store(true, Ordering::Relaxed);
         }
+        self.raw_develop_receiver = None;
+    }
+
+    /// ピーキング・ヒストグラムの解析対象（RAWのフル現像結果があれば優先）
+    fn analysis_target(&self) -> Option<(String, Arc<image::DynamicImage>)> {
+        let path = self.current_path.as_ref()?;
+        let image = self
+            .analysis_image
+            .clone()
+            .or_else(|| self.displayed_image())?;
+        Some((path.to_string_lossy().to_string(), image))
     }
 
     fn trigger_peaking(&mut self) {
-        if let Some(path) = &self.current_path {
-            let path_str = path.to_string_lossy().to_string();
+        if let Some((path_str, image)) = self.analysis_target() {
             let threshold = self.settings.peaking_threshold;
             let (tx, rx) = mpsc::channel();
             self.peaking_receiver = Some(rx);
 
             thread::spawn(move || {
-                let res = peaking::focus_peaking(path_str, threshold, None);
+                let request_id = format!("{}:{}", path_str, threshold);
+                let res = peaking::focus_peaking_image(&image, threshold, request_id);
 
                 if let Ok(result) = res {
                     let _ = tx.send(result);
@@ -385,13 +1278,38 @@ impl VdiApp {
     }
 
     fn trigger_histogram(&mut self) {
-        if let Some(path) = &self.current_path {
-            let path_str = path.to_string_lossy().to_string();
+        if let Some((path_str, image)) = self.analysis_target() {
+            // 表示色空間モードでは表示プロファイルへ変換した値を集計する
+            let converter = match self.settings.histogram_space {
+                HistogramSpace::Display => self.color_converter.clone(),
+                HistogramSpace::Source => None,
+            };
+            // 表示調整を反映する場合は、表示と同じ変換（LUTを含む）を適用した値を集計する
+            let adjustment = self
+                .settings
+                .histogram_adjusted
+                .then(|| (self.display_transform(), self.lut_grade()));
             let (tx, rx) = mpsc::channel();
             self.histogram_receiver = Some(rx);
 
             thread::spawn(move || {
-                let res = histogram::calculate_histogram(path_str, "rgb".to_string(), None);
+                let image = match adjustment {
+                    Some((transform, grade)) => {
+                        let mut rendered = transform.render(&image);
+                        if let Some(grade) = grade {
+                            grade.apply_rgba8(&mut rendered);
+                        }
+                        Arc::new(image::DynamicImage::ImageRgba8(rendered))
+                    }
+                    None => image,
+                };
+                let image = match &converter {
+                    Some(converter) => Arc::new(converter.to_display_space(&image)),
+                    None => image,
+                };
+                let request_id = format!("{}:rgb", path_str);
+                let res =
+                    histogram::calculate_histogram_image(&image, "rgb".to_string(), request_id);
                 if let Ok(result) = res {
                     let _ = tx.send(result);
                 }
@@ -399,28 +1317,74 @@ impl VdiApp {
         }
     }
 
+    /// 画像の親フォルダのインデックスを用意する（フォルダが変わった場合のみ再作成）
+    fn ensure_folder_index(&mut self, path: &std::path::Path, ctx: &egui::Context) {
+        let Some(folder) = path.parent() else {
+            return;
+        };
+
+        if let Some(index) = &self.folder_index {
+            if index.folder() == folder {
+                return;
+            }
+        }
+
+        let repaint_ctx = ctx.clone();
+        self.folder_index = Some(navigation::FolderIndex::build(
+            folder.to_path_buf(),
+            self.settings.sort_order,
+            self.settings.sort_reverse,
+            move || repaint_ctx.request_repaint(),
+        ));
+    }
+
+    /// ナビゲーションの基準となる画像（読み込み中の画像を優先）
+    fn navigation_anchor(&self) -> Option<PathBuf> {
+        self.load_job
+            .as_ref()
+            .map(|job| job.path().to_path_buf())
+            .or_else(|| self.current_path.clone())
+    }
+
     fn next_image(&mut self, ctx: &egui::Context) {
-        if let Some(path) = &self.current_path {
-            if let Some(next) = navigation::get_next_image(path.to_string_lossy().to_string(), true)
-            {
-                self.load_image(PathBuf::from(next), ctx);
+        let next = match (&self.folder_index, self.navigation_anchor()) {
+            (Some(index), Some(path)) if index.is_ready() => index.next(&path),
+            (Some(_), Some(_)) => {
+                self.status_message = "フォルダを読み込み中...".to_string();
+                None
             }
+            _ => None,
+        };
+        if let Some(next) = next {
+            self.load_image(next, ctx);
         }
     }
 
     fn prev_image(&mut self, ctx: &egui::Context) {
-        if let Some(path) = &self.current_path {
-            if let Some(prev) =
-                navigation::get_previous_image(path.to_string_lossy().to_string(), true)
-            {
-                self.load_image(PathBuf::from(prev), ctx);
+        let prev = match (&self.folder_index, self.navigation_anchor()) {
+            (Some(index), Some(path)) if index.is_ready() => index.previous(&path),
+            (Some(_), Some(_)) => {
+                self.status_message = "フォルダを読み込み中...".to_string();
+                None
             }
+            _ => None,
+        };
+        if let Some(prev) = prev {
+            self.load_image(prev, ctx);
         }
     }
 
     fn rotate_image(&mut self, _ctx: &egui::Context) {
         println!("[ROTATE_IMAGE] Function called");
 
+        // 実行中の回転には重ねて予約できるが、それ以外の編集・一括回転の最中は受け付けない
+        if self.edit_busy() && !self.rotation_in_progress {
+            self.status_message = "処理中のため操作できません".to_string();
+            return;
+        }
+        // 切り抜き範囲は回転前の画像が基準のため、切り抜きモードを終了する
+        self.crop_rect = None;
+
         if let Some(path) = &self.current_path {
             // 処理状態に関係なく、視覚的なフィードバックのために回転を即座に更新する
             let old_rotation = self.rotation;
@@ -458,47 +1422,464 @@ impl VdiApp {
         let (tx, rx) = mpsc::channel();
         self.rotation_receiver = Some(rx);
 
-        let path_str = path.to_string_lossy().to_string();
         let reload_path = path.clone();
+        // 複数ページのTIFFは表示中のページのみ回転する
+        let page = self.current_page;
+        let jpeg_edges = self.jpeg_edge_mode();
+        let mode = self.settings.rotation_mode;
+        // 読み込み後に他のアプリで変更されていれば、回転せずにエラーにする
+        let expected = self.file_stamp;
+        let history = self.history.clone();
 
         thread::spawn(move || {
-            let result = img::rotate_image(path_str, 90.0);
+            // 元に戻せるよう、回転前の状態を保持しておく
+            let before = history::FileState::capture(&reload_path);
+            let result = img::apply_transform(
+                &reload_path,
+                orientation::Orientation::default().rotated_by(90),
+                page,
+                mode,
+                jpeg_edges,
+                expected,
+            );
 
-            if result.is_ok() {
-                // Wait a bit for file to be written
-                thread::sleep(std::time::Duration::from_millis(100));
-                let _ = tx.send(reload_path);
+            match result {
+                Ok(_) => {
+                    if let (Some(history), Ok(before)) = (&history, before) {
+                        if let Err(e) = history.record(&reload_path, "回転", before) {
+                            eprintln!("[History] 履歴の保存に失敗しました: {}", e);
+                        }
+                    }
+                    // Wait a bit for file to be written
+                    thread::sleep(std::time::Duration::from_millis(100));
+                    let _ = tx.send(Ok(reload_path));
+                }
+                Err(err) => {
+                    println!("[ROTATE_IMAGE] Rotation failed: {}", err);
+                    let _ = tx.send(Err(err));
+                }
             }
         });
     }
 
-    fn screen_fit(&mut self, available_size: egui::Vec2) {
-        if let Some(texture) = &self.texture {
-            let image_size = texture.size_vec2();
-
-            // 利用可能なスペースに合わせて画像のズームを計算
-            // 回転を考慮
-            let (display_width, display_height) = if self.rotation == 90.0 || self.rotation == 270.0
-            {
-                (image_size.y, image_size.x)
-            } else {
-                (image_size.x, image_size.y)
-            };
+    /// JPEGのサイズがMCUの倍数でない場合の扱い（設定による）
+    fn jpeg_edge_mode(&self) -> jpeg_lossless::EdgeMode {
+        if self.settings.jpeg_trim_edges {
+            jpeg_lossless::EdgeMode::Trim
+        } else {
+            jpeg_lossless::EdgeMode::Reject
+        }
+    }
 
-            let zoom_x = available_size.x / display_width;
-            let zoom_y = available_size.y / display_height;
+    /// 回転・履歴の復元・編集のいずれかを処理中か
+    fn edit_busy(&self) -> bool {
+        self.rotation_in_progress
+            || self.history_receiver.is_some()
+            || self.edit_receiver.is_some()
+            || matches!(self.batch_run, Some((BatchKind::Rotate, _)))
+    }
 
-            // 画像全体が表示されるように小さい方のズーム係数を使用
-            // 画面全体を埋めるために5%のマージンを削除
-            self.zoom = zoom_x.min(zoom_y).max(0.01);
-            self.pan = egui::Vec2::ZERO;
+    /// 表示中の画像をバックグラウンドで編集する
+    ///
+    /// 成功すれば編集前の状態を履歴に記録し、完了後に画像を再読み込みします。
+    fn start_edit(
+        &mut self,
+        label: &'static str,
+        edit: impl FnOnce(&std::path::Path, Option<safe_write::FileStamp>) -> Result<(), String>
+            + Send
+            + 'static,
+    ) {
+        let Some(path) = self.current_path.clone() else {
+            return;
+        };
+        if self.edit_busy() {
+            self.status_message = "処理中のため操作できません".to_string();
+            return;
         }
+
+        let (tx, rx) = mpsc::channel();
+        self.edit_receiver = Some(rx);
+        self.status_message = format!("{}を保存中...", label);
+        // 読み込み後に他のアプリで変更されていれば、編集せずにエラーにする
+        let expected = self.file_stamp;
+        let history = self.history.clone();
+        thread::spawn(move || {
+            // 元に戻せるよう、編集前の状態を保持しておく
+            let before = history::FileState::capture(&path);
+            let res = edit(&path, expected);
+            if res.is_ok() {
+                if let (Some(history), Ok(before)) = (&history, before) {
+                    if let Err(e) = history.record(&path, label, before) {
+                        eprintln!("[History] 履歴の保存に失敗しました: {}", e);
+                    }
+                }
+            }
+            let _ = tx.send(EditResult { path, label, res });
+        });
     }
 
-    fn reveal_in_explorer(&self) {
-        if let Some(path) = &self.current_path {
-            #[cfg(target_os = "windows")]
-            {
+    /// 表示中の画像を反転する（`horizontal`が`false`なら上下反転）
+    ///
+    /// 保存方法は回転と同じ設定に従います。
+    fn flip_image(&mut self, horizontal: bool) {
+        self.crop_rect = None;
+        let (label, transform) = if horizontal {
+            (
+                "左右反転",
+                orientation::Orientation::default().flipped_horizontally(),
+            )
+        } else {
+            (
+                "上下反転",
+                orientation::Orientation::default().flipped_vertically(),
+            )
+        };
+        let page = self.current_page;
+        let mode = self.settings.rotation_mode;
+        let jpeg_edges = self.jpeg_edge_mode();
+        self.start_edit(label, move |path, expected| {
+            img::apply_transform(path, transform, page, mode, jpeg_edges, expected)
+        });
+    }
+
+    /// 切り抜きモードを切り替える（開始時は画像全体を選択する）
+    fn toggle_crop(&mut self) {
+        if self.crop_rect.is_some() {
+            self.crop_rect = None;
+            return;
+        }
+        let Some(size) = self.texture.as_ref().map(|t| t.size_vec2()) else {
+            return;
+        };
+        self.show_straighten = false;
+        self.straighten_angle = 0.0;
+        self.show_letterbox = false;
+        self.crop_rect = Some(
+            crop::CropRect::full(size.x as u32, size.y as u32).fit_aspect(self.crop_aspect.ratio()),
+        );
+    }
+
+    /// 切り抜いた画像を保存する（`save_as`なら保存先を選んで新しいファイルに保存する）
+    fn save_crop(&mut self, save_as: bool) {
+        let (Some(rect), Some(size)) =
+            (self.crop_rect, self.texture.as_ref().map(|t| t.size_vec2()))
+        else {
+            return;
+        };
+        let pixels = rect.to_pixels((size.x as u32, size.y as u32));
+        let page = self.current_page;
+        if save_as {
+            self.start_save_as("切り抜き", "crop", move |path, output| {
+                img::crop_image(path, pixels, page, Some(output), None)
+            });
+        } else {
+            self.start_edit("切り抜き", move |path, expected| {
+                img::crop_image(path, pixels, page, None, expected)
+            });
+        }
+    }
+
+    /// 黒帯を付けた画像を保存する（`save_as`なら保存先を選んで新しいファイルに保存する）
+    fn save_letterbox(&mut self, save_as: bool) {
+        let letterbox = self.settings.letterbox;
+        let page = self.current_page;
+        if save_as {
+            self.start_save_as("黒帯", "letterbox", move |path, output| {
+                img::letterbox_image(path, &letterbox, page, Some(output), None)
+            });
+        } else {
+            self.start_edit("黒帯", move |path, expected| {
+                img::letterbox_image(path, &letterbox, page, None, expected)
+            });
+        }
+    }
+
+    /// LUTを適用した画像を保存する
+    ///
+    /// 既にLUTを適用して保存した画像は二重に適用しないよう、`confirmed`でなければ
+    /// 保存せずに確認を求めます。
+    fn save_lut(&mut self, kind: LutSave, confirmed: bool) {
+        let (Some(path), Some(grade)) = (self.current_path.clone(), self.loaded_lut_grade()) else {
+            return;
+        };
+        let output = img::edited_output_path(&path, "lut");
+        if !confirmed {
+            let graded = self.lut_saved.contains(&path)
+                || path
+                    .file_stem()
+                    .is_some_and(|stem| stem.to_string_lossy().ends_with("_lut"));
+            let warning = if graded {
+                Some("この画像には既にLUTを適用して保存しています。\nもう一度保存するとLUTが二重にかかる場合があります。".to_string())
+            } else if kind == LutSave::Auto && output.exists() {
+                Some(format!(
+                    "保存先に既にファイルがあります。\n{}",
+                    output.display()
+                ))
+            } else {
+                None
+            };
+            if let Some(warning) = warning {
+                self.status_message = "LUTの保存を確認してください".to_string();
+                self.lut_confirm = Some((kind, warning));
+                return;
+            }
+        }
+
+        let page = self.current_page;
+        match kind {
+            LutSave::Overwrite => self.start_edit(LUT_LABEL, move |path, expected| {
+                img::lut_image(path, &grade, page, None, expected)
+            }),
+            LutSave::SaveAs => self.start_save_as(LUT_LABEL, "lut", move |path, output| {
+                img::lut_image(path, &grade, page, Some(output), None)
+            }),
+            LutSave::Auto => {
+                // 完了前に同じ画像を開き直しても、二重に保存しないよう先に記録する
+                self.lut_saved.insert(path.clone());
+                self.status_message = format!("LUTを適用して保存中: {}", output.display());
+                let tx = self.lut_save_sender.clone();
+                thread::spawn(move || {
+                    let res = img::lut_image(&path, &grade, page, Some(&output), None);
+                    let _ = tx.send(LutSaveResult {
+                        source: path,
+                        output,
+                        res,
+                    });
+                });
+            }
+        }
+    }
+
+    /// 書き出す画像のサイズ（表示中の回転・角度補正と長辺の指定を反映する）
+    fn export_size(&self) -> Option<(u32, u32)> {
+        let (mut width, mut height) = self.image_dimensions?;
+        if (self.rotation as i32).rem_euclid(180) == 90 {
+            std::mem::swap(&mut width, &mut height);
+        }
+        if self.straighten_angle != 0.0 {
+            let (w, h) =
+                straighten::inscribed_size(width as f32, height as f32, self.straighten_angle);
+            width = (w.floor() as u32).max(1);
+            height = (h.floor() as u32).max(1);
+        }
+        Some(self.settings.export.output_size(width, height))
+    }
+
+    /// 表示中の画像を、保存先を選んで書き出す（表示中の回転を適用し、元のファイルは変更しない）
+    fn start_export(&mut self) {
+        let (Some(path), Some(size)) = (self.current_path.clone(), self.export_size()) else {
+            return;
+        };
+        if self.export_receiver.is_some() {
+            self.status_message = "書き出し中のため操作できません".to_string();
+            return;
+        }
+        let options = self.settings.export.clone();
+        let mut dialog = rfd::FileDialog::new()
+            .add_filter(options.format.label(), &[options.format.extension()])
+            .set_file_name(options.file_name(&path, size));
+        if let Some(dir) = path.parent() {
+            dialog = dialog.set_directory(dir);
+        }
+        let Some(output) = dialog.save_file() else {
+            return;
+        };
+
+        let page = self.current_page;
+        let rotation = self.rotation as i32;
+        let straighten = self.straighten_angle;
+        let (tx, rx) = mpsc::channel();
+        self.export_receiver = Some(rx);
+        self.status_message = "書き出し中...".to_string();
+        thread::spawn(move || {
+            let res = export::export_image(&path, page, rotation, straighten, &options, &output)
+                .map(|_| output);
+            let _ = tx.send(res);
+        });
+    }
+
+    /// 一括処理ウィンドウの表示を切り替える（開くときに対象一覧を更新する）
+    fn toggle_batch(&mut self) {
+        self.show_batch = !self.show_batch;
+        if self.show_batch {
+            self.refresh_batch_files();
+        }
+    }
+
+    /// 一括処理の対象一覧を表示中のフォルダの画像で更新する（既存の選択状態は引き継ぐ）
+    fn refresh_batch_files(&mut self) {
+        let Some(index) = &self.folder_index else {
+            self.batch_files.clear();
+            return;
+        };
+        let previous: std::collections::HashMap<PathBuf, bool> =
+            self.batch_files.drain(..).collect();
+        self.batch_files = index
+            .paths()
+            .into_iter()
+            .map(|path| {
+                let selected = previous.get(&path).copied().unwrap_or(true);
+                (path, selected)
+            })
+            .collect();
+    }
+
+    /// 選択した画像の一括処理をバックグラウンドで開始する
+    fn start_batch(&mut self, ctx: &egui::Context) {
+        if self.batch_run.is_some() {
+            return;
+        }
+        let files: Vec<PathBuf> = self
+            .batch_files
+            .iter()
+            .filter(|(_, selected)| *selected)
+            .map(|(path, _)| path.clone())
+            .collect();
+        if files.is_empty() {
+            return;
+        }
+        let operation = match self.batch_kind {
+            BatchKind::Rotate => {
+                if self.edit_busy() {
+                    self.status_message = "処理中のため回転できません".to_string();
+                    return;
+                }
+                batch::BatchOperation::Rotate {
+                    angle: self.batch_angle,
+                    mode: self.settings.rotation_mode,
+                    jpeg_edges: self.jpeg_edge_mode(),
+                }
+            }
+            BatchKind::Export => batch::BatchOperation::Export(self.settings.export.clone()),
+            BatchKind::Letterbox => batch::BatchOperation::Letterbox(self.settings.letterbox),
+        };
+        let output_dir = match self.batch_kind {
+            BatchKind::Rotate => None,
+            _ => self.batch_output_dir.clone(),
+        };
+        let job = batch::BatchJob {
+            operation,
+            files,
+            output_dir,
+            overwrite: self.batch_overwrite,
+        };
+        let repaint_ctx = ctx.clone();
+        let run = batch::BatchRun::start(job, self.history.clone(), move || {
+            repaint_ctx.request_repaint()
+        });
+        self.batch_run = Some((self.batch_kind, run));
+        self.batch_outcome = None;
+        self.status_message = "一括処理中...".to_string();
+    }
+
+    /// 表示中の画像を編集して、保存先を選んで新しいファイルに保存する
+    ///
+    /// 元のファイルは変更しないため履歴には記録せず、保存後は保存した画像を表示します。
+    fn start_save_as(
+        &mut self,
+        label: &'static str,
+        suffix: &str,
+        save: impl FnOnce(&std::path::Path, &std::path::Path) -> Result<(), String> + Send + 'static,
+    ) {
+        let Some(path) = self.current_path.clone() else {
+            return;
+        };
+        if self.edit_busy() {
+            self.status_message = "処理中のため操作できません".to_string();
+            return;
+        }
+        let default = img::edited_output_path(&path, suffix);
+        let mut dialog = rfd::FileDialog::new();
+        if let Some(dir) = default.parent() {
+            dialog = dialog.set_directory(dir);
+        }
+        if let Some(name) = default.file_name() {
+            dialog = dialog.set_file_name(name.to_string_lossy());
+        }
+        let Some(output) = dialog.save_file() else {
+            return;
+        };
+
+        let (tx, rx) = mpsc::channel();
+        self.edit_receiver = Some(rx);
+        self.status_message = format!("{}を保存中...", label);
+        thread::spawn(move || {
+            let res = save(&path, &output);
+            let _ = tx.send(EditResult {
+                path: output,
+                label,
+                res,
+            });
+        });
+    }
+
+    /// プレビュー中の角度補正を画像に適用する
+    fn apply_straighten(&mut self) {
+        let angle = self.straighten_angle;
+        let crop = self.settings.straighten_crop;
+        let page = self.current_page;
+        self.start_edit("角度補正", move |path, expected| {
+            img::straighten_image(path, angle, crop, page, expected)
+        });
+    }
+
+    /// 表示中の画像の編集を元に戻す（`undo`が`false`ならやり直す）
+    fn step_history(&mut self, undo: bool) {
+        let (Some(path), Some(history)) = (&self.current_path, &self.history) else {
+            return;
+        };
+        if self.edit_busy() {
+            self.status_message = "処理中のため操作できません".to_string();
+            return;
+        }
+
+        let (tx, rx) = mpsc::channel();
+        self.history_receiver = Some(rx);
+        let path = path.clone();
+        let history = history.clone();
+        // 読み込み後に他のアプリで変更されていれば、書き戻さずにエラーにする
+        let expected = self.file_stamp;
+        thread::spawn(move || {
+            let result = if undo {
+                history
+                    .undo(&path, expected)
+                    .map(|label| label.map(|label| format!("{}を元に戻しました", label)))
+            } else {
+                history
+                    .redo(&path, expected)
+                    .map(|label| label.map(|label| format!("{}をやり直しました", label)))
+            };
+            let _ = tx.send(HistoryResult { path, res: result });
+        });
+    }
+
+    fn screen_fit(&mut self, available_size: egui::Vec2) {
+        if let Some(texture) = &self.texture {
+            let image_size = texture.size_vec2();
+
+            // 利用可能なスペースに合わせて画像のズームを計算
+            // 回転を考慮
+            let (display_width, display_height) = if self.rotation == 90.0 || self.rotation == 270.0
+            {
+                (image_size.y, image_size.x)
+            } else {
+                (image_size.x, image_size.y)
+            };
+
+            let zoom_x = available_size.x / display_width;
+            let zoom_y = available_size.y / display_height;
+
+            // 画像全体が表示されるように小さい方のズーム係数を使用
+            // 画面全体を埋めるために5%のマージンを削除
+            self.zoom = zoom_x.min(zoom_y).max(0.01);
+            self.pan = egui::Vec2::ZERO;
+        }
+    }
+
+    fn reveal_in_explorer(&self) {
+        if let Some(path) = &self.current_path {
+            #[cfg(target_os = "windows")]
+            {
                 let _ = std::process::Command::new("explorer")
                     .args(&["/select,", &path.to_string_lossy()])
                     .spawn();
@@ -536,6 +1917,124 @@ impl VdiApp {
         }
     }
 
+    /// 角度補正用の水平・垂直のガイド線と、切り抜き範囲の枠を描画する
+    ///
+    /// `size`は角度補正前の表示サイズ（90度単位の回転は反映済み）です。
+    fn draw_level_guide(
+        &self,
+        painter: &egui::Painter,
+        rect: egui::Rect,
+        center: egui::Pos2,
+        size: egui::Vec2,
+    ) {
+        const SPACING: f32 = 48.0;
+        let stroke = egui::Stroke::new(1.0, egui::Color32::from_white_alpha(90));
+
+        // 画像の中心を通るよう、一定間隔の線を画面全体に引く
+        let first_x = center.x - ((center.x - rect.min.x) / SPACING).floor() * SPACING;
+        let mut x = first_x;
+        while x <= rect.max.x {
+            painter.line_segment(
+                [egui::pos2(x, rect.min.y), egui::pos2(x, rect.max.y)],
+                stroke,
+            );
+            x += SPACING;
+        }
+        let first_y = center.y - ((center.y - rect.min.y) / SPACING).floor() * SPACING;
+        let mut y = first_y;
+        while y <= rect.max.y {
+            painter.line_segment(
+                [egui::pos2(rect.min.x, y), egui::pos2(rect.max.x, y)],
+                stroke,
+            );
+            y += SPACING;
+        }
+
+        if self.settings.straighten_crop {
+            let (w, h) = straighten::inscribed_size(size.x, size.y, self.straighten_angle);
+            painter.rect_stroke(
+                egui::Rect::from_center_size(center, egui::vec2(w, h)),
+                0.0,
+                egui::Stroke::new(2.0, egui::Color32::from_rgb(255, 200, 0)),
+            );
+        }
+    }
+
+    /// 画面上の画像の範囲`image`の外側に、黒帯のプレビューを描画する
+    fn draw_letterbox(&self, painter: &egui::Painter, image: egui::Rect) {
+        let letterbox = &self.settings.letterbox;
+        let (width, height, x, y) = letterbox.layout_exact(image.width(), image.height());
+        let outer =
+            egui::Rect::from_min_size(image.min - egui::vec2(x, y), egui::vec2(width, height));
+        let [r, g, b] = letterbox.color;
+        let color = egui::Color32::from_rgb(r, g, b);
+        for bar in [
+            egui::Rect::from_min_max(outer.min, egui::pos2(outer.max.x, image.min.y)),
+            egui::Rect::from_min_max(egui::pos2(outer.min.x, image.max.y), outer.max),
+            egui::Rect::from_min_max(
+                egui::pos2(outer.min.x, image.min.y),
+                egui::pos2(image.min.x, image.max.y),
+            ),
+            egui::Rect::from_min_max(
+                egui::pos2(image.max.x, image.min.y),
+                egui::pos2(outer.max.x, image.max.y),
+            ),
+        ] {
+            if bar.is_positive() {
+                painter.rect_filled(bar, 0.0, color);
+            }
+        }
+        painter.rect_stroke(
+            outer,
+            0.0,
+            egui::Stroke::new(1.0, egui::Color32::from_gray(90)),
+        );
+    }
+
+    /// 切り抜き範囲の画面上の位置（切り抜きモードでなければ`None`）
+    ///
+    /// 切り抜きモードは回転のプレビューが無い状態でのみ有効なため、回転は考慮しません。
+    fn crop_screen_rect(&self, center: egui::Pos2, image_size: egui::Vec2) -> Option<egui::Rect> {
+        let rect = self.crop_rect?;
+        let origin = center - image_size * self.zoom / 2.0;
+        Some(egui::Rect::from_min_size(
+            origin + egui::vec2(rect.x, rect.y) * self.zoom,
+            egui::vec2(rect.width, rect.height) * self.zoom,
+        ))
+    }
+
+    /// 切り抜き範囲の外側を暗くし、枠・つまみ・グリッドを描画する
+    fn draw_crop_overlay(&self, painter: &egui::Painter, clip: egui::Rect, rect: egui::Rect) {
+        let shade = egui::Color32::from_black_alpha(150);
+        for outside in [
+            egui::Rect::from_min_max(clip.min, egui::pos2(clip.max.x, rect.min.y)),
+            egui::Rect::from_min_max(egui::pos2(clip.min.x, rect.max.y), clip.max),
+            egui::Rect::from_min_max(
+                egui::pos2(clip.min.x, rect.min.y),
+                egui::pos2(rect.min.x, rect.max.y),
+            ),
+            egui::Rect::from_min_max(
+                egui::pos2(rect.max.x, rect.min.y),
+                egui::pos2(clip.max.x, rect.max.y),
+            ),
+        ] {
+            painter.rect_filled(outside, 0.0, shade);
+        }
+
+        self.draw_grid(painter, rect);
+        painter.rect_stroke(rect, 0.0, egui::Stroke::new(1.5, egui::Color32::WHITE));
+        for handle in crop::Handle::EDGES {
+            painter.rect_filled(
+                egui::Rect::from_center_size(
+                    crop_handle_pos(rect, handle),
+                    egui::vec2(CROP_HANDLE_SIZE, CROP_HANDLE_SIZE),
+                ),
+                1.0,
+                egui::Color32::WHITE,
+            );
+        }
+    }
+
     fn draw_grid(&self, painter: &egui::Painter, rect: egui::Rect) {
         let color = egui::Color32::from_white_alpha((self.settings.grid_opacity * 255.0) as u8);
         let stroke = egui::Stroke::new(1.0, color);
@@ -630,10 +2129,37 @@ impl eframe::App for VdiApp {
             self.load_image(path, ctx);
         }
 
+        // バックグラウンド読み込みの完了を確認
+        self.poll_load_job(ctx);
+        self.maybe_start_prefetch();
+        self.poll_display_render(ctx);
+        self.poll_page();
+        self.advance_animation(ctx);
+
         // Update blink time
         self.blink_time += ctx.input(|i| i.stable_dt);
 
         // バックグラウンドの結果を処理
+        if let Some(rx) = &self.raw_develop_receiver {
+            if let Ok(res) = rx.try_recv() {
+                self.raw_develop_receiver = None;
+                self.raw_develop_cancel = None;
+                match res {
+                    Ok(developed) => {
+                        self.analysis_image = Some(developed);
+                        if self.peaking_enabled {
+                            self.trigger_peaking();
+                        }
+                        if self.histogram_enabled {
+                            self.trigger_histogram();
+                        }
+                    }
+                    Err(err) => {
+                        self.status_message = format!("RAWのフル現像に失敗しました: {}", err);
+                    }
+                }
+            }
+        }
         if let Some(rx) = &self.peaking_receiver {
             if let Ok(res) = rx.try_recv() {
                 self.peaking_result = Some(Arc::new(res));
@@ -647,21 +2173,153 @@ impl eframe::App for VdiApp {
             }
         }
         if let Some(rx) = &self.rotation_receiver {
-            if let Ok(path) = rx.try_recv() {
+            if let Ok(result) = rx.try_recv() {
                 self.rotation_receiver = None;
 
-                if self.pending_rotations > 0 {
-                    println!(
-                        "[ROTATION_COMPLETE] Pending rotations: {}. Processing next rotation.",
-                        self.pending_rotations
-                    );
-                    self.pending_rotations -= 1;
-                    self.start_rotation_process(path);
-                } else {
-                    println!("[ROTATION_COMPLETE] All rotations finished. Reloading image.");
+                // 自身の書き込みによる変更は、次の回転の変更検出の対象外にする
+                if let Ok(path) = &result {
+                    self.file_stamp = safe_write::FileStamp::read(path);
+                }
+                match result {
+                    Ok(path) if self.pending_rotations > 0 => {
+                        println!(
+                            "[ROTATION_COMPLETE] Pending rotations: {}. Processing next rotation.",
+                            self.pending_rotations
+                        );
+                        self.pending_rotations -= 1;
+                        self.start_rotation_process(path);
+                    }
+                    Ok(path) => {
+                        println!("[ROTATION_COMPLETE] All rotations finished. Reloading image.");
+                        self.restore_page = Some((path.clone(), self.current_page));
+                        self.load_image(path, ctx);
+                        self.rotation_in_progress = false;
+                        self.status_message = "回転完了".to_string();
+                    }
+                    Err(err) => {
+                        // 失敗した回転と保留中の回転を取り消し、表示をファイルの状態に戻す
+                        let cancelled = (self.pending_rotations + 1) as f32 * 90.0;
+                        self.rotation = (self.rotation - cancelled).rem_euclid(360.0);
+                        self.pending_rotations = 0;
+                        self.rotation_in_progress = false;
+                        self.status_message = format!("回転に失敗しました: {}", err);
+                    }
+                }
+            }
+        }
+
+        if let Some(rx) = &self.history_receiver {
+            if let Ok(HistoryResult { path, res }) = rx.try_recv() {
+                self.history_receiver = None;
+                match res {
+                    Ok(Some(message)) => {
+                        // 自身の書き込みによる変更は、次の編集の変更検出の対象外にする
+                        self.file_stamp = safe_write::FileStamp::read(&path);
+                        self.status_message = message;
+                        self.restore_page = Some((path.clone(), self.current_page));
+                        self.load_image(path, ctx);
+                    }
+                    Ok(None) => {
+                        self.status_message = "操作できる履歴がありません".to_string();
+                    }
+                    Err(err) => {
+                        self.status_message = format!("履歴の復元に失敗しました: {}", err);
+                    }
+                }
+            }
+        }
+
+        if let Some(rx) = &self.edit_receiver {
+            if let Ok(EditResult { path, label, res }) = rx.try_recv() {
+                self.edit_receiver = None;
+                match res {
+                    Ok(()) => {
+                        if label == LUT_LABEL {
+                            // 元の画像（別名で保存した場合）と保存先の両方を適用済みにする
+                            if let Some(source) = self.current_path.clone() {
+                                self.lut_saved.insert(source);
+                            }
+                            self.lut_saved.insert(path.clone());
+                        }
+                        // 再読み込みする画像には焼き込まれているため、表示では重ねて適用しない
+                        match BakedPreview::from_label(label) {
+                            Some(BakedPreview::Lut) => {
+                                self.lut_preview = false;
+                                self.request_display_render();
+                            }
+                            Some(BakedPreview::Adjustments) => self.reset_display_adjustments(),
+                            None => {}
+                        }
+                        // 自身の書き込みによる変更は、次の編集の変更検出の対象外にする
+                        self.file_stamp = safe_write::FileStamp::read(&path);
+                        self.status_message = format!("{}を保存しました", label);
+                        self.restore_page = Some((path.clone(), self.current_page));
+                        self.load_image(path, ctx);
+                    }
+                    Err(err) => {
+                        // プレビュー中の角度補正を取り消し、表示をファイルの状態に戻す
+                        self.straighten_angle = 0.0;
+                        self.status_message = format!("{}に失敗しました: {}", label, err);
+                    }
+                }
+            }
+        }
+
+        if let Some(rx) = &self.export_receiver {
+            if let Ok(res) = rx.try_recv() {
+                self.export_receiver = None;
+                self.status_message = match res {
+                    Ok(output) => format!("書き出しました: {}", output.display()),
+                    Err(err) => format!("書き出しに失敗しました: {}", err),
+                };
+            }
+        }
+
+        if let Some(outcome) = self.batch_run.as_ref().and_then(|(_, run)| run.poll()) {
+            let (kind, _) = self.batch_run.take().unwrap();
+            let report = &outcome.report;
+            self.status_message = match &outcome.report_path {
+                Ok(path) => format!(
+                    "一括処理（{}）: {} (レポート: {})",
+                    report.operation,
+                    report.summary(),
+                    path.display()
+                ),
+                Err(_) => format!("一括処理（{}）: {}", report.operation, report.summary()),
+            };
+            // 回転した画像を表示中なら読み込み直す
+            let rotated_current = kind == BatchKind::Rotate
+                && report.files.iter().any(|(path, status)| {
+                    Some(path) == self.current_path.as_ref()
+                        && matches!(status, batch::FileStatus::Done(_))
+                });
+            if rotated_current {
+                if let Some(path) = self.current_path.clone() {
+                    self.file_stamp = safe_write::FileStamp::read(&path);
+                    self.restore_page = Some((path.clone(), self.current_page));
                     self.load_image(path, ctx);
-                    self.rotation_in_progress = false;
-                    self.status_message = "回転完了".to_string();
+                }
+            }
+            self.batch_outcome = Some(outcome);
+        }
+
+        let lut_results: Vec<_> = self.lut_save_receiver.try_iter().collect();
+        for LutSaveResult {
+            source,
+            output,
+            res,
+        } in lut_results
+        {
+            match res {
+                Ok(()) => {
+                    println!("[LUT] 自動保存: {}", output.display());
+                    self.lut_saved.insert(output.clone());
+                    self.status_message =
+                        format!("LUTを適用して保存しました: {}", output.display());
+                }
+                Err(err) => {
+                    self.lut_saved.remove(&source);
+                    self.status_message = format!("LUTの保存に失敗しました: {}", err);
                 }
             }
         }
@@ -740,37 +2398,75 @@ impl eframe::App for VdiApp {
             }
         }
 
-        // キー入力
-        if ctx.input(|i| i.key_pressed(egui::Key::ArrowRight)) {
-            self.next_image(ctx);
-        }
-        if ctx.input(|i| i.key_pressed(egui::Key::ArrowLeft)) {
-            self.prev_image(ctx);
+        if ctx.input(|i| i.modifiers.command && i.key_pressed(egui::Key::E)) {
+            self.show_export = !self.show_export;
         }
-        if ctx.input(|i| i.key_pressed(egui::Key::R)) {
-            self.rotate_image(ctx);
+        if ctx.input(|i| i.modifiers.command && i.key_pressed(egui::Key::B)) {
+            self.toggle_batch();
         }
-        if ctx.input(|i| i.key_pressed(egui::Key::G)) {
-            self.grid_enabled = !self.grid_enabled;
-        }
-        if ctx.input(|i| i.key_pressed(egui::Key::P)) {
-            self.peaking_enabled = !self.peaking_enabled;
-            if self.peaking_enabled {
-                self.trigger_peaking();
-            } else {
-                self.peaking_result = None;
+
+        // キー入力（Ctrl+Zで元に戻す、Ctrl+Shift+Zでやり直し）
+        // テキスト入力中は文字・カーソル移動・取り消しとして扱い、ショートカットにしない
+        if !ctx.wants_keyboard_input() {
+            let (undo_pressed, redo_pressed) = ctx.input(|i| {
+                let z = i.modifiers.command && i.key_pressed(egui::Key::Z);
+                (z && !i.modifiers.shift, z && i.modifiers.shift)
+            });
+            if undo_pressed {
+                self.step_history(true);
             }
-        }
-        if ctx.input(|i| i.key_pressed(egui::Key::H)) {
-            self.histogram_enabled = !self.histogram_enabled;
-            if self.histogram_enabled {
-                self.trigger_histogram();
-            } else {
-                self.histogram_result = None;
+            if redo_pressed {
+                self.step_history(false);
+            }
+            if ctx.input(|i| i.key_pressed(egui::Key::ArrowRight)) {
+                self.next_image(ctx);
+            }
+            if ctx.input(|i| i.key_pressed(egui::Key::ArrowLeft)) {
+                self.prev_image(ctx);
+            }
+            if ctx.input(|i| i.key_pressed(egui::Key::R)) {
+                self.rotate_image(ctx);
+            }
+            if ctx.input(|i| i.key_pressed(egui::Key::G)) {
+                self.grid_enabled = !self.grid_enabled;
+            }
+            if ctx.input(|i| i.key_pressed(egui::Key::P)) {
+                self.peaking_enabled = !self.peaking_enabled;
+                if self.peaking_enabled {
+                    self.trigger_peaking();
+                } else {
+                    self.peaking_result = None;
+                }
+            }
+            if ctx.input(|i| i.key_pressed(egui::Key::H)) {
+                self.histogram_enabled = !self.histogram_enabled;
+                if self.histogram_enabled {
+                    self.trigger_histogram();
+                } else {
+                    self.histogram_result = None;
+                }
+            }
+            if ctx.input(|i| i.key_pressed(egui::Key::F)) {
+                self.fit_requested = true;
+            }
+            if ctx.input(|i| i.key_pressed(egui::Key::Num0)) {
+                self.reset_display_adjustments();
+            }
+            if ctx.input(|i| i.key_pressed(egui::Key::Space)) {
+                self.toggle_animation();
+            }
+            if ctx.input(|i| i.key_pressed(egui::Key::Period)) {
+                self.step_frame(true);
+            }
+            if ctx.input(|i| i.key_pressed(egui::Key::Comma)) {
+                self.step_frame(false);
+            }
+            if ctx.input(|i| i.key_pressed(egui::Key::PageDown)) {
+                self.step_page(true, ctx);
+            }
+            if ctx.input(|i| i.key_pressed(egui::Key::PageUp)) {
+                self.step_page(false, ctx);
             }
-        }
-        if ctx.input(|i| i.key_pressed(egui::Key::F)) {
-            self.fit_requested = true;
         }
 
         // 上部パネル
@@ -791,6 +2487,79 @@ impl eframe::App for VdiApp {
                 if ui.button("🔄").clicked() {
                     self.rotate_image(ctx);
                 }
+                if ui
+                    .add_enabled(self.history_depth.0 > 0, egui::Button::new("↶"))
+                    .on_hover_text("元に戻す (Ctrl+Z)")
+                    .clicked()
+                {
+                    self.step_history(true);
+                }
+                if ui
+                    .add_enabled(self.history_depth.1 > 0, egui::Button::new("↷"))
+                    .on_hover_text("やり直し (Ctrl+Shift+Z)")
+                    .clicked()
+                {
+                    self.step_history(false);
+                }
+                if ui.button("⇋").on_hover_text("左右反転").clicked() {
+                    self.flip_image(true);
+                }
+                if ui.button("⇅").on_hover_text("上下反転").clicked() {
+                    self.flip_image(false);
+                }
+                if ui
+                    .selectable_label(self.show_straighten, "📐")
+                    .on_hover_text("角度補正")
+                    .clicked()
+                {
+                    self.show_straighten = !self.show_straighten;
+                    self.straighten_angle = 0.0;
+                    self.crop_rect = None;
+                    self.show_letterbox = false;
+                }
+                if ui
+                    .add_enabled(
+                        !self.edit_busy(),
+                        egui::SelectableLabel::new(self.crop_rect.is_some(), "✂"),
+                    )
+                    .on_hover_text("切り抜き")
+                    .clicked()
+                {
+                    self.toggle_crop();
+                }
+                if ui
+                    .selectable_label(self.show_letterbox, "▭")
+                    .on_hover_text("黒帯")
+                    .clicked()
+                {
+                    self.show_letterbox = !self.show_letterbox;
+                    if self.show_letterbox {
+                        self.show_straighten = false;
+                        self.straighten_angle = 0.0;
+                        self.crop_rect = None;
+                    }
+                }
+                if ui
+                    .selectable_label(self.show_lut, "LUT")
+                    .on_hover_text("3D LUT (.cube)")
+                    .clicked()
+                {
+                    self.show_lut = !self.show_lut;
+                }
+                if ui
+                    .selectable_label(self.show_export, "💾")
+                    .on_hover_text("エクスポート (Ctrl+E)")
+                    .clicked()
+                {
+                    self.show_export = !self.show_export;
+                }
+                if ui
+                    .selectable_label(self.show_batch, "🗂")
+                    .on_hover_text("一括処理 (Ctrl+B)")
+                    .clicked()
+                {
+                    self.toggle_batch();
+                }
 
                 ui.separator();
 
@@ -827,6 +2596,10 @@ impl eframe::App for VdiApp {
                     self.fit_requested = true;
                 }
 
+                if ui.button("☀ 表示調整").clicked() {
+                    self.show_display_panel = !self.show_display_panel;
+                }
+
                 if ui.button("⚙ 設定").clicked() {
                     self.show_settings = !self.show_settings;
                 }
@@ -850,7 +2623,15 @@ impl eframe::App for VdiApp {
                 }
 
                 ui.separator();
-                ui.label(&self.status_message);
+                if let Some(job) = &self.load_job {
+                    ui.spinner();
+                    ui.label(Self::load_progress_text(job));
+                } else if self.raw_develop_receiver.is_some() {
+                    ui.spinner();
+                    ui.label("RAWを現像中...");
+                } else {
+                    ui.label(&self.status_message);
+                }
             });
         });
 
@@ -874,6 +2655,180 @@ impl eframe::App for VdiApp {
                         changed = true;
                     }
 
+                    ui.separator();
+                    ui.heading("ナビゲーション");
+                    let mut sort_changed = false;
+                    egui::ComboBox::from_label("並び順")
+                        .selected_text(self.settings.sort_order.label())
+                        .show_ui(ui, |ui| {
+                            for order in navigation::SortOrder::ALL {
+                                if ui
+                                    .selectable_value(
+                                        &mut self.settings.sort_order,
+                                        order,
+                                        order.label(),
+                                    )
+                                    .changed()
+                                {
+                                    sort_changed = true;
+                                }
+                            }
+                        });
+                    if ui
+                        .checkbox(&mut self.settings.sort_reverse, "逆順")
+                        .changed()
+                    {
+                        sort_changed = true;
+                    }
+                    if sort_changed {
+                        if let Some(index) = &self.folder_index {
+                            index.set_sort(self.settings.sort_order, self.settings.sort_reverse);
+                        }
+                        changed = true;
+                    }
+
+                    if ui
+                        .add(
+                            egui::Slider::new(&mut self.settings.prefetch_count, 0..=5)
+                                .text("先読み枚数 (前後)"),
+                        )
+                        .changed()
+                    {
+                        changed = true;
+                    }
+                    if ui
+                        .add(
+                            egui::Slider::new(&mut self.settings.cache_size_mb, 128..=8192)
+                                .text("キャッシュ上限 (MB)"),
+                        )
+                        .changed()
+                    {
+                        self.image_cache
+                            .lock()
+                            .unwrap()
+                            .set_budget_mb(self.settings.cache_size_mb);
+                        changed = true;
+                    }
+
+                    ui.separator();
+                    ui.heading("編集履歴");
+
+                    if ui
+                        .add(
+                            egui::Slider::new(&mut self.settings.history_quota_mb, 64..=16384)
+                                .logarithmic(true)
+                                .text("履歴の容量上限 (MB)"),
+                        )
+                        .changed()
+                    {
+                        self.history = history::EditHistory::open_default(self.settings.history_quota_mb);
+                        changed = true;
+                    }
+
+                    ui.separator();
+                    ui.heading("RAW");
+
+                    if ui
+                        .checkbox(
+                            &mut self.settings.raw_full_decode,
+                            "フル現像してピーキング・ヒストグラムに使用",
+                        )
+                        .on_hover_text("CR3はフル現像に対応していないため、埋め込みプレビューを使用します")
+                        .changed()
+                    {
+                        changed = true;
+                    }
+
+                    ui.separator();
+                    ui.heading("回転");
+
+                    ui.horizontal(|ui| {
+                        for (mode, label) in [
+                            (img::RotationMode::Pixels, "画素を書き換える"),
+                            (img::RotationMode::Orientation, "EXIFの向きのみ変更"),
+                        ] {
+                            if ui
+                                .selectable_value(&mut self.settings.rotation_mode, mode, label)
+                                .on_hover_text(match mode {
+                                    img::RotationMode::Pixels => {
+                                        "画像データを回転して保存します（JPEGは無劣化）"
+                                    }
+                                    img::RotationMode::Orientation => {
+                                        "画像データに触れずOrientationタグのみを書き換えます。タグの無い画像は画素を書き換えます"
+                                    }
+                                })
+                                .changed()
+                            {
+                                changed = true;
+                            }
+                        }
+                    });
+
+                    if ui
+                        .checkbox(
+                            &mut self.settings.jpeg_trim_edges,
+                            "JPEGの無劣化回転でMCUに揃わない端を切り捨てる",
+                        )
+                        .on_hover_text(
+                            "オフの場合、幅・高さが8または16の倍数でないJPEGは回転せずにエラーを表示します",
+                        )
+                        .changed()
+                    {
+                        changed = true;
+                    }
+
+                    ui.separator();
+                    ui.heading("カラーマネジメント");
+
+                    let mut color_changed = false;
+                    if ui
+                        .checkbox(
+                            &mut self.settings.color_management,
+                            "埋め込みICCプロファイルを表示プロファイルに変換",
+                        )
+                        .changed()
+                    {
+                        color_changed = true;
+                    }
+                    ui.horizontal(|ui| {
+                        let name = self
+                            .display_icc
+                            .as_deref()
+                            .and_then(|icc| color::profile_description(icc))
+                            .unwrap_or_else(|| "sRGB".to_string());
+                        ui.label(format!("表示プロファイル: {}", name));
+                        if ui.button("選択...").clicked() {
+                            if let Some(path) = rfd::FileDialog::new()
+                                .add_filter("ICC", &["icc", "icm"])
+                                .pick_file()
+                            {
+                                match std::fs::read(&path) {
+                                    Ok(icc) => {
+                                        self.display_icc = Some(Arc::new(icc));
+                                        self.settings.display_icc_path =
+                                            Some(path.to_string_lossy().to_string());
+                                        color_changed = true;
+                                    }
+                                    Err(e) => {
+                                        self.status_message = format!(
+                                            "表示プロファイルを読み込めませんでした: {}",
+                                            e
+                                        );
+                                    }
+                                }
+                            }
+                        }
+                        if self.display_icc.is_some() && ui.button("sRGBに戻す").clicked() {
+                            self.display_icc = None;
+                            self.settings.display_icc_path = None;
+                            color_changed = true;
+                        }
+                    });
+                    if color_changed {
+                        self.apply_color_settings();
+                        changed = true;
+                    }
+
                     ui.separator();
                     ui.heading("ピーキング");
 
@@ -957,107 +2912,789 @@ impl eframe::App for VdiApp {
                                 changed = true;
                             }
                             if ui
-                                .selectable_value(
-                                    &mut self.settings.grid_pattern,
-                                    GridPattern::Grid8x8,
-                                    "8x8 グリッド",
-                                )
-                                .changed()
+                                .selectable_value(
+                                    &mut self.settings.grid_pattern,
+                                    GridPattern::Grid8x8,
+                                    "8x8 グリッド",
+                                )
+                                .changed()
+                            {
+                                changed = true;
+                            }
+                        });
+                    if ui
+                        .add(
+                            egui::Slider::new(&mut self.settings.grid_opacity, 0.0..=1.0)
+                                .text("グリッド不透明度"),
+                        )
+                        .changed()
+                    {
+                        changed = true;
+                    }
+
+                    ui.separator();
+                    ui.heading("ヒストグラム");
+
+                    if ui
+                        .add(
+                            egui::Slider::new(&mut self.settings.histogram_size, 0.5..=2.0)
+                                .text("サイズ"),
+                        )
+                        .changed()
+                    {
+                        changed = true;
+                    }
+
+                    if ui
+                        .add(
+                            egui::Slider::new(&mut self.settings.histogram_opacity, 0.0..=1.0)
+                                .text("不透明度"),
+                        )
+                        .changed()
+                    {
+                        changed = true;
+                    }
+
+                    egui::ComboBox::from_label("位置")
+                        .selected_text(format!("{:?}", self.settings.histogram_position))
+                        .show_ui(ui, |ui| {
+                            if ui
+                                .selectable_value(
+                                    &mut self.settings.histogram_position,
+                                    HistogramPosition::TopLeft,
+                                    "左上",
+                                )
+                                .changed()
+                            {
+                                changed = true;
+                            }
+                            if ui
+                                .selectable_value(
+                                    &mut self.settings.histogram_position,
+                                    HistogramPosition::TopRight,
+                                    "右上",
+                                )
+                                .changed()
+                            {
+                                changed = true;
+                            }
+                            if ui
+                                .selectable_value(
+                                    &mut self.settings.histogram_position,
+                                    HistogramPosition::BottomLeft,
+                                    "左下",
+                                )
+                                .changed()
+                            {
+                                changed = true;
+                            }
+                            if ui
+                                .selectable_value(
+                                    &mut self.settings.histogram_position,
+                                    HistogramPosition::BottomRight,
+                                    "右下",
+                                )
+                                .changed()
+                            {
+                                changed = true;
+                            }
+                        });
+
+                    if changed {
+                        self.settings.save();
+                    }
+
+                    ui.separator();
+                    ui.separator();
+                    // ボタンを横並びにする
+                    ui.horizontal(|ui| {
+                        if ui.button("閉じる").clicked() {
+                            self.show_settings = false;
+                        }
+                    });
+                });
+        }
+
+        // 表示調整ウィンドウ
+        if self.show_display_panel {
+            let mut open = true;
+            let mut changed = false;
+            let mut reset = false;
+            let mut save = None;
+            let busy = self.edit_busy();
+            egui::Window::new("表示調整")
+                .open(&mut open)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    if ui
+                        .add(
+                            egui::Slider::new(&mut self.exposure, -5.0..=5.0)
+                                .step_by(0.1)
+                                .text("露出 (EV)"),
+                        )
+                        .changed()
+                    {
+                        changed = true;
+                    }
+                    for (value, range, label) in [
+                        (&mut self.contrast, -1.0..=1.0, "コントラスト"),
+                        (&mut self.temperature, -1.0..=1.0, "色温度 (青 ↔ 黄)"),
+                        (&mut self.tint, -1.0..=1.0, "色かぶり (緑 ↔ マゼンタ)"),
+                        (&mut self.saturation, 0.0..=2.0, "彩度"),
+                    ] {
+                        changed |= ui
+                            .add(egui::Slider::new(value, range).step_by(0.01).text(label))
+                            .changed();
+                    }
+                    if ui
+                        .add(
+                            egui::Slider::new(&mut self.display_gamma, 1.0..=3.0)
+                                .step_by(0.05)
+                                .text("ガンマ"),
+                        )
+                        .changed()
+                    {
+                        changed = true;
+                    }
+
+                    let mut tone_map_changed = false;
+                    egui::ComboBox::from_label("トーンマッピング")
+                        .selected_text(format!("{:?}", self.settings.tone_map))
+                        .show_ui(ui, |ui| {
+                            for tone_map in [
+                                display::ToneMap::Clip,
+                                display::ToneMap::Reinhard,
+                                display::ToneMap::Aces,
+                            ] {
+                                if ui
+                                    .selectable_value(
+                                        &mut self.settings.tone_map,
+                                        tone_map,
+                                        format!("{:?}", tone_map),
+                                    )
+                                    .changed()
+                                {
+                                    tone_map_changed = true;
+                                }
+                            }
+                        });
+                    if tone_map_changed {
+                        self.settings.save();
+                        changed = true;
+                    }
+
+                    ui.horizontal(|ui| {
+                        if ui.button("リセット (0)").clicked() {
+                            reset = true;
+                        }
+                        ui.separator();
+                        ui.label("焼き込み:");
+                        if ui
+                            .add_enabled(!busy, egui::Button::new("上書き保存"))
+                            .clicked()
+                        {
+                            save = Some(false);
+                        }
+                        if ui
+                            .add_enabled(!busy, egui::Button::new("別名で保存..."))
+                            .clicked()
+                        {
+                            save = Some(true);
+                        }
+                    });
+                });
+            self.show_display_panel = open;
+            if changed {
+                self.request_display_render();
+            }
+            if reset {
+                self.reset_display_adjustments();
+            }
+            if let Some(save_as) = save {
+                self.save_adjustments(save_as);
+            }
+        }
+
+        // 角度補正ウィンドウ
+        if self.show_straighten {
+            let mut open = true;
+            let mut apply = false;
+            let busy = self.edit_busy();
+            egui::Window::new("角度補正")
+                .open(&mut open)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    ui.add(
+                        egui::Slider::new(
+                            &mut self.straighten_angle,
+                            -straighten::MAX_ANGLE..=straighten::MAX_ANGLE,
+                        )
+                        .step_by(straighten::ANGLE_STEP as f64)
+                        .fixed_decimals(1)
+                        .suffix("°")
+                        .text("角度"),
+                    );
+                    if ui
+                        .checkbox(
+                            &mut self.settings.straighten_crop,
+                            "透明な角が出ないよう切り抜く",
+                        )
+                        .changed()
+                    {
+                        self.settings.save();
+                    }
+                    ui.horizontal(|ui| {
+                        if ui
+                            .add_enabled(
+                                !busy && self.straighten_angle != 0.0,
+                                egui::Button::new("適用"),
+                            )
+                            .clicked()
+                        {
+                            apply = true;
+                        }
+                        if ui.button("リセット").clicked() {
+                            self.straighten_angle = 0.0;
+                        }
+                        if ui.button("キャンセル").clicked() {
+                            self.straighten_angle = 0.0;
+                            self.show_straighten = false;
+                        }
+                    });
+                });
+            if !open {
+                self.straighten_angle = 0.0;
+                self.show_straighten = false;
+            }
+            if apply {
+                // 保存後の再読み込みまでは補正後のプレビューを表示し続ける
+                self.show_straighten = false;
+                self.apply_straighten();
+            }
+        }
+
+        // 切り抜きウィンドウ
+        if let Some(rect) = self.crop_rect {
+            let mut open = true;
+            let mut save = None;
+            let busy = self.edit_busy();
+            egui::Window::new("切り抜き")
+                .open(&mut open)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    let mut aspect_changed = false;
+                    egui::ComboBox::from_label("縦横比")
+                        .selected_text(self.crop_aspect.label())
+                        .show_ui(ui, |ui| {
+                            for preset in crop::AspectPreset::ALL {
+                                if ui
+                                    .selectable_value(&mut self.crop_aspect, preset, preset.label())
+                                    .changed()
+                                {
+                                    aspect_changed = true;
+                                }
+                            }
+                        });
+                    if aspect_changed {
+                        self.crop_rect = Some(rect.fit_aspect(self.crop_aspect.ratio()));
+                    }
+                    if let Some(size) = self.texture.as_ref().map(|t| t.size_vec2()) {
+                        let (_, _, w, h) = rect.to_pixels((size.x as u32, size.y as u32));
+                        ui.label(format!("{} × {} px", w, h));
+                        if ui.button("全体を選択").clicked() {
+                            self.crop_rect = Some(
+                                crop::CropRect::full(size.x as u32, size.y as u32)
+                                    .fit_aspect(self.crop_aspect.ratio()),
+                            );
+                        }
+                    }
+                    ui.horizontal(|ui| {
+                        if ui
+                            .add_enabled(!busy, egui::Button::new("上書き保存"))
+                            .clicked()
+                        {
+                            save = Some(false);
+                        }
+                        if ui
+                            .add_enabled(!busy, egui::Button::new("別名で保存..."))
+                            .clicked()
+                        {
+                            save = Some(true);
+                        }
+                        if ui.button("キャンセル").clicked() {
+                            self.crop_rect = None;
+                        }
+                    });
+                });
+            if !open {
+                self.crop_rect = None;
+            }
+            if let Some(save_as) = save {
+                self.save_crop(save_as);
+            }
+        }
+
+        // 黒帯ウィンドウ
+        if self.show_letterbox {
+            let mut open = true;
+            let mut save = None;
+            let mut changed = false;
+            let busy = self.edit_busy();
+            egui::Window::new("黒帯")
+                .open(&mut open)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    let letterbox = &mut self.settings.letterbox;
+                    ui.horizontal(|ui| {
+                        for (label, aspect) in letterbox::ASPECT_PRESETS {
+                            if ui
+                                .selectable_label((letterbox.aspect - aspect).abs() < 1e-3, label)
+                                .clicked()
                             {
+                                letterbox.aspect = aspect;
                                 changed = true;
                             }
-                        });
-                    if ui
+                        }
+                    });
+                    changed |= ui
                         .add(
-                            egui::Slider::new(&mut self.settings.grid_opacity, 0.0..=1.0)
-                                .text("グリッド不透明度"),
+                            egui::DragValue::new(&mut letterbox.aspect)
+                                .range(letterbox::ASPECT_RANGE)
+                                .speed(0.01)
+                                .fixed_decimals(2)
+                                .prefix("縦横比 "),
                         )
-                        .changed()
-                    {
-                        changed = true;
+                        .changed();
+                    ui.horizontal(|ui| {
+                        ui.label("帯の色");
+                        changed |= ui.color_edit_button_srgb(&mut letterbox.color).changed();
+                    });
+                    changed |= ui
+                        .add(
+                            egui::Slider::new(&mut letterbox.offset, -1.0..=1.0)
+                                .step_by(0.01)
+                                .text("位置 (上・左 ↔ 下・右)"),
+                        )
+                        .changed();
+                    ui.horizontal(|ui| {
+                        if ui
+                            .add_enabled(!busy, egui::Button::new("上書き保存"))
+                            .clicked()
+                        {
+                            save = Some(false);
+                        }
+                        if ui
+                            .add_enabled(!busy, egui::Button::new("別名で保存..."))
+                            .clicked()
+                        {
+                            save = Some(true);
+                        }
+                    });
+                });
+            self.show_letterbox = open;
+            if changed {
+                self.settings.save();
+            }
+            if let Some(save_as) = save {
+                self.save_letterbox(save_as);
+            }
+        }
+
+        // LUTウィンドウ
+        if self.show_lut {
+            let mut open = true;
+            let mut pick = false;
+            let mut save = None;
+            let mut changed = false;
+            let can_save = !self.edit_busy() && self.lut.is_some() && self.current_path.is_some();
+            egui::Window::new("3D LUT")
+                .open(&mut open)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        if ui.button("読み込み...").clicked() {
+                            pick = true;
+                        }
+                        match &self.lut {
+                            Some(lut) => {
+                                ui.label(format!("{} ({}点)", self.lut_name, lut.size()));
+                            }
+                            None => {
+                                ui.label("未読み込み");
+                            }
+                        }
+                    });
+                    ui.add_enabled_ui(self.lut.is_some(), |ui| {
+                        changed |= ui.checkbox(&mut self.lut_preview, "プレビュー").changed();
+                        ui.horizontal(|ui| {
+                            ui.label("補間");
+                            for (interpolation, label) in [
+                                (lut::Interpolation::Tetrahedral, "テトラヘドラル"),
+                                (lut::Interpolation::Trilinear, "トライリニア"),
+                            ] {
+                                changed |= ui
+                                    .radio_value(
+                                        &mut self.settings.lut_interpolation,
+                                        interpolation,
+                                        label,
+                                    )
+                                    .changed();
+                            }
+                        });
+                        ui.checkbox(&mut self.lut_auto_save, "開いた画像に適用して自動保存")
+                            .on_hover_text("元の画像の隣に <名前>_lut で保存します");
+                    });
+                    ui.horizontal(|ui| {
+                        if ui
+                            .add_enabled(can_save, egui::Button::new("上書き保存"))
+                            .clicked()
+                        {
+                            save = Some(LutSave::Overwrite);
+                        }
+                        if ui
+                            .add_enabled(can_save, egui::Button::new("別名で保存..."))
+                            .clicked()
+                        {
+                            save = Some(LutSave::SaveAs);
+                        }
+                    });
+                });
+            self.show_lut = open;
+            if changed {
+                self.settings.save();
+                self.request_display_render();
+            }
+            if pick {
+                let mut dialog = rfd::FileDialog::new().add_filter("3D LUT", &["cube"]);
+                if let Some(dir) = &self.settings.lut_dir {
+                    dialog = dialog.set_directory(dir);
+                }
+                if let Some(path) = dialog.pick_file() {
+                    self.open_lut(&path);
+                }
+            }
+            if let Some(kind) = save {
+                self.save_lut(kind, false);
+            }
+        }
+
+        // エクスポートウィンドウ
+        if self.show_export {
+            let mut open = true;
+            let mut export = false;
+            let mut changed = false;
+            let size = self.export_size();
+            let file_name = self
+                .current_path
+                .as_ref()
+                .zip(size)
+                .map(|(path, size)| self.settings.export.file_name(path, size));
+            let can_export = file_name.is_some() && self.export_receiver.is_none();
+            egui::Window::new("エクスポート")
+                .open(&mut open)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    let options = &mut self.settings.export;
+                    egui::ComboBox::from_label("形式")
+                        .selected_text(options.format.label())
+                        .show_ui(ui, |ui| {
+                            for format in export::ExportFormat::ALL {
+                                changed |= ui
+                                    .selectable_value(&mut options.format, format, format.label())
+                                    .changed();
+                            }
+                        });
+                    if options.format == export::ExportFormat::Jpeg {
+                        changed |= ui
+                            .add(egui::Slider::new(&mut options.jpeg_quality, 1..=100).text("品質"))
+                            .changed();
                     }
 
                     ui.separator();
-                    ui.heading("ヒストグラム");
+                    let mut resize = options.long_edge.is_some();
+                    ui.horizontal(|ui| {
+                        if ui.checkbox(&mut resize, "長辺を縮小").changed() {
+                            options.long_edge = resize.then_some(2048);
+                            changed = true;
+                        }
+                        if let Some(edge) = &mut options.long_edge {
+                            changed |= ui
+                                .add(egui::DragValue::new(edge).range(16..=65535).suffix(" px"))
+                                .changed();
+                        }
+                    });
+                    ui.add_enabled_ui(resize, |ui| {
+                        egui::ComboBox::from_label("補間")
+                            .selected_text(options.filter.label())
+                            .show_ui(ui, |ui| {
+                                for filter in export::ResizeFilter::ALL {
+                                    changed |= ui
+                                        .selectable_value(
+                                            &mut options.filter,
+                                            filter,
+                                            filter.label(),
+                                        )
+                                        .changed();
+                                }
+                            });
+                    });
 
-                    if ui
-                        .add(
-                            egui::Slider::new(&mut self.settings.histogram_size, 0.5..=2.0)
-                                .text("サイズ"),
-                        )
-                        .changed()
-                    {
-                        changed = true;
+                    ui.separator();
+                    ui.label("メタデータ");
+                    for (mode, label) in [
+                        (export::MetadataMode::Keep, "すべて残す"),
+                        (export::MetadataMode::StripGps, "位置情報 (GPS) を削除"),
+                        (
+                            export::MetadataMode::Strip,
+                            "すべて削除 (ICCプロファイルは残す)",
+                        ),
+                    ] {
+                        changed |= ui.radio_value(&mut options.metadata, mode, label).changed();
+                    }
+
+                    ui.separator();
+                    let tokens = export::TEMPLATE_TOKENS
+                        .iter()
+                        .map(|(token, description)| format!("{} : {}", token, description))
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    ui.horizontal(|ui| {
+                        ui.label("ファイル名");
+                        changed |= ui
+                            .text_edit_singleline(&mut options.template)
+                            .on_hover_text(tokens)
+                            .changed();
+                    });
+                    if let (Some(file_name), Some((width, height))) = (&file_name, size) {
+                        ui.label(format!("{} ({}x{})", file_name, width, height));
                     }
 
                     if ui
-                        .add(
-                            egui::Slider::new(&mut self.settings.histogram_opacity, 0.0..=1.0)
-                                .text("不透明度"),
-                        )
-                        .changed()
+                        .add_enabled(can_export, egui::Button::new("エクスポート..."))
+                        .clicked()
                     {
-                        changed = true;
+                        export = true;
                     }
+                });
+            self.show_export = open;
+            if changed {
+                self.settings.save();
+            }
+            if export {
+                self.start_export();
+            }
+        }
 
-                    egui::ComboBox::from_label("位置")
-                        .selected_text(format!("{:?}", self.settings.histogram_position))
-                        .show_ui(ui, |ui| {
-                            if ui
-                                .selectable_value(
-                                    &mut self.settings.histogram_position,
-                                    HistogramPosition::TopLeft,
-                                    "左上",
-                                )
-                                .changed()
-                            {
-                                changed = true;
+        // 一括処理ウィンドウ
+        if self.show_batch {
+            let mut open = true;
+            let mut start = false;
+            let mut refresh = false;
+            let running = self.batch_run.as_ref().map(|(_, run)| run);
+            let selected = self.batch_files.iter().filter(|(_, s)| *s).count();
+            egui::Window::new("一括処理")
+                .open(&mut open)
+                .default_width(420.0)
+                .show(ctx, |ui| {
+                    ui.add_enabled_ui(running.is_none(), |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label(format!(
+                                "{} / {} 件を選択",
+                                selected,
+                                self.batch_files.len()
+                            ));
+                            if ui.button("すべて選択").clicked() {
+                                self.batch_files.iter_mut().for_each(|(_, s)| *s = true);
                             }
-                            if ui
-                                .selectable_value(
-                                    &mut self.settings.histogram_position,
-                                    HistogramPosition::TopRight,
-                                    "右上",
-                                )
-                                .changed()
-                            {
-                                changed = true;
+                            if ui.button("選択解除").clicked() {
+                                self.batch_files.iter_mut().for_each(|(_, s)| *s = false);
                             }
-                            if ui
-                                .selectable_value(
-                                    &mut self.settings.histogram_position,
-                                    HistogramPosition::BottomLeft,
-                                    "左下",
-                                )
-                                .changed()
-                            {
-                                changed = true;
+                            if ui.button("🔄").on_hover_text("一覧を更新").clicked() {
+                                refresh = true;
                             }
-                            if ui
-                                .selectable_value(
-                                    &mut self.settings.histogram_position,
-                                    HistogramPosition::BottomRight,
-                                    "右下",
-                                )
-                                .changed()
-                            {
-                                changed = true;
+                        });
+                        egui::ScrollArea::vertical()
+                            .id_salt("batch_files")
+                            .max_height(180.0)
+                            .show(ui, |ui| {
+                                for (path, selected) in &mut self.batch_files {
+                                    let name = path
+                                        .file_name()
+                                        .map(|n| n.to_string_lossy().to_string())
+                                        .unwrap_or_default();
+                                    ui.checkbox(selected, name);
+                                }
+                            });
+
+                        ui.separator();
+                        ui.horizontal(|ui| {
+                            for (kind, label) in [
+                                (BatchKind::Export, "書き出し"),
+                                (BatchKind::Rotate, "回転"),
+                                (BatchKind::Letterbox, "黒帯"),
+                            ] {
+                                ui.radio_value(&mut self.batch_kind, kind, label);
                             }
                         });
+                        match self.batch_kind {
+                            BatchKind::Rotate => {
+                                ui.horizontal(|ui| {
+                                    for (angle, label) in
+                                        [(90, "右に90°"), (180, "180°"), (270, "左に90°")]
+                                    {
+                                        ui.radio_value(&mut self.batch_angle, angle, label);
+                                    }
+                                });
+                                ui.label("元の画像を上書きします（設定の保存方法を使用）");
+                            }
+                            BatchKind::Export => {
+                                let options = &self.settings.export;
+                                let resize = match options.long_edge {
+                                    Some(edge) => format!(", 長辺 {} px", edge),
+                                    None => String::new(),
+                                };
+                                ui.horizontal(|ui| {
+                                    ui.label(format!(
+                                        "{}{} / {}",
+                                        options.format.label(),
+                                        resize,
+                                        options.template
+                                    ));
+                                    if ui.button("設定...").clicked() {
+                                        self.show_export = true;
+                                    }
+                                });
+                            }
+                            BatchKind::Letterbox => {
+                                let letterbox = &self.settings.letterbox;
+                                ui.horizontal(|ui| {
+                                    ui.label(format!(
+                                        "縦横比 {:.2}:1 / <名前>_letterbox",
+                                        letterbox.aspect
+                                    ));
+                                    if ui.button("設定...").clicked() {
+                                        self.show_letterbox = true;
+                                    }
+                                });
+                            }
+                        }
+                        if self.batch_kind != BatchKind::Rotate {
+                            ui.horizontal(|ui| {
+                                let folder = match &self.batch_output_dir {
+                                    Some(dir) => dir.display().to_string(),
+                                    None => "元の画像と同じフォルダ".to_string(),
+                                };
+                                ui.label(format!("保存先: {}", folder));
+                                if ui.button("選択...").clicked() {
+                                    if let Some(dir) = rfd::FileDialog::new().pick_folder() {
+                                        self.batch_output_dir = Some(dir);
+                                    }
+                                }
+                                if self.batch_output_dir.is_some() && ui.button("×").clicked() {
+                                    self.batch_output_dir = None;
+                                }
+                            });
+                            ui.checkbox(&mut self.batch_overwrite, "同名のファイルを上書き");
+                        }
+                        if ui
+                            .add_enabled(selected > 0, egui::Button::new("開始"))
+                            .clicked()
+                        {
+                            start = true;
+                        }
+                    });
 
-                    if changed {
-                        self.settings.save();
+                    if let Some(run) = running {
+                        ui.separator();
+                        let (finished, total) = run.progress();
+                        ui.add(
+                            egui::ProgressBar::new(finished as f32 / total.max(1) as f32)
+                                .text(format!("{} / {}", finished, total)),
+                        );
+                        if run.is_cancelled() {
+                            ui.label("処理中の画像の完了を待っています...");
+                        } else if ui.button("キャンセル").clicked() {
+                            run.cancel();
+                        }
                     }
 
-                    ui.separator();
-                    ui.separator();
-                    // ボタンを横並びにする
+                    let failures = match (running, &self.batch_outcome) {
+                        (Some(run), _) => run.failures(),
+                        (None, Some(outcome)) => {
+                            ui.separator();
+                            ui.label(format!(
+                                "{}: {}",
+                                outcome.report.operation,
+                                outcome.report.summary()
+                            ));
+                            match &outcome.report_path {
+                                Ok(path) => ui.label(format!("レポート: {}", path.display())),
+                                Err(e) => ui.label(format!("レポートを保存できません: {}", e)),
+                            };
+                            outcome
+                                .report
+                                .files
+                                .iter()
+                                .filter_map(|(path, status)| match status {
+                                    batch::FileStatus::Failed(e) => Some((path.clone(), e.clone())),
+                                    _ => None,
+                                })
+                                .collect()
+                        }
+                        (None, None) => Vec::new(),
+                    };
+                    if !failures.is_empty() {
+                        ui.label(format!("失敗: {} 件", failures.len()));
+                        egui::ScrollArea::vertical()
+                            .id_salt("batch_failures")
+                            .max_height(120.0)
+                            .show(ui, |ui| {
+                                for (path, error) in failures {
+                                    let name = path
+                                        .file_name()
+                                        .map(|n| n.to_string_lossy().to_string())
+                                        .unwrap_or_default();
+                                    ui.label(format!("{}: {}", name, error))
+                                        .on_hover_text(path.display().to_string());
+                                }
+                            });
+                    }
+                });
+            self.show_batch = open;
+            if refresh {
+                self.refresh_batch_files();
+            }
+            if start {
+                self.start_batch(ctx);
+            }
+        }
+
+        // LUTの再適用の確認
+        if let Some((kind, warning)) = self.lut_confirm.clone() {
+            let mut decision = None;
+            egui::Window::new("LUTの保存")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+                .show(ctx, |ui| {
+                    ui.label(format!("⚠ {}", warning));
                     ui.horizontal(|ui| {
-                        if ui.button("閉じる").clicked() {
-                            self.show_settings = false;
+                        if ui.button("それでも保存").clicked() {
+                            decision = Some(true);
+                        }
+                        if ui.button("キャンセル").clicked() {
+                            decision = Some(false);
                         }
                     });
                 });
+            if let Some(save) = decision {
+                self.lut_confirm = None;
+                if save {
+                    self.save_lut(kind, true);
+                } else {
+                    self.status_message = "LUTの保存を取り消しました".to_string();
+                }
+            }
         }
 
         // アップデートダイアログ
@@ -1157,7 +3794,7 @@ impl eframe::App for VdiApp {
         egui::CentralPanel::default()
             .frame(egui::Frame::none().inner_margin(0.0))
             .show(ctx, |ui| {
-                if let Some(texture) = &self.texture {
+                if let Some(image_size) = self.texture.as_ref().map(|t| t.size_vec2()) {
                     let available_size = ui.available_size();
 
                     // Store size for fit processing outside closure
@@ -1191,8 +3828,6 @@ impl eframe::App for VdiApp {
                         }
                     }
 
-                    let image_size = texture.size_vec2();
-
                     // 90度および270度回転の場合、幅と高さを入れ替える
                     let display_size = if self.rotation == 90.0 || self.rotation == 270.0 {
                         egui::vec2(image_size.y, image_size.x)
@@ -1201,8 +3836,30 @@ impl eframe::App for VdiApp {
                     };
                     let scaled_size = display_size * self.zoom;
 
+                    // 切り抜きモードでは、範囲の内側・つまみのドラッグで範囲を変える
+                    if response.drag_started() {
+                        let center = response.rect.center() + self.pan;
+                        self.crop_drag = self
+                            .crop_screen_rect(center, image_size)
+                            .zip(response.interact_pointer_pos())
+                            .and_then(|(rect, pos)| crop_handle_at(rect, pos));
+                    }
                     if response.dragged() {
-                        self.pan += response.drag_delta();
+                        match (self.crop_drag, self.crop_rect) {
+                            (Some(handle), Some(rect)) => {
+                                let delta = response.drag_delta() / self.zoom;
+                                self.crop_rect = Some(rect.drag(
+                                    handle,
+                                    (delta.x, delta.y),
+                                    self.crop_aspect.ratio(),
+                                    (image_size.x, image_size.y),
+                                ));
+                            }
+                            _ => self.pan += response.drag_delta(),
+                        }
+                    }
+                    if response.drag_stopped() {
+                        self.crop_drag = None;
                     }
 
                     // 画像がある程度見えるようにパンを制限
@@ -1214,57 +3871,32 @@ impl eframe::App for VdiApp {
 
                     // 画像を中央に配置 + パン
                     let center = response.rect.center() + self.pan;
-                    let rect = egui::Rect::from_center_size(center, scaled_size);
-
-                    // 回転付きで画像を描画
-                    if self.rotation == 0.0 {
-                        // 回転なし - 通常通り描画
-                        painter.image(
-                            texture.id(),
-                            rect,
-                            egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0)),
-                            egui::Color32::WHITE,
-                        );
-                    } else {
-                        // メッシュを使用して回転を適用
-                        use egui::epaint::{Mesh, Vertex};
-
-                        let mut mesh = Mesh::with_texture(texture.id());
-
-                        // 標準のRectコーナー
-                        let corners = [
-                            rect.min,                           // Top-left
-                            egui::pos2(rect.max.x, rect.min.y), // Top-right
-                            rect.max,                           // Bottom-right
-                            egui::pos2(rect.min.x, rect.max.y), // Bottom-left
-                        ];
-
-                        // 回転に基づくUV座標
-                        let uvs = match self.rotation as i32 {
-                            90 => [[0.0, 1.0], [0.0, 0.0], [1.0, 0.0], [1.0, 1.0]],
-                            180 => [[1.0, 1.0], [0.0, 1.0], [0.0, 0.0], [1.0, 0.0]],
-                            270 => [[1.0, 0.0], [1.0, 1.0], [0.0, 1.0], [0.0, 0.0]],
-                            _ => [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]],
-                        };
-
-                        // 頂点を追加
-                        for (i, corner) in corners.iter().enumerate() {
-                            mesh.vertices.push(Vertex {
-                                pos: *corner,
-                                uv: egui::pos2(uvs[i][0], uvs[i][1]),
-                                color: egui::Color32::WHITE,
-                            });
-                        }
 
-                        // インデックスを追加
-                        mesh.indices.extend_from_slice(&[0, 1, 2, 0, 2, 3]);
+                    // 表示範囲と重なるタイルのみを転送し、回転付きで描画
+                    let placement = tiles::ImagePlacement {
+                        center,
+                        size: image_size * self.zoom,
+                        rotation: self.rotation + self.straighten_angle,
+                    };
+                    if let Some(texture) = &mut self.texture {
+                        texture.paint(ctx, &painter, &placement);
+                    }
+
+                    // 黒帯のプレビュー（画像の外側に帯を描く）
+                    if self.show_letterbox {
+                        self.draw_letterbox(&painter, placement.bounding_rect());
+                    }
 
-                        painter.add(egui::Shape::mesh(mesh));
+                    // グリッドオーバーレイ（切り抜きモードでは切り抜き範囲の内側に表示）
+                    if let Some(crop_rect) = self.crop_screen_rect(center, image_size) {
+                        self.draw_crop_overlay(&painter, response.rect, crop_rect);
+                    } else if self.grid_enabled {
+                        self.draw_grid(&painter, placement.bounding_rect());
                     }
 
-                    // グリッドオーバーレイ
-                    if self.grid_enabled {
-                        self.draw_grid(&painter, rect);
+                    // 角度補正の水平ガイドと切り抜き範囲
+                    if self.show_straighten {
+                        self.draw_level_guide(&painter, response.rect, center, scaled_size);
                     }
 
                     // ピーキングオーバーレイ
@@ -1291,11 +3923,11 @@ impl eframe::App for VdiApp {
                                     let points: Vec<egui::Pos2> = edge
                                         .iter()
                                         .map(|p| {
-                                            let u = p.x / image_size.x;
-                                            let v = p.y / image_size.y;
-                                            egui::pos2(
-                                                rect.min.x + u * rect.width(),
-                                                rect.min.y + v * rect.height(),
+                                            // 解析画像（RAWのフル現像など）の座標系で正規化し、
+                                            // タイルと同じ配置（回転を含む）で画面座標に変換する
+                                            placement.to_screen(
+                                                p.x / peaking.width as f32,
+                                                p.y / peaking.height as f32,
                                             )
                                         })
                                         .collect();
@@ -1307,6 +3939,10 @@ impl eframe::App for VdiApp {
                             }
                         }
                     }
+                } else if self.load_job.is_some() {
+                    ui.centered_and_justified(|ui| {
+                        ui.spinner();
+                    });
                 } else {
                     ui.centered_and_justified(|ui| {
                         ui.label(
@@ -1324,6 +3960,14 @@ impl eframe::App for VdiApp {
                         "📄 {}",
                         path.file_name().unwrap_or_default().to_string_lossy()
                     ));
+
+                    if let Some(index) = &self.folder_index {
+                        if !index.is_ready() {
+                            ui.label("- / -");
+                        } else if let Some(position) = index.position(path) {
+                            ui.label(format!("{} / {}", position + 1, index.len()));
+                        }
+                    }
                 }
 
                 if let Some((w, h)) = self.image_dimensions {
@@ -1334,6 +3978,60 @@ impl eframe::App for VdiApp {
                     ui.label(Self::format_file_size(size));
                 }
 
+                if let Some(image) = &self.original_image {
+                    ui.label(display::bit_depth_label(image));
+                }
+                if let Some(applied) = self
+                    .applied_orientation
+                    .filter(|applied| !applied.orientation.is_identity())
+                {
+                    let source = match applied.source {
+                        orientation::OrientationSource::Exif => "EXIF",
+                        orientation::OrientationSource::Sidecar => "XMP",
+                    };
+                    ui.label(format!("↻ {} {}", source, applied.orientation.to_exif()))
+                        .on_hover_text(format!(
+                            "{}の向き（{}）を適用済み",
+                            source,
+                            applied.orientation.label()
+                        ));
+                }
+                if let Some(icc) = &self.icc_profile {
+                    let name = color::profile_description(icc).unwrap_or_else(|| "ICC".to_string());
+                    let hover = if self.color_converter.is_some() {
+                        "埋め込みプロファイルを表示プロファイルに変換して表示中"
+                    } else {
+                        "埋め込みプロファイル（変換なし）"
+                    };
+                    ui.label(name).on_hover_text(hover);
+                }
+                if let Some(animation) = &self.animation {
+                    let frame_count = animation.len();
+                    let icon = if self.animation_playing { "⏸" } else { "▶" };
+                    if ui
+                        .small_button(icon)
+                        .on_hover_text("再生/一時停止 (Space)")
+                        .clicked()
+                    {
+                        self.toggle_animation();
+                    }
+                    ui.label(format!("フレーム {}/{}", self.frame_index + 1, frame_count));
+                }
+                if self.page_count > 1 {
+                    ui.label(format!(
+                        "page {}/{}",
+                        self.current_page + 1,
+                        self.page_count
+                    ))
+                    .on_hover_text("PageUp / PageDown でページを切り替え");
+                }
+                if self.exposure != 0.0 {
+                    ui.label(format!("{:+.1} EV", self.exposure));
+                }
+                if self.display_transform().is_adjusted() {
+                    ui.label("表示調整中").on_hover_text("0 キーでリセット");
+                }
+
                 ui.label(format!("Zoom: {:.0}%", self.zoom * 100.0));
 
                 ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
@@ -1352,6 +4050,7 @@ impl eframe::App for VdiApp {
 
         // Histogram Window
         if self.histogram_enabled {
+            let mut histogram_settings_changed = false;
             if let Some(hist) = &self.histogram_result {
                 let window_size = egui::vec2(
                     300.0 * self.settings.histogram_size,
@@ -1365,6 +4064,7 @@ impl eframe::App for VdiApp {
                     HistogramPosition::BottomRight => egui::Align2::RIGHT_BOTTOM,
                 };
 
+                let has_color_conversion = self.color_converter.is_some();
                 egui::Window::new("Histogram")
                     .anchor(anchor, egui::vec2(10.0, 10.0))
                     .default_size(window_size)
@@ -1372,6 +4072,34 @@ impl eframe::App for VdiApp {
                     .show(ctx, |ui| {
                         use egui_plot::{Bar, BarChart, Plot, PlotBounds};
 
+                        // 埋め込みプロファイルを変換している場合のみ、集計する色空間を選べる
+                        if has_color_conversion {
+                            ui.horizontal(|ui| {
+                                for (space, label) in [
+                                    (HistogramSpace::Source, "元の色空間"),
+                                    (HistogramSpace::Display, "表示色空間"),
+                                ] {
+                                    if ui
+                                        .selectable_value(
+                                            &mut self.settings.histogram_space,
+                                            space,
+                                            label,
+                                        )
+                                        .changed()
+                                    {
+                                        histogram_settings_changed = true;
+                                    }
+                                }
+                            });
+                        }
+
+                        if ui
+                            .checkbox(&mut self.settings.histogram_adjusted, "表示調整を反映")
+                            .changed()
+                        {
+                            histogram_settings_changed = true;
+                        }
+
                         if let histogram::HistogramData::RGB { r, g, b } = &hist.data {
                             // 全チャンネルの最大値を取得
                             let max_r = r.iter().max().copied().unwrap_or(0) as f64;
@@ -1445,9 +4173,42 @@ impl eframe::App for VdiApp {
                                         BarChart::new(b_bars).color(egui::Color32::BLUE),
                                     );
                                 });
+
+                            // 白飛び（最大値）と、露出補正で復元できる範囲外の値を区別して表示
+                            let total = (hist.width as f64 * hist.height as f64).max(1.0);
+                            let percent =
+                                |counts: &[u32; 3]| counts.map(|c| c as f64 / total * 100.0);
+                            let [cr, cg, cb] = percent(&hist.clipped);
+                            ui.label(format!("白飛び R {:.2}% G {:.2}% B {:.2}%", cr, cg, cb));
+                            if hist.over_range.iter().any(|&c| c > 0) {
+                                let [or, og, ob] = percent(&hist.over_range);
+                                ui.label(format!(
+                                    "範囲外 (復元可) R {:.2}% G {:.2}% B {:.2}%",
+                                    or, og, ob
+                                ));
+                            }
                         }
                     });
             }
+            if histogram_settings_changed {
+                self.settings.save();
+                self.trigger_histogram();
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_baked_preview_from_label() {
+        assert_eq!(BakedPreview::from_label(LUT_LABEL), Some(BakedPreview::Lut));
+        assert_eq!(
+            BakedPreview::from_label(ADJUST_LABEL),
+            Some(BakedPreview::Adjustments)
+        );
+        assert_eq!(BakedPreview::from_label("切り抜き"), None);
+    }
+}
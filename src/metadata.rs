@@ -0,0 +1,1292 @@
+use crate::jpeg_lossless;
+use crate::multipage;
+use crate::orientation::{self, Orientation};
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use image::ImageFormat;
+use std::io::{Read, Write};
+
+/// JPEGのAPP1に格納するXMPの識別子
+const JPEG_XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+/// JPEGのAPP2に格納するICCプロファイルの識別子
+const JPEG_ICC_HEADER: &[u8] = b"ICC_PROFILE\0";
+/// JPEGのAPP13（Photoshopのイメージリソース）の識別子
+const JPEG_PHOTOSHOP_HEADER: &[u8] = b"Photoshop 3.0\0";
+/// PNGのiTXtに格納するXMPのキーワード
+const PNG_XMP_KEYWORD: &[u8] = b"XML:com.adobe.xmp";
+/// PNGのzTXtに格納するIPTCのキーワード（ExifTool・ImageMagickの慣例）
+const PNG_IPTC_KEYWORD: &[u8] = b"Raw profile type iptc";
+/// PhotoshopのイメージリソースのうちIPTC-IIMを表すID
+const IPTC_RESOURCE_ID: u16 = 0x0404;
+
+/// TIFFタグ: XMP・IPTC・ICCプロファイル
+const TAG_XMP: u16 = 700;
+const TAG_IPTC: u16 = 33723;
+const TAG_ICC: u16 = 34675;
+/// TIFFタグ: Orientation
+#[cfg(test)]
+const TAG_ORIENTATION: u16 = 274;
+/// PNG・WebPのチャンク（種類とデータ）
+type Chunk<'a> = (&'a [u8; 4], &'a [u8]);
+
+/// TIFFタグ: GPSのサブディレクトリ
+const TAG_GPS_IFD: u16 = 34853;
+/// サブディレクトリを指すタグ（Exif・GPS・互換性）
+const SUB_DIRECTORY_TAGS: [u16; 3] = [34665, TAG_GPS_IFD, 40965];
+
+/// EXIFのIFD0から引き継ぐタグ
+///
+/// 画像の構造（サイズ・圧縮・ストリップ位置など）に関わるタグは書き出し側のものを使うため、
+/// 撮影情報・著作権・解像度などの記述的なタグのみを対象にします。
+const DESCRIPTIVE_TAGS: [u16; 23] = [
+    270,   // ImageDescription
+    271,   // Make
+    272,   // Model
+    274,   // Orientation
+    282,   // XResolution
+    283,   // YResolution
+    296,   // ResolutionUnit
+    305,   // Software
+    306,   // DateTime
+    315,   // Artist
+    316,   // HostComputer
+    318,   // WhitePoint
+    319,   // PrimaryChromaticities
+    18246, // Rating
+    18249, // RatingPercent
+    33432, // Copyright
+    34665, // ExifIFD
+    34853, // GPSIFD
+    40091, // XPTitle
+    40092, // XPComment
+    40093, // XPAuthor
+    40094, // XPKeywords
+    40095, // XPSubject
+];
+
+/// 画像ファイルに埋め込まれたメタデータ
+///
+/// 保存時に元ファイルから読み込み、書き出したファイルへ同じ内容を埋め込みます。
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metadata {
+    /// EXIF（TIFF形式。`Exif\0\0`は含まない）
+    pub exif: Option<Vec<u8>>,
+    /// XMPパケット
+    pub xmp: Option<Vec<u8>>,
+    /// IPTC-IIM
+    pub iptc: Option<Vec<u8>>,
+    /// ICCプロファイル
+    pub icc: Option<Vec<u8>>,
+}
+
+impl Metadata {
+    /// ファイル内容からメタデータを読み込む（JPEG・PNG・WebP・TIFFに対応）
+    pub fn read(bytes: &[u8]) -> Self {
+        Self::read_page(bytes, 0)
+    }
+
+    /// 複数ページのTIFFの指定ページからメタデータを読み込む（TIFF以外は`page`を無視する）
+    pub fn read_page(bytes: &[u8], page: usize) -> Self {
+        if jpeg_lossless::is_jpeg(bytes) {
+            read_jpeg(bytes)
+        } else if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+            read_png(bytes)
+        } else if bytes.len() >= 12 && &bytes[0..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+            read_webp(bytes)
+        } else if multipage::is_tiff(bytes) {
+            read_tiff_page(bytes, page).unwrap_or_default()
+        } else {
+            Self::default()
+        }
+    }
+
+    /// メタデータが1つも無いかどうか
+    pub fn is_empty(&self) -> bool {
+        self.exif.is_none() && self.xmp.is_none() && self.iptc.is_none() && self.icc.is_none()
+    }
+
+    /// EXIF・XMPのOrientationを1（変換なし）に戻す
+    ///
+    /// 向きを画素に反映して保存する場合に、表示時に二重に回転されないようにします。
+    pub fn reset_orientation(&mut self) {
+        if let Some(exif) = &mut self.exif {
+            orientation::set_exif_orientation(exif, Orientation::default());
+        }
+        if let Some(xmp) = &self.xmp {
+            if let Some(updated) = reset_xmp_orientation(xmp) {
+                self.xmp = Some(updated);
+            }
+        }
+    }
+
+    /// EXIF・XMPから位置情報（GPS）を取り除く
+    ///
+    /// 解析できないEXIF・XMPは位置情報が残っていないと確認できないため、全体を取り除きます。
+    pub fn strip_gps(&mut self) {
+        match self.exif.as_deref().map(strip_exif_gps) {
+            Some(Ok(Some(stripped))) => self.exif = Some(stripped),
+            Some(Err(e)) => {
+                eprintln!("[Metadata] {}（EXIFを削除します）", e);
+                self.exif = None;
+            }
+            _ => {}
+        }
+        match self.xmp.as_deref().map(strip_xmp_gps) {
+            Some(Ok(Some(stripped))) => self.xmp = Some(stripped),
+            Some(Err(e)) => {
+                eprintln!("[Metadata] {}（XMPを削除します）", e);
+                self.xmp = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// エンコード済みの画像にメタデータを埋め込む
+    ///
+    /// JPEG・PNG・WebP・TIFFに対応し、それ以外の形式はそのまま返します。
+    /// WebPにはIPTCの格納場所が無いため、IPTCは埋め込みません。
+    ///
+    /// # Errors
+    ///
+    /// * エンコード済みの画像を解析できない場合
+    pub fn embed(&self, encoded: Vec<u8>, format: ImageFormat) -> Result<Vec<u8>, String> {
+        if self.is_empty() {
+            return Ok(encoded);
+        }
+        match format {
+            ImageFormat::Jpeg => embed_jpeg(self, encoded),
+            ImageFormat::Png => embed_png(self, encoded),
+            ImageFormat::WebP => embed_webp(self, encoded),
+            ImageFormat::Tiff => embed_tiff_pages(encoded, std::slice::from_ref(self)),
+            _ => Ok(encoded),
+        }
+    }
+}
+
+/// JPEGのAPP1セグメント（EXIF・XMP）のOrientationを1に戻す
+///
+/// `segment`はマーカーと長さを含むセグメント全体です。
+pub fn reset_jpeg_segment_orientation(segment: &mut Vec<u8>) {
+    if segment.len() < 4 || segment[1] != 0xE1 {
+        return;
+    }
+    if segment[4..].starts_with(b"Exif\0\0") {
+        orientation::set_exif_orientation(&mut segment[10..], Orientation::default());
+    } else if segment[4..].starts_with(JPEG_XMP_HEADER) {
+        let start = 4 + JPEG_XMP_HEADER.len();
+        if let Some(xmp) = reset_xmp_orientation(&segment[start..]) {
+            let length = start - 2 + xmp.len();
+            if length <= u16::MAX as usize {
+                segment.truncate(start);
+                segment.extend_from_slice(&xmp);
+                segment[2..4].copy_from_slice(&(length as u16).to_be_bytes());
+            }
+        }
+    }
+}
+
+/// ファイル内容に埋め込まれたEXIF・XMPのOrientationをその場で書き換える
+///
+/// 値を同じ長さで上書きするだけなので、画像データや他のセグメントの位置は変わりません。
+/// JPEG・PNG・WebPはEXIF、TIFFはIFD0のOrientationタグを対象とし、
+/// XMPに`tiff:Orientation`が記録されていれば合わせて書き換えます。
+///
+/// # Returns
+///
+/// * `true` - 書き換えた場合
+/// * `false` - 書き換え可能なOrientationタグが無い場合（内容は変更しません）
+pub fn set_orientation_in_place(bytes: &mut [u8], orientation: Orientation) -> bool {
+    let metadata = Metadata::read(bytes);
+    let patched = if multipage::is_tiff(bytes) {
+        orientation::set_exif_orientation(bytes, orientation)
+    } else {
+        match metadata
+            .exif
+            .as_deref()
+            .and_then(|exif| find_range(bytes, exif))
+        {
+            Some(range) => orientation::set_exif_orientation(&mut bytes[range], orientation),
+            None => false,
+        }
+    };
+    if !patched {
+        return false;
+    }
+
+    // 値は1桁なので、XMPのOrientationも長さを変えずに書き換えられる
+    if let Some(xmp) = metadata.xmp.as_deref() {
+        let updated = std::str::from_utf8(xmp)
+            .ok()
+            .filter(|text| text.contains("tiff:Orientation"))
+            .and_then(|text| orientation::update_xmp_orientation(text, orientation));
+        if let (Some(updated), Some(range)) = (updated, find_range(bytes, xmp)) {
+            if updated.len() == range.len() {
+                bytes[range].copy_from_slice(updated.as_bytes());
+            }
+        }
+    }
+    true
+}
+
+/// `haystack`の中で`needle`が現れる範囲
+fn find_range(haystack: &[u8], needle: &[u8]) -> Option<std::ops::Range<usize>> {
+    if needle.is_empty() {
+        return None;
+    }
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|start| start..start + needle.len())
+}
+
+/// XMPの`tiff:Orientation`を1に書き換える（記録されていない場合は`None`）
+pub fn reset_xmp_orientation(xmp: &[u8]) -> Option<Vec<u8>> {
+    let text = std::str::from_utf8(xmp).ok()?;
+    if !text.contains("tiff:Orientation") {
+        return None;
+    }
+    orientation::update_xmp_orientation(text, Orientation::default()).map(String::into_bytes)
+}
+
+fn read_jpeg(bytes: &[u8]) -> Metadata {
+    let mut metadata = Metadata::default();
+    let mut icc_chunks: Vec<(u8, &[u8])> = Vec::new();
+    let mut pos = 2;
+
+    // SOSまでのセグメントを走査する
+    while pos + 4 <= bytes.len() && bytes[pos] == 0xFF {
+        let marker = bytes[pos + 1];
+        if marker == 0xDA || marker == 0xD9 {
+            break;
+        }
+        let length = u16::from_be_bytes([bytes[pos + 2], bytes[pos + 3]]) as usize;
+        let Some(body) = bytes.get(pos + 4..pos + 2 + length) else {
+            break;
+        };
+        match marker {
+            0xE1 if body.starts_with(b"Exif\0\0") => metadata.exif = Some(body[6..].to_vec()),
+            0xE1 if body.starts_with(JPEG_XMP_HEADER) => {
+                metadata.xmp = Some(body[JPEG_XMP_HEADER.len()..].to_vec())
+            }
+            0xE2 if body.starts_with(JPEG_ICC_HEADER) && body.len() > JPEG_ICC_HEADER.len() + 2 => {
+                let sequence = body[JPEG_ICC_HEADER.len()];
+                icc_chunks.push((sequence, &body[JPEG_ICC_HEADER.len() + 2..]));
+            }
+            0xED if body.starts_with(JPEG_PHOTOSHOP_HEADER) => {
+                metadata.iptc =
+                    find_photoshop_resource(&body[JPEG_PHOTOSHOP_HEADER.len()..], IPTC_RESOURCE_ID);
+            }
+            _ => {}
+        }
+        pos += 2 + length;
+    }
+
+    // 分割されたICCプロファイルは連番順に連結する
+    if !icc_chunks.is_empty() {
+        icc_chunks.sort_by_key(|(sequence, _)| *sequence);
+        metadata.icc = Some(
+            icc_chunks
+                .iter()
+                .flat_map(|(_, chunk)| *chunk)
+                .copied()
+                .collect(),
+        );
+    }
+    metadata
+}
+
+/// Photoshopのイメージリソース（8BIM）から指定IDのデータを取り出す
+fn find_photoshop_resource(mut data: &[u8], id: u16) -> Option<Vec<u8>> {
+    while data.len() >= 12 && data.starts_with(b"8BIM") {
+        let resource_id = u16::from_be_bytes([data[4], data[5]]);
+        // 名前はPascal文字列（長さを含めて偶数バイトに揃える）
+        let name_length = (data[6] as usize + 2) & !1;
+        let size_pos = 6 + name_length;
+        let size = u32::from_be_bytes(data.get(size_pos..size_pos + 4)?.try_into().ok()?) as usize;
+        let start = size_pos + 4;
+        let value = data.get(start..start + size)?;
+        if resource_id == id {
+            return Some(value.to_vec());
+        }
+        data = data.get(start + ((size + 1) & !1)..)?;
+    }
+    None
+}
+
+fn read_png(bytes: &[u8]) -> Metadata {
+    let mut metadata = Metadata::default();
+    for (kind, data) in png_chunks(bytes) {
+        match kind {
+            b"eXIf" => metadata.exif = Some(data.to_vec()),
+            b"iCCP" => {
+                // プロファイル名\0、圧縮方式、zlib圧縮データ
+                if let Some(name_end) = data.iter().position(|&b| b == 0) {
+                    metadata.icc = data.get(name_end + 2..).and_then(inflate);
+                }
+            }
+            b"iTXt" if data.starts_with(PNG_XMP_KEYWORD) => {
+                metadata.xmp = parse_itxt(data);
+            }
+            b"zTXt" if data.starts_with(PNG_IPTC_KEYWORD) => {
+                metadata.iptc = data
+                    .get(PNG_IPTC_KEYWORD.len() + 2..)
+                    .and_then(inflate)
+                    .and_then(|text| parse_raw_profile(&text));
+            }
+            _ => {}
+        }
+    }
+    metadata
+}
+
+/// PNGのチャンク（種類, データ）を順に返す
+fn png_chunks(bytes: &[u8]) -> Vec<Chunk<'_>> {
+    let mut chunks = Vec::new();
+    let mut pos = 8;
+    while pos + 12 <= bytes.len() {
+        let length = u32::from_be_bytes(bytes[pos..pos + 4].try_into().unwrap()) as usize;
+        let kind: &[u8; 4] = bytes[pos + 4..pos + 8].try_into().unwrap();
+        let Some(data) = bytes.get(pos + 8..pos + 8 + length) else {
+            break;
+        };
+        chunks.push((kind, data));
+        pos += 12 + length;
+    }
+    chunks
+}
+
+/// iTXtチャンクのテキストを取り出す
+fn parse_itxt(data: &[u8]) -> Option<Vec<u8>> {
+    // キーワード\0、圧縮フラグ、圧縮方式、言語タグ\0、翻訳キーワード\0、テキスト
+    let keyword_end = data.iter().position(|&b| b == 0)?;
+    let compressed = *data.get(keyword_end + 1)? == 1;
+    let rest = data.get(keyword_end + 3..)?;
+    let language_end = rest.iter().position(|&b| b == 0)?;
+    let rest = &rest[language_end + 1..];
+    let translated_end = rest.iter().position(|&b| b == 0)?;
+    let text = &rest[translated_end + 1..];
+    if compressed {
+        inflate(text)
+    } else {
+        Some(text.to_vec())
+    }
+}
+
+/// ImageMagick形式の生プロファイル（"\n種類\n  長さ\n16進数..."）を復号する
+fn parse_raw_profile(text: &[u8]) -> Option<Vec<u8>> {
+    let text = std::str::from_utf8(text).ok()?;
+    let mut lines = text.trim_start().lines();
+    lines.next()?;
+    let length: usize = lines.next()?.trim().parse().ok()?;
+    let hex: Vec<u8> = lines
+        .flat_map(|line| line.bytes())
+        .filter(|b| b.is_ascii_hexdigit())
+        .collect();
+    let data: Vec<u8> = hex
+        .chunks_exact(2)
+        .filter_map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect();
+    (data.len() >= length).then(|| data[..length].to_vec())
+}
+
+fn format_raw_profile(kind: &str, data: &[u8]) -> Vec<u8> {
+    let mut text = format!("\n{}\n{:8}\n", kind, data.len());
+    for line in data.chunks(36) {
+        for byte in line {
+            text.push_str(&format!("{:02x}", byte));
+        }
+        text.push('\n');
+    }
+    text.into_bytes()
+}
+
+fn inflate(data: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    ZlibDecoder::new(data).read_to_end(&mut out).ok()?;
+    Some(out)
+}
+
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    // Vecへの書き込みは失敗しない
+    let _ = encoder.write_all(data);
+    encoder.finish().unwrap_or_default()
+}
+
+fn read_webp(bytes: &[u8]) -> Metadata {
+    let mut metadata = Metadata::default();
+    for (kind, data) in webp_chunks(bytes) {
+        match kind {
+            b"EXIF" => {
+                // 古い書き出し側は`Exif\0\0`を付けることがある
+                let data = data.strip_prefix(b"Exif\0\0").unwrap_or(data);
+                metadata.exif = Some(data.to_vec());
+            }
+            b"XMP " => metadata.xmp = Some(data.to_vec()),
+            b"ICCP" => metadata.icc = Some(data.to_vec()),
+            _ => {}
+        }
+    }
+    metadata
+}
+
+/// WebP（RIFF）のチャンク（種類, データ）を順に返す
+fn webp_chunks(bytes: &[u8]) -> Vec<Chunk<'_>> {
+    let mut chunks = Vec::new();
+    let mut pos = 12;
+    while pos + 8 <= bytes.len() {
+        let kind: &[u8; 4] = bytes[pos..pos + 4].try_into().unwrap();
+        let length = u32::from_le_bytes(bytes[pos + 4..pos + 8].try_into().unwrap()) as usize;
+        let Some(data) = bytes.get(pos + 8..pos + 8 + length) else {
+            break;
+        };
+        chunks.push((kind, data));
+        pos += 8 + ((length + 1) & !1);
+    }
+    chunks
+}
+
+fn read_tiff_page(bytes: &[u8], page: usize) -> Option<Metadata> {
+    let little_endian = tiff_little_endian(bytes)?;
+    let mut offset = read_u32(bytes, 4, little_endian)?;
+    for _ in 0..page {
+        let (_, next) = parse_ifd(bytes, offset, little_endian, 0)?;
+        offset = next;
+    }
+    let (entries, _) = parse_ifd(bytes, offset, little_endian, 0)?;
+
+    let take = |tag: u16| {
+        entries
+            .iter()
+            .find(|e| e.tag == tag)
+            .map(|e| e.data.clone())
+    };
+    let descriptive: Vec<Entry> = entries
+        .iter()
+        .filter(|e| DESCRIPTIVE_TAGS.contains(&e.tag))
+        .cloned()
+        .collect();
+    Some(Metadata {
+        exif: (!descriptive.is_empty()).then(|| write_exif_blob(&descriptive)),
+        xmp: take(TAG_XMP),
+        iptc: take(TAG_IPTC),
+        icc: take(TAG_ICC),
+    })
+}
+
+fn embed_jpeg(metadata: &Metadata, encoded: Vec<u8>) -> Result<Vec<u8>, String> {
+    if !jpeg_lossless::is_jpeg(&encoded) {
+        return Err("JPEGの書き出し結果を解析できませんでした".to_string());
+    }
+    let mut segments = Vec::new();
+    let mut push = |marker: u8, parts: &[&[u8]]| {
+        let length: usize = parts.iter().map(|p| p.len()).sum::<usize>() + 2;
+        if length > u16::MAX as usize {
+            println!(
+                "[Metadata] JPEGのセグメントに収まらないため省略しました（{}バイト）",
+                length
+            );
+            return;
+        }
+        segments.extend_from_slice(&[0xFF, marker]);
+        segments.extend_from_slice(&(length as u16).to_be_bytes());
+        for part in parts {
+            segments.extend_from_slice(part);
+        }
+    };
+
+    if let Some(exif) = &metadata.exif {
+        push(0xE1, &[b"Exif\0\0", exif]);
+    }
+    if let Some(xmp) = &metadata.xmp {
+        push(0xE1, &[JPEG_XMP_HEADER, xmp]);
+    }
+    if let Some(icc) = &metadata.icc {
+        // 1セグメントに収まらないプロファイルは連番付きで分割する
+        let chunks: Vec<&[u8]> = icc.chunks(65519).collect();
+        for (i, chunk) in chunks.iter().enumerate() {
+            push(
+                0xE2,
+                &[JPEG_ICC_HEADER, &[(i + 1) as u8, chunks.len() as u8], chunk],
+            );
+        }
+    }
+    if let Some(iptc) = &metadata.iptc {
+        let mut resource = b"8BIM".to_vec();
+        resource.extend_from_slice(&IPTC_RESOURCE_ID.to_be_bytes());
+        resource.extend_from_slice(&[0, 0]);
+        resource.extend_from_slice(&(iptc.len() as u32).to_be_bytes());
+        resource.extend_from_slice(iptc);
+        if iptc.len() % 2 == 1 {
+            resource.push(0);
+        }
+        push(0xED, &[JPEG_PHOTOSHOP_HEADER, &resource]);
+    }
+
+    // JFIF（APP0）があればその直後、なければSOIの直後に挿入する
+    let mut insert_at = 2;
+    if encoded.get(2..4) == Some(&[0xFF, 0xE0]) {
+        insert_at = 4 + u16::from_be_bytes([encoded[4], encoded[5]]) as usize;
+    }
+    let mut out = Vec::with_capacity(encoded.len() + segments.len());
+    out.extend_from_slice(&encoded[..insert_at]);
+    out.extend_from_slice(&segments);
+    out.extend_from_slice(&encoded[insert_at..]);
+    Ok(out)
+}
+
+fn push_png_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(kind);
+    hasher.update(data);
+    out.extend_from_slice(&hasher.finalize().to_be_bytes());
+}
+
+fn embed_png(metadata: &Metadata, encoded: Vec<u8>) -> Result<Vec<u8>, String> {
+    // シグネチャ（8バイト）とIHDR（25バイト）の直後に挿入する
+    const IHDR_END: usize = 8 + 25;
+    if encoded.len() < IHDR_END || &encoded[12..16] != b"IHDR" {
+        return Err("PNGの書き出し結果を解析できませんでした".to_string());
+    }
+
+    let mut chunks = Vec::new();
+    if let Some(icc) = &metadata.icc {
+        let mut data = b"ICC Profile\0\0".to_vec();
+        data.extend_from_slice(&deflate(icc));
+        push_png_chunk(&mut chunks, b"iCCP", &data);
+    }
+    if let Some(exif) = &metadata.exif {
+        push_png_chunk(&mut chunks, b"eXIf", exif);
+    }
+    if let Some(xmp) = &metadata.xmp {
+        let mut data = PNG_XMP_KEYWORD.to_vec();
+        data.extend_from_slice(&[0, 0, 0, 0, 0]);
+        data.extend_from_slice(xmp);
+        push_png_chunk(&mut chunks, b"iTXt", &data);
+    }
+    if let Some(iptc) = &metadata.iptc {
+        let mut data = PNG_IPTC_KEYWORD.to_vec();
+        data.extend_from_slice(&[0, 0]);
+        data.extend_from_slice(&deflate(&format_raw_profile("iptc", iptc)));
+        push_png_chunk(&mut chunks, b"zTXt", &data);
+    }
+
+    let mut out = Vec::with_capacity(encoded.len() + chunks.len());
+    out.extend_from_slice(&encoded[..IHDR_END]);
+    out.extend_from_slice(&chunks);
+    out.extend_from_slice(&encoded[IHDR_END..]);
+    Ok(out)
+}
+
+fn embed_webp(metadata: &Metadata, encoded: Vec<u8>) -> Result<Vec<u8>, String> {
+    let invalid = || "WebPの書き出し結果を解析できませんでした".to_string();
+    if encoded.len() < 12 || &encoded[0..4] != b"RIFF" || &encoded[8..12] != b"WEBP" {
+        return Err(invalid());
+    }
+    let chunks = webp_chunks(&encoded);
+
+    // 拡張形式（VP8X）でない場合は、画像チャンクからキャンバスサイズを求めて作成する
+    let (mut header, rest): (Vec<u8>, Vec<Chunk>) = match chunks.first() {
+        Some((b"VP8X", data)) if data.len() >= 10 => (data.to_vec(), chunks[1..].to_vec()),
+        _ => {
+            let (width, height, alpha) = webp_canvas(&chunks).ok_or_else(invalid)?;
+            let mut data = vec![if alpha { 0x10 } else { 0 }, 0, 0, 0];
+            data.extend_from_slice(&(width - 1).to_le_bytes()[..3]);
+            data.extend_from_slice(&(height - 1).to_le_bytes()[..3]);
+            (data, chunks)
+        }
+    };
+    // 既存のメタデータチャンクは置き換える
+    let rest: Vec<_> = rest
+        .into_iter()
+        .filter(|(kind, _)| !matches!(*kind, b"ICCP" | b"EXIF" | b"XMP "))
+        .collect();
+
+    if metadata.icc.is_some() {
+        header[0] |= 0x20;
+    }
+    if metadata.exif.is_some() {
+        header[0] |= 0x08;
+    }
+    if metadata.xmp.is_some() {
+        header[0] |= 0x04;
+    }
+
+    let mut body = b"WEBP".to_vec();
+    let mut push = |kind: &[u8; 4], data: &[u8]| {
+        body.extend_from_slice(kind);
+        body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        body.extend_from_slice(data);
+        if data.len() % 2 == 1 {
+            body.push(0);
+        }
+    };
+    push(b"VP8X", &header);
+    if let Some(icc) = &metadata.icc {
+        push(b"ICCP", icc);
+    }
+    for (kind, data) in &rest {
+        push(kind, data);
+    }
+    if let Some(exif) = &metadata.exif {
+        push(b"EXIF", exif);
+    }
+    if let Some(xmp) = &metadata.xmp {
+        push(b"XMP ", xmp);
+    }
+
+    let mut out = b"RIFF".to_vec();
+    out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    out.extend_from_slice(&body);
+    Ok(out)
+}
+
+/// 単純形式のWebPの画像チャンクからキャンバスサイズとアルファの有無を読み取る
+fn webp_canvas(chunks: &[Chunk]) -> Option<(u32, u32, bool)> {
+    let has_alpha_chunk = chunks.iter().any(|(kind, _)| *kind == b"ALPH");
+    chunks.iter().find_map(|(kind, data)| match *kind {
+        b"VP8L" if data.len() >= 5 && data[0] == 0x2F => {
+            let bits = u32::from_le_bytes(data[1..5].try_into().ok()?);
+            let width = (bits & 0x3FFF) + 1;
+            let height = ((bits >> 14) & 0x3FFF) + 1;
+            Some((width, height, bits >> 28 & 1 == 1))
+        }
+        b"VP8 " if data.len() >= 10 => {
+            let width = u16::from_le_bytes([data[6], data[7]]) as u32 & 0x3FFF;
+            let height = u16::from_le_bytes([data[8], data[9]]) as u32 & 0x3FFF;
+            Some((width, height, has_alpha_chunk))
+        }
+        _ => None,
+    })
+}
+
+/// TIFFのディレクトリエントリ（値はリトルエンディアンに正規化して保持する）
+#[derive(Debug, Clone)]
+struct Entry {
+    tag: u16,
+    field_type: u16,
+    count: u32,
+    data: Vec<u8>,
+    /// サブディレクトリ（ExifIFDなど）の内容
+    sub: Option<Vec<Entry>>,
+}
+
+/// TIFFの型ごとの要素サイズ（バイトオーダー変換の単位, 要素のバイト数）
+fn type_size(field_type: u16) -> Option<(usize, usize)> {
+    match field_type {
+        1 | 2 | 6 | 7 => Some((1, 1)),
+        3 | 8 => Some((2, 2)),
+        4 | 9 | 11 | 13 => Some((4, 4)),
+        5 | 10 => Some((4, 8)),
+        12 => Some((8, 8)),
+        _ => None,
+    }
+}
+
+/// 値のバイトオーダーを反転する（リトルエンディアンとビッグエンディアンの相互変換）
+fn swap_byte_order(field_type: u16, data: &mut [u8]) {
+    if let Some((unit, _)) = type_size(field_type) {
+        if unit > 1 {
+            data.chunks_exact_mut(unit)
+                .for_each(|value| value.reverse());
+        }
+    }
+}
+
+fn tiff_little_endian(bytes: &[u8]) -> Option<bool> {
+    match bytes.get(0..4)? {
+        b"II*\0" => Some(true),
+        b"MM\0*" => Some(false),
+        _ => None,
+    }
+}
+
+fn read_u16(bytes: &[u8], pos: usize, little_endian: bool) -> Option<u16> {
+    let b: [u8; 2] = bytes.get(pos..pos + 2)?.try_into().ok()?;
+    Some(if little_endian {
+        u16::from_le_bytes(b)
+    } else {
+        u16::from_be_bytes(b)
+    })
+}
+
+fn read_u32(bytes: &[u8], pos: usize, little_endian: bool) -> Option<u32> {
+    let b: [u8; 4] = bytes.get(pos..pos + 4)?.try_into().ok()?;
+    Some(if little_endian {
+        u32::from_le_bytes(b)
+    } else {
+        u32::from_be_bytes(b)
+    })
+}
+
+/// ディレクトリを読み込み、エントリと次のディレクトリの位置を返す
+fn parse_ifd(
+    bytes: &[u8],
+    offset: u32,
+    little_endian: bool,
+    depth: usize,
+) -> Option<(Vec<Entry>, u32)> {
+    let offset = offset as usize;
+    let count = read_u16(bytes, offset, little_endian)? as usize;
+    let mut entries = Vec::with_capacity(count);
+    for i in 0..count {
+        let pos = offset + 2 + i * 12;
+        let tag = read_u16(bytes, pos, little_endian)?;
+        let field_type = read_u16(bytes, pos + 2, little_endian)?;
+        let value_count = read_u32(bytes, pos + 4, little_endian)?;
+        // 未知の型や範囲外のデータは読み飛ばす
+        let Some((_, element)) = type_size(field_type) else {
+            continue;
+        };
+        let size = element * value_count as usize;
+        let data = if size <= 4 {
+            bytes.get(pos + 8..pos + 8 + size)
+        } else {
+            let value_offset = read_u32(bytes, pos + 8, little_endian)? as usize;
+            bytes.get(value_offset..value_offset + size)
+        };
+        let Some(data) = data else { continue };
+        let mut data = data.to_vec();
+        // XMP・IPTC・ICCはLONG型で記録されることもあるが、中身はバイト列として扱う
+        let field_type = if matches!(tag, TAG_XMP | TAG_IPTC | TAG_ICC) {
+            7
+        } else {
+            field_type
+        };
+        let value_count = if field_type == 7 {
+            data.len() as u32
+        } else {
+            value_count
+        };
+        if !little_endian {
+            swap_byte_order(field_type, &mut data);
+        }
+
+        let sub = if SUB_DIRECTORY_TAGS.contains(&tag) && depth < 2 && data.len() >= 4 {
+            let sub_offset = u32::from_le_bytes(data[0..4].try_into().ok()?);
+            parse_ifd(bytes, sub_offset, little_endian, depth + 1).map(|(entries, _)| entries)
+        } else {
+            None
+        };
+        if SUB_DIRECTORY_TAGS.contains(&tag) && sub.is_none() {
+            continue;
+        }
+        entries.push(Entry {
+            tag,
+            field_type,
+            count: value_count,
+            data,
+            sub,
+        });
+    }
+    let next = read_u32(bytes, offset + 2 + count * 12, little_endian).unwrap_or(0);
+    Some((entries, next))
+}
+
+/// ディレクトリを末尾に書き込み、ディレクトリの位置と「次のディレクトリ」欄の位置を返す
+///
+/// サブディレクトリと4バイトを超える値はディレクトリより前に書き込みます。
+fn write_ifd(out: &mut Vec<u8>, entries: &[Entry], next: u32, little_endian: bool) -> (u32, usize) {
+    let mut resolved: Vec<(u16, u16, u32, Vec<u8>)> = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let mut data = match &entry.sub {
+            Some(sub) => {
+                let (offset, _) = write_ifd(out, sub, 0, little_endian);
+                offset.to_le_bytes().to_vec()
+            }
+            None => entry.data.clone(),
+        };
+        if !little_endian {
+            swap_byte_order(entry.field_type, &mut data);
+        }
+        if data.len() > 4 {
+            if out.len() % 2 == 1 {
+                out.push(0);
+            }
+            let offset = out.len() as u32;
+            out.extend_from_slice(&data);
+            data = u32_bytes(offset, little_endian).to_vec();
+        }
+        data.resize(4, 0);
+        let field_type = if entry.sub.is_some() {
+            4
+        } else {
+            entry.field_type
+        };
+        let count = if entry.sub.is_some() { 1 } else { entry.count };
+        resolved.push((entry.tag, field_type, count, data));
+    }
+    resolved.sort_by_key(|(tag, ..)| *tag);
+
+    if out.len() % 2 == 1 {
+        out.push(0);
+    }
+    let offset = out.len() as u32;
+    let u16_bytes = |v: u16| {
+        if little_endian {
+            v.to_le_bytes()
+        } else {
+            v.to_be_bytes()
+        }
+    };
+    out.extend_from_slice(&u16_bytes(resolved.len() as u16));
+    for (tag, field_type, count, data) in &resolved {
+        out.extend_from_slice(&u16_bytes(*tag));
+        out.extend_from_slice(&u16_bytes(*field_type));
+        out.extend_from_slice(&u32_bytes(*count, little_endian));
+        out.extend_from_slice(data);
+    }
+    let next_pos = out.len();
+    out.extend_from_slice(&u32_bytes(next, little_endian));
+    (offset, next_pos)
+}
+
+fn u32_bytes(value: u32, little_endian: bool) -> [u8; 4] {
+    if little_endian {
+        value.to_le_bytes()
+    } else {
+        value.to_be_bytes()
+    }
+}
+
+/// エントリからEXIF（リトルエンディアンのTIFF形式）を作成する
+fn write_exif_blob(entries: &[Entry]) -> Vec<u8> {
+    let mut out = b"II*\0\0\0\0\0".to_vec();
+    let (offset, _) = write_ifd(&mut out, entries, 0, true);
+    out[4..8].copy_from_slice(&offset.to_le_bytes());
+    out
+}
+
+/// EXIFのIFD0のうち、引き継ぐエントリを読み込む
+fn exif_entries(exif: &[u8]) -> Vec<Entry> {
+    let Some(little_endian) = tiff_little_endian(exif) else {
+        return Vec::new();
+    };
+    read_u32(exif, 4, little_endian)
+        .and_then(|offset| parse_ifd(exif, offset, little_endian, 0))
+        .map(|(entries, _)| {
+            entries
+                .into_iter()
+                .filter(|e| DESCRIPTIVE_TAGS.contains(&e.tag))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// ディレクトリのタグの一覧（値は解釈しない）
+fn ifd_tags(bytes: &[u8], offset: u32, little_endian: bool) -> Option<Vec<u16>> {
+    let offset = offset as usize;
+    let count = read_u16(bytes, offset, little_endian)? as usize;
+    (0..count)
+        .map(|i| read_u16(bytes, offset + 2 + i * 12, little_endian))
+        .collect()
+}
+
+/// EXIFのIFD0からGPSのサブディレクトリを取り除いたEXIFを返す
+///
+/// 取り除く場合はIFD0を書き直すため、サムネイル（IFD1）は引き継ぎません。
+///
+/// # Returns
+///
+/// * `Ok(Some(Vec<u8>))` - GPSを取り除いたEXIF
+/// * `Ok(None)` - GPSが無い場合
+///
+/// # Errors
+///
+/// * IFD0を解析できない場合
+fn strip_exif_gps(exif: &[u8]) -> Result<Option<Vec<u8>>, String> {
+    let invalid = || "EXIFを解析できないため、位置情報を取り除けません".to_string();
+    let little_endian = tiff_little_endian(exif).ok_or_else(invalid)?;
+    let offset = read_u32(exif, 4, little_endian).ok_or_else(invalid)?;
+    // 解析できないGPSのサブディレクトリは`parse_ifd`が読み飛ばすため、タグの有無は別に調べる
+    let tags = ifd_tags(exif, offset, little_endian).ok_or_else(invalid)?;
+    if !tags.contains(&TAG_GPS_IFD) {
+        return Ok(None);
+    }
+    let (mut entries, _) = parse_ifd(exif, offset, little_endian, 0).ok_or_else(invalid)?;
+    entries.retain(|e| e.tag != TAG_GPS_IFD);
+    Ok(Some(write_exif_blob(&entries)))
+}
+
+/// XMPから`exif:GPS`で始まるプロパティ（属性・要素）を取り除く
+///
+/// # Returns
+///
+/// * `Ok(Some(Vec<u8>))` - 位置情報を取り除いたXMP
+/// * `Ok(None)` - 位置情報が無い場合
+///
+/// # Errors
+///
+/// * UTF-8でない場合、または位置情報のプロパティが閉じていない場合
+fn strip_xmp_gps(xmp: &[u8]) -> Result<Option<Vec<u8>>, String> {
+    const PREFIX: &str = "exif:GPS";
+    let invalid = || "XMPを解析できないため、位置情報を取り除けません".to_string();
+    let text = std::str::from_utf8(xmp).map_err(|_| invalid())?;
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    let mut changed = false;
+    while let Some(pos) = rest.find(PREFIX) {
+        let before = &rest[..pos];
+        let end = if let Some(preceding) = before.strip_suffix('<') {
+            // 要素: 自身で閉じるか、対応する終了タグまでを取り除く
+            let name_len = rest[pos..]
+                .find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+                .ok_or_else(invalid)?;
+            let close = format!("</{}>", &rest[pos..pos + name_len]);
+            let tag_end = pos + rest[pos..].find('>').ok_or_else(invalid)?;
+            out.push_str(preceding);
+            if rest[..tag_end].ends_with('/') {
+                tag_end + 1
+            } else {
+                tag_end + rest[tag_end..].find(&close).ok_or_else(invalid)? + close.len()
+            }
+        } else if before.ends_with(char::is_whitespace) {
+            // 属性: 名前="値"を取り除く
+            let eq = pos + rest[pos..].find('=').ok_or_else(invalid)?;
+            let quote = rest[eq + 1..].chars().next().ok_or_else(invalid)?;
+            let value_end = eq + 2 + rest[eq + 2..].find(quote).ok_or_else(invalid)?;
+            out.push_str(before.trim_end());
+            value_end + 1
+        } else {
+            // 終了タグなど（要素ごと取り除くため通常は現れない）
+            out.push_str(&rest[..pos + PREFIX.len()]);
+            rest = &rest[pos + PREFIX.len()..];
+            continue;
+        };
+        rest = &rest[end..];
+        changed = true;
+    }
+    out.push_str(rest);
+    Ok(changed.then(|| out.into_bytes()))
+}
+
+/// 書き出したTIFFの各ページにメタデータを埋め込む
+///
+/// 各ページのディレクトリにEXIFの記述的なタグとXMP・IPTC・ICCのタグを加えたものを
+/// ファイル末尾に書き直し、ページの連結を付け替えます。
+///
+/// # Errors
+///
+/// * 書き出したTIFFを解析できない場合
+pub fn embed_tiff_pages(encoded: Vec<u8>, pages: &[Metadata]) -> Result<Vec<u8>, String> {
+    let invalid = || "TIFFの書き出し結果を解析できませんでした".to_string();
+    let little_endian = tiff_little_endian(&encoded).ok_or_else(invalid)?;
+    let mut out = encoded;
+    let mut pointer_pos = 4;
+
+    for metadata in pages {
+        let offset = read_u32(&out, pointer_pos, little_endian).ok_or_else(invalid)?;
+        if offset == 0 {
+            break;
+        }
+        let (mut entries, next) = parse_ifd(&out, offset, little_endian, 0).ok_or_else(invalid)?;
+
+        let mut added = metadata
+            .exif
+            .as_deref()
+            .map(exif_entries)
+            .unwrap_or_default();
+        for (tag, field_type, value) in [
+            (TAG_XMP, 1, &metadata.xmp),
+            (TAG_IPTC, 7, &metadata.iptc),
+            (TAG_ICC, 7, &metadata.icc),
+        ] {
+            if let Some(value) = value {
+                added.push(Entry {
+                    tag,
+                    field_type,
+                    count: value.len() as u32,
+                    data: value.clone(),
+                    sub: None,
+                });
+            }
+        }
+        entries.retain(|e| !added.iter().any(|a| a.tag == e.tag));
+        entries.extend(added);
+
+        let (new_offset, next_pos) = write_ifd(&mut out, &entries, next, little_endian);
+        out[pointer_pos..pointer_pos + 4].copy_from_slice(&u32_bytes(new_offset, little_endian));
+        pointer_pos = next_pos;
+    }
+    Ok(out)
+}
+
+/// テスト用のEXIF（Make・Orientation・ExifIFDのDateTimeOriginalを持つ）
+#[cfg(test)]
+pub(crate) fn sample_exif(orientation: u16) -> Vec<u8> {
+    let date = b"2024:01:02 03:04:05\0".to_vec();
+    write_exif_blob(&[
+        Entry {
+            tag: 271,
+            field_type: 2,
+            count: 8,
+            data: b"TestCam\0".to_vec(),
+            sub: None,
+        },
+        Entry {
+            tag: TAG_ORIENTATION,
+            field_type: 3,
+            count: 1,
+            data: orientation.to_le_bytes().to_vec(),
+            sub: None,
+        },
+        Entry {
+            tag: 34665,
+            field_type: 4,
+            count: 1,
+            data: vec![0; 4],
+            sub: Some(vec![Entry {
+                tag: 36867,
+                field_type: 2,
+                count: date.len() as u32,
+                data: date,
+                sub: None,
+            }]),
+        },
+    ])
+}
+
+/// テスト用のメタデータ（EXIF・XMP・IPTC・ICCをすべて持つ）
+#[cfg(test)]
+pub(crate) fn sample_metadata() -> Metadata {
+    Metadata {
+        exif: Some(sample_exif(6)),
+        xmp: Some(
+            b"<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"><rdf:RDF><rdf:Description tiff:Orientation=\"6\" dc:rights=\"(c) Test\"/></rdf:RDF></x:xmpmeta>"
+                .to_vec(),
+        ),
+        iptc: Some(b"\x1c\x02\x00\x00\x02\x00\x04\x1c\x02\x74\x00\x07(c)Test".to_vec()),
+        icc: Some((0..600u32).map(|i| (i % 251) as u8).collect()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, Rgb, RgbImage};
+
+    /// EXIFに記録された向き
+    fn exif_orientation(exif: &[u8]) -> Option<u16> {
+        exif_entries(exif)
+            .iter()
+            .find(|e| e.tag == TAG_ORIENTATION)
+            .map(|e| u16::from_le_bytes([e.data[0], e.data[1]]))
+    }
+
+    fn encode(format: ImageFormat) -> Vec<u8> {
+        let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(16, 8, Rgb([10, 200, 30])));
+        let mut bytes = Vec::new();
+        img.write_to(&mut std::io::Cursor::new(&mut bytes), format)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_round_trip_per_format() {
+        let metadata = sample_metadata();
+        for format in [
+            ImageFormat::Jpeg,
+            ImageFormat::Png,
+            ImageFormat::WebP,
+            ImageFormat::Tiff,
+        ] {
+            let bytes = metadata.embed(encode(format), format).unwrap();
+            assert!(
+                image::load_from_memory_with_format(&bytes, format).is_ok(),
+                "{:?}: 埋め込み後も画像として読み込める",
+                format
+            );
+
+            let read = Metadata::read(&bytes);
+            assert_eq!(read.xmp, metadata.xmp, "{:?}: XMP", format);
+            assert_eq!(read.icc, metadata.icc, "{:?}: ICC", format);
+            if format == ImageFormat::WebP {
+                assert_eq!(read.iptc, None, "WebPにはIPTCの格納場所が無い");
+            } else {
+                assert_eq!(read.iptc, metadata.iptc, "{:?}: IPTC", format);
+            }
+
+            let exif = read.exif.expect("EXIFが引き継がれる");
+            assert_eq!(exif_orientation(&exif), Some(6), "{:?}", format);
+            let entries = exif_entries(&exif);
+            let make = entries.iter().find(|e| e.tag == 271).unwrap();
+            assert_eq!(make.data, b"TestCam\0");
+            let exif_ifd = entries.iter().find(|e| e.tag == 34665).unwrap();
+            assert_eq!(exif_ifd.sub.as_ref().unwrap()[0].tag, 36867);
+        }
+    }
+
+    #[test]
+    fn test_reset_orientation() {
+        let mut metadata = sample_metadata();
+        metadata.reset_orientation();
+        assert_eq!(exif_orientation(metadata.exif.as_ref().unwrap()), Some(1));
+        let xmp = String::from_utf8(metadata.xmp.unwrap()).unwrap();
+        assert!(xmp.contains("tiff:Orientation=\"1\""));
+        assert!(xmp.contains("dc:rights"), "他の項目は保持する");
+    }
+
+    #[test]
+    fn test_set_orientation_in_place() {
+        let target = Orientation::from_exif(8).unwrap();
+        for format in [
+            ImageFormat::Jpeg,
+            ImageFormat::Png,
+            ImageFormat::WebP,
+            ImageFormat::Tiff,
+        ] {
+            let original = sample_metadata().embed(encode(format), format).unwrap();
+            let mut bytes = original.clone();
+            assert!(set_orientation_in_place(&mut bytes, target), "{:?}", format);
+
+            // 長さは変わらず、書き換えたのはOrientationの値のみ
+            assert_eq!(bytes.len(), original.len(), "{:?}", format);
+            let changed = bytes.iter().zip(&original).filter(|(a, b)| a != b).count();
+            assert!(changed <= 2, "{:?}: {}バイト変更", format, changed);
+
+            assert_eq!(orientation::read_exif_orientation(&bytes), Some(target));
+            let xmp = Metadata::read(&bytes).xmp.unwrap();
+            assert!(
+                String::from_utf8_lossy(&xmp).contains("tiff:Orientation=\"8\""),
+                "{:?}",
+                format
+            );
+        }
+
+        // Orientationタグが無い場合は何も変更しない
+        let mut bytes = encode(ImageFormat::Png);
+        let original = bytes.clone();
+        assert!(!set_orientation_in_place(&mut bytes, target));
+        assert_eq!(bytes, original);
+    }
+
+    #[test]
+    fn test_multipage_tiff_keeps_metadata_per_page() {
+        let pages = vec![
+            DynamicImage::ImageRgb8(RgbImage::new(4, 4)),
+            DynamicImage::ImageRgb8(RgbImage::new(2, 2)),
+        ];
+        let first = sample_metadata();
+        let second = Metadata {
+            icc: Some(vec![1, 2, 3, 4, 5]),
+            ..Default::default()
+        };
+        let bytes = embed_tiff_pages(
+            multipage::encode_pages(&pages).unwrap(),
+            &[first.clone(), second.clone()],
+        )
+        .unwrap();
+
+        assert_eq!(multipage::decode_all_pages(&bytes).unwrap().len(), 2);
+        assert_eq!(Metadata::read_page(&bytes, 0).xmp, first.xmp);
+        assert_eq!(Metadata::read_page(&bytes, 1).icc, second.icc);
+        assert_eq!(Metadata::read_page(&bytes, 1).xmp, None);
+    }
+
+    #[test]
+    fn test_strip_gps() {
+        let exif = write_exif_blob(&[
+            Entry {
+                tag: 271,
+                field_type: 2,
+                count: 8,
+                data: b"TestCam\0".to_vec(),
+                sub: None,
+            },
+            Entry {
+                tag: TAG_GPS_IFD,
+                field_type: 4,
+                count: 1,
+                data: vec![0; 4],
+                sub: Some(vec![Entry {
+                    tag: 1,
+                    field_type: 2,
+                    count: 2,
+                    data: b"N\0".to_vec(),
+                    sub: None,
+                }]),
+            },
+        ]);
+        let mut metadata = Metadata {
+            exif: Some(exif),
+            xmp: Some(
+                b"<rdf:Description exif:GPSLatitude=\"35,40.5N\" dc:rights=\"(c) Test\"><exif:GPSVersionID>2.2.0.0</exif:GPSVersionID><exif:GPSAltitude/></rdf:Description>"
+                    .to_vec(),
+            ),
+            ..sample_metadata()
+        };
+        metadata.strip_gps();
+
+        let tags: Vec<u16> = exif_entries(metadata.exif.as_deref().unwrap())
+            .iter()
+            .map(|e| e.tag)
+            .collect();
+        assert_eq!(tags, vec![271]);
+        assert_eq!(
+            metadata.xmp.as_deref(),
+            Some(&b"<rdf:Description dc:rights=\"(c) Test\"></rdf:Description>"[..])
+        );
+
+        // GPSが無ければ変更しない
+        let mut plain = sample_metadata();
+        plain.strip_gps();
+        assert_eq!(plain, sample_metadata());
+    }
+
+    #[test]
+    fn test_strip_gps_drops_unparsable_blocks() {
+        let mut exif = write_exif_blob(&[
+            Entry {
+                tag: 271,
+                field_type: 2,
+                count: 8,
+                data: b"TestCam\0".to_vec(),
+                sub: None,
+            },
+            Entry {
+                tag: TAG_GPS_IFD,
+                field_type: 4,
+                count: 1,
+                data: vec![0; 4],
+                sub: Some(vec![Entry {
+                    tag: 2,
+                    field_type: 5,
+                    count: 3,
+                    data: vec![1; 24],
+                    sub: None,
+                }]),
+            },
+        ]);
+        // GPSのサブディレクトリを途中で切る（ポインタをファイル末尾の直前に向ける）
+        let ifd0 = u32::from_le_bytes(exif[4..8].try_into().unwrap()) as usize;
+        let gps_entry = ifd0 + 2 + 12;
+        assert_eq!(read_u16(&exif, gps_entry, true), Some(TAG_GPS_IFD));
+        let truncated = (exif.len() - 1) as u32;
+        exif[gps_entry + 8..gps_entry + 12].copy_from_slice(&truncated.to_le_bytes());
+
+        let mut metadata = Metadata {
+            exif: Some(exif),
+            // 閉じていない位置情報の要素
+            xmp: Some(b"<rdf:Description><exif:GPSLatitude>35,40.5N".to_vec()),
+            ..sample_metadata()
+        };
+        metadata.strip_gps();
+        let exif = metadata.exif.as_deref().unwrap();
+        let offset = read_u32(exif, 4, true).unwrap();
+        assert_eq!(ifd_tags(exif, offset, true), Some(vec![271]));
+        assert_eq!(metadata.xmp, None);
+
+        // IFD0を解析できないEXIFは全体を取り除く
+        let mut broken = Metadata {
+            exif: Some(b"II*\0\xff\xff\0\0".to_vec()),
+            ..sample_metadata()
+        };
+        broken.strip_gps();
+        assert_eq!(broken.exif, None);
+        assert_eq!(broken.icc, sample_metadata().icc);
+    }
+}
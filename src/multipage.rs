@@ -0,0 +1,216 @@
+use crate::orientation;
+use image::{DynamicImage, ImageBuffer};
+use std::io::Cursor;
+use std::path::Path;
+use tiff::decoder::{Decoder, DecodingResult};
+use tiff::encoder::{colortype, TiffEncoder};
+use tiff::ColorType;
+
+/// ファイル内容がTIFFかどうか
+pub fn is_tiff(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"II*\0") || bytes.starts_with(b"MM\0*")
+}
+
+/// TIFFのページ数を数える（TIFF以外・解析できない場合は1）
+pub fn page_count(bytes: &[u8]) -> usize {
+    if !is_tiff(bytes) {
+        return 1;
+    }
+    let Ok(mut decoder) = Decoder::new(Cursor::new(bytes)) else {
+        return 1;
+    };
+
+    let mut count = 1;
+    while decoder.more_images() && decoder.next_image().is_ok() {
+        count += 1;
+    }
+    count
+}
+
+/// TIFFの指定ページをデコードする
+///
+/// サイドカーXMP（なければEXIF）の向きを適用します（先頭ページの表示と同じ扱い）。
+///
+/// # Arguments
+///
+/// * `bytes` - TIFFファイルの内容
+/// * `path` - 画像ファイルのパス（サイドカーXMPの参照に使用）
+/// * `page` - ページ番号（0始まり）
+///
+/// # Returns
+///
+/// * `Ok(DynamicImage)` - デコード済みのページ
+/// * `Err(String)` - エラーメッセージ
+pub fn decode_page(bytes: &[u8], path: &Path, page: usize) -> Result<DynamicImage, String> {
+    let mut decoder = Decoder::new(Cursor::new(bytes))
+        .map_err(|e| format!("TIFFの読み込みに失敗しました: {}", e))?;
+    decoder
+        .seek_to_image(page)
+        .map_err(|e| format!("TIFFのページ{}が見つかりません: {}", page + 1, e))?;
+    let img = read_current_page(&mut decoder)?;
+
+    Ok(match orientation::resolve_orientation(bytes, path) {
+        Some(applied) => applied.orientation.apply(img),
+        None => img,
+    })
+}
+
+/// TIFFの全ページをデコードする（向きは適用しない）
+pub fn decode_all_pages(bytes: &[u8]) -> Result<Vec<DynamicImage>, String> {
+    let mut decoder = Decoder::new(Cursor::new(bytes))
+        .map_err(|e| format!("TIFFの読み込みに失敗しました: {}", e))?;
+
+    let mut pages = vec![read_current_page(&mut decoder)?];
+    while decoder.more_images() {
+        decoder
+            .next_image()
+            .map_err(|e| format!("TIFFの読み込みに失敗しました: {}", e))?;
+        pages.push(read_current_page(&mut decoder)?);
+    }
+    Ok(pages)
+}
+
+/// 複数ページのTIFFとして書き出す
+///
+/// # Errors
+///
+/// * TIFFとして書き出せない色形式のページが含まれる場合
+/// * エンコードに失敗した場合
+pub fn encode_pages(pages: &[DynamicImage]) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    let mut encoder = TiffEncoder::new(Cursor::new(&mut bytes))
+        .map_err(|e| format!("TIFFの書き込みに失敗しました: {}", e))?;
+
+    for page in pages {
+        let (width, height) = (page.width(), page.height());
+        let result = match page {
+            DynamicImage::ImageLuma8(img) => {
+                encoder.write_image::<colortype::Gray8>(width, height, img.as_raw())
+            }
+            DynamicImage::ImageLuma16(img) => {
+                encoder.write_image::<colortype::Gray16>(width, height, img.as_raw())
+            }
+            DynamicImage::ImageRgb8(img) => {
+                encoder.write_image::<colortype::RGB8>(width, height, img.as_raw())
+            }
+            DynamicImage::ImageRgb16(img) => {
+                encoder.write_image::<colortype::RGB16>(width, height, img.as_raw())
+            }
+            DynamicImage::ImageRgba16(img) => {
+                encoder.write_image::<colortype::RGBA16>(width, height, img.as_raw())
+            }
+            DynamicImage::ImageLumaA16(_) => {
+                encoder.write_image::<colortype::RGBA16>(width, height, page.to_rgba16().as_raw())
+            }
+            DynamicImage::ImageRgb32F(img) => {
+                encoder.write_image::<colortype::RGB32Float>(width, height, img.as_raw())
+            }
+            DynamicImage::ImageRgba32F(img) => {
+                encoder.write_image::<colortype::RGBA32Float>(width, height, img.as_raw())
+            }
+            _ => encoder.write_image::<colortype::RGBA8>(width, height, page.to_rgba8().as_raw()),
+        };
+        result.map_err(|e| format!("TIFFの書き込みに失敗しました: {}", e))?;
+    }
+
+    Ok(bytes)
+}
+
+/// デコーダーが指している現在のページを読み込む
+fn read_current_page<R: std::io::Read + std::io::Seek>(
+    decoder: &mut Decoder<R>,
+) -> Result<DynamicImage, String> {
+    let (width, height) = decoder
+        .dimensions()
+        .map_err(|e| format!("TIFFの読み込みに失敗しました: {}", e))?;
+    let color_type = decoder
+        .colortype()
+        .map_err(|e| format!("TIFFの読み込みに失敗しました: {}", e))?;
+    let data = decoder
+        .read_image()
+        .map_err(|e| format!("TIFFのデコードに失敗しました: {}", e))?;
+
+    let img = match (color_type, data) {
+        (ColorType::Gray(8), DecodingResult::U8(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLuma8)
+        }
+        (ColorType::Gray(16), DecodingResult::U16(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLuma16)
+        }
+        (ColorType::GrayA(8), DecodingResult::U8(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLumaA8)
+        }
+        (ColorType::GrayA(16), DecodingResult::U16(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageLumaA16)
+        }
+        (ColorType::RGB(8), DecodingResult::U8(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgb8)
+        }
+        (ColorType::RGB(16), DecodingResult::U16(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgb16)
+        }
+        (ColorType::RGB(32), DecodingResult::F32(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgb32F)
+        }
+        (ColorType::RGBA(8), DecodingResult::U8(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgba8)
+        }
+        (ColorType::RGBA(16), DecodingResult::U16(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgba16)
+        }
+        (ColorType::RGBA(32), DecodingResult::F32(data)) => {
+            ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgba32F)
+        }
+        (color_type, _) => {
+            return Err(format!(
+                "このTIFFの色形式には対応していません: {:?}",
+                color_type
+            ))
+        }
+    };
+    img.ok_or_else(|| "TIFFの画素データが不正です".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GenericImageView, Luma, Rgb, RgbImage};
+
+    fn three_page_tiff() -> Vec<u8> {
+        let pages = vec![
+            DynamicImage::ImageRgb8(RgbImage::from_pixel(4, 2, Rgb([255, 0, 0]))),
+            DynamicImage::ImageLuma16(ImageBuffer::from_pixel(3, 3, Luma([40000u16]))),
+            DynamicImage::ImageRgb8(RgbImage::from_pixel(2, 5, Rgb([0, 0, 255]))),
+        ];
+        encode_pages(&pages).unwrap()
+    }
+
+    #[test]
+    fn test_page_count_and_decode() {
+        let bytes = three_page_tiff();
+        assert!(is_tiff(&bytes));
+        assert_eq!(page_count(&bytes), 3);
+
+        let page = decode_page(&bytes, Path::new("scan.tif"), 1).unwrap();
+        assert_eq!(page.dimensions(), (3, 3));
+        assert!(
+            matches!(page, DynamicImage::ImageLuma16(_)),
+            "16bitのページは16bitのまま読み込む"
+        );
+
+        let page = decode_page(&bytes, Path::new("scan.tif"), 2).unwrap();
+        assert_eq!(page.to_rgb8().get_pixel(0, 0), &Rgb([0, 0, 255]));
+        assert!(decode_page(&bytes, Path::new("scan.tif"), 3).is_err());
+    }
+
+    #[test]
+    fn test_round_trip_keeps_all_pages() {
+        let bytes = three_page_tiff();
+        let pages = decode_all_pages(&bytes).unwrap();
+        assert_eq!(pages.len(), 3);
+
+        let rewritten = encode_pages(&pages).unwrap();
+        assert_eq!(decode_all_pages(&rewritten).unwrap().len(), 3);
+        assert_eq!(page_count(b"\x89PNG"), 1);
+    }
+}
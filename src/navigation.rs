@@ -1,5 +1,39 @@
+use notify::event::{ModifyKind, RenameMode};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// ナビゲーション対象となる画像拡張子
+pub const IMAGE_EXTENSIONS: &[&str] = &[
+    "jpg", "jpeg", "png", "gif", "bmp", "webp", "tiff", "tif", "jxl", "exr", "hdr",
+];
+
+/// AVIFの拡張子（`avif`フィーチャー有効時のみ対象）
+pub const AVIF_EXTENSIONS: &[&str] = &["avif"];
+
+/// HEIFの拡張子（`heif`フィーチャー有効時のみ対象）
+pub const HEIF_EXTENSIONS: &[&str] = &["heic", "heif"];
+
+/// 拡張子からナビゲーション対象の画像かどうかを判定する
+pub fn is_supported_image(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| {
+            let ext_str = ext.to_string_lossy().to_lowercase();
+            let ext_str = ext_str.as_str();
+            IMAGE_EXTENSIONS.contains(&ext_str)
+                || (cfg!(feature = "avif") && AVIF_EXTENSIONS.contains(&ext_str))
+                || (cfg!(feature = "heif") && HEIF_EXTENSIONS.contains(&ext_str))
+                || crate::raw::RAW_EXTENSIONS.contains(&ext_str)
+        })
+        .unwrap_or(false)
+}
 
 /// フォルダ内の画像ファイル一覧を作成日時順で取得する
 ///
@@ -14,110 +48,692 @@ use std::path::Path;
 ///
 /// # Notes
 ///
-/// サポートされる画像形式: jpg, jpeg, png, gif, bmp, webp, tiff, tif, jxl
+/// サポートされる画像形式は`IMAGE_EXTENSIONS`を参照
 pub fn get_folder_images(folder_path: String) -> Option<Vec<String>> {
     let folder = Path::new(&folder_path);
     if !folder.is_dir() {
         return None;
     }
 
-    let mut images: Vec<(String, std::time::SystemTime)> = Vec::new();
+    let sorted_paths: Vec<String> = scan_folder(folder, SortOrder::Created, false)
+        .into_iter()
+        .map(|entry| entry.path.to_string_lossy().to_string())
+        .collect();
+
+    if sorted_paths.is_empty() {
+        None
+    } else {
+        Some(sorted_paths)
+    }
+}
+
+/// フォルダナビゲーションの並び順
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum SortOrder {
+    /// ファイル名の自然順（IMG_2 が IMG_10 より前）
+    Name,
+    /// 作成日時（取得できない場合は更新日時）
+    #[default]
+    Created,
+    /// 更新日時
+    Modified,
+    /// ファイルサイズ
+    Size,
+    /// EXIFの撮影日時（取得できない場合は更新日時）
+    CaptureTime,
+}
 
-    // 画像拡張子フィルター
-    let image_extensions = [
-        "jpg", "jpeg", "png", "gif", "bmp", "webp", "tiff", "tif", "jxl",
+impl SortOrder {
+    /// 設定画面に表示する順の全並び順
+    pub const ALL: [Self; 5] = [
+        Self::Name,
+        Self::Created,
+        Self::Modified,
+        Self::Size,
+        Self::CaptureTime,
     ];
 
-    if let Ok(entries) = fs::read_dir(folder) {
-        for entry in entries.flatten() {
-            let path = entry.path();
-            if path.is_file() {
-                if let Some(extension) = path.extension() {
-                    let ext_str = extension.to_string_lossy().to_lowercase();
-                    if image_extensions.contains(&ext_str.as_str()) {
-                        if let Ok(metadata) = entry.metadata() {
-                            let timestamp = metadata
-                                .created()
-                                .or_else(|_| metadata.modified())
-                                .unwrap_or(std::time::UNIX_EPOCH);
-                            images.push((path.to_string_lossy().to_string(), timestamp));
-                        }
-                    }
+    /// 設定画面に表示する名前
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Name => "ファイル名",
+            Self::Created => "作成日時",
+            Self::Modified => "更新日時",
+            Self::Size => "ファイルサイズ",
+            Self::CaptureTime => "撮影日時 (EXIF)",
+        }
+    }
+
+    /// CLI引数（`--sort`）で指定する名前
+    pub fn cli_name(self) -> &'static str {
+        match self {
+            Self::Name => "name",
+            Self::Created => "created",
+            Self::Modified => "modified",
+            Self::Size => "size",
+            Self::CaptureTime => "exif",
+        }
+    }
+
+    /// CLI引数（`--sort`）の値から並び順を取得する
+    ///
+    /// 受け付ける値: name, created, modified, size, exif（大文字小文字を区別しない）
+    pub fn from_cli_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|order| order.cli_name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// ファイル名を自然順で比較する
+///
+/// 数字の連続は数値として比較し、それ以外は大文字小文字を区別せずに比較します。
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+
+    loop {
+        match (a_chars.peek().copied(), b_chars.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(ac), Some(bc)) if ac.is_ascii_digit() && bc.is_ascii_digit() => {
+                let a_num = take_digits(&mut a_chars);
+                let b_num = take_digits(&mut b_chars);
+                let a_trimmed = a_num.trim_start_matches('0');
+                let b_trimmed = b_num.trim_start_matches('0');
+                let ordering = a_trimmed
+                    .len()
+                    .cmp(&b_trimmed.len())
+                    .then_with(|| a_trimmed.cmp(b_trimmed))
+                    // 数値が同じ場合は先頭ゼロの少ない方を前にする
+                    .then_with(|| a_num.len().cmp(&b_num.len()));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(ac), Some(bc)) => {
+                let ordering = ac.to_lowercase().cmp(bc.to_lowercase());
+                if ordering != Ordering::Equal {
+                    return ordering;
                 }
+                a_chars.next();
+                b_chars.next();
             }
         }
     }
+}
 
-    // 作成日付順でソート（古い順）
-    images.sort_by(|a, b| a.1.cmp(&b.1));
+fn take_digits(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.peek().copied() {
+        if !c.is_ascii_digit() {
+            break;
+        }
+        digits.push(c);
+        chars.next();
+    }
+    digits
+}
 
-    let sorted_paths: Vec<String> = images.into_iter().map(|(path, _)| path).collect();
+/// フォルダインデックス内の1エントリ
+#[derive(Debug, Clone)]
+struct ImageEntry {
+    path: PathBuf,
+    created: SystemTime,
+    modified: SystemTime,
+    size: u64,
+    /// EXIFの撮影日時（撮影日時順の場合のみ読み込む）
+    captured: Option<SystemTime>,
+}
 
-    if sorted_paths.is_empty() {
-        None
-    } else {
-        Some(sorted_paths)
+impl ImageEntry {
+    /// ファイルのメタデータからエントリを作成する（画像でない場合は`None`）
+    fn read(path: &Path, with_capture_time: bool) -> Option<Self> {
+        if !is_supported_image(path) {
+            return None;
+        }
+        let metadata = fs::metadata(path).ok()?;
+        if !metadata.is_file() {
+            return None;
+        }
+        let modified = metadata.modified().unwrap_or(std::time::UNIX_EPOCH);
+        let created = metadata.created().unwrap_or(modified);
+        let captured = if with_capture_time {
+            read_capture_time(path)
+        } else {
+            None
+        };
+        Some(Self {
+            path: path.to_path_buf(),
+            created,
+            modified,
+            size: metadata.len(),
+            captured,
+        })
+    }
+
+    fn file_name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default()
     }
 }
 
-/// 指定された画像の次の画像パスを取得
-///
-/// # Arguments
-///
-/// * `current_path` - 現在表示している画像ファイルのパス
-/// * `folder_navigation_enabled` - フォルダ内ナビゲーションが有効かどうか
-///
-/// # Returns
-///
-/// * `Some(String)` - 次の画像のパス（ループする）
-/// * `None` - ナビゲーションが無効、または親フォルダ/画像一覧が取得できない場合
-pub fn get_next_image(current_path: String, folder_navigation_enabled: bool) -> Option<String> {
-    if !folder_navigation_enabled {
+/// EXIFのDateTimeOriginal（なければDateTime）を読み込む
+fn read_capture_time(path: &Path) -> Option<SystemTime> {
+    let file = fs::File::open(path).ok()?;
+    let exif = exif::Reader::new()
+        .read_from_container(&mut std::io::BufReader::new(file))
+        .ok()?;
+
+    let field = exif
+        .get_field(exif::Tag::DateTimeOriginal, exif::In::PRIMARY)
+        .or_else(|| exif.get_field(exif::Tag::DateTime, exif::In::PRIMARY))?;
+    let exif::Value::Ascii(ref values) = field.value else {
         return None;
+    };
+    let datetime = exif::DateTime::from_ascii(values.first()?).ok()?;
+
+    // タイムゾーン情報はカメラ間で揃っていないため、ローカル時刻をそのまま比較に使う
+    let timestamp = chrono::NaiveDate::from_ymd_opt(
+        datetime.year as i32,
+        datetime.month as u32,
+        datetime.day as u32,
+    )?
+    .and_hms_opt(
+        datetime.hour as u32,
+        datetime.minute as u32,
+        datetime.second as u32,
+    )?
+    .and_utc()
+    .timestamp();
+
+    std::time::UNIX_EPOCH.checked_add(Duration::from_secs(u64::try_from(timestamp).ok()?))
+}
+
+/// 並び順に従ってエントリを比較する（同順位はファイル名の自然順）
+fn compare_entries(a: &ImageEntry, b: &ImageEntry, order: SortOrder, reverse: bool) -> Ordering {
+    let primary = match order {
+        SortOrder::Name => Ordering::Equal,
+        SortOrder::Created => a.created.cmp(&b.created),
+        SortOrder::Modified => a.modified.cmp(&b.modified),
+        SortOrder::Size => a.size.cmp(&b.size),
+        SortOrder::CaptureTime => a
+            .captured
+            .unwrap_or(a.modified)
+            .cmp(&b.captured.unwrap_or(b.modified)),
+    };
+    let ordering = primary.then_with(|| natural_cmp(&a.file_name(), &b.file_name()));
+
+    if reverse {
+        ordering.reverse()
+    } else {
+        ordering
+    }
+}
+
+/// フォルダを走査し、指定の並び順に並べたエントリ一覧を返す
+fn scan_folder(folder: &Path, order: SortOrder, reverse: bool) -> Vec<ImageEntry> {
+    let paths: Vec<PathBuf> = match fs::read_dir(folder) {
+        Ok(entries) => entries.flatten().map(|entry| entry.path()).collect(),
+        Err(_) => Vec::new(),
+    };
+
+    // EXIF読み込みはファイル数が多いと重いため並列化する
+    let with_capture_time = order == SortOrder::CaptureTime;
+    let mut images: Vec<ImageEntry> = paths
+        .par_iter()
+        .filter_map(|path| ImageEntry::read(path, with_capture_time))
+        .collect();
+
+    images.sort_by(|a, b| compare_entries(a, b, order, reverse));
+    images
+}
+
+/// ファイルシステム監視から届いたインデックスへの変更
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum IndexChange {
+    Added(PathBuf),
+    Removed(PathBuf),
+}
+
+#[derive(Default)]
+struct IndexState {
+    /// ソート済みのエントリ一覧
+    entries: Vec<ImageEntry>,
+    /// 初回走査が完了したかどうか
+    ready: bool,
+    /// 初回走査中に届いた変更（走査完了後に適用する）
+    pending: Vec<IndexChange>,
+    order: SortOrder,
+    reverse: bool,
+    /// 全エントリのEXIF撮影日時を読み込み済みかどうか
+    capture_loaded: bool,
+}
+
+impl IndexState {
+    fn sort(&mut self) {
+        let (order, reverse) = (self.order, self.reverse);
+        self.entries
+            .sort_by(|a, b| compare_entries(a, b, order, reverse));
     }
 
-    let current = std::path::Path::new(&current_path);
-    if let Some(parent) = current.parent() {
-        if let Some(folder_images) = get_folder_images(parent.to_string_lossy().to_string()) {
-            if let Some(current_index) = folder_images.iter().position(|path| path == &current_path)
-            {
-                let next_index = (current_index + 1) % folder_images.len();
-                return Some(folder_images[next_index].clone());
+    fn needs_capture_times(&self) -> bool {
+        self.ready && self.order == SortOrder::CaptureTime && !self.capture_loaded
+    }
+
+    fn apply(&mut self, change: IndexChange) {
+        match change {
+            IndexChange::Added(path) => {
+                self.entries.retain(|entry| entry.path != path);
+                if let Some(entry) = ImageEntry::read(&path, self.capture_loaded) {
+                    let (order, reverse) = (self.order, self.reverse);
+                    let index = self.entries.partition_point(|e| {
+                        compare_entries(e, &entry, order, reverse) != Ordering::Greater
+                    });
+                    self.entries.insert(index, entry);
+                }
+            }
+            IndexChange::Removed(path) => {
+                self.entries.retain(|entry| entry.path != path);
             }
         }
     }
-    None
 }
 
-/// 指定された画像の前の画像パスを取得
-///
-/// # Arguments
-///
-/// * `current_path` - 現在表示している画像ファイルのパス
-/// * `folder_navigation_enabled` - フォルダ内ナビゲーションが有効かどうか
-///
-/// # Returns
+/// 変更通知用のコールバック（UIの再描画要求など）
+type ChangeCallback = Arc<dyn Fn() + Send + Sync>;
+
+/// フォルダ内画像のキャッシュ済みインデックス
 ///
-/// * `Some(String)` - 前の画像のパス（ループする）
-/// * `None` - ナビゲーションが無効、または親フォルダ/画像一覧が取得できない場合
-pub fn get_previous_image(current_path: String, folder_navigation_enabled: bool) -> Option<String> {
-    if !folder_navigation_enabled {
-        return None;
+/// 初回の走査はバックグラウンドスレッドで行い、以降はファイルシステムの変更通知
+/// （追加・削除・リネーム・内容の変更）でインデックスを差分更新します。
+/// キー入力のたびにフォルダを再走査せずに前後の画像を求められます。
+pub struct FolderIndex {
+    folder: PathBuf,
+    state: Arc<Mutex<IndexState>>,
+    on_change: ChangeCallback,
+    _watcher: Option<RecommendedWatcher>,
+}
+
+impl FolderIndex {
+    /// 指定フォルダのインデックス作成をバックグラウンドで開始する
+    ///
+    /// # Arguments
+    ///
+    /// * `folder` - 対象フォルダのパス
+    /// * `order` - 並び順
+    /// * `reverse` - 逆順にするかどうか
+    /// * `on_change` - 走査完了時やファイル変更時に呼ばれるコールバック
+    pub fn build(
+        folder: PathBuf,
+        order: SortOrder,
+        reverse: bool,
+        on_change: impl Fn() + Send + Sync + 'static,
+    ) -> Self {
+        let state = Arc::new(Mutex::new(IndexState {
+            order,
+            reverse,
+            ..Default::default()
+        }));
+        let on_change: ChangeCallback = Arc::new(on_change);
+
+        // 走査中の変更を取りこぼさないよう、監視を先に開始する
+        let watcher = Self::start_watcher(&folder, state.clone(), on_change.clone());
+
+        let scan_folder_path = folder.clone();
+        let scan_state = state.clone();
+        let scan_on_change = on_change.clone();
+        thread::spawn(move || {
+            let entries = scan_folder(&scan_folder_path, order, reverse);
+            println!(
+                "[FolderIndex] 走査完了: {} ({} 件)",
+                scan_folder_path.display(),
+                entries.len()
+            );
+
+            let mut state = scan_state.lock().unwrap();
+            state.entries = entries;
+            state.capture_loaded = order == SortOrder::CaptureTime;
+            state.ready = true;
+            let pending = std::mem::take(&mut state.pending);
+            for change in pending {
+                state.apply(change);
+            }
+            // 走査中に並び順が変更された場合に備えて並べ直す
+            state.sort();
+            let needs_capture_times = state.needs_capture_times();
+            drop(state);
+
+            if needs_capture_times {
+                Self::spawn_capture_time_load(scan_state, scan_on_change.clone());
+            }
+            scan_on_change();
+        });
+
+        Self {
+            folder,
+            state,
+            on_change,
+            _watcher: watcher,
+        }
+    }
+
+    /// 並び順を変更する
+    ///
+    /// 撮影日時順への変更でEXIFが未読み込みの場合は、読み込み完了後に
+    /// バックグラウンドで並べ替えます。
+    pub fn set_sort(&self, order: SortOrder, reverse: bool) {
+        let mut state = self.state.lock().unwrap();
+        if state.order == order && state.reverse == reverse {
+            return;
+        }
+        state.order = order;
+        state.reverse = reverse;
+        state.sort();
+        let needs_capture_times = state.needs_capture_times();
+        drop(state);
+
+        if needs_capture_times {
+            Self::spawn_capture_time_load(self.state.clone(), self.on_change.clone());
+        }
+        (self.on_change)();
+    }
+
+    fn spawn_capture_time_load(state: Arc<Mutex<IndexState>>, on_change: ChangeCallback) {
+        let paths: Vec<PathBuf> = state
+            .lock()
+            .unwrap()
+            .entries
+            .iter()
+            .map(|entry| entry.path.clone())
+            .collect();
+
+        thread::spawn(move || {
+            let captured: HashMap<PathBuf, SystemTime> = paths
+                .par_iter()
+                .filter_map(|path| read_capture_time(path).map(|time| (path.clone(), time)))
+                .collect();
+
+            let mut state = state.lock().unwrap();
+            for entry in state.entries.iter_mut() {
+                if let Some(time) = captured.get(&entry.path) {
+                    entry.captured = Some(*time);
+                }
+            }
+            state.capture_loaded = true;
+            state.sort();
+            drop(state);
+            on_change();
+        });
     }
 
-    let current = std::path::Path::new(&current_path);
-    if let Some(parent) = current.parent() {
-        if let Some(folder_images) = get_folder_images(parent.to_string_lossy().to_string()) {
-            if let Some(current_index) = folder_images.iter().position(|path| path == &current_path)
-            {
-                let prev_index = if current_index == 0 {
-                    folder_images.len() - 1
+    fn start_watcher(
+        folder: &Path,
+        state: Arc<Mutex<IndexState>>,
+        on_change: ChangeCallback,
+    ) -> Option<RecommendedWatcher> {
+        let handler = move |res: notify::Result<Event>| {
+            let event = match res {
+                Ok(event) => event,
+                Err(e) => {
+                    eprintln!("[FolderIndex] 監視エラー: {}", e);
+                    return;
+                }
+            };
+
+            let changes = Self::changes_from_event(event);
+            if changes.is_empty() {
+                return;
+            }
+
+            let mut state = state.lock().unwrap();
+            for change in changes {
+                if state.ready {
+                    state.apply(change);
                 } else {
-                    current_index - 1
-                };
-                return Some(folder_images[prev_index].clone());
+                    state.pending.push(change);
+                }
+            }
+            drop(state);
+            on_change();
+        };
+
+        let mut watcher = match notify::recommended_watcher(handler) {
+            Ok(watcher) => watcher,
+            Err(e) => {
+                eprintln!("[FolderIndex] 監視を開始できませんでした: {}", e);
+                return None;
+            }
+        };
+
+        if let Err(e) = watcher.watch(folder, RecursiveMode::NonRecursive) {
+            eprintln!(
+                "[FolderIndex] フォルダを監視できませんでした: {} ({})",
+                folder.display(),
+                e
+            );
+            return None;
+        }
+
+        Some(watcher)
+    }
+
+    /// 通知イベントをインデックスへの変更に変換する
+    fn changes_from_event(event: Event) -> Vec<IndexChange> {
+        let mut paths = event.paths.into_iter();
+        match event.kind {
+            EventKind::Create(_) => paths.map(IndexChange::Added).collect(),
+            EventKind::Remove(_) => paths.map(IndexChange::Removed).collect(),
+            EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+                paths.map(IndexChange::Removed).collect()
             }
+            EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+                paths.map(IndexChange::Added).collect()
+            }
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+                let mut changes = Vec::new();
+                if let Some(from) = paths.next() {
+                    changes.push(IndexChange::Removed(from));
+                }
+                if let Some(to) = paths.next() {
+                    changes.push(IndexChange::Added(to));
+                }
+                changes
+            }
+            EventKind::Modify(ModifyKind::Name(_)) => paths
+                .map(|path| {
+                    if path.exists() {
+                        IndexChange::Added(path)
+                    } else {
+                        IndexChange::Removed(path)
+                    }
+                })
+                .collect(),
+            // 内容・属性の変更（他のアプリによる上書きなど）は、更新日時・サイズを取得し直す
+            EventKind::Modify(_) => paths
+                .filter(|path| path.exists())
+                .map(IndexChange::Added)
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// インデックス対象のフォルダ
+    pub fn folder(&self) -> &Path {
+        &self.folder
+    }
+
+    /// 初回走査が完了しているかどうか
+    pub fn is_ready(&self) -> bool {
+        self.state.lock().unwrap().ready
+    }
+
+    /// インデックス内の画像数
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().entries.len()
+    }
+
+    /// インデックスが空かどうか
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// インデックス内のすべての画像パス（並び順）
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.state
+            .lock()
+            .unwrap()
+            .entries
+            .iter()
+            .map(|entry| entry.path.clone())
+            .collect()
+    }
+
+    /// 指定画像のインデックス内の位置（0始まり）
+    pub fn position(&self, path: &Path) -> Option<usize> {
+        self.state
+            .lock()
+            .unwrap()
+            .entries
+            .iter()
+            .position(|entry| entry.path == path)
+    }
+
+    /// 指定された画像の次の画像パスを取得（ループする）
+    pub fn next(&self, current: &Path) -> Option<PathBuf> {
+        self.step(current, 1)
+    }
+
+    /// 指定された画像の前の画像パスを取得（ループする）
+    pub fn previous(&self, current: &Path) -> Option<PathBuf> {
+        self.step(current, -1)
+    }
+
+    /// 指定された画像の前後`count`枚を近い順（次、前、2つ次、…）に取得する
+    ///
+    /// 先読み対象の決定に使用します。指定画像自身と重複は含みません。
+    pub fn neighbours(&self, current: &Path, count: usize) -> Vec<PathBuf> {
+        let state = self.state.lock().unwrap();
+        let len = state.entries.len() as isize;
+        let Some(index) = state.entries.iter().position(|entry| entry.path == current) else {
+            return Vec::new();
+        };
+
+        let mut paths: Vec<PathBuf> = Vec::new();
+        for distance in 1..=count as isize {
+            for offset in [distance, -distance] {
+                let target = (index as isize + offset).rem_euclid(len) as usize;
+                let path = &state.entries[target].path;
+                if target != index && !paths.contains(path) {
+                    paths.push(path.clone());
+                }
+            }
+        }
+        paths
+    }
+
+    fn step(&self, current: &Path, offset: isize) -> Option<PathBuf> {
+        let state = self.state.lock().unwrap();
+        let len = state.entries.len() as isize;
+        let index = state
+            .entries
+            .iter()
+            .position(|entry| entry.path == current)? as isize;
+        let target = (index + offset).rem_euclid(len) as usize;
+        Some(state.entries[target].path.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, size: u64, created_secs: u64) -> ImageEntry {
+        let created = std::time::UNIX_EPOCH + Duration::from_secs(created_secs);
+        ImageEntry {
+            path: PathBuf::from(name),
+            created,
+            modified: created,
+            size,
+            captured: None,
+        }
+    }
+
+    #[test]
+    fn test_natural_cmp_orders_numbers_by_value() {
+        assert_eq!(natural_cmp("IMG_2.jpg", "IMG_10.jpg"), Ordering::Less);
+        assert_eq!(natural_cmp("IMG_10.jpg", "IMG_9.jpg"), Ordering::Greater);
+        assert_eq!(natural_cmp("img_001.jpg", "IMG_1.jpg"), Ordering::Greater);
+        assert_eq!(natural_cmp("a.jpg", "B.jpg"), Ordering::Less);
+        assert_eq!(natural_cmp("IMG_2.jpg", "IMG_2.jpg"), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_identical_timestamps_fall_back_to_name() {
+        // カードからコピーした画像は作成日時が揃っていることが多い
+        let mut entries = [
+            entry("IMG_10.jpg", 10, 100),
+            entry("IMG_2.jpg", 20, 100),
+            entry("IMG_1.jpg", 30, 100),
+        ];
+        entries.sort_by(|a, b| compare_entries(a, b, SortOrder::Created, false));
+        let names: Vec<String> = entries.iter().map(|e| e.file_name()).collect();
+        assert_eq!(names, ["IMG_1.jpg", "IMG_2.jpg", "IMG_10.jpg"]);
+    }
+
+    #[test]
+    fn test_content_change_restats_entry() {
+        use notify::event::{DataChange, MetadataKind};
+
+        let dir = std::env::temp_dir().join(format!("vdi_nav_modify_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let existing = dir.join("a.jpg");
+        std::fs::write(&existing, b"jpg").unwrap();
+        let missing = dir.join("b.jpg");
+
+        // 上書きされたファイルは追加として取得し直し、既に無いファイルは無視する
+        for kind in [
+            ModifyKind::Data(DataChange::Content),
+            ModifyKind::Metadata(MetadataKind::WriteTime),
+            ModifyKind::Any,
+        ] {
+            let event = Event::new(EventKind::Modify(kind))
+                .add_path(existing.clone())
+                .add_path(missing.clone());
+            assert_eq!(
+                FolderIndex::changes_from_event(event),
+                [IndexChange::Added(existing.clone())]
+            );
+        }
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_reverse_size_order() {
+        let mut entries = [
+            entry("a.jpg", 10, 0),
+            entry("b.jpg", 30, 0),
+            entry("c.jpg", 20, 0),
+        ];
+        entries.sort_by(|a, b| compare_entries(a, b, SortOrder::Size, true));
+        let names: Vec<String> = entries.iter().map(|e| e.file_name()).collect();
+        assert_eq!(names, ["b.jpg", "c.jpg", "a.jpg"]);
+    }
+
+    #[test]
+    fn test_sort_order_from_cli_name() {
+        assert_eq!(
+            SortOrder::from_cli_name("exif"),
+            Some(SortOrder::CaptureTime)
+        );
+        assert_eq!(SortOrder::from_cli_name("Name"), Some(SortOrder::Name));
+        assert_eq!(SortOrder::from_cli_name("random"), None);
+        for order in SortOrder::ALL {
+            assert_eq!(SortOrder::from_cli_name(order.cli_name()), Some(order));
         }
     }
-    None
 }
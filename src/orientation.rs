@@ -0,0 +1,489 @@
+use crate::safe_write;
+use image::DynamicImage;
+use std::path::{Path, PathBuf};
+
+/// EXIF Orientationタグ（1〜8）で表される画像の向き
+///
+/// 内部的には「時計回りに`quarter_turns`×90度回転した後、必要なら左右反転する」
+/// 変換として扱います。`Default`は変換なし（EXIF値1）です。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Orientation {
+    quarter_turns: u8,
+    flipped: bool,
+}
+
+impl Orientation {
+    /// EXIF Orientationの値から作成する（範囲外は`None`）
+    pub fn from_exif(value: u32) -> Option<Self> {
+        let (quarter_turns, flipped) = match value {
+            1 => (0, false),
+            2 => (0, true),
+            3 => (2, false),
+            4 => (2, true),
+            5 => (1, true),
+            6 => (1, false),
+            7 => (3, true),
+            8 => (3, false),
+            _ => return None,
+        };
+        Some(Self {
+            quarter_turns,
+            flipped,
+        })
+    }
+
+    /// EXIF Orientationの値に変換する
+    pub fn to_exif(self) -> u32 {
+        match (self.quarter_turns, self.flipped) {
+            (0, false) => 1,
+            (0, true) => 2,
+            (2, false) => 3,
+            (2, true) => 4,
+            (1, true) => 5,
+            (1, false) => 6,
+            (3, true) => 7,
+            _ => 8,
+        }
+    }
+
+    /// 変換なし（EXIF値1）かどうか
+    pub fn is_identity(self) -> bool {
+        self == Self::default()
+    }
+
+    /// 表示用の説明（例: 「90°回転」「左右反転」）
+    pub fn label(self) -> &'static str {
+        match self.to_exif() {
+            1 => "回転なし",
+            2 => "左右反転",
+            3 => "180°回転",
+            4 => "上下反転",
+            5 => "90°回転 + 左右反転",
+            6 => "90°回転",
+            7 => "270°回転 + 左右反転",
+            _ => "270°回転",
+        }
+    }
+
+    /// この向きで表示された画像をさらに時計回りに90度回転した向きを返す
+    pub fn rotated_cw(self) -> Self {
+        // 反転後の回転は、反転前の逆回転と等しい
+        let quarter_turns = if self.flipped {
+            (self.quarter_turns + 3) % 4
+        } else {
+            (self.quarter_turns + 1) % 4
+        };
+        Self {
+            quarter_turns,
+            flipped: self.flipped,
+        }
+    }
+
+    /// 指定角度（90度単位）だけ時計回りに回転した向きを返す
+    pub fn rotated_by(self, angle: i32) -> Self {
+        let steps = angle.rem_euclid(360) / 90;
+        (0..steps).fold(self, |orientation, _| orientation.rotated_cw())
+    }
+
+    /// この向きで表示された画像をさらに左右反転した向きを返す
+    pub fn flipped_horizontally(self) -> Self {
+        Self {
+            quarter_turns: self.quarter_turns,
+            flipped: !self.flipped,
+        }
+    }
+
+    /// この向きで表示された画像をさらに上下反転した向きを返す
+    ///
+    /// 上下反転は左右反転の後に180度回転したものと等しくなります。
+    pub fn flipped_vertically(self) -> Self {
+        self.flipped_horizontally().rotated_by(180)
+    }
+
+    /// この向きで表示された画像に、さらに`next`の変換を加えた向きを返す
+    pub fn then(self, next: Orientation) -> Self {
+        let rotated = self.rotated_by(next.quarter_turns as i32 * 90);
+        if next.flipped {
+            rotated.flipped_horizontally()
+        } else {
+            rotated
+        }
+    }
+
+    /// 画素データにこの向きを適用し、正しい向きの画像を返す
+    pub fn apply(self, img: DynamicImage) -> DynamicImage {
+        let rotated = match self.quarter_turns {
+            1 => img.rotate90(),
+            2 => img.rotate180(),
+            3 => img.rotate270(),
+            _ => img,
+        };
+        if self.flipped {
+            rotated.fliph()
+        } else {
+            rotated
+        }
+    }
+}
+
+/// 画像に適用した向きの出所
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrientationSource {
+    /// サイドカーXMP（アプリで回転した結果）
+    Sidecar,
+    /// ファイルに埋め込まれたEXIF Orientationタグ
+    Exif,
+}
+
+/// 画像に適用した向き
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AppliedOrientation {
+    pub orientation: Orientation,
+    pub source: OrientationSource,
+}
+
+/// ファイル内容に埋め込まれたEXIFのOrientationタグを読み込む
+///
+/// JPEG・TIFF（カメラRAWを含む）・PNG・WebPのEXIFに対応します。
+pub fn read_exif_orientation(bytes: &[u8]) -> Option<Orientation> {
+    let exif = exif::Reader::new()
+        .read_from_container(&mut std::io::Cursor::new(bytes))
+        .ok()?;
+    let field = exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)?;
+    Orientation::from_exif(field.value.get_uint(0)?)
+}
+
+/// EXIF（TIFF形式）のIFD0にあるOrientationタグの値を書き換える
+///
+/// タグの追加は行わないため、EXIFにOrientationが無い場合は何もしません。
+///
+/// # Arguments
+///
+/// * `tiff` - `Exif\0\0`に続くTIFFヘッダー以降のデータ
+/// * `orientation` - 書き込む向き
+///
+/// # Returns
+///
+/// * `true` - 書き換えた場合
+/// * `false` - Orientationタグが見つからない場合
+pub fn set_exif_orientation(tiff: &mut [u8], orientation: Orientation) -> bool {
+    let little_endian = match tiff.get(0..2) {
+        Some(b"II") => true,
+        Some(b"MM") => false,
+        _ => return false,
+    };
+    let read_u16 = |bytes: &[u8], pos: usize| -> Option<u16> {
+        let b: [u8; 2] = bytes.get(pos..pos + 2)?.try_into().ok()?;
+        Some(if little_endian {
+            u16::from_le_bytes(b)
+        } else {
+            u16::from_be_bytes(b)
+        })
+    };
+    let read_u32 = |bytes: &[u8], pos: usize| -> Option<u32> {
+        let b: [u8; 4] = bytes.get(pos..pos + 4)?.try_into().ok()?;
+        Some(if little_endian {
+            u32::from_le_bytes(b)
+        } else {
+            u32::from_be_bytes(b)
+        })
+    };
+    let Some(ifd) = read_u32(tiff, 4).map(|offset| offset as usize) else {
+        return false;
+    };
+    let Some(count) = read_u16(tiff, ifd) else {
+        return false;
+    };
+
+    for i in 0..count as usize {
+        let entry = ifd + 2 + i * 12;
+        // タグ0x0112（Orientation）、型3（SHORT）
+        if read_u16(tiff, entry) == Some(0x0112) && read_u16(tiff, entry + 2) == Some(3) {
+            let value = orientation.to_exif() as u16;
+            let bytes = if little_endian {
+                value.to_le_bytes()
+            } else {
+                value.to_be_bytes()
+            };
+            return match tiff.get_mut(entry + 8..entry + 10) {
+                Some(slot) => {
+                    slot.copy_from_slice(&bytes);
+                    true
+                }
+                None => false,
+            };
+        }
+    }
+    false
+}
+
+/// 表示時に適用する向きを求める
+///
+/// サイドカーXMPがあれば優先し、なければ埋め込みEXIFのOrientationタグを使用します。
+///
+/// # Arguments
+///
+/// * `bytes` - 画像ファイルの内容
+/// * `path` - 画像ファイルのパス（サイドカーXMPの参照に使用）
+///
+/// # Returns
+///
+/// * `Some(AppliedOrientation)` - 適用する向きと出所
+/// * `None` - 向きの情報が無い場合
+pub fn resolve_orientation(bytes: &[u8], path: &Path) -> Option<AppliedOrientation> {
+    if let Some(orientation) = read_sidecar_orientation(path) {
+        return Some(AppliedOrientation {
+            orientation,
+            source: OrientationSource::Sidecar,
+        });
+    }
+    read_exif_orientation(bytes).map(|orientation| AppliedOrientation {
+        orientation,
+        source: OrientationSource::Exif,
+    })
+}
+
+/// 画像に対応するサイドカーXMPのパス（`<ファイル名>.xmp`）
+///
+/// 拡張子違いの同名ファイル（RAW+JPEGなど）で衝突しないよう、元の拡張子を残します。
+pub fn sidecar_path(image_path: &Path) -> PathBuf {
+    let mut name = image_path.as_os_str().to_os_string();
+    name.push(".xmp");
+    PathBuf::from(name)
+}
+
+/// サイドカーXMPに記録された向きを読み込む
+pub fn read_sidecar_orientation(image_path: &Path) -> Option<Orientation> {
+    let xmp = std::fs::read_to_string(sidecar_path(image_path)).ok()?;
+    parse_xmp_orientation(&xmp)
+}
+
+/// サイドカーXMPに向きを書き込む
+///
+/// 既存のサイドカーがあればOrientationのみを更新し、他の情報は保持します。
+///
+/// # Errors
+///
+/// * 既存のサイドカーの形式を解釈できない場合
+/// * ファイルの書き込みに失敗した場合
+pub fn write_sidecar_orientation(
+    image_path: &Path,
+    orientation: Orientation,
+) -> Result<(), String> {
+    let path = sidecar_path(image_path);
+    let xmp = match std::fs::read_to_string(&path) {
+        Ok(existing) => update_xmp_orientation(&existing, orientation).ok_or_else(|| {
+            format!(
+                "既存のサイドカーXMPを更新できませんでした: {}",
+                path.display()
+            )
+        })?,
+        Err(_) => new_xmp_packet(orientation),
+    };
+
+    safe_write::write_atomic(&path, xmp.as_bytes())
+        .map_err(|e| format!("サイドカーXMPの書き込みに失敗しました: {}", e))
+}
+
+fn new_xmp_packet(orientation: Orientation) -> String {
+    format!(
+        concat!(
+            "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n",
+            "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n",
+            " <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n",
+            "  <rdf:Description rdf:about=\"\"\n",
+            "    xmlns:tiff=\"http://ns.adobe.com/tiff/1.0/\"\n",
+            "   tiff:Orientation=\"{}\"/>\n",
+            " </rdf:RDF>\n",
+            "</x:xmpmeta>\n",
+            "<?xpacket end=\"w\"?>\n"
+        ),
+        orientation.to_exif()
+    )
+}
+
+/// XMPパケットから`tiff:Orientation`（属性形式・要素形式の両方）を取り出す
+fn parse_xmp_orientation(xmp: &str) -> Option<Orientation> {
+    let value = if let Some(start) = xmp.find("tiff:Orientation=\"") {
+        let rest = &xmp[start + "tiff:Orientation=\"".len()..];
+        &rest[..rest.find('"')?]
+    } else {
+        let start = xmp.find("<tiff:Orientation>")?;
+        let rest = &xmp[start + "<tiff:Orientation>".len()..];
+        &rest[..rest.find('<')?]
+    };
+    Orientation::from_exif(value.trim().parse().ok()?)
+}
+
+/// XMPパケットの`tiff:Orientation`を書き換えた文字列を返す（解釈できない場合は`None`）
+pub fn update_xmp_orientation(xmp: &str, orientation: Orientation) -> Option<String> {
+    let value = orientation.to_exif().to_string();
+
+    for (open, close) in [("tiff:Orientation=\"", "\""), ("<tiff:Orientation>", "<")] {
+        if let Some(start) = xmp.find(open) {
+            let value_start = start + open.len();
+            let value_end = value_start + xmp[value_start..].find(close)?;
+            return Some(format!(
+                "{}{}{}",
+                &xmp[..value_start],
+                value,
+                &xmp[value_end..]
+            ));
+        }
+    }
+
+    // Orientationが未記録の場合はDescriptionに属性として追加する
+    let start = xmp.find("<rdf:Description")? + "<rdf:Description".len();
+    let namespace = if xmp.contains("xmlns:tiff=") {
+        ""
+    } else {
+        " xmlns:tiff=\"http://ns.adobe.com/tiff/1.0/\""
+    };
+    Some(format!(
+        "{}{} tiff:Orientation=\"{}\"{}",
+        &xmp[..start],
+        namespace,
+        value,
+        &xmp[start..]
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GenericImageView, Rgb, RgbImage};
+
+    #[test]
+    fn test_exif_values_round_trip() {
+        for value in 1..=8 {
+            let orientation = Orientation::from_exif(value).unwrap();
+            assert_eq!(orientation.to_exif(), value);
+        }
+        assert!(Orientation::from_exif(0).is_none());
+        assert!(Orientation::from_exif(9).is_none());
+    }
+
+    #[test]
+    fn test_rotation_composition() {
+        let normal = Orientation::default();
+        assert_eq!(normal.rotated_cw().to_exif(), 6, "1を90度回転すると6");
+        assert_eq!(normal.rotated_by(180).to_exif(), 3);
+        assert_eq!(normal.rotated_by(270).to_exif(), 8);
+        assert_eq!(normal.rotated_by(360), normal, "4回転で元に戻る");
+
+        // 反転を含む向きの回転
+        let mirrored = Orientation::from_exif(2).unwrap();
+        assert_eq!(mirrored.rotated_cw().to_exif(), 7);
+        assert_eq!(mirrored.rotated_by(180).to_exif(), 4);
+    }
+
+    #[test]
+    fn test_apply_matches_rotated_composition() {
+        // 2x1の画像で、回転後の画素配置が合成結果と一致するか確認
+        let mut img = RgbImage::new(2, 1);
+        img.put_pixel(0, 0, Rgb([255, 0, 0]));
+        img.put_pixel(1, 0, Rgb([0, 0, 255]));
+        let img = DynamicImage::ImageRgb8(img);
+
+        for value in 1..=8 {
+            let orientation = Orientation::from_exif(value).unwrap();
+            let direct = orientation.rotated_cw().apply(img.clone());
+            let stepwise = orientation.apply(img.clone()).rotate90();
+            assert_eq!(
+                direct.to_rgb8().into_raw(),
+                stepwise.to_rgb8().into_raw(),
+                "向き{}の回転合成が画素配置と一致しない",
+                value
+            );
+            assert_eq!(direct.dimensions(), stepwise.dimensions());
+        }
+    }
+
+    #[test]
+    fn test_flip_and_then_match_pixels() {
+        // 3x2の画像で、反転・合成した向きが画素配置と一致するか確認
+        let img = DynamicImage::ImageRgb8(RgbImage::from_fn(3, 2, |x, y| {
+            Rgb([x as u8 * 80, y as u8 * 120, 7])
+        }));
+
+        for value in 1..=8 {
+            let orientation = Orientation::from_exif(value).unwrap();
+            let shown = orientation.apply(img.clone());
+            assert_eq!(
+                orientation
+                    .flipped_horizontally()
+                    .apply(img.clone())
+                    .to_rgb8(),
+                shown.fliph().to_rgb8(),
+                "向き{}の左右反転",
+                value
+            );
+            assert_eq!(
+                orientation
+                    .flipped_vertically()
+                    .apply(img.clone())
+                    .to_rgb8(),
+                shown.flipv().to_rgb8(),
+                "向き{}の上下反転",
+                value
+            );
+            for next in 1..=8 {
+                let next = Orientation::from_exif(next).unwrap();
+                assert_eq!(
+                    orientation.then(next).apply(img.clone()).to_rgb8(),
+                    next.apply(shown.clone()).to_rgb8()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_exif_orientation_from_jpeg() {
+        // APP1(EXIF)にOrientation=6のみを持つJPEG
+        let mut tiff = b"II*\0\x08\0\0\0\x01\0".to_vec();
+        tiff.extend_from_slice(&[0x12, 0x01, 0x03, 0x00, 0x01, 0x00, 0x00, 0x00]);
+        tiff.extend_from_slice(&[0x06, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        let mut app1 = b"Exif\0\0".to_vec();
+        app1.extend_from_slice(&tiff);
+
+        let mut jpeg = Vec::new();
+        DynamicImage::ImageRgb8(RgbImage::new(4, 2))
+            .write_to(
+                &mut std::io::Cursor::new(&mut jpeg),
+                image::ImageFormat::Jpeg,
+            )
+            .unwrap();
+        let mut tagged = jpeg[..2].to_vec();
+        tagged.extend_from_slice(&[0xFF, 0xE1]);
+        tagged.extend_from_slice(&(app1.len() as u16 + 2).to_be_bytes());
+        tagged.extend_from_slice(&app1);
+        tagged.extend_from_slice(&jpeg[2..]);
+
+        let applied = resolve_orientation(&tagged, Path::new("no-sidecar.jpg")).unwrap();
+        assert_eq!(applied.orientation.to_exif(), 6);
+        assert_eq!(applied.source, OrientationSource::Exif);
+        assert!(read_exif_orientation(&jpeg).is_none());
+    }
+
+    #[test]
+    fn test_xmp_orientation_update() {
+        let orientation = Orientation::from_exif(6).unwrap();
+        let packet = new_xmp_packet(orientation);
+        assert_eq!(parse_xmp_orientation(&packet), Some(orientation));
+
+        let updated = update_xmp_orientation(&packet, Orientation::from_exif(3).unwrap()).unwrap();
+        assert_eq!(parse_xmp_orientation(&updated).unwrap().to_exif(), 3);
+
+        // 要素形式のOrientation
+        let element = "<rdf:Description><tiff:Orientation>8</tiff:Orientation></rdf:Description>";
+        assert_eq!(parse_xmp_orientation(element).unwrap().to_exif(), 8);
+        let updated = update_xmp_orientation(element, Orientation::default()).unwrap();
+        assert!(updated.contains("<tiff:Orientation>1</tiff:Orientation>"));
+
+        // Orientationが無いサイドカーには属性を追加する
+        let other = "<rdf:Description rdf:about=\"\" xmp:Rating=\"5\"/>";
+        let updated = update_xmp_orientation(other, orientation).unwrap();
+        assert_eq!(parse_xmp_orientation(&updated), Some(orientation));
+        assert!(updated.contains("xmp:Rating=\"5\""));
+    }
+}
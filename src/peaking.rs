@@ -291,36 +291,49 @@ pub fn focus_peaking(
     threshold: u8,
     request_id: Option<String>,
 ) -> Result<PeakingResult, String> {
-    let total_start = Instant::now();
-
-    // ベースキーとユニークなリクエストIDを生成
-    let base_key = request_id.unwrap_or_else(|| format!("{}:{}", image_path, threshold));
-    let unique_request_id = generate_unique_request_id(&base_key);
-    println!("[Peaking] 新規リクエスト開始: {}", unique_request_id);
-
-    let cancel_flag = register_cancel_flag(&unique_request_id, &base_key);
-
     let path = Path::new(&image_path);
 
     // ファイル存在チェック
     if !path.exists() {
-        unregister_cancel_flag(&unique_request_id);
         return Err("File not found".to_string());
     }
 
     // 画像読み込み
     let load_start = Instant::now();
-    let img = image::open(path).map_err(|e| {
-        unregister_cancel_flag(&unique_request_id);
-        format!("Failed to load image: {}", e)
-    })?;
+    let img = crate::img::open_image(path).map_err(|e| format!("Failed to load image: {}", e))?;
+    println!("[Peaking] 画像読み込み: {:?}", load_start.elapsed());
+
+    let base_key = request_id.unwrap_or_else(|| format!("{}:{}", image_path, threshold));
+    focus_peaking_image(&img, threshold, base_key)
+}
+
+/// デコード済みの画像に対してフォーカスピーキング処理を行う
+///
+/// RAWのフル現像結果など、ファイルとは別に用意した画像を解析する場合に使用します。
+///
+/// # Arguments
+/// * `img` - 解析する画像
+/// * `threshold` - エッジ検出閾値 (0-255)
+/// * `request_id` - リクエストID（同じIDの古い処理はキャンセルされる）
+///
+/// # Returns
+/// * `Ok(PeakingResult)` - エッジ座標リスト
+/// * `Err(String)` - エラーメッセージ
+pub fn focus_peaking_image(
+    img: &DynamicImage,
+    threshold: u8,
+    request_id: String,
+) -> Result<PeakingResult, String> {
+    let total_start = Instant::now();
+
+    // ユニークなリクエストIDを生成
+    let base_key = request_id;
+    let unique_request_id = generate_unique_request_id(&base_key);
+    println!("[Peaking] 新規リクエスト開始: {}", unique_request_id);
+
+    let cancel_flag = register_cancel_flag(&unique_request_id, &base_key);
+
     let (original_width, original_height) = img.dimensions();
-    println!(
-        "[Peaking] 画像読み込み: {:?}, サイズ: {}x{}",
-        load_start.elapsed(),
-        original_width,
-        original_height
-    );
 
     // キャンセルチェック1
     if cancel_flag.load(Ordering::Relaxed) {
@@ -331,7 +344,7 @@ pub fn focus_peaking(
 
     // ダウンサンプリング（必要な場合）
     let downsample_start = Instant::now();
-    let (processing_img, scale) = downsample_if_needed(&img, DOWNSAMPLE_THRESHOLD);
+    let (processing_img, scale) = downsample_if_needed(img, DOWNSAMPLE_THRESHOLD);
     if scale.is_some() {
         println!(
             "[Peaking] ダウンサンプリング: {:?}",
@@ -0,0 +1,247 @@
+use image::{DynamicImage, ImageFormat};
+use std::path::Path;
+
+/// カメラRAWの拡張子
+pub const RAW_EXTENSIONS: &[&str] = &["dng", "cr2", "cr3", "nef", "arw"];
+
+/// 埋め込みJPEGのうち、デコード可能なSOFマーカー（ベースライン・拡張・プログレッシブ）
+///
+/// CR2やDNGのRAWデータ本体はロスレスJPEG（SOF3）で格納されているため除外します。
+const DECODABLE_SOF_MARKERS: &[u8] = &[0xC0, 0xC1, 0xC2];
+
+/// 埋め込みプレビューのみ表示でき、フル現像に対応しないRAWの拡張子
+///
+/// CR3（ISOBMFFコンテナ）は現像ライブラリが対応していません。
+pub const PREVIEW_ONLY_EXTENSIONS: &[&str] = &["cr3"];
+
+/// 拡張子がカメラRAWかどうか
+pub fn is_raw_path(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| {
+            let ext_str = ext.to_string_lossy().to_lowercase();
+            RAW_EXTENSIONS.contains(&ext_str.as_str())
+        })
+        .unwrap_or(false)
+}
+
+/// フル現像に対応するカメラRAWかどうか
+pub fn supports_full_decode(path: &Path) -> bool {
+    is_raw_path(path)
+        && path
+            .extension()
+            .map(|ext| {
+                let ext_str = ext.to_string_lossy().to_lowercase();
+                !PREVIEW_ONLY_EXTENSIONS.contains(&ext_str.as_str())
+            })
+            .unwrap_or(false)
+}
+
+/// RAWに埋め込まれたプレビューJPEGをデコードする
+///
+/// ファイル内で最も大きいデコード可能なJPEGを使用します。向きは適用しません
+/// （RAW本体のOrientationは`orientation::read_exif_orientation`で取得します）。
+///
+/// # Arguments
+///
+/// * `bytes` - RAWファイルの内容
+///
+/// # Returns
+///
+/// * `Ok(DynamicImage)` - プレビュー画像
+/// * `Err(String)` - エラーメッセージ
+pub fn decode_preview(bytes: &[u8]) -> Result<DynamicImage, String> {
+    let preview = find_embedded_preview(bytes)
+        .ok_or_else(|| "RAWに埋め込みプレビューが見つかりませんでした".to_string())?;
+    image::load_from_memory_with_format(preview, ImageFormat::Jpeg)
+        .map_err(|e| format!("RAWプレビューのデコードに失敗しました: {}", e))
+}
+
+/// RAWをフル現像（デモザイク）する
+///
+/// 時間がかかるため、バックグラウンドでの解析用途を想定しています。
+/// プレビューと同様に向きは適用しません。
+/// 中断要求は読み込み・現像の各段階の間で確認します（段階の途中では中断できません）。
+///
+/// # Arguments
+///
+/// * `path` - RAWファイルのパス
+/// * `cancelled` - 中断が要求されているかどうか
+///
+/// # Returns
+///
+/// * `Ok(DynamicImage)` - 16bit sRGBの現像結果
+/// * `Err(String)` - エラーメッセージ
+///
+/// # Errors
+///
+/// * フル現像に対応しない形式（CR3）の場合
+/// * 読み込み・現像に失敗した場合
+/// * 中断された場合
+pub fn decode_full(path: &Path, cancelled: impl Fn() -> bool) -> Result<DynamicImage, String> {
+    if !supports_full_decode(path) {
+        return Err(
+            "この形式のRAWはフル現像に対応していません（埋め込みプレビューのみ表示します）"
+                .to_string(),
+        );
+    }
+    let interrupted = || "RAWの現像を中断しました".to_string();
+    if cancelled() {
+        return Err(interrupted());
+    }
+    let mut raw =
+        rawloader::decode_file(path).map_err(|e| format!("RAWの読み込みに失敗しました: {}", e))?;
+    if cancelled() {
+        return Err(interrupted());
+    }
+    // 向きは呼び出し側でプレビューと同じ処理により適用するため、パイプラインでは回転させない
+    raw.orientation = rawloader::Orientation::Normal;
+
+    let mut pipeline = imagepipe::Pipeline::new_from_source(imagepipe::ImageSource::Raw(raw))
+        .map_err(|e| format!("RAW現像の準備に失敗しました: {}", e))?;
+    let developed = pipeline
+        .output_16bit(None)
+        .map_err(|e| format!("RAWの現像に失敗しました: {}", e))?;
+    if cancelled() {
+        return Err(interrupted());
+    }
+
+    image::ImageBuffer::from_raw(
+        developed.width as u32,
+        developed.height as u32,
+        developed.data,
+    )
+    .map(DynamicImage::ImageRgb16)
+    .ok_or_else(|| "RAWの現像結果が不正です".to_string())
+}
+
+/// ファイル内で最も大きいデコード可能な埋め込みJPEGを探す
+fn find_embedded_preview(bytes: &[u8]) -> Option<&[u8]> {
+    let mut best: Option<&[u8]> = None;
+    let mut pos = 0;
+
+    while pos + 3 <= bytes.len() {
+        if bytes[pos] != 0xFF || bytes[pos + 1] != 0xD8 || bytes[pos + 2] != 0xFF {
+            pos += 1;
+            continue;
+        }
+
+        match jpeg_extent(&bytes[pos..]) {
+            Some((len, sof)) => {
+                if DECODABLE_SOF_MARKERS.contains(&sof)
+                    && best.map(|b| len > b.len()).unwrap_or(true)
+                {
+                    best = Some(&bytes[pos..pos + len]);
+                }
+                // APP1内のサムネイルなど、入れ子のJPEGは読み飛ばす
+                pos += len;
+            }
+            None => pos += 1,
+        }
+    }
+
+    best
+}
+
+/// SOIから始まるJPEGの長さとSOFマーカーを求める（構造が壊れている場合は`None`）
+fn jpeg_extent(data: &[u8]) -> Option<(usize, u8)> {
+    let mut pos = 2;
+    let mut sof = None;
+
+    loop {
+        // マーカー前の埋め草（0xFF）を読み飛ばす
+        if *data.get(pos)? != 0xFF {
+            return None;
+        }
+        while *data.get(pos)? == 0xFF {
+            pos += 1;
+        }
+        let marker = *data.get(pos)?;
+        pos += 1;
+
+        match marker {
+            // EOI
+            0xD9 => return Some((pos, sof?)),
+            // 長さを持たないマーカー（RST、TEM）
+            0xD0..=0xD7 | 0x01 => continue,
+            _ => {}
+        }
+
+        let len = u16::from_be_bytes([*data.get(pos)?, *data.get(pos + 1)?]) as usize;
+        if len < 2 {
+            return None;
+        }
+        if matches!(marker, 0xC0..=0xCF) && !matches!(marker, 0xC4 | 0xC8 | 0xCC) {
+            sof.get_or_insert(marker);
+        }
+        pos += len;
+
+        if marker == 0xDA {
+            // エントロピー符号化データ：RST以外のマーカーが現れるまで進む
+            loop {
+                if *data.get(pos)? == 0xFF {
+                    let next = *data.get(pos + 1)?;
+                    if next != 0x00 && !(0xD0..=0xD7).contains(&next) {
+                        break;
+                    }
+                    pos += 2;
+                } else {
+                    pos += 1;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GenericImageView, Rgb, RgbImage};
+
+    fn encode_jpeg(width: u32, height: u32) -> Vec<u8> {
+        let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(width, height, Rgb([200, 80, 40])));
+        let mut bytes = Vec::new();
+        img.write_to(&mut std::io::Cursor::new(&mut bytes), ImageFormat::Jpeg)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_largest_embedded_preview_is_used() {
+        let thumbnail = encode_jpeg(16, 8);
+        let preview = encode_jpeg(64, 32);
+
+        // ダミーのヘッダー・RAWデータの間にサムネイルとプレビューを配置
+        let mut raw = b"II*\0dummy raw header".to_vec();
+        raw.extend_from_slice(&thumbnail);
+        raw.extend_from_slice(&[0xFF, 0xD8, 0x12, 0x34, 0x00]);
+        raw.extend_from_slice(&preview);
+        raw.extend_from_slice(&[0u8; 32]);
+
+        let found = find_embedded_preview(&raw).unwrap();
+        assert_eq!(found, preview.as_slice());
+        assert_eq!(decode_preview(&raw).unwrap().dimensions(), (64, 32));
+    }
+
+    #[test]
+    fn test_lossless_jpeg_is_skipped() {
+        // SOF3（ロスレス）のみのJPEG構造はプレビューとして扱わない
+        let lossless = [
+            0xFF, 0xD8, 0xFF, 0xC3, 0x00, 0x02, 0xFF, 0xDA, 0x00, 0x02, 0x12, 0x34, 0xFF, 0xD9,
+        ];
+        assert_eq!(jpeg_extent(&lossless), Some((lossless.len(), 0xC3)));
+        assert!(find_embedded_preview(&lossless).is_none());
+    }
+
+    #[test]
+    fn test_raw_extensions() {
+        assert!(is_raw_path(Path::new("IMG_0001.CR3")));
+        assert!(supports_full_decode(Path::new("IMG_0001.CR2")));
+        assert!(!supports_full_decode(Path::new("IMG_0001.CR3")));
+        assert!(!supports_full_decode(Path::new("photo.jpg")));
+        assert!(decode_full(Path::new("IMG_0001.CR3"), || false)
+            .unwrap_err()
+            .contains("埋め込みプレビュー"));
+        assert!(is_raw_path(Path::new("DSC_0001.nef")));
+        assert!(!is_raw_path(Path::new("photo.jpg")));
+    }
+}
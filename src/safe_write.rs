@@ -0,0 +1,238 @@
+use crate::orientation;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+
+/// 一時ファイル名の衝突を避けるための連番
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// ファイルの更新を検出するためのスタンプ（更新日時とサイズ）
+///
+/// 表示の向きはサイドカーXMPでも変わるため、サイドカーの更新日時も含めます。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStamp {
+    pub modified: SystemTime,
+    pub len: u64,
+    pub sidecar_modified: Option<SystemTime>,
+}
+
+impl FileStamp {
+    /// ファイルの現在のスタンプを取得する
+    pub fn read(path: &Path) -> Option<Self> {
+        let metadata = std::fs::metadata(path).ok()?;
+        let sidecar_modified = std::fs::metadata(orientation::sidecar_path(path))
+            .and_then(|m| m.modified())
+            .ok();
+        Some(Self {
+            modified: metadata.modified().ok()?,
+            len: metadata.len(),
+            sidecar_modified,
+        })
+    }
+}
+
+/// ファイルが読み込み時から変更されていないことを確認する
+///
+/// # Errors
+///
+/// * 現在のスタンプが`expected`と異なる場合（削除された場合を含む）
+pub fn ensure_unchanged(path: &Path, expected: FileStamp) -> Result<(), String> {
+    if FileStamp::read(path) == Some(expected) {
+        Ok(())
+    } else {
+        Err(format!(
+            "読み込み後にファイルが変更されたため、上書きを中止しました: {}",
+            path.display()
+        ))
+    }
+}
+
+/// ファイルの内容を安全に置き換える
+///
+/// 同じディレクトリの一時ファイルに書き込んで同期した後、元のパスへ名前を変更します。
+/// 書き込み中にクラッシュやディスク不足が起きても、元のファイルは壊れません。
+/// 既存のファイルのパーミッションは引き継ぎます。
+///
+/// # Arguments
+///
+/// * `path` - 書き込み先のパス
+/// * `contents` - 書き込む内容
+///
+/// # Errors
+///
+/// * 一時ファイルの作成・書き込み・同期に失敗した場合
+/// * 名前の変更に失敗した場合
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), String> {
+    let temp_path = temp_path_for(path)?;
+    let result = write_and_sync(&temp_path, path, contents)
+        .and_then(|_| std::fs::rename(&temp_path, path))
+        .map_err(|e| {
+            format!(
+                "ファイルの書き込みに失敗しました: {} ({})",
+                path.display(),
+                e
+            )
+        });
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+        return result;
+    }
+    sync_parent_dir(path);
+    Ok(())
+}
+
+/// 読み込み時から変更されていない場合のみ、ファイルの内容を安全に置き換える
+///
+/// `expected`が`None`の場合は変更の確認を行わず、`write_atomic`と同じ処理を行います。
+///
+/// # Errors
+///
+/// * `ensure_unchanged`・`write_atomic`のエラー
+pub fn replace_file(
+    path: &Path,
+    contents: &[u8],
+    expected: Option<FileStamp>,
+) -> Result<(), String> {
+    if let Some(expected) = expected {
+        ensure_unchanged(path, expected)?;
+    }
+    write_atomic(path, contents)
+}
+
+/// 大文字・小文字の違いを無視して、パスを比較用の文字列にする
+///
+/// 既存のファイルは正規化し、存在しないファイルは親フォルダを正規化します。
+/// 大文字・小文字を区別しないファイルシステム（Windows・macOSの既定）で同じファイルに
+/// なるパスは同じ値になります。区別するファイルシステムでは別のファイルも同じ値になり
+/// ますが、上書きの防止には安全側の判定になります。
+pub fn path_key(path: &Path) -> String {
+    let canonical =
+        std::fs::canonicalize(path).unwrap_or_else(|_| match (path.parent(), path.file_name()) {
+            (Some(parent), Some(name)) => std::fs::canonicalize(if parent.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                parent
+            })
+            .map(|parent| parent.join(name))
+            .unwrap_or_else(|_| path.to_path_buf()),
+            _ => path.to_path_buf(),
+        });
+    canonical.to_string_lossy().to_lowercase()
+}
+
+/// 書き込み先と同じディレクトリの一時ファイルパス（`.<ファイル名>.<プロセスID>-<連番>.tmp`）
+fn temp_path_for(path: &Path) -> Result<PathBuf, String> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| format!("ファイル名がありません: {}", path.display()))?;
+    let temp_name = format!(
+        ".{}.{}-{}.tmp",
+        name.to_string_lossy(),
+        std::process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    );
+    Ok(path.with_file_name(temp_name))
+}
+
+fn write_and_sync(temp_path: &Path, path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut file = File::create(temp_path)?;
+    file.write_all(contents)?;
+    if let Ok(metadata) = std::fs::metadata(path) {
+        file.set_permissions(metadata.permissions())?;
+    }
+    file.sync_all()
+}
+
+/// 名前の変更を確定させるため、親ディレクトリを同期する（対応するOSのみ）
+fn sync_parent_dir(path: &Path) {
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        if let Ok(dir) = File::open(dir) {
+            let _ = dir.sync_all();
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("vdi_safe_write_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_write_atomic_replaces_contents() {
+        let dir = temp_dir("replace");
+        let path = dir.join("a.bin");
+        std::fs::write(&path, b"old").unwrap();
+
+        write_atomic(&path, b"new contents").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"new contents");
+
+        // 一時ファイルは残らない
+        let entries: Vec<_> = std::fs::read_dir(&dir).unwrap().collect();
+        assert_eq!(entries.len(), 1);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_replace_file_refuses_changed_source() {
+        let dir = temp_dir("changed");
+        let path = dir.join("a.bin");
+        std::fs::write(&path, b"loaded").unwrap();
+        let stamp = FileStamp::read(&path).unwrap();
+
+        // 読み込み後に他のアプリがサイズを変えて書き換えた
+        std::fs::write(&path, b"changed elsewhere").unwrap();
+        assert!(replace_file(&path, b"ours", Some(stamp)).is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), b"changed elsewhere");
+
+        // 最新のスタンプであれば置き換えられる
+        let stamp = FileStamp::read(&path).unwrap();
+        replace_file(&path, b"ours", Some(stamp)).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"ours");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_path_key() {
+        let dir = temp_dir("same");
+        let path = dir.join("IMG_1.JPG");
+        std::fs::write(&path, b"jpeg").unwrap();
+
+        let key = path_key(&path);
+        assert_eq!(path_key(&dir.join("IMG_1.jpg")), key);
+        assert_eq!(path_key(&dir.join(".").join("IMG_1.JPG")), key);
+        assert_ne!(path_key(&dir.join("IMG_1_export.jpg")), key);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_atomic_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = temp_dir("permissions");
+        let path = dir.join("a.bin");
+        std::fs::write(&path, b"old").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o640)).unwrap();
+
+        write_atomic(&path, b"new").unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,6 +1,14 @@
+use crate::display::ToneMap;
+use crate::export::ExportOptions;
+use crate::img::RotationMode;
+use crate::letterbox::Letterbox;
+use crate::lut::Interpolation;
+use crate::navigation::SortOrder;
+use crate::safe_write;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct AppSettings {
     // ピーキング設定
     pub peaking_threshold: u8,
@@ -20,6 +28,48 @@ pub struct AppSettings {
     pub histogram_size: f32,
     pub histogram_opacity: f32,
     pub histogram_position: HistogramPosition,
+    pub histogram_space: HistogramSpace,
+    /// 表示調整（露出・コントラストなど）を適用した値を集計するか
+    pub histogram_adjusted: bool,
+
+    // ナビゲーション設定
+    pub sort_order: SortOrder,
+    pub sort_reverse: bool,
+
+    // 先読みキャッシュ設定
+    pub cache_size_mb: usize,
+    pub prefetch_count: usize,
+
+    // RAW設定
+    pub raw_full_decode: bool,
+
+    // 回転設定
+    pub rotation_mode: RotationMode,
+    pub jpeg_trim_edges: bool,
+
+    // 角度補正設定
+    pub straighten_crop: bool,
+
+    // 黒帯設定
+    pub letterbox: Letterbox,
+
+    // LUT設定
+    pub lut_interpolation: Interpolation,
+    /// 最後に読み込んだLUTのフォルダ（ファイル選択の初期位置）
+    pub lut_dir: Option<String>,
+
+    // 書き出し設定
+    pub export: ExportOptions,
+
+    // 編集履歴設定
+    pub history_quota_mb: u64,
+
+    // 表示設定
+    pub tone_map: ToneMap,
+
+    // カラーマネジメント設定
+    pub color_management: bool,
+    pub display_icc_path: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -30,6 +80,15 @@ pub enum GridPattern {
     Grid8x8,
 }
 
+/// ヒストグラムを計算する色空間
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum HistogramSpace {
+    /// 画像本来の値（埋め込みプロファイルの色空間）
+    Source,
+    /// 表示プロファイルへ変換した値
+    Display,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum HistogramPosition {
     TopLeft,
@@ -52,6 +111,24 @@ impl Default for AppSettings {
             histogram_size: 1.0,
             histogram_opacity: 0.9,
             histogram_position: HistogramPosition::BottomRight,
+            histogram_space: HistogramSpace::Source,
+            histogram_adjusted: false,
+            sort_order: SortOrder::Created,
+            sort_reverse: false,
+            cache_size_mb: 1024,
+            prefetch_count: 2,
+            raw_full_decode: false,
+            rotation_mode: RotationMode::Pixels,
+            jpeg_trim_edges: false,
+            straighten_crop: true,
+            letterbox: Letterbox::default(),
+            lut_interpolation: Interpolation::default(),
+            lut_dir: None,
+            export: ExportOptions::default(),
+            history_quota_mb: 1024,
+            tone_map: ToneMap::Clip,
+            color_management: true,
+            display_icc_path: None,
         }
     }
 }
@@ -79,7 +156,7 @@ impl AppSettings {
             let settings_path = settings_dir.join("settings.json");
 
             if let Ok(json) = serde_json::to_string_pretty(self) {
-                let _ = std::fs::write(&settings_path, json);
+                let _ = safe_write::write_atomic(&settings_path, json.as_bytes());
             }
         }
     }
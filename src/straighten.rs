@@ -0,0 +1,257 @@
+use image::{ColorType, DynamicImage, ImageBuffer};
+use rayon::prelude::*;
+
+/// 角度補正で指定できる最大角度（度、±）
+pub const MAX_ANGLE: f32 = 45.0;
+/// 角度補正の刻み（度）
+pub const ANGLE_STEP: f32 = 0.1;
+
+/// 幅`width`・高さ`height`の画像を`angle`度回転したときの外接矩形のサイズ
+pub fn expanded_size(width: f32, height: f32, angle: f32) -> (f32, f32) {
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let (sin, cos) = (sin.abs(), cos.abs());
+    (width * cos + height * sin, width * sin + height * cos)
+}
+
+/// 幅`width`・高さ`height`の画像を`angle`度回転したとき、内側に収まる
+/// 最大面積の軸に平行な矩形のサイズ
+///
+/// 回転後の画像をこのサイズで中央から切り抜くと、透明な角が現れません。
+pub fn inscribed_size(width: f32, height: f32, angle: f32) -> (f32, f32) {
+    if width <= 0.0 || height <= 0.0 {
+        return (0.0, 0.0);
+    }
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let (sin, cos) = (sin.abs(), cos.abs());
+    let (long, short) = if width >= height {
+        (width, height)
+    } else {
+        (height, width)
+    };
+
+    if short <= 2.0 * sin * cos * long || (sin - cos).abs() < 1e-10 {
+        // 短辺側で制約される場合（矩形の2頂点が長辺に接する）
+        let half = 0.5 * short;
+        if width >= height {
+            (half / sin, half / cos)
+        } else {
+            (half / cos, half / sin)
+        }
+    } else {
+        // 4頂点がすべて回転後の画像の辺に接する場合
+        let cos_2a = cos * cos - sin * sin;
+        (
+            (width * cos - height * sin) / cos_2a,
+            (height * cos - width * sin) / cos_2a,
+        )
+    }
+}
+
+/// 画像を時計回りに`angle`度回転する（双線形補間）
+///
+/// # Arguments
+///
+/// * `img` - 元の画像
+/// * `angle` - 回転角度（度、時計回りが正）
+/// * `crop` - `true`なら最大内接矩形で切り抜き、`false`なら外接矩形に広げて角を透明にする
+///
+/// # Returns
+///
+/// 回転後の画像。切り抜く場合は元と同じ色形式、広げる場合はアルファ付きの同じビット深度です。
+pub fn straighten(img: &DynamicImage, angle: f32, crop: bool) -> DynamicImage {
+    let (width, height) = (img.width() as f32, img.height() as f32);
+    let (out_w, out_h) = if crop {
+        let (w, h) = inscribed_size(width, height, angle);
+        // 浮動小数点の誤差で角が入らないよう切り捨てる
+        (w.floor(), h.floor())
+    } else {
+        let (w, h) = expanded_size(width, height, angle);
+        (w.ceil(), h.ceil())
+    };
+    let (out_w, out_h) = ((out_w as u32).max(1), (out_h as u32).max(1));
+
+    let src_size = (img.width(), img.height());
+    let out_size = (out_w, out_h);
+    let rotated = match img.color() {
+        ColorType::L8 | ColorType::La8 | ColorType::Rgb8 | ColorType::Rgba8 => {
+            let data = warp(
+                img.to_rgba8().as_raw(),
+                src_size,
+                out_size,
+                angle,
+                |v| v as f32 / 255.0,
+                |v| (v * 255.0).round().clamp(0.0, 255.0) as u8,
+            );
+            DynamicImage::ImageRgba8(ImageBuffer::from_raw(out_w, out_h, data).unwrap())
+        }
+        ColorType::Rgb32F | ColorType::Rgba32F => {
+            let data = warp(
+                img.to_rgba32f().as_raw(),
+                src_size,
+                out_size,
+                angle,
+                |v| v,
+                |v| v,
+            );
+            DynamicImage::ImageRgba32F(ImageBuffer::from_raw(out_w, out_h, data).unwrap())
+        }
+        _ => {
+            let data = warp(
+                img.to_rgba16().as_raw(),
+                src_size,
+                out_size,
+                angle,
+                |v| v as f32 / 65535.0,
+                |v| (v * 65535.0).round().clamp(0.0, 65535.0) as u16,
+            );
+            DynamicImage::ImageRgba16(ImageBuffer::from_raw(out_w, out_h, data).unwrap())
+        }
+    };
+
+    if crop {
+        convert_to(rotated, img.color())
+    } else {
+        rotated
+    }
+}
+
+/// 出力画像の各画素に対応する元画像の位置を双線形補間で求める
+///
+/// `src`・戻り値はRGBAの画素を並べたバッファです。
+/// 範囲外は透明として扱い、色はアルファで重み付けして補間します。
+fn warp<T: Copy + Send + Sync>(
+    src: &[T],
+    (src_w, src_h): (u32, u32),
+    (out_w, out_h): (u32, u32),
+    angle: f32,
+    to_f32: impl Fn(T) -> f32 + Sync,
+    from_f32: impl Fn(f32) -> T + Sync,
+) -> Vec<T> {
+    let (src_w, src_h) = (src_w as i64, src_h as i64);
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let src_center = (src_w as f32 / 2.0, src_h as f32 / 2.0);
+    let out_center = (out_w as f32 / 2.0, out_h as f32 / 2.0);
+    let zero = from_f32(0.0);
+
+    let texel = |x: i64, y: i64| -> [f32; 4] {
+        if x < 0 || y < 0 || x >= src_w || y >= src_h {
+            return [0.0; 4];
+        }
+        let i = ((y * src_w + x) * 4) as usize;
+        [
+            to_f32(src[i]),
+            to_f32(src[i + 1]),
+            to_f32(src[i + 2]),
+            to_f32(src[i + 3]),
+        ]
+    };
+
+    let mut data = vec![zero; out_w as usize * out_h as usize * 4];
+    data.par_chunks_mut(out_w as usize * 4)
+        .enumerate()
+        .for_each(|(oy, row)| {
+            for (ox, px) in row.chunks_mut(4).enumerate() {
+                // 出力の画素中心を逆回転して元画像の座標を求める
+                let dx = ox as f32 + 0.5 - out_center.0;
+                let dy = oy as f32 + 0.5 - out_center.1;
+                let sx = dx * cos + dy * sin + src_center.0 - 0.5;
+                let sy = -dx * sin + dy * cos + src_center.1 - 0.5;
+
+                let (x0, y0) = (sx.floor(), sy.floor());
+                let (fx, fy) = (sx - x0, sy - y0);
+                let (x0, y0) = (x0 as i64, y0 as i64);
+                let mut sum = [0.0f32; 4];
+                for (x, y, weight) in [
+                    (x0, y0, (1.0 - fx) * (1.0 - fy)),
+                    (x0 + 1, y0, fx * (1.0 - fy)),
+                    (x0, y0 + 1, (1.0 - fx) * fy),
+                    (x0 + 1, y0 + 1, fx * fy),
+                ] {
+                    let t = texel(x, y);
+                    let alpha = t[3] * weight;
+                    sum[0] += t[0] * alpha;
+                    sum[1] += t[1] * alpha;
+                    sum[2] += t[2] * alpha;
+                    sum[3] += alpha;
+                }
+                if sum[3] > 0.0 {
+                    px[0] = from_f32(sum[0] / sum[3]);
+                    px[1] = from_f32(sum[1] / sum[3]);
+                    px[2] = from_f32(sum[2] / sum[3]);
+                    px[3] = from_f32(sum[3]);
+                }
+            }
+        });
+    data
+}
+
+/// 指定した色形式に変換する（対応しない形式はそのまま返す）
+fn convert_to(img: DynamicImage, color: ColorType) -> DynamicImage {
+    match color {
+        ColorType::L8 => DynamicImage::ImageLuma8(img.to_luma8()),
+        ColorType::La8 => DynamicImage::ImageLumaA8(img.to_luma_alpha8()),
+        ColorType::Rgb8 => DynamicImage::ImageRgb8(img.to_rgb8()),
+        ColorType::Rgba8 => DynamicImage::ImageRgba8(img.to_rgba8()),
+        ColorType::L16 => DynamicImage::ImageLuma16(img.to_luma16()),
+        ColorType::La16 => DynamicImage::ImageLumaA16(img.to_luma_alpha16()),
+        ColorType::Rgb16 => DynamicImage::ImageRgb16(img.to_rgb16()),
+        ColorType::Rgba16 => DynamicImage::ImageRgba16(img.to_rgba16()),
+        ColorType::Rgb32F => DynamicImage::ImageRgb32F(img.to_rgb32f()),
+        ColorType::Rgba32F => DynamicImage::ImageRgba32F(img.to_rgba32f()),
+        _ => img,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GenericImageView, Rgb, RgbImage};
+
+    fn close(a: (f32, f32), b: (f32, f32)) -> bool {
+        (a.0 - b.0).abs() < 1e-3 && (a.1 - b.1).abs() < 1e-3
+    }
+
+    #[test]
+    fn test_inscribed_size() {
+        assert!(close(inscribed_size(400.0, 300.0, 0.0), (400.0, 300.0)));
+        // 正方形を45度回転すると、内接する正方形の辺は1/√2
+        let side = 100.0 / 2f32.sqrt();
+        assert!(close(inscribed_size(100.0, 100.0, 45.0), (side, side)));
+        // 回転方向と縦横の入れ替えに対して対称
+        let (w, h) = inscribed_size(400.0, 300.0, 10.0);
+        assert!(close(inscribed_size(400.0, 300.0, -10.0), (w, h)));
+        assert!(close(inscribed_size(300.0, 400.0, 10.0), (h, w)));
+        assert!(w < 400.0 && h < 300.0);
+    }
+
+    #[test]
+    fn test_straighten_crop_has_no_transparent_corners() {
+        let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(120, 80, Rgb([200, 100, 50])));
+
+        let cropped = straighten(&img, 7.5, true);
+        assert_eq!(cropped.color(), ColorType::Rgb8);
+        let (w, h) = inscribed_size(120.0, 80.0, 7.5);
+        assert_eq!(cropped.dimensions(), (w.floor() as u32, h.floor() as u32));
+        // 切り抜き範囲はすべて元画像の内側（ほぼ元の色）
+        let cropped = cropped.to_rgb8();
+        assert!(cropped.pixels().all(|p| p
+            .0
+            .iter()
+            .zip([200, 100, 50])
+            .all(|(a, b)| a.abs_diff(b) <= 1)));
+
+        let expanded = straighten(&img, 7.5, false).to_rgba8();
+        assert!(expanded.width() > 120 && expanded.height() > 80);
+        assert_eq!(expanded.get_pixel(0, 0).0[3], 0, "角は透明");
+        let center = expanded.get_pixel(expanded.width() / 2, expanded.height() / 2);
+        assert_eq!(center.0, [200, 100, 50, 255]);
+    }
+
+    #[test]
+    fn test_straighten_zero_keeps_pixels() {
+        let img = DynamicImage::ImageRgb8(RgbImage::from_fn(9, 5, |x, y| {
+            Rgb([x as u8 * 20, y as u8 * 40, 90])
+        }));
+        assert_eq!(straighten(&img, 0.0, true).to_rgb8(), img.to_rgb8());
+    }
+}
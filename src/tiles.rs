@@ -0,0 +1,399 @@
+use eframe::egui;
+use egui::epaint::{Mesh, Vertex};
+use egui::{Color32, ColorImage};
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// タイル1枚の一辺（ピクセル）
+///
+/// GPUのテクスチャ上限（一般的に8192〜16384）より十分小さくし、転送単位を細かくします。
+pub const TILE_SIZE: usize = 1024;
+
+/// 1フレームで転送するタイル数の上限（超過分は次のフレームに回す）
+const MAX_UPLOADS_PER_FRAME: usize = 8;
+
+/// 表示用画像のミップピラミッド
+///
+/// レベル0が原寸で、以降は1/2ずつ縮小し、最後のレベルは1枚のタイルに収まります。
+pub struct MipPyramid {
+    levels: Vec<ColorImage>,
+}
+
+impl MipPyramid {
+    /// 原寸の表示用画像からピラミッドを作成する
+    pub fn new(base: ColorImage) -> Self {
+        let mut levels = vec![base];
+        while let Some(last) = levels.last() {
+            if last.size[0] <= TILE_SIZE && last.size[1] <= TILE_SIZE {
+                break;
+            }
+            let next = downsample(last);
+            levels.push(next);
+        }
+        Self { levels }
+    }
+
+    /// 原寸のサイズ（幅, 高さ）
+    pub fn size(&self) -> [usize; 2] {
+        self.levels[0].size
+    }
+
+    /// レベル数
+    pub fn level_count(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// 保持に必要なおおよそのメモリ量（バイト）
+    pub fn memory_size(&self) -> usize {
+        self.levels.iter().map(|level| level.pixels.len() * 4).sum()
+    }
+
+    /// 表示倍率（画面の物理ピクセル / 原寸ピクセル）に適したレベル
+    ///
+    /// 表示倍率以上の解像度を持つ、最も小さいレベルを選びます。
+    pub fn level_for_scale(&self, scale: f32) -> usize {
+        if scale >= 1.0 || scale <= 0.0 {
+            return 0;
+        }
+        let level = (1.0 / scale).log2().floor() as usize;
+        level.min(self.levels.len() - 1)
+    }
+
+    /// 指定レベルのタイル数（横, 縦）
+    fn tile_grid(&self, level: usize) -> (usize, usize) {
+        let [w, h] = self.levels[level].size;
+        (w.div_ceil(TILE_SIZE), h.div_ceil(TILE_SIZE))
+    }
+
+    /// タイルの範囲（レベル内のピクセル座標 x0, y0, x1, y1）
+    fn tile_bounds(&self, key: TileKey) -> [usize; 4] {
+        let [w, h] = self.levels[key.level].size;
+        let x0 = key.x * TILE_SIZE;
+        let y0 = key.y * TILE_SIZE;
+        [x0, y0, (x0 + TILE_SIZE).min(w), (y0 + TILE_SIZE).min(h)]
+    }
+
+    /// タイルの画素を切り出す
+    ///
+    /// 隣接タイルとの境界で線形補間が途切れないよう、周囲1ピクセルを含めて切り出します。
+    /// 戻り値のUV範囲は、切り出した画像のうちタイル本体に当たる部分です。
+    fn tile_image(&self, key: TileKey) -> (ColorImage, egui::Rect) {
+        let level = &self.levels[key.level];
+        let [w, h] = level.size;
+        let [x0, y0, x1, y1] = self.tile_bounds(key);
+        let (ex0, ey0) = (x0.saturating_sub(1), y0.saturating_sub(1));
+        let (ex1, ey1) = ((x1 + 1).min(w), (y1 + 1).min(h));
+        let (tw, th) = (ex1 - ex0, ey1 - ey0);
+
+        let mut pixels = Vec::with_capacity(tw * th);
+        for y in ey0..ey1 {
+            pixels.extend_from_slice(&level.pixels[y * w + ex0..y * w + ex1]);
+        }
+        let uv = egui::Rect::from_min_max(
+            egui::pos2((x0 - ex0) as f32 / tw as f32, (y0 - ey0) as f32 / th as f32),
+            egui::pos2((x1 - ex0) as f32 / tw as f32, (y1 - ey0) as f32 / th as f32),
+        );
+        (
+            ColorImage {
+                size: [tw, th],
+                pixels,
+            },
+            uv,
+        )
+    }
+
+    /// タイルが覆う範囲（画像全体を0〜1とした座標）
+    fn tile_region(&self, key: TileKey) -> egui::Rect {
+        let [w, h] = self.levels[key.level].size;
+        let [x0, y0, x1, y1] = self.tile_bounds(key);
+        egui::Rect::from_min_max(
+            egui::pos2(x0 as f32 / w as f32, y0 as f32 / h as f32),
+            egui::pos2(x1 as f32 / w as f32, y1 as f32 / h as f32),
+        )
+    }
+}
+
+/// 2x2の平均で半分のサイズに縮小する（奇数サイズの端は端の画素を繰り返す）
+fn downsample(src: &ColorImage) -> ColorImage {
+    let [w, h] = src.size;
+    let (nw, nh) = (w.div_ceil(2), h.div_ceil(2));
+    let pixels: Vec<Color32> = (0..nh)
+        .into_par_iter()
+        .flat_map_iter(|y| {
+            let (y0, y1) = (2 * y, (2 * y + 1).min(h - 1));
+            (0..nw).map(move |x| {
+                let (x0, x1) = (2 * x, (2 * x + 1).min(w - 1));
+                let samples = [
+                    src.pixels[y0 * w + x0].to_array(),
+                    src.pixels[y0 * w + x1].to_array(),
+                    src.pixels[y1 * w + x0].to_array(),
+                    src.pixels[y1 * w + x1].to_array(),
+                ];
+                let avg =
+                    |c: usize| ((samples.iter().map(|s| s[c] as u32).sum::<u32>() + 2) / 4) as u8;
+                // Color32は乗算済みアルファのため、そのまま平均できる
+                Color32::from_rgba_premultiplied(avg(0), avg(1), avg(2), avg(3))
+            })
+        })
+        .collect();
+    ColorImage {
+        size: [nw, nh],
+        pixels,
+    }
+}
+
+/// タイルの識別子（レベル, 列, 行）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct TileKey {
+    level: usize,
+    x: usize,
+    y: usize,
+}
+
+/// 画面上での画像の配置（中心・回転前のサイズ・時計回りの回転角）
+#[derive(Debug, Clone, Copy)]
+pub struct ImagePlacement {
+    pub center: egui::Pos2,
+    pub size: egui::Vec2,
+    pub rotation: f32,
+}
+
+impl ImagePlacement {
+    /// 画像上の位置（全体を0〜1とした座標）を画面座標に変換する
+    pub fn to_screen(self, u: f32, v: f32) -> egui::Pos2 {
+        let offset = egui::vec2((u - 0.5) * self.size.x, (v - 0.5) * self.size.y);
+        // 画面座標はy軸が下向きのため、(x, y) → (-y, x) が時計回りの90度回転になる
+        // 90度単位は誤差の無いよう個別に扱い、それ以外（角度補正のプレビュー）は三角関数で回転する
+        let angle = self.rotation.rem_euclid(360.0);
+        let rotated = match angle {
+            0.0 => offset,
+            90.0 => egui::vec2(-offset.y, offset.x),
+            180.0 => -offset,
+            270.0 => egui::vec2(offset.y, -offset.x),
+            _ => {
+                let (sin, cos) = angle.to_radians().sin_cos();
+                egui::vec2(
+                    offset.x * cos - offset.y * sin,
+                    offset.x * sin + offset.y * cos,
+                )
+            }
+        };
+        self.center + rotated
+    }
+
+    /// 回転後の表示範囲
+    pub fn bounding_rect(self) -> egui::Rect {
+        self.region_rect(egui::Rect::from_min_max(
+            egui::pos2(0.0, 0.0),
+            egui::pos2(1.0, 1.0),
+        ))
+    }
+
+    /// 画像上の範囲が画面上で占める範囲
+    fn region_rect(self, region: egui::Rect) -> egui::Rect {
+        // 任意の角度では対角の2点で範囲が決まらないため、4隅すべてを含める
+        egui::Rect::from_points(&[
+            self.to_screen(region.min.x, region.min.y),
+            self.to_screen(region.max.x, region.min.y),
+            self.to_screen(region.max.x, region.max.y),
+            self.to_screen(region.min.x, region.max.y),
+        ])
+    }
+
+    /// 画像上の範囲をテクスチャのUV範囲で描画するメッシュ
+    fn mesh(self, texture: egui::TextureId, region: egui::Rect, uv: egui::Rect) -> Mesh {
+        let mut mesh = Mesh::with_texture(texture);
+        let corners = [
+            (region.left_top(), uv.left_top()),
+            (region.right_top(), uv.right_top()),
+            (region.right_bottom(), uv.right_bottom()),
+            (region.left_bottom(), uv.left_bottom()),
+        ];
+        for (point, uv) in corners {
+            mesh.vertices.push(Vertex {
+                pos: self.to_screen(point.x, point.y),
+                uv,
+                color: Color32::WHITE,
+            });
+        }
+        mesh.indices.extend_from_slice(&[0, 1, 2, 0, 2, 3]);
+        mesh
+    }
+}
+
+/// タイル分割してGPUに転送する表示用テクスチャ
+///
+/// 表示倍率に応じたレベルのうち、表示範囲と重なるタイルのみを転送します。
+/// 転送が間に合わないタイルは、最も小さいレベルの該当部分で代替表示します。
+pub struct TiledTexture {
+    pyramid: Arc<MipPyramid>,
+    tiles: HashMap<TileKey, (egui::TextureHandle, egui::Rect)>,
+}
+
+impl TiledTexture {
+    pub fn new(pyramid: Arc<MipPyramid>) -> Self {
+        Self {
+            pyramid,
+            tiles: HashMap::new(),
+        }
+    }
+
+    /// 表示する画像を差し替える（転送済みのタイルは破棄する）
+    pub fn set(&mut self, pyramid: Arc<MipPyramid>) {
+        self.pyramid = pyramid;
+        self.tiles.clear();
+    }
+
+    /// 原寸のサイズ
+    pub fn size_vec2(&self) -> egui::Vec2 {
+        let [w, h] = self.pyramid.size();
+        egui::vec2(w as f32, h as f32)
+    }
+
+    /// 表示範囲と重なるタイルを転送して描画する
+    pub fn paint(
+        &mut self,
+        ctx: &egui::Context,
+        painter: &egui::Painter,
+        placement: &ImagePlacement,
+    ) {
+        let clip = painter.clip_rect();
+        let scale = placement.size.x / self.size_vec2().x * ctx.pixels_per_point();
+        let level = self.pyramid.level_for_scale(scale);
+        let coarsest = self.pyramid.level_count() - 1;
+
+        // 最も小さいレベルは代替表示用に常に転送しておく
+        let fallback_key = TileKey {
+            level: coarsest,
+            x: 0,
+            y: 0,
+        };
+        let fallback = self.upload(ctx, fallback_key).0.id();
+
+        let (cols, rows) = self.pyramid.tile_grid(level);
+        let mut visible = Vec::new();
+        for y in 0..rows {
+            for x in 0..cols {
+                let key = TileKey { level, x, y };
+                let region = self.pyramid.tile_region(key);
+                if placement.region_rect(region).intersects(clip) {
+                    visible.push((key, region));
+                }
+            }
+        }
+
+        // 表示範囲外・別レベルのタイルは破棄する
+        self.tiles
+            .retain(|key, _| *key == fallback_key || visible.iter().any(|(k, _)| k == key));
+
+        let mut uploads = 0;
+        for (key, region) in visible {
+            let ready = self.tiles.contains_key(&key) || uploads < MAX_UPLOADS_PER_FRAME;
+            if ready {
+                if !self.tiles.contains_key(&key) {
+                    uploads += 1;
+                }
+                let (handle, uv) = self.upload(ctx, key);
+                painter.add(placement.mesh(handle.id(), region, uv));
+            } else {
+                // 転送待ちの部分は縮小版で埋める
+                painter.add(placement.mesh(fallback, region, region));
+            }
+        }
+        if uploads >= MAX_UPLOADS_PER_FRAME {
+            ctx.request_repaint();
+        }
+    }
+
+    /// タイルを転送する（転送済みであればそのまま返す）
+    fn upload(&mut self, ctx: &egui::Context, key: TileKey) -> (&egui::TextureHandle, egui::Rect) {
+        let pyramid = &self.pyramid;
+        let (handle, uv) = self.tiles.entry(key).or_insert_with(|| {
+            let (image, uv) = pyramid.tile_image(key);
+            let name = format!("main_image_{}_{}_{}", key.level, key.x, key.y);
+            (
+                ctx.load_texture(name, image, egui::TextureOptions::LINEAR),
+                uv,
+            )
+        });
+        (handle, *uv)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient(width: usize, height: usize) -> ColorImage {
+        ColorImage {
+            size: [width, height],
+            pixels: (0..width * height)
+                .map(|i| Color32::from_gray((i % 256) as u8))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_pyramid_levels_end_in_single_tile() {
+        let pyramid = MipPyramid::new(gradient(TILE_SIZE * 3 + 5, 100));
+        assert_eq!(pyramid.level_count(), 3);
+        assert_eq!(
+            pyramid.levels[1].size,
+            [(TILE_SIZE * 3 + 5).div_ceil(2), 50]
+        );
+        let last = &pyramid.levels[2].size;
+        assert!(last[0] <= TILE_SIZE && last[1] <= TILE_SIZE);
+
+        assert_eq!(pyramid.level_for_scale(2.0), 0);
+        assert_eq!(pyramid.level_for_scale(0.5), 1);
+        assert_eq!(pyramid.level_for_scale(0.3), 1);
+        assert_eq!(pyramid.level_for_scale(0.01), 2, "最小レベルで打ち切る");
+    }
+
+    #[test]
+    fn test_tile_image_includes_border() {
+        let pyramid = MipPyramid::new(gradient(TILE_SIZE + 10, 4));
+        let (image, uv) = pyramid.tile_image(TileKey {
+            level: 0,
+            x: 1,
+            y: 0,
+        });
+        // 左隣の1列を含む11列
+        assert_eq!(image.size, [11, 4]);
+        assert_eq!(image.pixels[0], pyramid.levels[0].pixels[TILE_SIZE - 1]);
+        assert!((uv.min.x - 1.0 / 11.0).abs() < 1e-6);
+        assert_eq!(uv.max.x, 1.0);
+    }
+
+    #[test]
+    fn test_placement_rotation() {
+        let placement = ImagePlacement {
+            center: egui::pos2(100.0, 100.0),
+            size: egui::vec2(40.0, 20.0),
+            rotation: 90.0,
+        };
+        // 90度回転で左上の角は右上に移動する
+        assert_eq!(placement.to_screen(0.0, 0.0), egui::pos2(110.0, 80.0));
+        let bounds = placement.bounding_rect();
+        assert_eq!(bounds.size(), egui::vec2(20.0, 40.0));
+    }
+
+    #[test]
+    fn test_placement_fine_rotation() {
+        let placement = ImagePlacement {
+            center: egui::pos2(0.0, 0.0),
+            size: egui::vec2(40.0, 20.0),
+            rotation: 30.0,
+        };
+        // 右端の中点は時計回り（画面上では下向き）に30度回転する
+        let right = placement.to_screen(1.0, 0.5);
+        assert!((right.x - 20.0 * 30f32.to_radians().cos()).abs() < 1e-4);
+        assert!((right.y - 10.0).abs() < 1e-4);
+        // 表示範囲は4隅すべてを含む
+        let (w, h) = crate::straighten::expanded_size(40.0, 20.0, 30.0);
+        let bounds = placement.bounding_rect();
+        assert!((bounds.width() - w).abs() < 1e-3);
+        assert!((bounds.height() - h).abs() < 1e-3);
+    }
+}